@@ -0,0 +1,496 @@
+//! Checks built packages for what their recipes got wrong without the build
+//! failing, like namcap: libraries linked but not depended on, dependencies
+//! nothing links, and files that don't belong where they were installed.
+
+use std::{collections::BTreeSet, io::Read, path::Path};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tar::EntryType;
+
+use crate::{
+    archive::{self, entry_path},
+    database::{FileKind, InstalledPackage},
+    error::Result,
+    lint::{Finding, Severity},
+    package::{PeachInfo, dependency_name},
+    sbom::Sbom,
+};
+
+/// The directories at the root packages install into.
+const PREFIXES: [&str; 6] = ["boot", "etc", "opt", "srv", "usr", "var"];
+
+/// The directories in `/usr` packages install into, the others being
+/// links to these or left to the administrator, as `/usr/local` is.
+const USR_PREFIXES: [&str; 6] = ["bin", "include", "lib", "libexec", "share", "src"];
+
+/// Where libraries are looked up in.
+const LIBRARY_DIRS: [&str; 2] = ["usr/lib", "lib"];
+
+/// The `d_tag` of dynamic section entries naming a needed library, and
+/// the library itself.
+const DT_NEEDED: u64 = 1;
+const DT_SONAME: u64 = 14;
+
+/// The `sh_type` of the dynamic section.
+const SHT_DYNAMIC: u32 = 6;
+
+/// A file of a built package, as far as analyzing it goes.
+#[derive(Debug, Clone)]
+pub struct PackageFile {
+    pub path: Utf8PathBuf,
+    pub kind: FileKind,
+    pub mode: u32,
+    /// The dynamic section of ELF objects linked against libraries.
+    pub dynamic: Option<Dynamic>,
+}
+
+/// What the dynamic section of an ELF object says about linking it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dynamic {
+    /// The libraries the object is linked against.
+    pub needed: Vec<String>,
+    /// The name libraries are linked by.
+    pub soname: Option<String>,
+}
+
+/// Analyzes the package archive at `path`, looking its libraries up in the
+/// `installed` packages.
+pub fn analyze<P: AsRef<Path>>(path: P, installed: &[InstalledPackage]) -> Result<Vec<Finding>> {
+    let info = archive::read_info(&path)?;
+    let mut archive = archive::open(&path)?;
+    let mut files = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry_path(&entry.path()?)?;
+
+        if path.as_str().is_empty() || path == PeachInfo::FILE_NAME || path == Sbom::FILE_NAME {
+            continue;
+        }
+
+        let kind = match entry.header().entry_type() {
+            EntryType::Directory => FileKind::Directory,
+            EntryType::Symlink => FileKind::Symlink,
+            _ => FileKind::File,
+        };
+        let mode = entry.header().mode()?;
+
+        let dynamic = match kind {
+            FileKind::File => {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data)?;
+                dynamic(&data)
+            }
+            _ => None,
+        };
+
+        files.push(PackageFile {
+            path,
+            kind,
+            mode,
+            dynamic,
+        });
+    }
+
+    Ok(check(&info, &files, installed))
+}
+
+/// Analyzes the `files` of the package described by `info`.
+pub fn check(
+    info: &PeachInfo,
+    files: &[PackageFile],
+    installed: &[InstalledPackage],
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut warning = |code, message| {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            code,
+            message,
+        })
+    };
+
+    let required: Vec<&str> = info
+        .dependencies
+        .iter()
+        .flat_map(|dependencies| dependencies.required_names())
+        .collect();
+
+    // Libraries the package ships itself don't need a dependency.
+    let own: BTreeSet<&str> = files
+        .iter()
+        .flat_map(|file| {
+            file.path.file_name().into_iter().chain(
+                file.dynamic
+                    .as_ref()
+                    .and_then(|dynamic| dynamic.soname.as_deref()),
+            )
+        })
+        .collect();
+
+    let needed: BTreeSet<&str> = files
+        .iter()
+        .filter_map(|file| file.dynamic.as_ref())
+        .flat_map(|dynamic| dynamic.needed.iter().map(String::as_str))
+        .collect();
+
+    for library in needed.iter().filter(|library| !own.contains(*library)) {
+        match installed
+            .iter()
+            .find(|package| libraries(package).any(|name| name == *library))
+        {
+            Some(owner) if !is_declared(owner, &required) => warning(
+                "undeclared-dependency",
+                format!(
+                    "{library} of {} is linked, but {} isn't a dependency",
+                    owner.info.name, owner.info.name
+                ),
+            ),
+            Some(_) => {}
+            None => warning(
+                "missing-library",
+                format!("{library} is linked, but no installed package has it"),
+            ),
+        }
+    }
+
+    // Dependencies can be needed for more than their libraries, only
+    // packages linking against libraries at all are told.
+    if !needed.is_empty() {
+        for dependency in &required {
+            let Some(package) = installed
+                .iter()
+                .find(|package| package.info.name == *dependency)
+            else {
+                continue;
+            };
+
+            let mut ships_libraries = false;
+            let mut linked = false;
+            for library in libraries(package) {
+                ships_libraries = true;
+                linked |= needed.contains(library);
+            }
+
+            if ships_libraries && !linked {
+                warning(
+                    "unused-dependency",
+                    format!("{dependency} is a dependency, but none of its libraries are linked"),
+                );
+            }
+        }
+    }
+
+    let parents: BTreeSet<&Utf8Path> = files.iter().filter_map(|file| file.path.parent()).collect();
+    let mut prefixes = BTreeSet::new();
+
+    for file in files {
+        if file.kind != FileKind::Symlink && file.mode & 0o002 != 0 {
+            let sticky = file.kind == FileKind::Directory && file.mode & 0o1000 != 0;
+
+            if !sticky {
+                warning(
+                    "world-writable",
+                    format!("/{} can be written by anyone", file.path),
+                );
+            }
+        }
+
+        if let Some(prefix) = nonstandard_prefix(&file.path) {
+            prefixes.insert(prefix);
+        }
+
+        if file.kind == FileKind::Directory && !parents.contains(file.path.as_path()) {
+            warning(
+                "empty-directory",
+                format!("/{} is an empty directory", file.path),
+            );
+        }
+    }
+
+    for prefix in prefixes {
+        warning(
+            "nonstandard-path",
+            format!("files are installed in /{prefix}, which isn't a standard prefix"),
+        );
+    }
+
+    findings
+}
+
+/// The file names of the shared libraries of `package`.
+fn libraries(package: &InstalledPackage) -> impl Iterator<Item = &str> {
+    package
+        .files
+        .iter()
+        .filter(|file| file.kind != FileKind::Directory)
+        .filter(|file| {
+            LIBRARY_DIRS
+                .iter()
+                .any(|dir| file.path.parent() == Some(Utf8Path::new(dir)))
+        })
+        .filter_map(|file| file.path.file_name())
+        .filter(|name| name.contains(".so"))
+}
+
+/// Whether `package` is one of the `required` dependencies, by name or by
+/// what it provides.
+fn is_declared(package: &InstalledPackage, required: &[&str]) -> bool {
+    required.iter().any(|dependency| {
+        package.info.name == *dependency
+            || package
+                .info
+                .provides
+                .iter()
+                .any(|provided| dependency_name(provided) == *dependency)
+    })
+}
+
+/// The first directories of `path` if they aren't where packages install
+/// into.
+fn nonstandard_prefix(path: &Utf8Path) -> Option<String> {
+    let mut components = path.components().map(|component| component.as_str());
+
+    match (components.next(), components.next()) {
+        (Some("usr"), Some(dir)) if !USR_PREFIXES.contains(&dir) => Some(format!("usr/{dir}")),
+        (Some(dir), _) if !PREFIXES.contains(&dir) => Some(dir.to_string()),
+        _ => None,
+    }
+}
+
+/// The dynamic section of the ELF object `data`. `None` for other files,
+/// and for objects linked statically.
+pub fn dynamic(data: &[u8]) -> Option<Dynamic> {
+    let elf = Elf::new(data)?;
+
+    // Where the ELF header has the offset, size and number of section headers.
+    let (shoff, shentsize, shnum) = match elf.wide {
+        true => (0x28, 0x3a, 0x3c),
+        false => (0x20, 0x2e, 0x30),
+    };
+    let sections = elf.address(shoff)?;
+    let section_size = elf.uint(shentsize, 2)?;
+    let section_count = elf.uint(shnum, 2)?;
+
+    // The type, offset, size and link of a section.
+    let section = |index: u64| -> Option<(u32, u64, u64, u64)> {
+        let header =
+            usize::try_from(sections.checked_add(index.checked_mul(section_size)?)?).ok()?;
+        let kind = elf.uint(header + 4, 4)? as u32;
+
+        match elf.wide {
+            true => Some((
+                kind,
+                elf.uint(header + 0x18, 8)?,
+                elf.uint(header + 0x20, 8)?,
+                elf.uint(header + 0x28, 4)?,
+            )),
+            false => Some((
+                kind,
+                elf.uint(header + 0x10, 4)?,
+                elf.uint(header + 0x14, 4)?,
+                elf.uint(header + 0x18, 4)?,
+            )),
+        }
+    };
+
+    let (_, offset, size, link) = (0..section_count)
+        .filter_map(section)
+        .find(|(kind, ..)| *kind == SHT_DYNAMIC)?;
+    let (_, strings, strings_size, _) = section(link)?;
+
+    let string = |at: u64| -> Option<String> {
+        let start = usize::try_from(strings.checked_add(at)?).ok()?;
+        let end = usize::try_from(strings.checked_add(strings_size)?).ok()?;
+        let bytes = data.get(start..end.min(data.len()))?;
+        let length = bytes.iter().position(|byte| *byte == 0)?;
+
+        String::from_utf8(bytes[..length].to_vec()).ok()
+    };
+
+    let entry_size = if elf.wide { 16 } else { 8 };
+    let mut dynamic = Dynamic::default();
+
+    for entry in (offset..offset.saturating_add(size)).step_by(entry_size) {
+        let tag = elf.address(entry as usize)?;
+        let value = elf.address(entry as usize + entry_size / 2)?;
+
+        match tag {
+            0 => break,
+            DT_NEEDED => dynamic.needed.extend(string(value)),
+            DT_SONAME => dynamic.soname = string(value),
+            _ => {}
+        }
+    }
+
+    Some(dynamic)
+}
+
+/// Reads the integers of an ELF object in its width and byte order.
+struct Elf<'a> {
+    data: &'a [u8],
+    wide: bool,
+    big_endian: bool,
+}
+
+impl<'a> Elf<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        if data.get(..4)? != b"\x7fELF" {
+            return None;
+        }
+
+        Some(Self {
+            data,
+            wide: *data.get(4)? == 2,
+            big_endian: *data.get(5)? == 2,
+        })
+    }
+
+    fn uint(&self, offset: usize, size: usize) -> Option<u64> {
+        let bytes = self.data.get(offset..offset.checked_add(size)?)?;
+        let fold = |value: u64, byte: &u8| value << 8 | u64::from(*byte);
+
+        Some(match self.big_endian {
+            true => bytes.iter().fold(0, fold),
+            false => bytes.iter().rev().fold(0, fold),
+        })
+    }
+
+    /// An address, offset or size, as wide as the object.
+    fn address(&self, offset: usize) -> Option<u64> {
+        self.uint(offset, if self.wide { 8 } else { 4 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::{FileEntry, InstallReason},
+        package::Package,
+    };
+
+    fn file(path: &str, kind: FileKind, mode: u32) -> PackageFile {
+        PackageFile {
+            path: Utf8PathBuf::from(path),
+            kind,
+            mode,
+            dynamic: None,
+        }
+    }
+
+    fn installed(name: &str, files: &[&str]) -> InstalledPackage {
+        let package = Package::builder()
+            .name(name)
+            .version("1.0")
+            .license("MIT")
+            .build()
+            .unwrap();
+
+        InstalledPackage::new(
+            PeachInfo::from(&package),
+            InstallReason::Explicit,
+            files
+                .iter()
+                .map(|path| FileEntry {
+                    path: Utf8PathBuf::from(path),
+                    kind: FileKind::File,
+                    mode: 0o644,
+                    hash: None,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_dynamic() {
+        // Test binaries link against the C library.
+        let binary = std::fs::read("/proc/self/exe").unwrap();
+        let dynamic = dynamic(&binary).unwrap();
+        assert!(
+            dynamic
+                .needed
+                .iter()
+                .any(|library| library.starts_with("libc."))
+        );
+
+        assert_eq!(super::dynamic(b"#!/bin/sh\n"), None);
+    }
+
+    #[test]
+    fn test_check() {
+        let package = Package::builder()
+            .name("foo")
+            .version("1.0")
+            .license("MIT")
+            .dependency("zlib")
+            .dependency("openssl>=3.0")
+            .build()
+            .unwrap();
+        let info = PeachInfo::from(&package);
+
+        let mut binary = file("usr/bin/foo", FileKind::File, 0o755);
+        binary.dynamic = Some(Dynamic {
+            needed: vec![
+                "libfoo.so.1".to_string(),
+                "libc.so.6".to_string(),
+                "libbar.so.2".to_string(),
+            ],
+            soname: None,
+        });
+        let files = [
+            file("usr", FileKind::Directory, 0o755),
+            file("usr/bin", FileKind::Directory, 0o755),
+            binary,
+            file("usr/lib/libfoo.so.1", FileKind::File, 0o755),
+            file("usr/local/share/foo", FileKind::Directory, 0o777),
+            file("var/tmp/foo", FileKind::Directory, 0o1777),
+        ];
+        let installed = [
+            installed("glibc", &["usr/lib/libc.so.6", "usr/bin/ldd"]),
+            installed("zlib", &["usr/lib/libz.so.1"]),
+            installed("openssl", &["usr/lib/libssl.so.3", "usr/bin/openssl"]),
+        ];
+
+        let findings = check(&info, &files, &installed);
+        let codes: Vec<(&str, &str)> = findings
+            .iter()
+            .map(|finding| (finding.code, finding.message.as_str()))
+            .collect();
+
+        assert_eq!(
+            codes,
+            [
+                (
+                    "missing-library",
+                    "libbar.so.2 is linked, but no installed package has it"
+                ),
+                (
+                    "undeclared-dependency",
+                    "libc.so.6 of glibc is linked, but glibc isn't a dependency"
+                ),
+                (
+                    "unused-dependency",
+                    "zlib is a dependency, but none of its libraries are linked"
+                ),
+                (
+                    "unused-dependency",
+                    "openssl is a dependency, but none of its libraries are linked"
+                ),
+                (
+                    "world-writable",
+                    "/usr/local/share/foo can be written by anyone"
+                ),
+                (
+                    "empty-directory",
+                    "/usr/local/share/foo is an empty directory"
+                ),
+                ("empty-directory", "/var/tmp/foo is an empty directory"),
+                (
+                    "nonstandard-path",
+                    "files are installed in /usr/local, which isn't a standard prefix"
+                ),
+            ]
+        );
+    }
+}
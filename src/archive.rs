@@ -0,0 +1,103 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Component, Path},
+};
+
+use camino::Utf8PathBuf;
+use tar::Archive;
+
+use crate::{
+    error::{Result, bail, format_err},
+    package::PeachInfo,
+    sbom::Sbom,
+};
+
+pub const ARCHIVE_EXTENSION: &str = "peach";
+
+pub type PeachArchive = Archive<zstd::Decoder<'static, std::io::BufReader<File>>>;
+
+pub fn open<P: AsRef<Path>>(path: P) -> Result<PeachArchive> {
+    let file = File::open(path)?;
+
+    Ok(Archive::new(zstd::Decoder::new(file)?))
+}
+
+pub fn read_info<P: AsRef<Path>>(path: P) -> Result<PeachInfo> {
+    let mut archive = open(&path)?;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        if entry_path(&entry.path()?)?.as_str() == PeachInfo::FILE_NAME {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+
+            return Ok(toml_edit::de::from_str(&contents)?);
+        }
+    }
+
+    bail!(
+        "{} is missing from {}",
+        PeachInfo::FILE_NAME,
+        path.as_ref().display()
+    )
+}
+
+/// Reads the bill of materials of a package archive. Archives built before
+/// bills of materials were recorded don't have one.
+pub fn read_sbom<P: AsRef<Path>>(path: P) -> Result<Option<Sbom>> {
+    let mut archive = open(&path)?;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        if entry_path(&entry.path()?)?.as_str() == Sbom::FILE_NAME {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+
+            return Ok(Some(serde_json::from_str(&contents)?));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Normalizes an archive entry path into a path relative to the install root,
+/// rejecting anything that would escape it.
+pub fn entry_path(path: &Path) -> Result<Utf8PathBuf> {
+    let mut normalized = Utf8PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(part) => normalized.push(
+                part.to_str()
+                    .ok_or(format_err!("Non UTF-8 path in archive: {}", path.display()))?,
+            ),
+            _ => bail!("Unsafe path in archive: {}", path.display()),
+        }
+    }
+
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_path_strips_curdir() {
+        assert_eq!(
+            entry_path(Path::new("./usr/bin/foo")).unwrap(),
+            Utf8PathBuf::from("usr/bin/foo")
+        );
+        assert_eq!(entry_path(Path::new("./")).unwrap(), Utf8PathBuf::new());
+    }
+
+    #[test]
+    fn test_entry_path_rejects_escape() {
+        assert!(entry_path(Path::new("../etc/passwd")).is_err());
+        assert!(entry_path(Path::new("/etc/passwd")).is_err());
+    }
+}
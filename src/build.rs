@@ -0,0 +1,881 @@
+use std::{
+    env,
+    ffi::CStr,
+    fs::{self, File},
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use bzip2::read::BzDecoder;
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use nix::{
+    fcntl::{OFlag, open},
+    sched::{CloneFlags, unshare},
+    sys::stat::Mode,
+    unistd::{getgid, getuid, write},
+};
+use rayon::prelude::*;
+use reqwest::Url;
+use sha2::{Digest, Sha256 as Sha256Hasher};
+use tar::Archive;
+use xz2::read::XzDecoder;
+
+use crate::{
+    config::Config,
+    error::{Error, Result, bail, format_err},
+    events::{self, Event},
+    failure::Failure,
+    fetch::{self, Fetcher, Kind, Request},
+    lockfile::{LOCK_FILE, LockedSource, PackageLock},
+    log::info,
+    package::{self, Package, PeachInfo, Source, Step, StepVariant},
+    permissions,
+    runner::StepRunner,
+    sbom::Sbom,
+    upstream,
+};
+
+/// The name of the step running the tests of a package.
+pub const CHECK_STEP: &str = "check";
+
+/// Where and how a package is built. Recipes refer to the directories and
+/// settings as `%{recipedir}`, `%{pkgdir}`, `%{srcdir}`, `%{jobs}` and
+/// `%{target}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildContext {
+    /// The directory of the recipe, for patches and other files next to it.
+    pub recipe_dir: PathBuf,
+    /// Where sources are downloaded to and steps start running in.
+    pub build_dir: PathBuf,
+    /// What steps install into, which gets packaged.
+    pub pkg_dir: PathBuf,
+    /// Where sources are extracted and checked out.
+    pub src_dir: PathBuf,
+    /// How many jobs steps may run at once.
+    pub jobs: usize,
+    /// The architecture built for.
+    pub target: String,
+}
+
+impl BuildContext {
+    /// A context building the recipe in `recipe_dir` in `build_dir`, with
+    /// `package` and `sources` in it, as many jobs as there are CPUs, for the
+    /// architecture blossom runs on.
+    pub fn new<R: AsRef<Path>, B: AsRef<Path>>(recipe_dir: R, build_dir: B) -> Self {
+        let build_dir = build_dir.as_ref();
+
+        Self {
+            recipe_dir: recipe_dir.as_ref().to_path_buf(),
+            build_dir: build_dir.to_path_buf(),
+            pkg_dir: build_dir.join("package"),
+            src_dir: build_dir.join("sources"),
+            jobs: std::thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            target: env::consts::ARCH.to_string(),
+        }
+    }
+}
+
+/// Builds `package` in `context`: fetches its sources, pinned to `lock` if
+/// given, runs its steps and packs what they installed into `out_dir`. The
+/// version of packages with a `pkgver` command is set to what it prints,
+/// once the sources are fetched. Returns the path of the built archive.
+pub async fn build_package(
+    config: &Config,
+    package: &mut Package,
+    context: &BuildContext,
+    lock: Option<&PackageLock>,
+    out_dir: &Path,
+) -> Result<PathBuf> {
+    let sources = fetch_sources(package, lock, context, config).await?;
+
+    if let Some(pkgver) = &package.pkgver {
+        let version = run_pkgver(package, context, pkgver)?;
+        info!("Building {} as version {version}", package.info.name);
+
+        package.info.version = version;
+    }
+
+    let mut working_dir = context.build_dir.clone();
+
+    for step in &package.steps {
+        run_step(package, context, step, &mut working_dir)?;
+    }
+
+    create_tarball(
+        &context.pkg_dir,
+        package,
+        &sources,
+        config.compression,
+        config.parallelism.compression(),
+        out_dir,
+    )
+}
+
+/// Runs the `check` step of `package` alone, to iterate on its tests without
+/// rebuilding. The build directory of `context` is reused when the sources
+/// are there, otherwise they are fetched and the steps before it run first.
+pub async fn check_package(
+    config: &Config,
+    package: &Package,
+    context: &BuildContext,
+) -> Result<()> {
+    let Some(check) = package
+        .steps
+        .iter()
+        .position(|step| step.name == CHECK_STEP)
+    else {
+        bail!("{} has no {CHECK_STEP} step", package.info.name);
+    };
+
+    let built = context.src_dir.exists();
+    if !built {
+        fetch_sources(package, None, context, config).await?;
+    }
+
+    let mut working_dir = context.build_dir.clone();
+
+    for (i, step) in package.steps[..=check].iter().enumerate() {
+        // Moves still run, the check step may depend on where they lead.
+        let skip = built && i != check && matches!(step.variant, StepVariant::Command { .. });
+
+        if !skip {
+            run_step(package, context, step, &mut working_dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `step` of `package` in `working_dir`, which [`StepVariant::Move`]
+/// steps change for the steps after them.
+fn run_step(
+    package: &Package,
+    context: &BuildContext,
+    step: &Step,
+    working_dir: &mut PathBuf,
+) -> Result<()> {
+    info!("Running step: {}", step.name);
+    events::emit(Event::StepStarted {
+        package: &package.info.name,
+        step: &step.name,
+    });
+
+    match &step.variant {
+        StepVariant::Command { command, runner } => {
+            let jobs = context.jobs.to_string();
+            let mut step_command = runner.command(command);
+            step_command
+                .current_dir(&*working_dir)
+                .env("BLOSSOM_JOBS", &jobs)
+                .env("BLOSSOM_TARGET", &context.target);
+
+            if env::var_os("MAKEFLAGS").is_none() {
+                step_command.env("MAKEFLAGS", format!("-j{jobs}"));
+            }
+
+            if !package.network {
+                deny_network(&mut step_command);
+            }
+
+            let result = match events::observer() {
+                Some(observer) => events::run_observed(
+                    &mut step_command,
+                    &*observer,
+                    &package.info.name,
+                    &step.name,
+                ),
+                None => step_command.status(),
+            };
+
+            let result = result.map_err(|e| {
+                if package.network {
+                    format_err!("Failed to run step '{}': {e}", step.name)
+                } else {
+                    format_err!(
+                        "Failed to run step '{}' without network access, \
+                         network namespaces may be unavailable: {e}",
+                        step.name
+                    )
+                }
+            })?;
+
+            events::emit(Event::StepFinished {
+                package: &package.info.name,
+                step: &step.name,
+                success: result.success(),
+            });
+
+            if !result.success() {
+                return Err(Error::StepFailed {
+                    step: step.name.clone(),
+                    status: result,
+                });
+            }
+        }
+        StepVariant::Move { path } => {
+            *working_dir = context.build_dir.join(path);
+
+            fs::create_dir_all(&*working_dir)?;
+
+            events::emit(Event::StepFinished {
+                package: &package.info.name,
+                step: &step.name,
+                success: true,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the `pkgver` command of `package` in the source directory, returning
+/// the version it printed.
+fn run_pkgver(package: &Package, context: &BuildContext, pkgver: &str) -> Result<String> {
+    let mut command = StepRunner::shell().command(pkgver);
+    command
+        .current_dir(&context.src_dir)
+        .env("BLOSSOM_TARGET", &context.target);
+
+    if !package.network {
+        deny_network(&mut command);
+    }
+
+    let output = command
+        .output()
+        .map_err(|e| format_err!("Failed to run pkgver: {e}"))?;
+    if !output.status.success() {
+        return Err(Error::StepFailed {
+            step: "pkgver".to_string(),
+            status: output.status,
+        });
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !package::is_valid_name(&version) {
+        bail!("pkgver printed the invalid version \"{version}\"");
+    }
+
+    Ok(version)
+}
+
+/// Runs a command in a network namespace of its own, whose only interface is
+/// a loopback left down, so that builds can't download anything behind the
+/// back of the fetch phase. Users other than root get a user namespace mapping
+/// only themselves, as creating a network namespace needs privileges.
+fn deny_network(command: &mut Command) {
+    let uid = getuid();
+    let maps = (!uid.is_root()).then(|| {
+        (
+            format!("{uid} {uid} 1"),
+            format!("{} {} 1", getgid(), getgid()),
+        )
+    });
+
+    // SAFETY: the closure only makes system calls, the maps are formatted
+    // before forking.
+    unsafe {
+        command.pre_exec(move || {
+            let flags = match maps {
+                Some(_) => CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNET,
+                None => CloneFlags::CLONE_NEWNET,
+            };
+            unshare(flags)?;
+
+            if let Some((uid_map, gid_map)) = &maps {
+                write_proc(c"/proc/self/setgroups", b"deny")?;
+                write_proc(c"/proc/self/uid_map", uid_map.as_bytes())?;
+                write_proc(c"/proc/self/gid_map", gid_map.as_bytes())?;
+            }
+
+            Ok(())
+        });
+    }
+}
+
+fn write_proc(path: &CStr, contents: &[u8]) -> io::Result<()> {
+    let fd = open(path, OFlag::O_WRONLY, Mode::empty())?;
+    write(&fd, contents)?;
+
+    Ok(())
+}
+
+/// Fetches every source of a package into the source directory of `context`,
+/// pinned to the lockfile if given. Returns exactly what was fetched, for
+/// locking. Offline, only downloaded and local sources are used. Cloning and
+/// extracting run on blocking threads, off the async runtime.
+pub async fn fetch_sources(
+    package: &Package,
+    lock: Option<&PackageLock>,
+    context: &BuildContext,
+    config: &Config,
+) -> Result<Vec<LockedSource>> {
+    let sources_dir = &context.src_dir;
+
+    if fs::metadata(sources_dir).is_ok() {
+        fs::remove_dir_all(sources_dir)?;
+    }
+    fs::create_dir_all(sources_dir)?;
+
+    let locked = match lock {
+        Some(lock) => lock
+            .locked_sources(&package.sources)?
+            .into_iter()
+            .map(Some)
+            .collect(),
+        None => vec![None; package.sources.len()],
+    };
+
+    let archives = package
+        .sources
+        .iter()
+        .zip(&locked)
+        .map(|(source, locked)| match fetch::get(&source.url)?.kind() {
+            Kind::Checkout => Ok(None),
+            Kind::Archive => ArchiveSource::new(source, *locked, &context.build_dir).map(Some),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Archives downloaded before are all hashed at once, and so are the ones
+    // fetched afterwards, reporting every mismatch rather than the first.
+    let downloaded = archives
+        .par_iter()
+        .map(|archive| archive.as_ref().map_or(Ok(false), ArchiveSource::is_valid))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut fetched = Vec::new();
+
+    for (i, (source, locked)) in package.sources.iter().zip(locked).enumerate() {
+        let source = match &archives[i] {
+            Some(archive) => fetch_archive(archive, downloaded[i], config).await?,
+            None => {
+                let fetcher = fetch::get(&source.url)?;
+                fetch_checkout(&*fetcher, source, locked, sources_dir, config.offline).await?
+            }
+        };
+
+        fetched.push(source);
+    }
+
+    let checksums = archives
+        .par_iter()
+        .zip(&downloaded)
+        .map(|(archive, downloaded)| match archive {
+            Some(archive) => archive.checksum(*downloaded).map(Some),
+            None => Ok(None),
+        })
+        .collect::<Vec<_>>();
+
+    let mut mismatches = Vec::new();
+    for (source, checksum) in fetched.iter_mut().zip(checksums) {
+        match checksum {
+            Ok(Some(checksum)) => source.checksum = Some(checksum),
+            Ok(None) => {}
+            Err(e) => mismatches.push(e),
+        }
+    }
+
+    match mismatches.len() {
+        0 => {}
+        1 => return Err(mismatches.remove(0)),
+        count => bail!(
+            "{count} sources don't match their checksums:\n{}",
+            mismatches
+                .iter()
+                .map(|e| format!("  {e}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    }
+
+    for (archive, downloaded) in archives.iter().zip(downloaded) {
+        if let Some(archive) = archive {
+            verify_signature(
+                package,
+                archive.source,
+                &archive.path,
+                downloaded,
+                &context.recipe_dir,
+                config,
+            )
+            .await?;
+        }
+    }
+
+    for archive in archives.into_iter().flatten() {
+        let (path, sources_dir) = (archive.path, sources_dir.clone());
+        events::spawn_blocking(move || extract_source(&path, &sources_dir))
+            .await
+            .map_err(io::Error::from)??;
+    }
+
+    Ok(fetched)
+}
+
+/// An archive source of a recipe, along with where it's downloaded to.
+struct ArchiveSource<'a> {
+    source: &'a Source,
+    locked: Option<&'a LockedSource>,
+    path: PathBuf,
+}
+
+impl<'a> ArchiveSource<'a> {
+    fn new(source: &'a Source, locked: Option<&'a LockedSource>, build_dir: &Path) -> Result<Self> {
+        if source.checksum.is_empty() {
+            bail!("Source {} has no checksum", source.url);
+        }
+
+        Ok(Self {
+            source,
+            locked,
+            path: source_path(build_dir, source)?,
+        })
+    }
+
+    /// Whether the archive was downloaded and matches the checksums of the
+    /// recipe and of the lockfile.
+    fn is_valid(&self) -> Result<bool> {
+        Ok(self.path.exists()
+            && check_hash(&self.path, &self.source.checksum)?
+            && self.matches_lock()?)
+    }
+
+    fn matches_lock(&self) -> Result<bool> {
+        match self.locked.and_then(|locked| locked.checksum.as_deref()) {
+            Some(checksum) => check_hash(&self.path, checksum),
+            None => Ok(true),
+        }
+    }
+
+    /// The blake3 checksum locking the archive, verifying it first unless it
+    /// was already found valid.
+    fn checksum(&self, valid: bool) -> Result<String> {
+        if !valid {
+            verify_hash(&self.path, &self.source.checksum)?;
+
+            if !self.matches_lock()? {
+                bail!(
+                    "{} doesn't match the checksum in {LOCK_FILE}",
+                    self.path.display()
+                );
+            }
+        }
+
+        match self.source.checksum.starts_with("blake3:") {
+            true => Ok(self.source.checksum.clone()),
+            false => compute_hash(&self.path, "blake3:"),
+        }
+    }
+}
+
+/// Fetches an archive source unless it was already `downloaded`, leaving its
+/// verification to the caller. Returns what it was fetched from, without its
+/// checksum.
+async fn fetch_archive(
+    archive: &ArchiveSource<'_>,
+    downloaded: bool,
+    config: &Config,
+) -> Result<LockedSource> {
+    let source = archive.source;
+    let offline = config.offline;
+
+    // Locked builds fetch from where the source was resolved to when locking.
+    let url = archive
+        .locked
+        .and_then(|locked| locked.resolved.as_deref())
+        .unwrap_or(&source.url);
+    let fetcher = fetch::get(url)?;
+
+    let resolved = if downloaded {
+        match archive.locked {
+            Some(_) => url.to_string(),
+            None if offline => url.to_string(),
+            None => fetcher.resolve(url).await?,
+        }
+    } else {
+        if offline && fetcher.is_remote(url) {
+            return Err(Failure::Network.error(format!(
+                "Source {} isn't downloaded, can't fetch it while offline",
+                source.url
+            )));
+        }
+
+        info!("Fetching source from {url}");
+
+        fetcher
+            .fetch(Request {
+                url,
+                rev: None,
+                target: &archive.path,
+                segments: config.download.segments,
+            })
+            .await?
+    };
+
+    Ok(LockedSource {
+        url: source.url.clone(),
+        resolved: Some(resolved),
+        checksum: None,
+        commit: None,
+    })
+}
+
+/// Checks out a source into `sources_dir`, at the locked commit or else the
+/// requested revision.
+async fn fetch_checkout(
+    fetcher: &dyn Fetcher,
+    source: &Source,
+    locked: Option<&LockedSource>,
+    sources_dir: &Path,
+    offline: bool,
+) -> Result<LockedSource> {
+    if offline && fetcher.is_remote(&source.url) {
+        return Err(Failure::Network.error(format!("Can't fetch {} while offline", source.url)));
+    }
+
+    let commit = fetcher
+        .fetch(Request {
+            url: &source.url,
+            rev: locked
+                .and_then(|locked| locked.commit.as_deref())
+                .or(source.rev.as_deref()),
+            target: &sources_dir.join(source.file_name()),
+            segments: 1,
+        })
+        .await?;
+
+    Ok(LockedSource {
+        url: source.url.clone(),
+        resolved: None,
+        checksum: None,
+        commit: Some(commit),
+    })
+}
+
+fn extract_source(target_path: &Path, sources_dir: &Path) -> Result<()> {
+    info!("Extracting \"{}\"", target_path.display());
+
+    let target = File::open(target_path)?;
+
+    match target_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("xz") => unpack_archive(XzDecoder::new(target), sources_dir)?,
+        // Crates are gzipped tarballs.
+        Some("gz" | "crate") => unpack_archive(GzDecoder::new(target), sources_dir)?,
+        Some("bz2") => unpack_archive(BzDecoder::new(target), sources_dir)?,
+        _ => {
+            return Err(Error::InvalidPackage(format!(
+                "{} isn't an archive blossom can extract, expected a .tar.gz, .tar.xz, \
+                 .tar.bz2 or .crate",
+                target_path.display()
+            )));
+        }
+    }
+
+    info!("Archive extracted successfully");
+
+    Ok(())
+}
+
+fn unpack_archive<R: Read>(decoder: R, sources_dir: &Path) -> Result<()> {
+    let mut archive = Archive::new(decoder);
+
+    archive.unpack(sources_dir)?;
+
+    Ok(())
+}
+
+pub fn check_hash<P: AsRef<Path>>(path: P, checksum: &str) -> Result<bool> {
+    Ok(compute_hash(path.as_ref(), checksum)? == checksum)
+}
+
+/// Like [`check_hash`], but a mismatch is an error telling both checksums.
+pub fn verify_hash<P: AsRef<Path>>(path: P, checksum: &str) -> Result<()> {
+    let path = path.as_ref();
+    let actual = compute_hash(path, checksum)?;
+
+    if actual != checksum {
+        return Err(Error::ChecksumMismatch {
+            path: path.to_path_buf(),
+            expected: checksum.to_string(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+/// Fetches the upstream signature of `source`, downloaded to `path`, and
+/// checks it against the upstream keys of `package`, if the source has one.
+/// A signature fetched before is reused when the archive was `downloaded`
+/// before too.
+pub async fn verify_signature(
+    package: &Package,
+    source: &Source,
+    path: &Path,
+    downloaded: bool,
+    recipe_dir: &Path,
+    config: &Config,
+) -> Result<()> {
+    let Some(url) = &source.signature else {
+        return Ok(());
+    };
+    let signature = upstream::signature_path(path, url);
+
+    if !(downloaded && signature.exists()) {
+        let fetcher = fetch::get(url)?;
+        if config.offline && fetcher.is_remote(url) {
+            return Err(Failure::Network.error(format!(
+                "Signature {url} isn't downloaded, can't fetch it while offline"
+            )));
+        }
+
+        info!("Fetching signature from {url}");
+
+        fetcher
+            .fetch(Request {
+                url,
+                rev: None,
+                target: &signature,
+                segments: 1,
+            })
+            .await?;
+    }
+
+    let keys = package.upstream_keys.clone();
+    let (path, recipe_dir) = (path.to_path_buf(), recipe_dir.to_path_buf());
+    let home = path.with_file_name("gnupg");
+    events::spawn_blocking(move || upstream::verify(&path, &signature, &keys, &recipe_dir, &home))
+        .await
+        .map_err(io::Error::from)?
+}
+
+/// The file an archive source is downloaded to in `build_dir`, named by
+/// its `filename` or else after its URL.
+pub fn source_path(build_dir: &Path, source: &Source) -> Result<PathBuf> {
+    if let Some(filename) = &source.filename {
+        return Ok(build_dir.join(filename));
+    }
+
+    let url: Url = source.url.as_str().try_into()?;
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| {
+            Error::InvalidPackage(format!(
+                "source {} has no file name in its URL, set its filename",
+                source.url
+            ))
+        })?;
+
+    Ok(build_dir.join(name))
+}
+
+/// The checksum of the file at `path`, with the same algorithm as `checksum`.
+pub fn compute_hash<P: AsRef<Path>>(path: P, checksum: &str) -> Result<String> {
+    let path = path.as_ref();
+    let algorithm = checksum
+        .split_once(':')
+        .map(|(algorithm, _)| algorithm)
+        .ok_or_else(|| Error::InvalidChecksum(checksum.to_string()))?;
+
+    // Large sources are mapped rather than read, blake3 hashing them on every
+    // core. SHA-256 can't be split, so it's fed in chunks instead.
+    let hash = match algorithm {
+        "blake3" => {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update_mmap_rayon(path)?;
+            hasher.finalize().to_hex().to_string()
+        }
+        "sha256" => {
+            let mut hasher = Sha256Hasher::new();
+            io::copy(&mut File::open(path)?, &mut hasher)?;
+            base16ct::lower::encode_string(hasher.finalize().as_slice())
+        }
+        _ => return Err(Error::InvalidChecksum(checksum.to_string())),
+    };
+
+    Ok(format!("{algorithm}:{hash}"))
+}
+
+/// Packs the built files along with the package information and a bill of
+/// materials describing the sources they were built from, compressed at the
+/// given zstd `level` by `threads` threads, into `out_dir`. Returns the path
+/// of the archive.
+pub fn create_tarball<P: AsRef<Path>>(
+    package_path: P,
+    package: &Package,
+    sources: &[LockedSource],
+    level: i32,
+    threads: usize,
+    out_dir: &Path,
+) -> Result<PathBuf> {
+    let package_path = package_path.as_ref();
+    let tarball_name = format!("{}-{}.peach", package.info.name, package.info.version);
+    let tarball_path = out_dir.join(&tarball_name);
+    let tar_gz = File::create(&tarball_path)?;
+    let mut enc = zstd::Encoder::new(tar_gz, level)?;
+    // Workers compress the tar stream in the background while it's written,
+    // a single worker would only add overhead.
+    if threads > 1 {
+        enc.multithread(threads as u32)?;
+    }
+    let mut tar = tar::Builder::new(enc);
+
+    let special_permissions = permissions::scan(package_path)?;
+    permissions::check_allowed(&special_permissions, &package.special_permissions)?;
+
+    for permission in &special_permissions {
+        info!("Packaging {permission}");
+    }
+
+    let build_date = Utc::now();
+
+    let peach_info = PeachInfo {
+        build_date: Some(build_date),
+        installed_size: directory_size(package_path)?,
+        special_permissions,
+        ..PeachInfo::from(package)
+    };
+
+    let peach_info = toml_edit::ser::to_string_pretty(&peach_info)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(peach_info.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, PeachInfo::FILE_NAME, peach_info.as_bytes())?;
+
+    let sbom = serde_json::to_string_pretty(&Sbom::new(package, sources, build_date))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(sbom.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, Sbom::FILE_NAME, sbom.as_bytes())?;
+
+    tar.append_dir_all(".", package_path)?;
+    tar.into_inner()?.finish()?;
+
+    info!("Created package: {}", tarball_name);
+    Ok(tarball_path)
+}
+
+fn directory_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            size += directory_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fetch_sources_mismatch() {
+        let dir = env::temp_dir().join(format!("blossom-test-fetch-{}", std::process::id()));
+        let upstream = dir.join("upstream");
+        fs::create_dir_all(&upstream).unwrap();
+
+        let mut builder = Package::builder().name("foo").version("1.0").license("MIT");
+        for name in ["a", "b", "c"] {
+            let path = upstream.join(format!("{name}.tar.gz"));
+            fs::write(&path, name).unwrap();
+
+            let checksum = match name {
+                "b" => compute_hash(upstream.join("a.tar.gz"), "blake3:").unwrap(),
+                _ => compute_hash(&path, "blake3:").unwrap(),
+            };
+            builder = builder.source(format!("file://{}", path.display()), checksum);
+        }
+        let package = builder.build().unwrap();
+
+        let context = BuildContext::new(&dir, dir.join("build"));
+        let result = fetch_sources(&package, None, &context, &Config::default()).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        match result {
+            Err(Error::ChecksumMismatch { path, .. }) => {
+                assert_eq!(path.file_name().unwrap(), "b.tar.gz");
+            }
+            result => panic!("expected a checksum mismatch, got {result:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pkgver() {
+        let dir = env::temp_dir().join(format!("blossom-test-pkgver-{}", std::process::id()));
+        let context = BuildContext::new(&dir, dir.join("build"));
+        fs::create_dir_all(&context.pkg_dir).unwrap();
+
+        let package = |pkgver| {
+            Package::builder()
+                .name("foo")
+                .version("1.0")
+                .license("MIT")
+                .network(true)
+                .pkgver(pkgver)
+                .build()
+                .unwrap()
+        };
+
+        let config = Config::default();
+        let mut built = package("echo 1.2.3");
+        let result = build_package(&config, &mut built, &context, None, &dir).await;
+        let built_path = dir.join("foo-1.2.3.peach");
+        let exists = built_path.exists();
+        let invalid =
+            build_package(&config, &mut package("echo 1.0/evil"), &context, None, &dir).await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap(), built_path);
+        assert!(exists);
+        assert_eq!(built.info.version, "1.2.3");
+        assert!(invalid.unwrap_err().to_string().contains("1.0/evil"));
+    }
+
+    #[test]
+    fn test_unnamed_sources() {
+        let source = |url: &str| Source {
+            url: url.to_string(),
+            checksum: "blake3:ab12".to_string(),
+            rev: None,
+            filename: None,
+            signature: None,
+        };
+        let build_dir = Path::new("/build");
+
+        assert_eq!(
+            source_path(build_dir, &source("https://example.com/foo-1.0.tar.gz")).unwrap(),
+            Path::new("/build/foo-1.0.tar.gz")
+        );
+        for url in ["https://example.com/foo/", "data:text/plain,foo"] {
+            assert!(matches!(
+                source_path(build_dir, &source(url)),
+                Err(Error::InvalidPackage(_))
+            ));
+        }
+
+        let dir = env::temp_dir().join(format!("blossom-test-unnamed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("foo"), "foo").unwrap();
+        let result = extract_source(&dir.join("foo"), &dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(result, Err(Error::InvalidPackage(_))));
+    }
+}
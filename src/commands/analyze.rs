@@ -0,0 +1,25 @@
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::{analyze, database::Database};
+
+/// Analyzes the package archive at `path`, looking its libraries up in the
+/// packages installed in `root`, and prints the findings, as JSON with
+/// `json`. Findings are only warnings.
+pub fn analyze<R: AsRef<Path>, P: AsRef<Path>>(root: R, path: P, json: bool) -> Result<()> {
+    let path = path.as_ref();
+    let findings = analyze::analyze(path, &Database::open(root)?.list()?)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+    } else {
+        for finding in &findings {
+            println!("{}: {finding}", path.display());
+        }
+    }
+
+    info!("{} has {} warnings", path.display(), findings.len());
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+use reqwest::Client;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    archive,
+    config::Config,
+    database::Database,
+    lockfile::{LOCK_FILE, PackageLock},
+    osv::{self, Finding, Query},
+    package::Package,
+};
+
+/// Something audited, with the label it is reported under.
+struct Target {
+    label: String,
+    query: Query,
+}
+
+/// Looks up the installed packages, and optionally the package built from the
+/// recipe in `recipe`, in OSV advisories. Packages are matched by name and
+/// version, git sources by commit, either through the OSV API or against an
+/// offline database dump.
+pub async fn audit<R: AsRef<Path>>(
+    root: R,
+    recipe: Option<&Path>,
+    database: Option<&Path>,
+    ecosystem: Option<&str>,
+    json: bool,
+) -> Result<()> {
+    let mut targets = installed_targets(root.as_ref(), ecosystem)?;
+
+    if let Some(recipe) = recipe {
+        targets.extend(recipe_targets(recipe, ecosystem)?);
+    }
+
+    let findings = match database {
+        Some(database) => audit_offline(database, &targets)?,
+        None => {
+            Config::load(root.as_ref())?
+                .require_online("query the OSV API, audit against --database")?;
+            audit_online(&targets).await?
+        }
+    };
+
+    let mut affected = 0;
+    let mut count = 0;
+    let mut report = Vec::new();
+
+    for (target, findings) in targets.iter().zip(&findings) {
+        if findings.is_empty() {
+            continue;
+        }
+
+        affected += 1;
+        count += findings.len();
+
+        if json {
+            report.push(json!({ "target": target.label, "findings": findings }));
+            continue;
+        }
+
+        println!("{}", target.label);
+
+        for finding in findings {
+            let mut id = finding.id.clone();
+            if !finding.aliases.is_empty() {
+                id = format!("{id} ({})", finding.aliases.join(", "));
+            }
+
+            if finding.summary.is_empty() {
+                println!("  {id}");
+            } else {
+                println!("  {id}: {}", finding.summary);
+            }
+
+            if !finding.affected.is_empty() {
+                println!("    affected: {}", finding.affected.join("; "));
+            }
+            if finding.fixed.is_empty() {
+                println!("    fixed in: no fix available");
+            } else {
+                println!("    fixed in: {}", finding.fixed.join(", "));
+            }
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+
+    if count > 0 {
+        bail!(
+            "{count} known vulnerabilities found in {affected} of {} packages and sources",
+            targets.len()
+        );
+    }
+
+    info!(
+        "No known vulnerabilities in {} packages and sources",
+        targets.len()
+    );
+
+    Ok(())
+}
+
+fn package_query(name: &str, version: &str, ecosystem: Option<&str>) -> Query {
+    Query::Package {
+        name: name.to_string(),
+        version: version.to_string(),
+        ecosystem: ecosystem.map(str::to_string),
+    }
+}
+
+/// Every installed package, and the git sources recorded in the bill of
+/// materials of its cached archive.
+fn installed_targets(root: &Path, ecosystem: Option<&str>) -> Result<Vec<Target>> {
+    let database = Database::open(root)?;
+    let mut targets = Vec::new();
+
+    for package in database.list()? {
+        let label = format!("{} {}", package.info.name, package.info.version);
+        targets.push(Target {
+            label: label.clone(),
+            query: package_query(&package.info.name, &package.info.version, ecosystem),
+        });
+
+        let cache_path = database.cache_path(&package.info.name, &package.info.version);
+        if !cache_path.exists() {
+            continue;
+        }
+
+        let Some(sbom) = archive::read_sbom(&cache_path)? else {
+            continue;
+        };
+
+        for source in sbom.sources {
+            if let Some(commit) = source.commit {
+                targets.push(Target {
+                    label: format!("{label} source {}@{commit}", source.url),
+                    query: Query::Commit(commit),
+                });
+            }
+        }
+    }
+
+    Ok(targets)
+}
+
+/// The package a recipe builds, and its git sources at the commit locked in
+/// its lockfile, or the revision it asks for if that is a full commit.
+fn recipe_targets(recipe: &Path, ecosystem: Option<&str>) -> Result<Vec<Target>> {
+    let package_path = recipe.join("package.toml");
+
+    if !package_path.exists() {
+        bail!("package.toml not found in {}", recipe.display());
+    }
+
+    let package = Package::from_path(package_path)?;
+    let lock_path = recipe.join(LOCK_FILE);
+    let lock = if lock_path.exists() {
+        Some(PackageLock::read(lock_path)?)
+    } else {
+        None
+    };
+
+    let label = format!("{} {} (recipe)", package.info.name, package.info.version);
+    let mut targets = vec![Target {
+        label: label.clone(),
+        query: package_query(&package.info.name, &package.info.version, ecosystem),
+    }];
+
+    for source in package
+        .sources
+        .iter()
+        .filter(|source| source.url.starts_with("git+"))
+    {
+        let locked = lock
+            .as_ref()
+            .and_then(|lock| lock.sources.iter().find(|locked| locked.url == source.url))
+            .and_then(|locked| locked.commit.clone());
+        let commit = locked.or(source
+            .rev
+            .clone()
+            .filter(|rev| rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit())));
+
+        match commit {
+            Some(commit) => targets.push(Target {
+                label: format!("{label} source {}@{commit}", source.url),
+                query: Query::Commit(commit),
+            }),
+            None => warn!(
+                "Skipping {}, it isn't pinned to a commit, run `blossom lock` first",
+                source.url
+            ),
+        }
+    }
+
+    Ok(targets)
+}
+
+fn audit_offline(database: &Path, targets: &[Target]) -> Result<Vec<Vec<Finding>>> {
+    let vulnerabilities = osv::load_database(database)?;
+
+    info!(
+        "Auditing against {} advisories from {}",
+        vulnerabilities.len(),
+        database.display()
+    );
+
+    let commits = targets
+        .iter()
+        .filter(|target| matches!(target.query, Query::Commit(_)))
+        .count();
+    if commits > 0 {
+        warn!("{commits} git sources can only be audited online, skipping them");
+    }
+
+    Ok(targets
+        .iter()
+        .map(|target| {
+            vulnerabilities
+                .iter()
+                .filter_map(|vulnerability| vulnerability.affects(&target.query))
+                .collect()
+        })
+        .collect())
+}
+
+async fn audit_online(targets: &[Target]) -> Result<Vec<Vec<Finding>>> {
+    let client = Client::new();
+    let queries: Vec<Query> = targets.iter().map(|target| target.query.clone()).collect();
+
+    info!(
+        "Querying {} for {} packages and sources",
+        osv::OSV_API,
+        queries.len()
+    );
+
+    let matches = osv::query_batch(&client, &queries).await?;
+
+    let ids: BTreeSet<&String> = matches.iter().flatten().collect();
+    let mut vulnerabilities = BTreeMap::new();
+
+    for id in ids {
+        vulnerabilities.insert(id, osv::fetch_vulnerability(&client, id).await?);
+    }
+
+    Ok(queries
+        .iter()
+        .zip(&matches)
+        .map(|(query, ids)| {
+            ids.iter()
+                .map(|id| vulnerabilities[id].describe(query))
+                .collect()
+        })
+        .collect())
+}
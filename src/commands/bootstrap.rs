@@ -0,0 +1,107 @@
+use std::{
+    fs,
+    os::unix::fs::{PermissionsExt, symlink},
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+use tracing::info;
+
+use crate::{
+    commands::install::install_into, config::CONFIG_FILE, database::Database, keyring::KEYRING_FILE,
+};
+
+/// The directories of a new root, and their modes.
+const SKELETON: [(&str, u32); 20] = [
+    ("boot", 0o755),
+    ("dev", 0o755),
+    ("etc", 0o755),
+    ("home", 0o755),
+    ("mnt", 0o755),
+    ("opt", 0o755),
+    ("proc", 0o555),
+    ("root", 0o700),
+    ("run", 0o755),
+    ("srv", 0o755),
+    ("sys", 0o555),
+    ("tmp", 0o1777),
+    ("usr/bin", 0o755),
+    ("usr/include", 0o755),
+    ("usr/lib", 0o755),
+    ("usr/share", 0o755),
+    ("var/cache", 0o755),
+    ("var/lib", 0o755),
+    ("var/log", 0o755),
+    ("var/tmp", 0o1777),
+];
+
+/// The links merging the top level directories into `/usr`.
+const LINKS: [(&str, &str); 5] = [
+    ("bin", "usr/bin"),
+    ("sbin", "usr/bin"),
+    ("lib", "usr/lib"),
+    ("lib64", "usr/lib"),
+    ("usr/sbin", "bin"),
+];
+
+/// Creates a new system in `root`: its directories, the configuration and
+/// keyring of `from`, and `packages` installed from the repositories synced
+/// in `from`.
+pub async fn bootstrap<R: AsRef<Path>, F: AsRef<Path>>(
+    root: R,
+    from: F,
+    packages: &[String],
+    wait: bool,
+) -> Result<()> {
+    let (root, from) = (root.as_ref(), from.as_ref());
+
+    fs::create_dir_all(root)?;
+    if root.canonicalize()? == from.canonicalize()? {
+        bail!(
+            "Can't bootstrap {} from itself, pass the new root with --root",
+            root.display()
+        );
+    }
+
+    for (dir, mode) in SKELETON {
+        let path = root.join(dir);
+
+        // Existing directories may be mount points, which keep their modes.
+        if !path.exists() {
+            fs::create_dir_all(&path)?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+        }
+    }
+
+    for (link, target) in LINKS {
+        let path = root.join(link);
+
+        if path.symlink_metadata().is_err() {
+            symlink(target, &path)?;
+        }
+    }
+
+    // The new system syncs from the same repositories, trusting the same keys.
+    for file in [CONFIG_FILE, KEYRING_FILE] {
+        let (source, dest) = (from.join(file), root.join(file));
+
+        if source.exists() && !dest.exists() {
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&source, &dest)?;
+        }
+    }
+
+    if !Database::open(root)?.list()?.is_empty() {
+        bail!(
+            "{} already has packages installed, install more with `blossom install`",
+            root.display()
+        );
+    }
+
+    install_into(from, root, packages, wait).await?;
+
+    info!("Bootstrapped {}", root.display());
+    Ok(())
+}
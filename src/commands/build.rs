@@ -1,31 +1,37 @@
-use std::{
-    env::current_dir,
-    fs::{self, File},
-    io::{Read, Write as _},
-    path::{Path, PathBuf},
+use std::{env::current_dir, fs, path::Path};
+
+use anyhow::{Result, bail};
+use tracing::{info, warn};
+
+use crate::{
+    analyze::analyze,
+    build::{BuildContext, build_package},
+    config::Config,
+    database::Database,
+    lockfile::{LOCK_FILE, PackageLock},
+    package::Package,
+    paths::Paths,
 };
 
-use anyhow::{Result, anyhow, bail};
-use bzip2::read::BzDecoder;
-use camino::Utf8Path;
-use flate2::read::GzDecoder;
-use indicatif::ProgressBar;
-use reqwest::{Client, Url};
-use sha2::{Digest, Sha256 as Sha256Hasher};
-use tar::Archive;
-use tracing::info;
-use xz2::read::XzDecoder;
-
-use crate::package::{Package, Source, StepVariant};
-
-pub async fn build() -> Result<()> {
-    let package_path = current_dir()?.join("package.toml");
+/// Builds the package in the current directory, in the build directory of
+/// [`Paths`]. With `locked`, sources are fetched exactly as recorded in the
+/// lockfile, and the build fails if the toolchain changed since.
+pub async fn build<R: AsRef<Path>>(root: R, locked: bool) -> Result<()> {
+    let root = root.as_ref();
+    let config = Config::load(root)?;
+    let dir = current_dir()?;
+    let package_path = dir.join("package.toml");
 
     if !package_path.exists() {
         bail!("package.toml not found in the specified path.");
     }
 
-    let package = Package::parse(&fs::read_to_string(package_path)?)?;
+    let build_dir = Paths::new(root).build(&Package::from_path(&package_path)?.info)?;
+    fs::create_dir_all(&build_dir)?;
+
+    let mut context = BuildContext::new(&dir, &build_dir);
+    context.jobs = config.parallelism.jobs();
+    let mut package = Package::from_path_in(&package_path, &context)?;
 
     let info = &package.info;
     info!(
@@ -37,157 +43,28 @@ pub async fn build() -> Result<()> {
     //     // info!("Installing dependency: {dependency}");
     // }
 
-    let client = Client::new();
-
-    if fs::metadata("sources").is_ok() {
-        fs::remove_dir_all("sources")?;
-    }
-
-    for source in &package.sources {
-        let file_path = fetch_and_verify_source(&client, source).await?;
-        extract_source(&file_path)?;
-    }
-
-    let mut working_dir = current_dir()?;
+    let lock = if locked {
+        let lock_path = dir.join(LOCK_FILE);
 
-    for step in &package.steps {
-        info!("Running step: {}", step.name);
-
-        match &step.variant {
-            StepVariant::Command { command, runner } => {
-                let result = runner
-                    .into_command()
-                    .arg(command)
-                    .current_dir(&working_dir)
-                    .status()?;
-
-                if !result.success() {
-                    bail!("Step '{}' failed.", step.name);
-                }
-            }
-            StepVariant::Move { path } => {
-                fs::create_dir_all(path)?;
-
-                working_dir = path.into();
-            }
+        if !lock_path.exists() {
+            bail!("{LOCK_FILE} not found, create it with `blossom lock`");
         }
-    }
-
-    create_tarball(current_dir()?.join("package"), &package)?;
-
-    info!("Package '{}' built successfully!", info.name);
-    Ok(())
-}
-
-async fn fetch_and_verify_source(client: &Client, source: &Source) -> Result<PathBuf> {
-    let url: Url = source.url.as_str().try_into()?;
-
-    let target_path = PathBuf::from(url.path_segments().unwrap().last().unwrap());
-
-    if Path::new(&target_path).exists() && check_hash(&target_path, &source.checksum)? {
-        return Ok(target_path);
-    }
-
-    info!("Fetching source from {}", url);
-
-    let mut target = File::create(&target_path)?;
-
-    info!("Downloading \"{}\"", url);
 
-    let mut res = client.get(url).send().await?;
-    let len = res.content_length().unwrap_or(0);
-
-    let progress_bar = ProgressBar::new(len);
-
-    while let Some(chunk) = res.chunk().await? {
-        progress_bar.inc(chunk.len() as u64);
-        target.write_all(&chunk)?;
-    }
-
-    progress_bar.finish();
-
-    info!("Source fetched successfully.");
-    info!("Verifying source hash.");
-
-    if !check_hash(&target_path, &source.checksum)? {
-        bail!("Hash didn't match!")
-    }
+        let lock = PackageLock::read(lock_path)?;
+        lock.check_toolchain()?;
 
-    info!("Source hash verified successfully.");
-
-    Ok(target_path)
-}
-
-fn extract_source(target_path: &Path) -> Result<()> {
-    let target_path = Utf8Path::from_path(target_path).unwrap();
-
-    info!("Extracting \"{target_path}\"");
-
-    // let archive_path = format!("sources/{}", source.name);
-    // let archive_path = Utf8Path::new(&archive_path);
-
-    // if archive_path.join(".ok").exists() {
-    //     return Ok(());
-    // }
-
-    // if archive_path.exists() {
-    //     fs::remove_dir_all(archive_path)?;
-    // }
-
-    let target = File::open(target_path)?;
-
-    match target_path.extension().unwrap() {
-        "xz" => {
-            unpack_archive(XzDecoder::new(target))?;
-        }
-        "gz" => {
-            unpack_archive(GzDecoder::new(target))?;
-        }
-        "bz2" => {
-            unpack_archive(BzDecoder::new(target))?;
-        }
-        _ => bail!("Something went wrong extracting"),
-    }
-
-    info!("Archive extracted successfully");
-
-    Ok(())
-}
-
-fn unpack_archive<R: Read>(decoder: R) -> Result<()> {
-    // println!("Unpacking {name}");
-
-    let mut archive = Archive::new(decoder);
-
-    archive.unpack("sources/")?;
-
-    Ok(())
-}
-
-pub fn check_hash<P: AsRef<Path>>(path: P, hash: &str) -> Result<bool> {
-    let file = fs::read(path)?;
-    let (hash_type, hash) = hash
-        .split_once(':')
-        .ok_or(anyhow!("Invalid checksum format"))?;
-
-    let computed_hash = match hash_type {
-        "blake3" => blake3::hash(&file).to_hex().to_string(),
-        "sha256" => base16ct::lower::encode_string(Sha256Hasher::digest(&file).as_slice()),
-        _ => bail!("Unsupported hash"),
+        Some(lock)
+    } else {
+        None
     };
 
-    Ok(hash == computed_hash)
-}
+    let archive = build_package(&config, &mut package, &context, lock.as_ref(), &dir).await?;
 
-pub fn create_tarball<P: AsRef<Path>>(package_path: P, package: &Package) -> Result<()> {
-    let tarball_name = format!("{}-{}.peach", package.info.name, package.info.version);
-    let tarball_path = current_dir()?.join(&tarball_name);
-    let tar_gz = File::create(&tarball_path)?;
-    let enc = zstd::Encoder::new(tar_gz, 22)?;
-    let mut tar = tar::Builder::new(enc);
+    info!("Package '{}' built successfully!", package.info.name);
 
-    tar.append_dir_all(".", package_path)?;
+    for finding in analyze(&archive, &Database::open(root)?.list()?)? {
+        warn!("{finding}");
+    }
 
-    info!("Created package: {}", tarball_name);
     Ok(())
 }
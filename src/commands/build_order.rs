@@ -0,0 +1,201 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use tracing::warn;
+
+use crate::{
+    package::{Package, dependency_name},
+    resolver::format_cycle,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    Runtime,
+    Build,
+}
+
+struct Recipe {
+    name: String,
+    provides: Vec<String>,
+    dependencies: Vec<(String, Edge)>,
+}
+
+/// Prints the recipe directories in the order they have to be built in, every
+/// package coming after the packages it needs at build or run time.
+pub fn build_order(dirs: &[PathBuf], json: bool) -> Result<()> {
+    let mut recipes = Vec::new();
+
+    for dir in dirs {
+        let path = dir.join("package.toml");
+        let package = Package::from_path(&path)?;
+
+        let mut dependencies = Vec::new();
+
+        if let Some(deps) = &package.dependencies {
+            dependencies.extend(deps.required.iter().map(|d| (d.clone(), Edge::Runtime)));
+            dependencies.extend(deps.build.iter().map(|d| (d.clone(), Edge::Build)));
+        }
+
+        recipes.push(Recipe {
+            name: package.info.name,
+            provides: package
+                .info
+                .provides
+                .iter()
+                .map(|provided| dependency_name(provided).to_string())
+                .collect(),
+            dependencies,
+        });
+    }
+
+    let (order, cycles) = order(&recipes)?;
+
+    for cycle in cycles {
+        warn!(
+            "Runtime dependency cycle {}, building its packages in an arbitrary order",
+            format_cycle(&cycle)
+        );
+    }
+
+    if json {
+        let order: Vec<&PathBuf> = order.iter().map(|&index| &dirs[index]).collect();
+        println!("{}", serde_json::to_string_pretty(&order)?);
+        return Ok(());
+    }
+
+    for index in order {
+        println!("{}", dirs[index].display());
+    }
+
+    Ok(())
+}
+
+/// Orders recipes topologically. Cycles made only of runtime dependencies are
+/// broken and returned, since none of their packages is needed to build the
+/// others. Cycles involving a build dependency can't be built and are errors.
+fn order(recipes: &[Recipe]) -> Result<(Vec<usize>, Vec<Vec<String>>)> {
+    let edges: Vec<Vec<(usize, Edge)>> = recipes
+        .iter()
+        .map(|recipe| {
+            recipe
+                .dependencies
+                .iter()
+                .filter_map(|(dependency, edge)| {
+                    let name = dependency_name(dependency);
+
+                    recipes
+                        .iter()
+                        .position(|other| {
+                            other.name == name || other.provides.iter().any(|p| p == name)
+                        })
+                        .map(|index| (index, *edge))
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut order = Vec::new();
+    let mut cycles = Vec::new();
+    let mut done = vec![false; recipes.len()];
+
+    for index in 0..recipes.len() {
+        visit(
+            recipes,
+            &edges,
+            index,
+            &mut Vec::new(),
+            &mut done,
+            &mut order,
+            &mut cycles,
+        )?;
+    }
+
+    Ok((order, cycles))
+}
+
+fn visit(
+    recipes: &[Recipe],
+    edges: &[Vec<(usize, Edge)>],
+    index: usize,
+    stack: &mut Vec<(usize, Edge)>,
+    done: &mut [bool],
+    order: &mut Vec<usize>,
+    cycles: &mut Vec<Vec<String>>,
+) -> Result<()> {
+    if done[index] {
+        return Ok(());
+    }
+
+    if let Some(start) = stack.iter().position(|(i, _)| *i == index) {
+        let cycle = &stack[start..];
+        let path: Vec<String> = cycle
+            .iter()
+            .map(|(i, _)| recipes[*i].name.clone())
+            .chain([recipes[index].name.clone()])
+            .collect();
+
+        // Each stack entry carries the kind of the edge leaving it, a build
+        // edge can be cut by building its target without it first.
+        if let Some(position) = cycle.iter().position(|(_, edge)| *edge == Edge::Build) {
+            bail!(
+                "Build dependency cycle {}, build a bootstrap package of {} without {} to break it",
+                format_cycle(&path),
+                path[position + 1],
+                path[position]
+            );
+        }
+
+        cycles.push(path);
+        return Ok(());
+    }
+
+    for &(dependency, edge) in &edges[index] {
+        stack.push((index, edge));
+        visit(recipes, edges, dependency, stack, done, order, cycles)?;
+        stack.pop();
+    }
+
+    done[index] = true;
+    order.push(index);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(name: &str, dependencies: &[(&str, Edge)]) -> Recipe {
+        Recipe {
+            name: name.to_string(),
+            provides: Vec::new(),
+            dependencies: dependencies
+                .iter()
+                .map(|(d, edge)| (d.to_string(), *edge))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_build_order_cycles() {
+        let runtime = [
+            recipe("a", &[("b", Edge::Runtime)]),
+            recipe("b", &[("a", Edge::Runtime)]),
+            recipe("c", &[("a", Edge::Build)]),
+        ];
+
+        let (order, cycles) = order(&runtime).unwrap();
+        assert_eq!(order, [1, 0, 2]);
+        assert_eq!(cycles, [["a", "b", "a"]]);
+
+        let build = [
+            recipe("gcc", &[("glibc", Edge::Build)]),
+            recipe("glibc", &[("gcc", Edge::Build)]),
+        ];
+
+        assert_eq!(
+            super::order(&build).unwrap_err().to_string(),
+            "Build dependency cycle gcc → glibc → gcc, build a bootstrap package of glibc without gcc to break it"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail, format_err};
+use chrono::{NaiveDate, Utc};
+use toml_edit::{DocumentMut, Item, Value};
+use tracing::{info, warn};
+
+use crate::{
+    build::{BuildContext, compute_hash, source_path, verify_signature},
+    config::Config,
+    fetch::{self, Request},
+    lockfile::LOCK_FILE,
+    package::Package,
+    paths::Paths,
+};
+
+/// The file next to recipes `--changelog` adds entries to.
+const CHANGELOG_FILE: &str = "CHANGELOG.md";
+
+/// Updates the recipe at `path`, or the `package.toml` in it, to `version`:
+/// its sources are fetched again for their new checksums, and `changelog`,
+/// if any, is added to the `CHANGELOG.md` next to it. Blossom versions have
+/// no release number to reset.
+pub async fn bump<R: AsRef<Path>, P: AsRef<Path>>(
+    root: R,
+    path: P,
+    version: &str,
+    changelog: Option<&str>,
+) -> Result<()> {
+    let path = match path.as_ref().is_dir() {
+        true => path.as_ref().join("package.toml"),
+        false => path.as_ref().to_path_buf(),
+    };
+    let dir = path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let recipe =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut document: DocumentMut = recipe.parse()?;
+    let previous = document
+        .get("info")
+        .and_then(|info| info.get("version"))
+        .and_then(Item::as_str)
+        .ok_or_else(|| format_err!("{} has no version", path.display()))?
+        .to_string();
+    if previous == version {
+        bail!("{} is already at {version}", path.display());
+    }
+    set_string(&mut document["info"]["version"], version);
+
+    let package = Package::parse_in(&document.to_string(), &BuildContext::new(&dir, &dir))?;
+    let config = Config::load(&root)?;
+    let build_dir = Paths::new(&root).build(&package.info)?;
+    fs::create_dir_all(&build_dir)?;
+
+    for (i, source) in package.sources.iter().enumerate() {
+        if source.url.starts_with("git+") {
+            continue;
+        }
+
+        let fetcher = fetch::get(&source.url)?;
+        if fetcher.is_remote(&source.url) {
+            config.require_online(&format!("fetch {}", source.url))?;
+        }
+
+        let target = source_path(&build_dir, source)?;
+        if target.exists() {
+            fs::remove_file(&target)?;
+        }
+
+        info!("Fetching source from {}", source.url);
+        fetcher
+            .fetch(Request {
+                url: &source.url,
+                rev: None,
+                target: &target,
+                segments: config.download.segments,
+            })
+            .await?;
+
+        // A new release signed by someone else is caught before its checksum
+        // is trusted.
+        verify_signature(&package, source, &target, false, &dir, &config).await?;
+
+        // Recipes keep the algorithm they use, new ones get blake3.
+        let algorithm = match source.checksum.split_once(':') {
+            Some((algorithm @ ("blake3" | "sha256"), _)) => algorithm,
+            _ => "blake3",
+        };
+        let checksum = compute_hash(&target, &format!("{algorithm}:"))?;
+
+        set_string(&mut document["sources"][i]["checksum"], &checksum);
+    }
+
+    fs::write(&path, document.to_string())?;
+
+    if let Some(message) = changelog {
+        let changelog_path = dir.join(CHANGELOG_FILE);
+        let existing = match changelog_path.exists() {
+            true => fs::read_to_string(&changelog_path)?,
+            false => String::new(),
+        };
+
+        fs::write(
+            &changelog_path,
+            add_entry(&existing, version, Utc::now().date_naive(), message),
+        )?;
+    }
+
+    if dir.join(LOCK_FILE).exists() {
+        warn!("{LOCK_FILE} still locks the sources of {previous}, run `blossom lock` again");
+    }
+
+    info!("Bumped {} from {previous} to {version}", package.info.name);
+    Ok(())
+}
+
+/// Sets `item` to the string `s`, keeping the comments around it.
+fn set_string(item: &mut Item, s: &str) {
+    match item.as_value_mut() {
+        Some(value) => {
+            let decor = value.decor().clone();
+            *value = Value::from(s);
+            *value.decor_mut() = decor;
+        }
+        None => *item = toml_edit::value(s),
+    }
+}
+
+/// `changelog` with an entry for `version` on top.
+fn add_entry(changelog: &str, version: &str, date: NaiveDate, message: &str) -> String {
+    let entry = format!("## {version} - {date}\n\n- {message}\n");
+
+    match changelog.find("## ") {
+        Some(start) => format!("{}{entry}\n{}", &changelog[..start], &changelog[start..]),
+        None if changelog.trim().is_empty() => format!("# Changelog\n\n{entry}"),
+        None => format!("{}\n\n{entry}", changelog.trim_end()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_string() {
+        let mut document: DocumentMut = r#"
+[info]
+version = "1.0" # Upstream's latest.
+
+[[sources]]
+url = "https://example.com/foo-%{version}.tar.gz"
+"#
+        .parse()
+        .unwrap();
+
+        set_string(&mut document["info"]["version"], "1.1");
+        set_string(&mut document["sources"][0]["checksum"], "blake3:ab12");
+
+        assert_eq!(
+            document.to_string(),
+            r#"
+[info]
+version = "1.1" # Upstream's latest.
+
+[[sources]]
+url = "https://example.com/foo-%{version}.tar.gz"
+checksum = "blake3:ab12"
+"#
+        );
+    }
+
+    #[test]
+    fn test_add_entry() {
+        let date = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+
+        let changelog = add_entry("", "1.0", date, "First release");
+        assert_eq!(
+            changelog,
+            "# Changelog\n\n## 1.0 - 2026-10-14\n\n- First release\n"
+        );
+
+        assert_eq!(
+            add_entry(&changelog, "1.1", date, "Fix the build"),
+            "# Changelog\n\n## 1.1 - 2026-10-14\n\n- Fix the build\n\n\
+             ## 1.0 - 2026-10-14\n\n- First release\n"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use tracing::info;
+
+use crate::{database::Database, package::ChangelogEntry, repository, version};
+
+/// Shows what changed in `name` between the installed version and the newest
+/// available one, or every recorded change when only one of them exists.
+pub fn changelog<R: AsRef<Path>>(root: R, name: &str, json: bool) -> Result<()> {
+    let installed = Database::open(&root)?.get(name)?;
+    let repositories = repository::load_synced_named(&root, &[name])?;
+    let available = repository::find_newest(&repositories, name).map(|(_, entry)| entry);
+
+    let (changelog, since, until) = match (&installed, available) {
+        (Some(installed), Some(available)) => (
+            &available.changelog,
+            Some(installed.info.version.as_str()),
+            &available.info.version,
+        ),
+        (None, Some(available)) => (&available.changelog, None, &available.info.version),
+        (Some(installed), None) => (&installed.changelog, None, &installed.info.version),
+        (None, None) => {
+            bail!("Package \"{name}\" is neither installed nor available in any repository")
+        }
+    };
+
+    let changes = changes(changelog, since, until);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+        return Ok(());
+    }
+
+    if changes.is_empty() {
+        match since {
+            Some(since) if since == until => info!("{name} {until} is up to date"),
+            _ => info!("No changes recorded for {name} {until}"),
+        }
+        return Ok(());
+    }
+
+    for (i, entry) in changes.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+
+        match &entry.date {
+            Some(date) => println!("{name} {} ({date})", entry.version),
+            None => println!("{name} {}", entry.version),
+        }
+        for change in &entry.changes {
+            println!("  - {change}");
+        }
+    }
+
+    Ok(())
+}
+
+/// The entries of versions newer than `since`, if given, up to `until`,
+/// newest first.
+fn changes<'a>(
+    changelog: &'a [ChangelogEntry],
+    since: Option<&str>,
+    until: &str,
+) -> Vec<&'a ChangelogEntry> {
+    let mut changes: Vec<_> = changelog
+        .iter()
+        .filter(|entry| since.is_none_or(|since| version::compare(&entry.version, since).is_gt()))
+        .filter(|entry| version::compare(&entry.version, until).is_le())
+        .collect();
+    changes.sort_by(|a, b| version::compare(&b.version, &a.version));
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes() {
+        let changelog: Vec<ChangelogEntry> = ["1.0", "1.2", "1.1", "2.0"]
+            .iter()
+            .map(|version| ChangelogEntry {
+                version: version.to_string(),
+                date: None,
+                changes: vec![format!("Released {version}")],
+            })
+            .collect();
+
+        let versions = |since, until| -> Vec<&str> {
+            changes(&changelog, since, until)
+                .iter()
+                .map(|entry| entry.version.as_str())
+                .collect()
+        };
+
+        assert_eq!(versions(Some("1.0"), "1.2"), ["1.2", "1.1"]);
+        assert_eq!(versions(None, "1.1"), ["1.1", "1.0"]);
+        assert!(versions(Some("2.0"), "2.0").is_empty());
+    }
+}
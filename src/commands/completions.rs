@@ -0,0 +1,145 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use clap::Command;
+use clap_complete::{CompletionCandidate, Shell, env::Shells};
+
+use crate::{
+    database::{DATABASE_DIR, Database},
+    repository,
+};
+
+/// The environment variable the registration scripts set when asking for
+/// completions, as `clap_complete::CompleteEnv` expects by default.
+const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Prints the script registering blossom for completions in `shell`. The
+/// script calls back into blossom, so package names are completed from the
+/// root at the time of completion.
+pub fn completions(command: &Command, shell: Shell) -> Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(&shell.to_string())
+        .ok_or(anyhow!("Completions aren't supported for {shell}"))?;
+    let name = command.get_name();
+
+    completer.write_registration(COMPLETE_VAR, name, name, name, &mut io::stdout())?;
+
+    Ok(())
+}
+
+/// The names of installed packages.
+pub fn installed_candidates() -> Vec<CompletionCandidate> {
+    candidates(installed(&completion_root()))
+}
+
+/// The names of packages in the synced repositories.
+pub fn repository_candidates() -> Vec<CompletionCandidate> {
+    candidates(available(&completion_root()))
+}
+
+/// The names of installed packages and of those in the synced repositories.
+pub fn package_candidates() -> Vec<CompletionCandidate> {
+    let root = completion_root();
+    let mut packages = available(&root);
+    packages.extend(installed(&root));
+
+    candidates(packages)
+}
+
+fn candidates(packages: BTreeMap<String, String>) -> Vec<CompletionCandidate> {
+    packages
+        .into_iter()
+        .map(|(name, description)| CompletionCandidate::new(name).help(Some(description.into())))
+        .collect()
+}
+
+/// Completions run before the command line is parsed, the root is picked out
+/// of the words being completed.
+fn completion_root() -> PathBuf {
+    root_from_args(env::args_os())
+        .or_else(|| env::var_os("BLOSSOM_ROOT").map(PathBuf::from))
+        .unwrap_or(PathBuf::from("/"))
+}
+
+fn root_from_args<I: IntoIterator<Item = OsString>>(args: I) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    let mut root = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--root" {
+            root = args.next().map(PathBuf::from);
+        } else if let Some(value) = arg.to_str().and_then(|arg| arg.strip_prefix("--root=")) {
+            root = Some(PathBuf::from(value));
+        }
+    }
+
+    root
+}
+
+/// Completing must never fail or create anything, a root without a database
+/// just has nothing installed.
+fn installed(root: &Path) -> BTreeMap<String, String> {
+    if !root.join(DATABASE_DIR).exists() {
+        return BTreeMap::new();
+    }
+
+    Database::open(root)
+        .and_then(|database| database.list())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|package| {
+            (
+                package.info.name,
+                package.info.description.localized().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn available(root: &Path) -> BTreeMap<String, String> {
+    repository::load_synced(root)
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|repository| repository.index.packages)
+        .map(|entry| {
+            (
+                entry.info.name,
+                entry.info.description.localized().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_root_from_args() {
+        assert_eq!(
+            root_from_args(args(&[
+                "blossom", "--", "blossom", "--root", "/mnt", "info"
+            ])),
+            Some(PathBuf::from("/mnt"))
+        );
+        assert_eq!(
+            root_from_args(args(&["blossom", "--", "blossom", "--root=/mnt", "info"])),
+            Some(PathBuf::from("/mnt"))
+        );
+        assert_eq!(
+            root_from_args(args(&["blossom", "--", "blossom", "info", ""])),
+            None
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, env, fs, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::Value;
+use toml_edit::{DocumentMut, Item, Table};
+use tracing::info;
+
+use crate::{
+    config::{CONFIG_FILE, Config, ENV_PREFIX, ENVIRONMENT, user_config_path},
+    database::DATABASE_DIR,
+    keyring::KEYRING_FILE,
+    mirrors::RANKING_FILE,
+    paths::Paths,
+};
+
+/// Prints where blossom reads its configuration from and keeps its caches and
+/// state, as resolved for the current user.
+pub fn config_paths<R: AsRef<Path>>(root: R, json: bool) -> Result<()> {
+    let root = root.as_ref();
+    let config = Config::load(root)?;
+    let paths = Paths::new(root);
+
+    let build = if paths.isolated_builds {
+        paths.cache.join("build").display().to_string()
+    } else {
+        "recipe directory".to_string()
+    };
+    let user_config = match user_config_path() {
+        Some(path) => path.display().to_string(),
+        None => "none".to_string(),
+    };
+
+    let entries = [
+        ("config", root.join(CONFIG_FILE).display().to_string()),
+        ("user config", user_config),
+        ("keyring", root.join(KEYRING_FILE).display().to_string()),
+        ("database", root.join(DATABASE_DIR).display().to_string()),
+        ("packages", config.cache_dir(root).display().to_string()),
+        ("build", build),
+        ("state", paths.state.display().to_string()),
+        ("sync", paths.sync().display().to_string()),
+        (
+            "mirrors",
+            paths.state.join(RANKING_FILE).display().to_string(),
+        ),
+    ];
+
+    if json {
+        let paths: BTreeMap<&str, String> = entries.into_iter().collect();
+        println!("{}", serde_json::to_string_pretty(&paths)?);
+        return Ok(());
+    }
+
+    let width = entries
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, path) in entries {
+        println!("{name:<width$}  {path}");
+    }
+
+    Ok(())
+}
+
+/// Prints the environment variables blossom reads, and the value of those
+/// that are set.
+pub fn config_env() {
+    let width = ENVIRONMENT
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+
+    for (name, description) in ENVIRONMENT {
+        match env::var(name) {
+            Ok(value) => println!("{name:<width$}  {description} (set to {value})"),
+            Err(_) => println!("{name:<width$}  {description}"),
+        }
+    }
+
+    println!(
+        "\nAny other setting can be set as {ENV_PREFIX} followed by its upper cased name, with \
+         __ between nested keys. The command line (--set, --cache-dir, --jobs, --color, \
+         --offline, --json) takes precedence over the environment, which takes precedence over the \
+         user and system configuration."
+    );
+}
+
+/// Prints the effective value of a setting, e.g. `parallelism.jobs`. Strings
+/// are printed as is unless `json`, anything else as JSON.
+pub fn config_get<R: AsRef<Path>>(root: R, key: &str, json: bool) -> Result<()> {
+    let config = serde_json::to_value(Config::load(root)?)?;
+
+    match lookup(&config, key).ok_or(anyhow!("{key} is not set"))? {
+        Value::String(value) if !json => println!("{value}"),
+        value => println!("{value}"),
+    }
+
+    Ok(())
+}
+
+/// Sets a setting in the system configuration, or the user one with `user`,
+/// keeping the rest of the file as it was written. The value is TOML, or else
+/// taken as a string.
+pub fn config_set<R: AsRef<Path>>(root: R, key: &str, value: &str, user: bool) -> Result<()> {
+    let path = if user {
+        user_config_path().ok_or(anyhow!("No user configuration directory, HOME isn't set"))?
+    } else {
+        root.as_ref().join(CONFIG_FILE)
+    };
+
+    let mut document = if path.exists() {
+        fs::read_to_string(&path)?
+            .parse::<DocumentMut>()
+            .map_err(|e| anyhow!("Invalid configuration {}: {e}", path.display()))?
+    } else {
+        DocumentMut::new()
+    };
+
+    let parts: Vec<&str> = key.split('.').collect();
+    if parts.iter().any(|part| part.is_empty()) {
+        bail!("Invalid setting name {key}");
+    }
+
+    let (name, parents) = parts.split_last().unwrap();
+    let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+
+    for (i, part) in parents.iter().enumerate() {
+        if !table.contains_key(part) {
+            let mut implicit = Table::new();
+            implicit.set_implicit(true);
+            table.insert(part, Item::Table(implicit));
+        }
+
+        table = table
+            .get_mut(part)
+            .and_then(Item::as_table_like_mut)
+            .ok_or(anyhow!("{} is not a table", parts[..=i].join(".")))?;
+    }
+
+    let new = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(value));
+
+    match table.get_mut(name) {
+        Some(Item::Value(existing)) => {
+            let decor = existing.decor().clone();
+            *existing = new;
+            *existing.decor_mut() = decor;
+        }
+        _ => {
+            table.insert(name, Item::Value(new));
+        }
+    }
+
+    let contents = document.to_string();
+    let config: Config =
+        toml_edit::de::from_str(&contents).map_err(|e| anyhow!("Invalid value for {key}: {e}"))?;
+
+    // Unknown settings are dropped when reading the configuration.
+    if lookup(&serde_json::to_value(config)?, key).is_none() {
+        bail!("Unknown setting {key}");
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, contents)?;
+
+    info!("Set {key} in {}", path.display());
+
+    Ok(())
+}
+
+fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(value, |value, part| value.get(part))
+}
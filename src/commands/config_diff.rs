@@ -0,0 +1,73 @@
+use std::{fs, path::Path, process::Command};
+
+use anyhow::Result;
+use tracing::{info, warn};
+
+use crate::{commands::install::with_peachnew, database::Database, prompt};
+
+pub fn config_diff<R: AsRef<Path>>(root: R, list: bool) -> Result<()> {
+    let database = Database::open(&root)?;
+
+    let mut pending = Vec::new();
+
+    for package in database.list()? {
+        for backup in &package.backup {
+            let current = root.as_ref().join(backup);
+            let new = with_peachnew(&current);
+
+            if new.exists() {
+                pending.push((package.info.name.clone(), current, new));
+            }
+        }
+    }
+
+    if pending.is_empty() {
+        info!("No pending .peachnew files");
+        return Ok(());
+    }
+
+    if list {
+        for (name, _, new) in &pending {
+            println!("{name}: {}", new.display());
+        }
+
+        return Ok(());
+    }
+
+    for (name, current, new) in pending {
+        println!("{name}: {}", current.display());
+
+        show_diff(&current, &new);
+
+        // Without an answer everything left is skipped.
+        let Some(answer) = prompt::answer("[k]eep current, [r]eplace with new, [s]kip? ")? else {
+            break;
+        };
+
+        match answer.as_str() {
+            "k" | "keep" => {
+                fs::remove_file(&new)?;
+                info!("Kept {}", current.display());
+            }
+            "r" | "replace" => {
+                fs::rename(&new, &current)?;
+                info!("Replaced {}", current.display());
+            }
+            _ => info!("Skipped {}", current.display()),
+        }
+    }
+
+    Ok(())
+}
+
+fn show_diff(current: &Path, new: &Path) {
+    let status = Command::new("diff")
+        .arg("-u")
+        .arg(current)
+        .arg(new)
+        .status();
+
+    if let Err(e) = status {
+        warn!("Failed to run diff: {e}");
+    }
+}
@@ -0,0 +1,508 @@
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result, anyhow};
+use nix::unistd::{Gid, Group, Uid, User, getgrouplist};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::{
+    io::{self, AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::Mutex,
+    task,
+};
+use tracing::{info, warn};
+
+use crate::{
+    commands::{
+        InstallOptions, ListFilter, install_packages, list_packages, search_packages, sync,
+    },
+    failure, prompt,
+};
+
+/// Where the daemon listens, relative to the root, unless told otherwise.
+pub const DEFAULT_SOCKET: &str = "run/blossom.sock";
+/// Who may do what through the daemon.
+pub const POLICY_FILE: &str = "etc/blossom/daemon.toml";
+
+/// JSON-RPC error codes, the standard ones and those of blossom.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const FAILED: i64 = -32000;
+const NOT_AUTHORIZED: i64 = -32001;
+
+/// The longest request line the daemon reads, newline included, so that a
+/// client can't make it hold an endless line in memory.
+const MAX_REQUEST: usize = 1 << 20;
+
+/// Which users may call each method, in the spirit of polkit actions: `*`
+/// for anyone, `@group` for the members of a group, or a user name. Root may
+/// call everything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    pub allow: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Policy {
+    /// Anyone may query, only root may change anything.
+    fn default() -> Self {
+        Self {
+            allow: BTreeMap::from([
+                ("query".to_string(), vec!["*".to_string()]),
+                ("search".to_string(), vec!["*".to_string()]),
+            ]),
+        }
+    }
+}
+
+impl Policy {
+    pub fn load<R: AsRef<Path>>(root: R) -> Result<Self> {
+        let path = root.as_ref().join(POLICY_FILE);
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        toml_edit::de::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn allows(&self, method: &str, peer: &Peer) -> bool {
+        if peer.uid == 0 {
+            return true;
+        }
+
+        self.allow.get(method).is_some_and(|allowed| {
+            allowed.iter().any(|entry| match entry.strip_prefix('@') {
+                Some(group) => peer.groups.iter().any(|g| g == group),
+                None => entry == "*" || peer.name.as_deref() == Some(entry),
+            })
+        })
+    }
+}
+
+/// The user on the other end of a connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Peer {
+    pub uid: u32,
+    pub name: Option<String>,
+    pub groups: Vec<String>,
+}
+
+impl Peer {
+    fn of(stream: &UnixStream) -> Result<Self> {
+        let credentials = stream.peer_cred()?;
+        let uid = credentials.uid();
+        let user = User::from_uid(Uid::from_raw(uid))?;
+
+        let mut gids = vec![Gid::from_raw(credentials.gid())];
+
+        if let Some(user) = &user {
+            gids.extend(getgrouplist(&CString::new(user.name.as_str())?, user.gid)?);
+        }
+
+        let groups = gids
+            .into_iter()
+            .filter_map(|gid| Group::from_gid(gid).ok().flatten())
+            .map(|group| group.name)
+            .collect();
+
+        Ok(Self {
+            uid,
+            name: user.map(|user| user.name),
+            groups,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response.
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Packages {
+    packages: Vec<String>,
+    /// For removals, also remove what depends on the packages.
+    cascade: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Query {
+    pattern: Option<String>,
+    explicit: bool,
+    orphans: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Search {
+    terms: Vec<String>,
+    tags: Vec<String>,
+}
+
+/// An error answered to a request.
+#[derive(Debug)]
+struct Failure {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl Failure {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Failure {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            code: FAILED,
+            message: format!("{error:#}"),
+            data: Some(json!({ "exit_code": failure::exit_code(&error) })),
+        }
+    }
+}
+
+/// Serves install, remove, query, search and sync requests as JSON-RPC 2.0,
+/// one JSON document per line, on a unix socket. Anyone can connect, each
+/// request is authorized against [`Policy`] by the credentials of the peer.
+/// Changes are made one at a time, confirmations being implied by the
+/// requests themselves.
+pub async fn daemon<R: AsRef<Path>>(root: R, socket: Option<&Path>) -> Result<()> {
+    let root = root.as_ref().to_path_buf();
+    let socket = socket.map_or_else(|| root.join(DEFAULT_SOCKET), Path::to_path_buf);
+    let policy = Arc::new(Policy::load(&root)?);
+
+    if let Some(parent) = socket.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if socket.exists() {
+        fs::remove_file(&socket)?;
+    }
+
+    let listener = UnixListener::bind(&socket)
+        .with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o666))?;
+
+    prompt::configure(prompt::Settings {
+        assume_yes: true,
+        confirm: prompt::Confirm::Normal,
+        interactive: false,
+    });
+
+    info!("Listening on {}", socket.display());
+
+    let state = Arc::new(State {
+        root,
+        policy,
+        changes: Mutex::new(()),
+    });
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = Arc::clone(&state);
+
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(stream, &state).await {
+                warn!("Connection failed: {e:#}");
+            }
+        });
+    }
+}
+
+struct State {
+    root: PathBuf,
+    policy: Arc<Policy>,
+    /// Held while installing, removing or syncing.
+    changes: Mutex<()>,
+}
+
+async fn serve_connection(stream: UnixStream, state: &State) -> Result<()> {
+    let peer = Peer::of(&stream)?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let response = match read_line(&mut reader).await? {
+            Line::End => break,
+            Line::TooLong => Some(error_response(
+                Value::Null,
+                Failure::new(
+                    INVALID_REQUEST,
+                    format!("Requests may be at most {MAX_REQUEST} bytes long"),
+                ),
+            )),
+            Line::Request(line) if line.trim().is_empty() => continue,
+            Line::Request(line) => respond(&line, &peer, state).await,
+        };
+
+        if let Some(response) = response {
+            writer.write_all(response.to_string().as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// What [`read_line`] read.
+#[derive(Debug, PartialEq, Eq)]
+enum Line {
+    Request(String),
+    /// A line over [`MAX_REQUEST`], skipped up to its end.
+    TooLong,
+    End,
+}
+
+/// Reads the next request line of `reader`, holding no more than
+/// [`MAX_REQUEST`] bytes of it.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Line> {
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_REQUEST as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .await?;
+
+    if line.is_empty() {
+        return Ok(Line::End);
+    }
+    if line.len() <= MAX_REQUEST {
+        return Ok(Line::Request(String::from_utf8_lossy(&line).into_owned()));
+    }
+
+    if line.last() != Some(&b'\n') {
+        loop {
+            let buffer = reader.fill_buf().await?;
+            if buffer.is_empty() {
+                break;
+            }
+
+            match buffer.iter().position(|&byte| byte == b'\n') {
+                Some(end) => {
+                    reader.consume(end + 1);
+                    break;
+                }
+                None => {
+                    let len = buffer.len();
+                    reader.consume(len);
+                }
+            }
+        }
+    }
+
+    Ok(Line::TooLong)
+}
+
+/// Handles one line, returning the response unless it was a notification.
+async fn respond(line: &str, peer: &Peer, state: &State) -> Option<Value> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            return Some(error_response(
+                Value::Null,
+                Failure::new(PARSE_ERROR, e.to_string()),
+            ));
+        }
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+
+    let request = match serde_json::from_value::<Request>(request) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        Ok(_) => {
+            return Some(error_response(
+                id,
+                Failure::new(INVALID_REQUEST, "Expected JSON-RPC 2.0"),
+            ));
+        }
+        Err(e) => {
+            return Some(error_response(
+                id,
+                Failure::new(INVALID_REQUEST, e.to_string()),
+            ));
+        }
+    };
+
+    let result = if state.policy.allows(&request.method, peer) {
+        info!(
+            "{} called {}",
+            peer.name.as_deref().unwrap_or("unknown user"),
+            request.method
+        );
+        call(&request.method, request.params, state).await
+    } else {
+        Err(Failure::new(
+            NOT_AUTHORIZED,
+            format!("Not authorized to call {}", request.method),
+        ))
+    };
+
+    let id = request.id?;
+
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(failure) => error_response(id, failure),
+    })
+}
+
+fn error_response(id: Value, failure: Failure) -> Value {
+    let mut error = json!({ "code": failure.code, "message": failure.message });
+
+    if let Some(data) = failure.data {
+        error["data"] = data;
+    }
+
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, Failure> {
+    let params = match params {
+        Value::Null => Value::Object(Default::default()),
+        params => params,
+    };
+
+    serde_json::from_value(params).map_err(|e| Failure::new(INVALID_PARAMS, e.to_string()))
+}
+
+async fn call(method: &str, params_value: Value, state: &State) -> Result<Value, Failure> {
+    let root = &state.root;
+
+    match method {
+        "install" => {
+            let Packages { packages, .. } = params(params_value)?;
+            let _changes = state.changes.lock().await;
+
+            install_packages(root, &packages, &InstallOptions::default(), true).await?;
+
+            Ok(Value::Null)
+        }
+        "remove" => {
+            let Packages { packages, cascade } = params(params_value)?;
+            let _changes = state.changes.lock().await;
+            let root = root.clone();
+
+            task::spawn_blocking(move || {
+                for package in &packages {
+                    crate::commands::uninstall(&root, package, cascade, false, true, false)?;
+                }
+
+                Ok::<_, anyhow::Error>(())
+            })
+            .await
+            .map_err(|e| anyhow!(e))??;
+
+            Ok(Value::Null)
+        }
+        "query" => {
+            let Query {
+                pattern,
+                explicit,
+                orphans,
+            } = params(params_value)?;
+            let filter = ListFilter {
+                pattern,
+                explicit,
+                orphans,
+                ..Default::default()
+            };
+
+            Ok(serde_json::to_value(list_packages(root, &filter)?).map_err(anyhow::Error::from)?)
+        }
+        "search" => {
+            let Search { terms, tags } = params(params_value)?;
+
+            Ok(serde_json::to_value(search_packages(root, &terms, &tags)?)
+                .map_err(anyhow::Error::from)?)
+        }
+        "sync" => {
+            let _changes = state.changes.lock().await;
+
+            sync(root, true).await?;
+
+            Ok(Value::Null)
+        }
+        _ => Err(Failure::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {method}, expected install, remove, query, search or sync"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(uid: u32, name: &str, groups: &[&str]) -> Peer {
+        Peer {
+            uid,
+            name: Some(name.to_string()),
+            groups: groups.iter().map(|group| group.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_line() {
+        let input = format!("{}\n{{}}\n", "a".repeat(MAX_REQUEST * 2));
+        let mut reader = input.as_bytes();
+
+        assert_eq!(read_line(&mut reader).await.unwrap(), Line::TooLong);
+        assert_eq!(
+            read_line(&mut reader).await.unwrap(),
+            Line::Request("{}\n".to_string())
+        );
+        assert_eq!(read_line(&mut reader).await.unwrap(), Line::End);
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy {
+            allow: BTreeMap::from([
+                ("query".to_string(), vec!["*".to_string()]),
+                (
+                    "install".to_string(),
+                    vec!["@wheel".to_string(), "ci".to_string()],
+                ),
+            ]),
+        };
+
+        assert!(policy.allows("remove", &peer(0, "root", &[])));
+        assert!(policy.allows("query", &peer(1000, "alice", &[])));
+        assert!(policy.allows("install", &peer(1000, "alice", &["users", "wheel"])));
+        assert!(policy.allows("install", &peer(1001, "ci", &[])));
+        assert!(!policy.allows("install", &peer(1002, "bob", &["users"])));
+        assert!(!policy.allows("sync", &peer(1000, "alice", &["wheel"])));
+
+        assert!(!Policy::default().allows("install", &peer(1000, "alice", &["wheel"])));
+        assert!(Policy::default().allows("search", &peer(1000, "alice", &[])));
+    }
+
+    #[test]
+    fn test_search_params() {
+        let Search { terms, tags } = params(json!({ "tags": ["editor"] })).unwrap();
+
+        assert!(terms.is_empty());
+        assert_eq!(tags, ["editor"]);
+    }
+}
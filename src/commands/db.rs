@@ -0,0 +1,117 @@
+use std::{fs, io, path::Path};
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    commands::install::install_archive,
+    database::{Database, InstallReason},
+    history::History,
+    hooks::Triggers,
+    lock::Lock,
+    snapshot,
+};
+
+/// Portable description of the packages installed in a root.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub packages: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub name: String,
+    pub version: String,
+    pub reason: InstallReason,
+}
+
+pub fn db_export<R: AsRef<Path>>(root: R) -> Result<()> {
+    let database = Database::open(root)?;
+
+    let snapshot = Snapshot {
+        packages: database
+            .list()?
+            .into_iter()
+            .map(|package| SnapshotEntry {
+                name: package.info.name,
+                version: package.info.version,
+                reason: package.reason,
+            })
+            .collect(),
+    };
+
+    println!("{}", serde_json::to_string_pretty(&snapshot)?);
+
+    Ok(())
+}
+
+pub fn db_import<R: AsRef<Path>>(root: R, file: Option<&Path>, wait: bool) -> Result<()> {
+    let state: Snapshot = match file {
+        Some(file) => serde_json::from_str(&fs::read_to_string(file)?)?,
+        None => serde_json::from_reader(io::stdin().lock())?,
+    };
+
+    let _lock = Lock::acquire(&root, wait)?;
+    let database = Database::open(&root)?;
+
+    let mut missing = Vec::new();
+    let mut pending = Vec::new();
+
+    for entry in &state.packages {
+        let installed = database
+            .get(&entry.name)?
+            .filter(|installed| installed.info.version == entry.version);
+
+        if let Some(mut installed) = installed {
+            if installed.reason != entry.reason {
+                installed.reason = entry.reason;
+                database.put(&installed)?;
+            }
+
+            continue;
+        }
+
+        let cache_path = database.cache_path(&entry.name, &entry.version);
+
+        if cache_path.exists() {
+            pending.push((entry, cache_path));
+        } else {
+            warn!(
+                "No archive available for {} {}, it has to be installed manually",
+                entry.name, entry.version
+            );
+            missing.push(entry);
+        }
+    }
+
+    let names: Vec<String> = pending
+        .iter()
+        .map(|(entry, _)| entry.name.clone())
+        .collect();
+    snapshot::before_transaction(&database, &names)?;
+
+    let mut changes = Vec::new();
+    let mut triggers = Triggers::default();
+
+    for (entry, cache_path) in pending {
+        changes.push(install_archive(
+            &database,
+            &cache_path,
+            Some(entry.reason),
+            &mut triggers,
+        )?);
+    }
+
+    History::open(&root)?.record(changes)?;
+
+    triggers.run(&root)?;
+
+    if !missing.is_empty() {
+        bail!("{} packages from the snapshot are missing", missing.len());
+    }
+
+    info!("Imported {} packages", state.packages.len());
+
+    Ok(())
+}
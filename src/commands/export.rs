@@ -0,0 +1,136 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::{Result, bail};
+use tracing::info;
+
+use crate::{
+    archive,
+    commands::install::install_into,
+    config::Config,
+    export::{self, Extension},
+};
+
+/// Rewraps the package archive at `path` into a Debian package in `out_dir`.
+pub fn export_deb<P: AsRef<Path>>(path: P, out_dir: &Path) -> Result<()> {
+    let deb_path = export::deb(path.as_ref(), out_dir)?;
+
+    info!("Created {}", deb_path.display());
+    Ok(())
+}
+
+/// Installs `packages` from the repositories synced in `root` into an empty
+/// root, and packs it into an OCI image tagged `reference` in `out_dir`.
+pub async fn export_oci<R: AsRef<Path>>(
+    root: R,
+    packages: &[String],
+    reference: &str,
+    out_dir: &Path,
+    wait: bool,
+) -> Result<()> {
+    let staging = env::temp_dir().join(format!("blossom-oci-{}", process::id()));
+    fs::create_dir_all(&staging)?;
+
+    let result = export_staged(root.as_ref(), packages, reference, out_dir, wait, &staging).await;
+
+    fs::remove_dir_all(&staging)?;
+
+    result
+}
+
+async fn export_staged(
+    root: &Path,
+    packages: &[String],
+    reference: &str,
+    out_dir: &Path,
+    wait: bool,
+    staging: &Path,
+) -> Result<()> {
+    install_into(root, staging, packages, wait).await?;
+    remove_cache(staging, &Config::load(staging)?.cache_dir(staging))?;
+
+    let image_path = export::oci(staging, reference, out_dir)?;
+
+    info!("Created {}", image_path.display());
+    Ok(())
+}
+
+/// Removes the package `cache` of the image staged in `staging`, which has
+/// no use for the archives it was installed from. The cache of unprivileged
+/// users is theirs, outside of the image, and is kept.
+fn remove_cache(staging: &Path, cache: &Path) -> Result<()> {
+    if cache.starts_with(staging) && cache.exists() {
+        fs::remove_dir_all(cache)?;
+    }
+
+    Ok(())
+}
+
+/// Rewraps the package archive at `path` into a pacman package in `out_dir`.
+pub fn export_pacman<P: AsRef<Path>>(path: P, out_dir: &Path) -> Result<()> {
+    let package_path = export::pacman(path.as_ref(), out_dir)?;
+
+    info!("Created {}", package_path.display());
+    Ok(())
+}
+
+/// Rewraps the package archive at `path` into an rpm package in `out_dir`.
+pub fn export_rpm<P: AsRef<Path>>(path: P, out_dir: &Path) -> Result<()> {
+    let rpm_path = export::rpm(path.as_ref(), out_dir)?;
+
+    info!("Created {}", rpm_path.display());
+    Ok(())
+}
+
+/// Merges the package archives at `paths` into a systemd-sysext image, or a
+/// confext one if `confext`, in `out_dir`. The image is named `name`, which
+/// defaults to the name of the package when there is a single one.
+pub fn export_sysext(
+    paths: &[PathBuf],
+    name: Option<&str>,
+    confext: bool,
+    raw: bool,
+    out_dir: &Path,
+) -> Result<()> {
+    let name = match (name, paths) {
+        (Some(name), _) => name.to_string(),
+        (None, [path]) => archive::read_info(path)?.info.name,
+        (None, _) => bail!("An extension of several packages needs a --name"),
+    };
+    let extension = match confext {
+        true => Extension::Confext,
+        false => Extension::Sysext,
+    };
+
+    let image_path = export::sysext(paths, &name, extension, raw, out_dir)?;
+
+    info!("Created {}", image_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_cache() {
+        let dir = env::temp_dir().join(format!("blossom-test-export-{}", process::id()));
+        let staging = dir.join("staging");
+        let inside = staging.join("var/cache/blossom/packages");
+        let outside = dir.join("home/.cache/blossom/packages");
+        fs::create_dir_all(&inside).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("foo-1.0.peach"), "foo").unwrap();
+
+        remove_cache(&staging, &inside).unwrap();
+        remove_cache(&staging, &outside).unwrap();
+        let (inside_exists, kept) = (inside.exists(), outside.join("foo-1.0.peach").exists());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!inside_exists);
+        assert!(kept);
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    database::{Database, FileKind},
+    repository,
+};
+
+pub fn files<R: AsRef<Path>>(root: R, name: &str, remote: bool, json: bool) -> Result<()> {
+    let paths: Vec<String> = if remote {
+        let repositories = repository::load_synced(&root)?;
+
+        let (_, entry) = repository::find_newest(&repositories, name).ok_or(anyhow!(
+            "Package \"{name}\" was not found in any repository"
+        ))?;
+
+        entry.files.iter().map(|path| format!("/{path}")).collect()
+    } else {
+        let installed = Database::open(&root)?
+            .get(name)?
+            .ok_or(anyhow!("Package \"{name}\" is not installed"))?;
+
+        installed
+            .files
+            .iter()
+            .map(|entry| match entry.kind {
+                FileKind::Directory => format!("/{}/", entry.path),
+                _ => format!("/{}", entry.path),
+            })
+            .collect()
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&paths)?);
+        return Ok(());
+    }
+
+    for path in &paths {
+        println!("{name} {path}");
+    }
+
+    Ok(())
+}
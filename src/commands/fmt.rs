@@ -0,0 +1,49 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use tracing::info;
+
+use crate::format;
+
+/// Formats the recipes at `paths`, or the `package.toml` in them. With
+/// `check`, prints the recipes that aren't formatted instead of rewriting
+/// them, and fails if there are any.
+pub fn fmt(paths: &[PathBuf], check: bool) -> Result<()> {
+    let mut unformatted = 0;
+
+    for path in paths {
+        let path = recipe_path(path);
+        let recipe = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let formatted = format::format(&recipe)
+            .with_context(|| format!("Failed to format {}", path.display()))?;
+
+        if formatted == recipe {
+            continue;
+        }
+
+        if check {
+            println!("{}", path.display());
+            unformatted += 1;
+        } else {
+            fs::write(&path, formatted)?;
+            info!("Formatted {}", path.display());
+        }
+    }
+
+    if unformatted > 0 {
+        bail!("{unformatted} recipes aren't formatted, run `blossom fmt`");
+    }
+
+    Ok(())
+}
+
+fn recipe_path(path: &Path) -> PathBuf {
+    match path.is_dir() {
+        true => path.join("package.toml"),
+        false => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow, bail};
+use tracing::info;
+
+use crate::{
+    commands::{install::install_archive, uninstall::remove_package},
+    database::Database,
+    failure::Failure,
+    history::{Change, History},
+    hooks::Triggers,
+    lock::Lock,
+    prompt, snapshot,
+};
+
+pub fn history<R: AsRef<Path>>(root: R, json: bool) -> Result<()> {
+    let transactions = History::open(root)?.list()?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&transactions)?);
+        return Ok(());
+    }
+
+    for transaction in transactions {
+        println!(
+            "#{} {} by {}",
+            transaction.id,
+            transaction.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            transaction.user
+        );
+
+        for change in &transaction.changes {
+            println!("    {change}");
+        }
+    }
+
+    Ok(())
+}
+
+pub fn undo<R: AsRef<Path>>(root: R, id: u64, wait: bool) -> Result<()> {
+    let _lock = Lock::acquire(&root, wait)?;
+    let database = Database::open(&root)?;
+    let history = History::open(&root)?;
+
+    let transaction = history
+        .get(id)?
+        .ok_or(anyhow!("Transaction #{id} does not exist"))?;
+
+    let mut problems = Vec::new();
+
+    for change in &transaction.changes {
+        if let Err(e) = check_inverse(&database, change) {
+            problems.push(format!("{change}: {e}"));
+        }
+    }
+
+    if !problems.is_empty() {
+        bail!(
+            "Transaction #{id} cannot be undone:\n  {}",
+            problems.join("\n  ")
+        );
+    }
+
+    let packages: Vec<String> = transaction
+        .changes
+        .iter()
+        .map(|change| change.name().to_string())
+        .collect();
+
+    if !prompt::confirm(&format!("Undo transaction #{id}?"), false)? {
+        return Err(Failure::Aborted.error("Undo cancelled").into());
+    }
+
+    snapshot::before_transaction(&database, &packages)?;
+
+    let mut changes = Vec::new();
+    let mut triggers = Triggers::default();
+
+    for change in transaction.changes.iter().rev() {
+        changes.push(match change {
+            Change::Install { name, .. } => remove_package(&database, name, &mut triggers)?,
+            Change::Remove { name, version }
+            | Change::Upgrade {
+                name,
+                from: version,
+                ..
+            } => install_archive(
+                &database,
+                &database.cache_path(name, version),
+                None,
+                &mut triggers,
+            )?,
+        });
+    }
+
+    if let Some(undo) = history.record(changes)? {
+        info!("Transaction #{id} undone as transaction #{}", undo.id);
+    }
+
+    triggers.run(&root)?;
+
+    Ok(())
+}
+
+/// Makes sure the state left behind by `change` is still current, and that
+/// everything needed to revert it is available.
+fn check_inverse(database: &Database, change: &Change) -> Result<()> {
+    let installed = database.get(change.name())?.map(|p| p.info.version);
+
+    let (expected, restore) = match change {
+        Change::Install { version, .. } => (Some(version), None),
+        Change::Remove { version, .. } => (None, Some(version)),
+        Change::Upgrade { from, to, .. } => (Some(to), Some(from)),
+    };
+
+    if installed.as_ref() != expected {
+        match installed {
+            Some(version) => bail!("version {version} is installed now"),
+            None => bail!("the package is no longer installed"),
+        }
+    }
+
+    if let Some(version) = restore {
+        let cache_path = database.cache_path(change.name(), version);
+
+        if !cache_path.exists() {
+            bail!("no cached archive at {}", cache_path.display());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use tracing::warn;
+
+use crate::import::{self, Import};
+
+/// Prints a recipe converted from the PKGBUILD at `path`. What couldn't be
+/// converted is marked `TODO` at the top of it.
+pub fn import_pkgbuild<P: AsRef<Path>>(path: P) -> Result<()> {
+    print_import(import::pkgbuild(&read(path.as_ref())?)?)
+}
+
+/// Prints a recipe converted from the APKBUILD at `path`, like
+/// [`import_pkgbuild`].
+pub fn import_apkbuild<P: AsRef<Path>>(path: P) -> Result<()> {
+    print_import(import::apkbuild(&read(path.as_ref())?)?)
+}
+
+/// Prints a recipe converted from the RPM spec at `path`, like
+/// [`import_pkgbuild`].
+pub fn import_spec<P: AsRef<Path>>(path: P) -> Result<()> {
+    print_import(import::spec(&read(path.as_ref())?)?)
+}
+
+/// Prints a recipe converted from the Homebrew formula at `path`, like
+/// [`import_pkgbuild`].
+pub fn import_formula<P: AsRef<Path>>(path: P) -> Result<()> {
+    print_import(import::formula(&read(path.as_ref())?)?)
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+pub(super) fn print_import(import: Import) -> Result<()> {
+    print!("{}", import.to_recipe()?);
+
+    if !import.todos.is_empty() {
+        warn!("The recipe has {} TODOs to check", import.todos.len());
+    }
+
+    Ok(())
+}
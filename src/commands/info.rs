@@ -1,7 +1,177 @@
-use anyhow::Result;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use tracing::info;
 
-pub fn info(name: &str) -> Result<()> {
+use crate::{
+    database::{Database, InstallReason, InstalledPackage},
+    package::{Dependencies, Info},
+    permissions::SpecialPermission,
+    repository::{self, IndexEntry},
+};
+
+/// A package as printed with `--json`.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum Found<'a> {
+    Installed(&'a InstalledPackage),
+    Available {
+        repository: &'a str,
+        #[serde(flatten)]
+        entry: &'a IndexEntry,
+    },
+}
+
+pub fn info<R: AsRef<Path>>(root: R, name: &str, json: bool) -> Result<()> {
     info!("Retrieving info for package: {}", name);
+
+    if let Some(installed) = Database::open(&root)?.get(name)? {
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&Found::Installed(&installed))?
+            );
+            return Ok(());
+        }
+
+        print_common(&installed.info, installed.dependencies.as_ref());
+
+        let reason = match installed.reason {
+            InstallReason::Explicit => "Explicitly installed",
+            InstallReason::Dependency => "Installed as a dependency",
+        };
+
+        print_field("Installed Size", &format_size(installed.installed_size));
+        print_field("Install Reason", reason);
+        print_field("Build Date", &format_date(installed.build_date));
+        print_field("Install Date", &format_date(installed.install_date));
+        print_field("Files", &installed.files.len().to_string());
+        print_field(
+            "Special Perms",
+            &join_permissions(&installed.special_permissions),
+        );
+
+        return Ok(());
+    }
+
+    let repositories = repository::load_synced_named(&root, &[name])?;
+
+    let (repository, entry) = repository::find_newest(&repositories, name).ok_or(anyhow!(
+        "Package \"{name}\" is neither installed nor available in any repository"
+    ))?;
+
+    if json {
+        let found = Found::Available {
+            repository: &repository.name,
+            entry,
+        };
+        println!("{}", serde_json::to_string_pretty(&found)?);
+        return Ok(());
+    }
+
+    print_field("Repository", &repository.name);
+    print_common(&entry.info, entry.dependencies.as_ref());
+    print_field("Files", &entry.files.len().to_string());
+    print_field(
+        "Special Perms",
+        &join_permissions(&entry.special_permissions),
+    );
+
     Ok(())
 }
+
+fn print_common(info: &Info, dependencies: Option<&Dependencies>) {
+    print_field("Name", &info.name);
+    print_field("Version", &info.version);
+    print_field("Description", info.description.localized());
+    print_field("License", info.license.as_ref());
+    print_field("Provides", &join_or_none(&info.provides));
+    print_field("Conflicts With", &join_or_none(&info.conflicts));
+    print_field("Replaces", &join_or_none(&info.replaces));
+    print_field("Keywords", &join_or_none(&info.keywords));
+
+    let list = |select: fn(&Dependencies) -> &Vec<String>| {
+        join_or_none(dependencies.map(select).map_or(&[], Vec::as_slice))
+    };
+
+    print_field("Depends On", &list(|d| &d.required));
+    print_field("Optional Deps", &list(|d| &d.optional));
+    print_field("Build Deps", &list(|d| &d.build));
+}
+
+fn join_or_none(list: &[String]) -> String {
+    if list.is_empty() {
+        "None".to_string()
+    } else {
+        list.join(" ")
+    }
+}
+
+fn join_permissions(permissions: &[SpecialPermission]) -> String {
+    if permissions.is_empty() {
+        "None".to_string()
+    } else {
+        permissions
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn print_field(name: &str, value: &str) {
+    println!("{name:<15} : {value}");
+}
+
+fn format_date(date: Option<DateTime<Utc>>) -> String {
+    date.map_or("Unknown".to_string(), |date| {
+        date.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    })
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.2} {}", UNITS[unit])
+    }
+}
+
+pub(crate) fn format_size_delta(old: u64, new: u64) -> String {
+    if new >= old {
+        format!("+{}", format_size(new - old))
+    } else {
+        format!("-{}", format_size(old - new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.50 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.00 MiB");
+    }
+
+    #[test]
+    fn test_format_size_delta() {
+        assert_eq!(format_size_delta(10, 2058), "+2.00 KiB");
+        assert_eq!(format_size_delta(300, 100), "-200 B");
+    }
+}
@@ -1,15 +1,1360 @@
-use std::{fs::File, path::Path};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{ErrorKind, Read, Write as _},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::{Mutex, mpsc},
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
 
-use anyhow::Result;
-use tracing::info;
+use anyhow::{Result, anyhow, bail};
+use camino::{Utf8Path, Utf8PathBuf};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use rayon::prelude::*;
+use reqwest::Client;
+use serde_json::{Value, json};
+use tar::EntryType;
+use tracing::{info, warn};
 
-pub fn install<P: AsRef<Path>>(tarball_path: P) -> Result<()> {
-    let file = File::open(&tarball_path)?;
-    let tar = flate2::read::GzDecoder::new(file);
-    let _archive = tar::Archive::new(tar);
+use crate::{
+    archive,
+    build::check_hash,
+    commands::{
+        info::{format_size, format_size_delta},
+        summary,
+        uninstall::remove_package,
+    },
+    config::{CONFIG_FILE, Config, RepositoryConfig, SignaturePolicy},
+    database::{
+        Database, FileEntry, FileKind, InstallReason, InstalledPackage, hash_bytes, hash_file,
+    },
+    download::{self, download, download_segmented, join_url},
+    error,
+    events::{self, Action, Event, Extraction, Phase, Transaction},
+    failure::Failure,
+    history::{Change, History},
+    hooks::Triggers,
+    keyring::Keyring,
+    lock::Lock,
+    mirrors::Ranking,
+    package::PeachInfo,
+    permissions::{self, SpecialPermission},
+    prompt,
+    repository::{self, IndexEntry},
+    resolver::{self, OptionalDependency, Plan, Reason, Resolver, Solver},
+    sbom::Sbom,
+    signing::{PublicKey, Signature, signature_path},
+    snapshot,
+    store::Store,
+};
 
-    // archive.unpack("/usr/local/")?;
-    info!("Installed package from {}", tarball_path.as_ref().display());
+pub fn install<P: AsRef<Path>, R: AsRef<Path>>(tarball_path: P, root: R, wait: bool) -> Result<()> {
+    let root = root.as_ref();
+    let _lock = Lock::acquire(root, wait)?;
+    let database = Database::open(root)?;
+
+    let mut config = Config::load(root)?;
+    if config.signatures == SignaturePolicy::Required {
+        Keyring::load(root)?.apply(&mut config);
+        verify_local_archive(tarball_path.as_ref(), &config)?;
+    }
+
+    let name = archive::read_info(&tarball_path)?.info.name;
+    snapshot::before_transaction(&database, &[name])?;
+
+    let mut triggers = Triggers::default();
+    let change = install_archive(&database, tarball_path.as_ref(), None, &mut triggers)?;
+    History::open(root)?.record(vec![change])?;
+
+    triggers.run(root)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    /// Install the selected optional dependencies as dependencies rather than
+    /// explicitly.
+    pub as_deps: bool,
+    /// Don't offer to install optional dependencies.
+    pub no_optional: bool,
+    pub solver: Solver,
+    /// Show why each package is part of the transaction.
+    pub explain: bool,
+    /// Print the plan as JSON.
+    pub json: bool,
+}
+
+/// Installs packages by name from the synced repositories, along with every
+/// required dependency that isn't installed yet.
+pub async fn install_packages<R: AsRef<Path>>(
+    root: R,
+    names: &[String],
+    options: &InstallOptions,
+    wait: bool,
+) -> Result<()> {
+    let root = root.as_ref();
+    let _lock = Lock::acquire_async(root, wait).await?;
+    let database = Database::open(root)?;
+
+    let repositories = repository::load_synced(root)?;
+    let installed = database.list()?;
+    let resolver = Resolver::new(&repositories, &installed)
+        .preferences(resolver::load_preferences(root)?)
+        .solver(options.solver)
+        .prompt(&prompt_provider);
+
+    events::emit(Event::Phase {
+        phase: Phase::Resolve,
+    });
+    let mut plan = resolver.resolve(names)?;
+
+    if !options.no_optional {
+        let optional = resolver.optional_dependencies(&plan)?;
+        let selected = select_optional(&optional)?;
+
+        if !selected.is_empty() {
+            plan = resolver.resolve_with(names, &selected)?;
+        }
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&plan_json(&plan))?);
+    } else if options.explain {
+        explain(&plan);
+    }
+
+    confirm_plan(&database, &plan, options.json)?;
+    let archives = fetch_archives(&database, &plan).await?;
+
+    execute_plan(&database, &plan, &archives, options.as_deps)
+}
+
+/// Installs packages by name from the repositories synced in `root` into the
+/// empty root `target`, which has no repositories of its own, downloading
+/// them into the package cache of `root`.
+pub(crate) async fn install_into(
+    root: &Path,
+    target: &Path,
+    names: &[String],
+    wait: bool,
+) -> Result<()> {
+    let _lock = Lock::acquire_async(root, wait).await?;
+    let database = Database::open(root)?;
+
+    let repositories = repository::load_synced(root)?;
+    let plan = Resolver::new(&repositories, &[])
+        .preferences(resolver::load_preferences(root)?)
+        .prompt(&prompt_provider)
+        .resolve(names)?;
+
+    let archives = fetch_archives(&database, &plan).await?;
+
+    execute_plan(&Database::open(target)?, &plan, &archives, false)
+}
+
+/// Downloads the archive of every planned package missing from the package
+/// cache, verifying it against the checksum from its repository index, and
+/// its signature when signatures are required.
+pub(crate) async fn fetch_archives(database: &Database, plan: &Plan) -> Result<Vec<PathBuf>> {
+    let mut config = Config::load(database.root())?;
+    Keyring::load(database.root())?.apply(&mut config);
+    let required = config.signatures == SignaturePolicy::Required;
+    let ranking = Ranking::load(database.root())?;
+    let client = Client::new();
+    let mut archives = Vec::new();
+    let mut downloads = Vec::new();
+
+    let mut planned = Vec::new();
+    for install in &plan.install {
+        let entry = &install.entry;
+        let repository = config.repository(&install.repository).ok_or(anyhow!(
+            "Repository {} is not configured, can't download {}",
+            install.repository,
+            entry.filename
+        ))?;
+
+        planned.push((
+            entry,
+            repository,
+            database.cache_path(&entry.info.name, &entry.info.version),
+        ));
+    }
+
+    // Every cached archive of the transaction is hashed at once.
+    let cached = planned
+        .par_iter()
+        .map(|(entry, repository, archive)| {
+            Ok(archive.exists()
+                && check_hash(archive, &entry.checksum)?
+                && (!required || verify_archive(archive, entry, repository).is_ok()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for ((entry, repository, archive), cached) in planned.into_iter().zip(cached) {
+        archives.push(archive.clone());
+
+        if cached {
+            continue;
+        }
+
+        let urls: Vec<String> = ranking
+            .urls(repository)
+            .into_iter()
+            .filter(|url| !config.offline || !download::is_remote(url))
+            .collect();
+
+        if urls.is_empty() {
+            return Err(Failure::Network
+                .error(format!(
+                    "{} isn't cached, can't download it while offline",
+                    entry.filename
+                ))
+                .into());
+        }
+
+        if let Some(parent) = archive.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        downloads.push((repository, urls, entry, archive));
+    }
+
+    if !downloads.is_empty() {
+        events::emit(Event::Phase {
+            phase: Phase::Download,
+        });
+    }
+
+    // Collected first, a stream of closures borrowing the downloads would make
+    // this future not `Send`.
+    let fetches: Vec<_> = downloads
+        .iter()
+        .map(|(repository, urls, entry, archive)| {
+            fetch_archive(
+                &client,
+                repository,
+                urls,
+                entry,
+                archive,
+                required,
+                config.download.segments,
+            )
+        })
+        .collect();
+
+    stream::iter(fetches)
+        .buffer_unordered(config.parallelism.downloads.max(1))
+        .try_collect::<Vec<()>>()
+        .await?;
+
+    Ok(archives)
+}
+
+/// Downloads an archive from the first of `urls` serving it with the expected
+/// checksum, and with a trusted signature if `signed`.
+async fn fetch_archive(
+    client: &Client,
+    repository: &RepositoryConfig,
+    urls: &[String],
+    entry: &IndexEntry,
+    archive: &Path,
+    signed: bool,
+    segments: usize,
+) -> Result<()> {
+    let mut errors = Vec::new();
+
+    for url in urls {
+        let file_url = join_url(url, &entry.filename);
+
+        let result = match download_segmented(client, &file_url, archive, segments)
+            .await
+            .map(drop)
+        {
+            Ok(()) if !check_hash(archive, &entry.checksum)? => {
+                fs::remove_file(archive)?;
+                anyhow!(
+                    "Checksum mismatch for {} downloaded from {url}",
+                    entry.filename
+                )
+            }
+            Ok(()) if !signed => return Ok(()),
+            Ok(()) => match fetch_signature(client, url, repository, entry, archive).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    fs::remove_file(archive)?;
+                    e
+                }
+            },
+            Err(e) => e.into(),
+        };
+
+        if urls.len() == 1 {
+            return Err(result);
+        }
+
+        warn!("Failed to download {} from {url}: {result}", entry.filename);
+        errors.push(format!("{url}: {result}"));
+    }
+
+    bail!(
+        "Every mirror of {} failed to serve {}:\n  {}",
+        repository.name,
+        entry.filename,
+        errors.join("\n  ")
+    )
+}
+
+async fn fetch_signature(
+    client: &Client,
+    url: &str,
+    repository: &RepositoryConfig,
+    entry: &IndexEntry,
+    archive: &Path,
+) -> Result<()> {
+    let signature = signature_path(archive);
+    let signature_file = format!("{}.sig", entry.filename);
+
+    // A missing signature is explained by the verification below.
+    if download(client, &join_url(url, &signature_file), &signature)
+        .await
+        .is_err()
+        && signature.exists()
+    {
+        fs::remove_file(&signature)?;
+    }
+
+    verify_archive(archive, entry, repository)
+}
+
+/// Checks the signature stored next to a cached archive against the keys its
+/// repository trusts, explaining what key would be needed when it fails.
+fn verify_archive(archive: &Path, entry: &IndexEntry, repository: &RepositoryConfig) -> Result<()> {
+    let name = &repository.name;
+    let trusted: Vec<String> = repository.keys.iter().map(PublicKey::id).collect();
+
+    if trusted.is_empty() {
+        return Err(Failure::Signature
+            .error(format!(
+                "{} can't be verified, signatures are required by {CONFIG_FILE} but {name} has no \
+             trusted keys. Import the key it's signed with using `blossom key import` and trust it \
+             with `blossom key trust <name> {name}`",
+                entry.filename
+            ))
+            .into());
+    }
+
+    let signature = signature_path(archive);
+    if !signature.exists() {
+        return Err(Failure::Signature.error(format!(
+            "{} from {name} is unsigned, signatures are required by {CONFIG_FILE} and it needs a \
+             signature by one of the keys {name} trusts: {}",
+            entry.filename,
+            trusted.join(", ")
+        )).into());
+    }
+
+    let signature = Signature::read(&signature).map_err(|e| {
+        Failure::Signature.error(format!("Invalid signature of {}: {e}", entry.filename))
+    })?;
+
+    if !trusted.contains(&signature.key) {
+        return Err(Failure::Signature.error(format!(
+            "{} is signed by key {}, which {name} doesn't trust. If the key is genuine, import it \
+             with `blossom key import` and trust it with `blossom key trust <name> {name}`",
+            entry.filename, signature.key
+        )).into());
+    }
+
+    signature
+        .verify(&fs::read(archive)?, &repository.keys)
+        .map_err(|e| {
+            Failure::Signature.error(format!("{} failed verification: {e}", entry.filename))
+        })?;
+
+    Ok(())
+}
+
+/// Checks that a local archive is signed by a key trusted by any repository,
+/// since it doesn't come with a verified checksum.
+fn verify_local_archive(archive: &Path, config: &Config) -> Result<()> {
+    let signature = signature_path(archive);
+
+    if !signature.exists() {
+        return Err(Failure::Signature.error(format!(
+            "{} is unsigned, signatures are required by {CONFIG_FILE} and it needs a signature in \
+             {} by a key trusted by one of the repositories",
+            archive.display(),
+            signature.display()
+        )).into());
+    }
+
+    let signature = Signature::read(&signature).map_err(|e| {
+        Failure::Signature.error(format!("Invalid signature of {}: {e}", archive.display()))
+    })?;
+    let trusted: Vec<PublicKey> = config
+        .repositories
+        .iter()
+        .flat_map(|repository| repository.keys.iter().cloned())
+        .collect();
+
+    signature
+        .verify(&fs::read(archive)?, &trusted)
+        .map_err(|e| {
+            Failure::Signature.error(format!("{} failed verification: {e}", archive.display()))
+        })?;
+
+    Ok(())
+}
+
+fn explain(plan: &Plan) {
+    let packages: Vec<(String, String)> = plan
+        .remove
+        .iter()
+        .map(|removal| {
+            (
+                format!("{} {}", removal.name, removal.version),
+                format!("removed, {}", removal.reason),
+            )
+        })
+        .chain(plan.install.iter().map(|planned| {
+            (
+                format!("{} {}", planned.entry.info.name, planned.entry.info.version),
+                planned.reason.to_string(),
+            )
+        }))
+        .collect();
+
+    let width = packages.iter().map(|(p, _)| p.len()).max().unwrap_or(0);
+
+    println!("Transaction:");
+
+    for (package, reason) in packages {
+        println!("  {package:width$}  {reason}");
+    }
+}
+
+/// Shows what a plan changes, unless it is printed as JSON instead, and asks
+/// for confirmation before anything is downloaded. Removals are always
+/// confirmed, the whole transaction only in strict mode.
+pub(crate) fn confirm_plan(database: &Database, plan: &Plan, json: bool) -> Result<()> {
+    if !json {
+        summary::print_summary(database, plan)?;
+    }
+
+    if !plan.remove.is_empty() {
+        for removal in &plan.remove {
+            println!("{} {} is {}", removal.name, removal.version, removal.reason);
+        }
+
+        if !prompt::confirm("Remove these packages?", true)? {
+            return Err(Failure::Aborted.error("Transaction cancelled").into());
+        }
+    }
+    if !prompt::confirm("Proceed with the transaction?", false)? {
+        return Err(Failure::Aborted.error("Transaction cancelled").into());
+    }
+
+    Ok(())
+}
+
+/// A plan as printed with `--json`.
+pub(crate) fn plan_json(plan: &Plan) -> Value {
+    json!({
+        "remove": plan.remove.iter().map(|removal| json!({
+            "name": removal.name,
+            "version": removal.version,
+            "reason": removal.reason.to_string(),
+        })).collect::<Vec<_>>(),
+        "install": plan.install.iter().map(|planned| json!({
+            "name": planned.entry.info.name,
+            "version": planned.entry.info.version,
+            "repository": planned.repository,
+            "reason": planned.reason.to_string(),
+        })).collect::<Vec<_>>(),
+        "cycles": plan.cycles,
+    })
+}
+
+/// Carries out a resolved plan: removals first, then installations in order.
+pub(crate) fn execute_plan(
+    database: &Database,
+    plan: &Plan,
+    archives: &[PathBuf],
+    as_deps: bool,
+) -> Result<()> {
+    let root = database.root();
+
+    for cycle in &plan.cycles {
+        warn!(
+            "Runtime dependency cycle {}, its packages are installed in an arbitrary order",
+            resolver::format_cycle(cycle)
+        );
+    }
+
+    let touched: Vec<String> = plan
+        .remove
+        .iter()
+        .map(|removal| removal.name.clone())
+        .chain(
+            plan.install
+                .iter()
+                .map(|planned| planned.entry.info.name.clone()),
+        )
+        .collect();
+    snapshot::before_transaction(database, &touched)?;
+
+    let mut triggers = Triggers::default();
+    let mut changes = Vec::new();
+    let mut transaction = Transaction::start(plan.remove.len() + plan.install.len());
+
+    if !plan.remove.is_empty() {
+        events::emit(Event::Phase {
+            phase: Phase::Remove,
+        });
+    }
+
+    for removal in &plan.remove {
+        transaction.package(Action::Remove, &removal.name, &removal.version);
+        changes.push(remove_package(database, &removal.name, &mut triggers)?);
+        transaction.done();
+    }
+
+    events::emit(Event::Phase {
+        phase: Phase::Install,
+    });
+
+    for (planned, archive) in plan.install.iter().zip(archives) {
+        // Packages that are already installed keep their install reason.
+        let reason = match planned.reason {
+            _ if database.get(&planned.entry.info.name)?.is_some() => None,
+            Reason::Requested => Some(InstallReason::Explicit),
+            Reason::Optional { .. } if !as_deps => Some(InstallReason::Explicit),
+            _ => Some(InstallReason::Dependency),
+        };
+
+        transaction.package(
+            Action::Install,
+            &planned.entry.info.name,
+            &planned.entry.info.version,
+        );
+        changes.push(install_archive(database, archive, reason, &mut triggers)?);
+        transaction.done();
+    }
+    transaction.finish();
+
+    History::open(root)?.record(changes)?;
+
+    events::emit(Event::Phase {
+        phase: Phase::Hooks,
+    });
+    triggers.run(root)?;
+    events::emit(Event::Phase { phase: Phase::Done });
+
+    Ok(())
+}
+
+/// Lists unfulfilled optional dependencies and asks which to install.
+fn select_optional(optional: &[OptionalDependency]) -> Result<Vec<OptionalDependency>> {
+    let available: Vec<&OptionalDependency> = optional
+        .iter()
+        .filter(|optional| optional.available)
+        .collect();
+
+    if optional.is_empty() || !prompt::can_ask() {
+        return Ok(Vec::new());
+    }
+
+    println!("Optional dependencies:");
+
+    let mut index = 0;
+
+    for optional in optional {
+        let description = optional
+            .description
+            .as_ref()
+            .map(|description| format!(": {description}"))
+            .unwrap_or_default();
+
+        if optional.available {
+            index += 1;
+            println!(
+                "  {index}) {}{description} (wanted by {})",
+                optional.requirement, optional.wanted_by
+            );
+        } else {
+            println!(
+                "  -) {}{description} (wanted by {}, not available)",
+                optional.requirement, optional.wanted_by
+            );
+        }
+    }
+
+    if available.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    Ok(prompt::select(
+        "Select optional dependencies to install (e.g. 1 3), or press enter to skip: ",
+        available.len(),
+    )?
+    .into_iter()
+    .map(|choice| available[choice].clone())
+    .collect())
+}
+
+pub(crate) fn prompt_provider(name: &str, providers: &[String]) -> error::Result<String> {
+    let question = format!(
+        "There are {} providers available for {name}:",
+        providers.len()
+    );
+
+    Ok(providers[prompt::choose(&question, providers)?].clone())
+}
+
+/// Installs or upgrades a package from an archive into an already locked
+/// database, keeping the previous install reason unless `reason` is given.
+pub(crate) fn install_archive(
+    database: &Database,
+    tarball_path: &Path,
+    reason: Option<InstallReason>,
+    triggers: &mut Triggers,
+) -> Result<Change> {
+    let root = database.root();
+
+    let peach_info = archive::read_info(tarball_path)?;
+    check_special_permissions(tarball_path, &peach_info)?;
+    check_file_conflicts(database, tarball_path, &peach_info.info.name)?;
+    let previous = database.get(&peach_info.info.name)?;
+
+    match &previous {
+        Some(old) => info!(
+            "Upgrading package \"{}\" from {} to {}",
+            peach_info.info.name, old.info.version, peach_info.info.version
+        ),
+        None => info!(
+            "Installing package \"{}\" version {}",
+            peach_info.info.name, peach_info.info.version
+        ),
+    }
+
+    let old_size = previous.as_ref().map_or(0, |old| old.installed_size);
+
+    info!(
+        "Package size: {}, installed size: {} ({})",
+        format_size(fs::metadata(tarball_path)?.len()),
+        format_size(peach_info.installed_size),
+        format_size_delta(old_size, peach_info.installed_size)
+    );
+
+    let threads = Config::load(root)?.parallelism.extraction();
+    let (files, new_configs) =
+        extract(root, tarball_path, &peach_info, previous.as_ref(), threads)?;
+
+    if let Some(previous) = &previous {
+        remove_obsolete(root, previous, &files)?;
+        Store::open(root).release(&previous.files)?;
+    }
+
+    for path in &new_configs {
+        warn!("{path} was modified locally, new version installed as {path}.peachnew");
+    }
+
+    if !new_configs.is_empty() {
+        info!("Run `blossom config-diff` to merge pending configuration changes");
+    }
+
+    cache_package(database, &peach_info, tarball_path)?;
+
+    let reason = reason
+        .or(previous.as_ref().map(|previous| previous.reason))
+        .unwrap_or_default();
+
+    let info = peach_info.info.clone();
+    let installed = InstalledPackage::new(peach_info, reason, files);
+    database.put(&installed)?;
+
+    info!(
+        "Installed package \"{}\" from {}",
+        info.name,
+        tarball_path.display()
+    );
+
+    let change = match previous {
+        Some(previous) => Change::Upgrade {
+            name: info.name,
+            from: previous.info.version,
+            to: info.version,
+        },
+        None => Change::Install {
+            name: info.name,
+            version: info.version,
+        },
+    };
+
+    triggers.record(&change, installed.files.iter().map(|entry| &entry.path));
+
+    Ok(change)
+}
+
+/// Files up to this size are read into memory for the extraction workers to
+/// write, larger ones are written as they're decompressed.
+const BUFFERED_SIZE: u64 = 16 << 20;
+
+/// A file read out of an archive, for an extraction worker to write.
+struct Write {
+    /// Its position in the files of the package.
+    index: usize,
+    dest: PathBuf,
+    contents: Vec<u8>,
+    mode: u32,
+    mtime: u64,
+    permission: Option<SpecialPermission>,
+}
+
+impl Write {
+    /// Writes the file, or links it from `store` when it's there, returning
+    /// its hash.
+    fn run(&self, store: &Store) -> Result<String> {
+        remove_existing(&self.dest)?;
+
+        let hash = hash_bytes(&self.contents);
+        if self.permission.is_none() && store.link(&hash, self.mode, &self.dest)? {
+            return Ok(hash);
+        }
+
+        let mut file = File::create(&self.dest)?;
+        file.write_all(&self.contents)?;
+        file.set_permissions(fs::Permissions::from_mode(self.mode & 0o777))?;
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(self.mtime))?;
+
+        match &self.permission {
+            Some(permission) => permissions::apply(&self.dest, permission, self.mode)?,
+            None => store.add(&self.dest, &hash, self.mode)?,
+        }
+
+        Ok(hash)
+    }
+}
+
+/// Unpacks the archive at `tarball_path` into `root`, linking the files of
+/// its store that are already there. It's decompressed on this thread while
+/// `threads` workers write its files, the modes of its
+/// directories and its hard links being applied once they're done, in the
+/// order of the archive. Returns the files of the package along with the
+/// configuration files installed as `.peachnew`.
+fn extract(
+    root: &Path,
+    tarball_path: &Path,
+    peach_info: &PeachInfo,
+    previous: Option<&InstalledPackage>,
+    threads: usize,
+) -> Result<(Vec<FileEntry>, Vec<Utf8PathBuf>)> {
+    let mut files = Vec::new();
+    let mut new_configs = Vec::new();
+    let mut directories = Vec::new();
+    let mut links = Vec::new();
+    let mut archive = archive::open(tarball_path)?;
+    let mut extraction = Extraction::start(&peach_info.info.name, peach_info.installed_size);
+    let store = Store::open(root);
+
+    let (sender, receiver) = mpsc::sync_channel::<Write>(threads * 2);
+    let receiver = Mutex::new(receiver);
+    let written = Mutex::new(Vec::new());
+    let observer = events::observer();
+
+    thread::scope(|scope| -> Result<()> {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                events::with_observer(observer.clone(), || {
+                    let next = || receiver.lock().unwrap().recv();
+
+                    while let Ok(write) = next() {
+                        let hash = write.run(&store);
+                        written.lock().unwrap().push((write.index, hash));
+                    }
+                })
+            });
+        }
+
+        // The workers stop once the sender is dropped, whether or not
+        // reading the archive succeeds.
+        let sender = sender;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = archive::entry_path(&entry.path()?)?;
+
+            if path.as_str().is_empty() || path == PeachInfo::FILE_NAME || path == Sbom::FILE_NAME {
+                continue;
+            }
+
+            let dest = root.join(&path);
+            let header = entry.header();
+            let mode = header.mode()? & 0o7777;
+            let size = header.size()?;
+            let mtime = header.mtime()?;
+            let entry_type = header.entry_type();
+
+            let kind = match entry_type {
+                EntryType::Directory => FileKind::Directory,
+                EntryType::Symlink => FileKind::Symlink,
+                _ => FileKind::File,
+            };
+
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let hash = match kind {
+                FileKind::Directory => {
+                    fs::create_dir_all(&dest)?;
+                    directories.push((dest, mode));
+                    None
+                }
+                FileKind::Symlink => {
+                    remove_existing(&dest)?;
+                    entry.unpack(&dest)?;
+                    None
+                }
+                FileKind::File if peach_info.backup.contains(&path) => {
+                    let new_path = with_peachnew(&dest);
+                    remove_existing(&new_path)?;
+                    entry.unpack(&new_path)?;
+
+                    let new_hash = hash_file(&new_path)?;
+
+                    if protect_config(&dest, &path, &new_hash, previous)? {
+                        new_configs.push(path.clone());
+                    } else {
+                        fs::rename(&new_path, &dest)?;
+                    }
+
+                    Some(new_hash)
+                }
+                // Linked once the file they point to is written.
+                FileKind::File if entry_type == EntryType::Link => {
+                    let Some(target) = entry.link_name()? else {
+                        bail!("{path} is a hard link to nothing");
+                    };
+                    links.push((files.len(), root.join(archive::entry_path(&target)?), dest));
+                    None
+                }
+                FileKind::File if entry_type.is_file() && size <= BUFFERED_SIZE => {
+                    let mut contents = Vec::with_capacity(size as usize);
+                    entry.read_to_end(&mut contents)?;
+
+                    sender.send(Write {
+                        index: files.len(),
+                        dest,
+                        contents,
+                        mode,
+                        mtime,
+                        permission: special_permission(peach_info, &path).cloned(),
+                    })?;
+                    None
+                }
+                FileKind::File => {
+                    remove_existing(&dest)?;
+                    entry.unpack(&dest)?;
+                    let hash = hash_file(&dest)?;
+
+                    match special_permission(peach_info, &path) {
+                        Some(permission) => permissions::apply(&dest, permission, mode)?,
+                        None if entry_type.is_file() => store.add(&dest, &hash, mode)?,
+                        None => {}
+                    }
+
+                    Some(hash)
+                }
+            };
+
+            extraction.file(size);
+            files.push(FileEntry {
+                path,
+                kind,
+                mode,
+                hash,
+            });
+        }
+
+        Ok(())
+    })?;
+
+    for (index, hash) in written.into_inner().unwrap() {
+        files[index].hash = Some(hash?);
+    }
+
+    for (index, target, dest) in links {
+        remove_existing(&dest)?;
+        fs::hard_link(&target, &dest)?;
+        files[index].hash = Some(hash_file(&dest)?);
+    }
+
+    // Children come after their parents in archives, so read-only
+    // directories are only made so once nothing goes in them anymore.
+    for (dest, mode) in directories.iter().rev() {
+        fs::set_permissions(dest, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    extraction.finish();
+
+    Ok((files, new_configs))
+}
+
+/// How the recipe of a package allowed `path` to be setuid, setgid or to
+/// have capabilities, if it did.
+pub(crate) fn special_permission<'a>(
+    peach_info: &'a PeachInfo,
+    path: &Utf8Path,
+) -> Option<&'a SpecialPermission> {
+    peach_info
+        .special_permissions
+        .iter()
+        .find(|permission| permission.path == path)
+}
+
+/// Refuses archives with setuid or setgid files that their package
+/// information doesn't declare, as the recipe would have had to allow them.
+fn check_special_permissions(tarball_path: &Path, peach_info: &PeachInfo) -> Result<()> {
+    let mut undeclared = Vec::new();
+
+    for entry in archive::open(tarball_path)?.entries()? {
+        let entry = entry?;
+        let bits = entry.header().mode()? & 0o6000;
+
+        if bits == 0 || entry.header().entry_type() == EntryType::Directory {
+            continue;
+        }
+
+        let path = archive::entry_path(&entry.path()?)?;
+
+        if !peach_info
+            .special_permissions
+            .iter()
+            .any(|permission| permission.path == path && permission.mode_bits() == bits)
+        {
+            undeclared.push(path);
+        }
+    }
+
+    if !undeclared.is_empty() {
+        bail!(
+            "{} contains undeclared setuid or setgid files, refusing to install it:\n  {}",
+            tarball_path.display(),
+            undeclared
+                .iter()
+                .map(|path| path.as_str())
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Refuses archives that would overwrite files of other installed packages.
+/// Directories are shared, and the package itself may replace its own files.
+fn check_file_conflicts(database: &Database, tarball_path: &Path, name: &str) -> Result<()> {
+    let mut paths = HashSet::new();
+
+    for entry in archive::open(tarball_path)?.entries()? {
+        let entry = entry?;
+
+        if entry.header().entry_type() != EntryType::Directory {
+            paths.insert(archive::entry_path(&entry.path()?)?);
+        }
+    }
+
+    let mut conflicts = Vec::new();
+
+    for package in database.list()? {
+        if package.info.name == name {
+            continue;
+        }
+
+        for file in &package.files {
+            if file.kind != FileKind::Directory && paths.contains(&file.path) {
+                conflicts.push(format!("{} (owned by {})", file.path, package.info.name));
+            }
+        }
+    }
+
+    if !conflicts.is_empty() {
+        conflicts.sort();
+
+        return Err(Failure::Conflict
+            .error(format!(
+                "{} would overwrite files of other packages:\n  {}",
+                tarball_path.display(),
+                conflicts.join("\n  ")
+            ))
+            .into());
+    }
+
+    Ok(())
+}
+
+fn cache_package(database: &Database, peach_info: &PeachInfo, tarball_path: &Path) -> Result<()> {
+    let cache_path = database.cache_path(&peach_info.info.name, &peach_info.info.version);
+
+    if cache_path.exists() && fs::canonicalize(&cache_path)? == fs::canonicalize(tarball_path)? {
+        return Ok(());
+    }
+
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::copy(tarball_path, cache_path)?;
 
     Ok(())
 }
+
+/// Decides whether the packaged version of a backup file must be kept aside as
+/// `.peachnew` because the copy on disk carries local modifications.
+fn protect_config(
+    dest: &Path,
+    path: &Utf8Path,
+    new_hash: &str,
+    previous: Option<&InstalledPackage>,
+) -> Result<bool> {
+    if !dest.exists() {
+        return Ok(false);
+    }
+
+    let current_hash = hash_file(dest)?;
+
+    if current_hash == new_hash {
+        return Ok(false);
+    }
+
+    let original_hash = previous
+        .and_then(|previous| previous.file(path))
+        .and_then(|entry| entry.hash.as_deref());
+
+    Ok(original_hash != Some(current_hash.as_str()))
+}
+
+fn remove_obsolete(root: &Path, previous: &InstalledPackage, files: &[FileEntry]) -> Result<()> {
+    let current: HashSet<&Utf8PathBuf> = files.iter().map(|entry| &entry.path).collect();
+
+    let mut directories = Vec::new();
+
+    for entry in previous.files.iter().filter(|e| !current.contains(&e.path)) {
+        let dest = root.join(&entry.path);
+
+        match entry.kind {
+            FileKind::Directory => directories.push(dest),
+            _ if previous.is_backup(&entry.path) && is_modified(&dest, entry)? => {
+                warn!("{} was modified locally, leaving it in place", entry.path);
+            }
+            _ => remove_existing(&dest)?,
+        }
+    }
+
+    directories.sort();
+
+    for dir in directories.iter().rev() {
+        // Directories shared with other packages or holding user files stay.
+        let _ = fs::remove_dir(dir);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn is_modified(dest: &Path, entry: &FileEntry) -> Result<bool> {
+    if !dest.exists() {
+        return Ok(false);
+    }
+
+    Ok(entry.hash.as_deref() != Some(hash_file(dest)?.as_str()))
+}
+
+pub(crate) fn remove_existing(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Ok(fs::remove_file(path)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(crate) fn with_peachnew(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".peachnew");
+
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::{
+        failure::categorize, package::Package, resolver::PlannedPackage, signing::SecretKey,
+        test_util::test_entry,
+    };
+
+    /// Appends an entry of `entry_type` at `path` to `tar`, holding
+    /// `contents` or linking to `link`.
+    fn append(
+        tar: &mut tar::Builder<impl std::io::Write>,
+        path: &str,
+        entry_type: EntryType,
+        mode: u32,
+        contents: &[u8],
+        link: Option<&str>,
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(contents.len() as u64);
+        if let Some(link) = link {
+            header.set_link_name(link).unwrap();
+        }
+        header.set_cksum();
+        tar.append_data(&mut header, path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_extract() {
+        let dir = env::temp_dir().join(format!("blossom-test-extract-{}", std::process::id()));
+        let root = dir.join("root");
+        let tarball_path = dir.join("foo-1.0.peach");
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(root.join("etc/foo.conf"), "edited").unwrap();
+
+        let mut tar =
+            tar::Builder::new(zstd::Encoder::new(File::create(&tarball_path).unwrap(), 1).unwrap());
+        append(&mut tar, "usr", EntryType::Directory, 0o755, b"", None);
+        append(&mut tar, "usr/bin", EntryType::Directory, 0o755, b"", None);
+        append(
+            &mut tar,
+            "usr/bin/foo",
+            EntryType::Regular,
+            0o755,
+            b"foo",
+            None,
+        );
+        append(
+            &mut tar,
+            "usr/bin/bar",
+            EntryType::Link,
+            0o755,
+            b"",
+            Some("usr/bin/foo"),
+        );
+        append(
+            &mut tar,
+            "usr/share/foo",
+            EntryType::Directory,
+            0o555,
+            b"",
+            None,
+        );
+        append(
+            &mut tar,
+            "usr/share/foo/data",
+            EntryType::Regular,
+            0o644,
+            b"data",
+            None,
+        );
+        append(
+            &mut tar,
+            "etc/foo.conf",
+            EntryType::Regular,
+            0o644,
+            b"packaged",
+            None,
+        );
+        tar.into_inner().unwrap().finish().unwrap();
+
+        let package = Package::builder()
+            .name("foo")
+            .version("1.0")
+            .license("MIT")
+            .backup("etc/foo.conf")
+            .build()
+            .unwrap();
+        let peach_info = PeachInfo::from(&package);
+
+        let (files, new_configs) = extract(&root, &tarball_path, &peach_info, None, 4).unwrap();
+
+        let hash = |path: &str| {
+            files
+                .iter()
+                .find(|entry| entry.path == path)
+                .and_then(|entry| entry.hash.clone())
+        };
+        let mode = |path: &str| fs::metadata(root.join(path)).unwrap().permissions().mode() & 0o777;
+
+        assert_eq!(hash("usr/bin/foo"), Some(hash_bytes(b"foo")));
+        assert_eq!(hash("usr/bin/bar"), Some(hash_bytes(b"foo")));
+        assert_eq!(hash("usr/share/foo/data"), Some(hash_bytes(b"data")));
+        assert_eq!(hash("etc/foo.conf"), Some(hash_bytes(b"packaged")));
+        assert_eq!(hash("usr/share/foo"), None);
+
+        assert_eq!(mode("usr/bin/foo"), 0o755);
+        assert_eq!(mode("usr/bin/bar"), 0o755);
+        assert_eq!(mode("usr/share/foo/data"), 0o644);
+        assert_eq!(mode("usr/share/foo"), 0o555);
+        assert_eq!(fs::read(root.join("usr/bin/bar")).unwrap(), b"foo");
+
+        assert_eq!(new_configs, ["etc/foo.conf"]);
+        assert_eq!(fs::read(root.join("etc/foo.conf")).unwrap(), b"edited");
+        assert_eq!(
+            fs::read(root.join("etc/foo.conf.peachnew")).unwrap(),
+            b"packaged"
+        );
+
+        // Editing an installed file in place edits its object in the store
+        // too, which reinstalling mustn't link again.
+        fs::write(root.join("usr/bin/foo"), "edited").unwrap();
+        extract(&root, &tarball_path, &peach_info, None, 4).unwrap();
+        assert_eq!(fs::read(root.join("usr/bin/foo")).unwrap(), b"foo");
+        assert_eq!(fs::read(root.join("usr/bin/bar")).unwrap(), b"foo");
+
+        fs::set_permissions(
+            root.join("usr/share/foo"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Fetches `foo-1.0.peach` from a mirror in `dir` with the `policy` of
+    /// signatures, the mirror signing it with `signer` if any and the
+    /// repository trusting `trusted`.
+    async fn fetch_signed(
+        dir: &Path,
+        policy: &str,
+        signer: Option<&SecretKey>,
+        trusted: &SecretKey,
+    ) -> Result<PathBuf> {
+        let (root, mirror) = (dir.join("root"), dir.join("mirror"));
+        fs::create_dir_all(root.join("etc/blossom")).unwrap();
+        fs::create_dir_all(&mirror).unwrap();
+
+        let archive = mirror.join("foo-1.0.peach");
+        fs::write(&archive, "foo").unwrap();
+        if let Some(signer) = signer {
+            signer.sign(b"foo").write(signature_path(&archive)).unwrap();
+        }
+
+        fs::write(
+            root.join(CONFIG_FILE),
+            format!(
+                r#"
+signatures = "{policy}"
+cache_dir = "/cache"
+
+[[repositories]]
+name = "core"
+url = "file://{}"
+keys = ["{}"]
+"#,
+                mirror.display(),
+                trusted.public()
+            ),
+        )
+        .unwrap();
+
+        let plan = Plan {
+            install: vec![PlannedPackage {
+                repository: "core".to_string(),
+                entry: IndexEntry {
+                    checksum: hash_bytes(b"foo"),
+                    ..test_entry("foo", "1.0", &[])
+                },
+                reason: Reason::Requested,
+            }],
+            ..Plan::default()
+        };
+
+        let archives = fetch_archives(&Database::open(&root)?, &plan).await;
+        fs::remove_dir_all(dir).unwrap();
+
+        Ok(archives?.remove(0))
+    }
+
+    #[tokio::test]
+    async fn test_signature_policies() {
+        let dir = env::temp_dir().join(format!("blossom-test-policy-{}", std::process::id()));
+        let (trusted, other) = (
+            SecretKey::generate().unwrap(),
+            SecretKey::generate().unwrap(),
+        );
+        // Configurations are loaded once per root, each case gets its own.
+        let case = |policy: &str, signer: &str| dir.join(format!("{policy}-{signer}"));
+
+        let fetched = fetch_signed(
+            &case("required", "trusted"),
+            "required",
+            Some(&trusted),
+            &trusted,
+        )
+        .await
+        .unwrap();
+        assert!(fetched.ends_with("cache/foo-1.0.peach"));
+
+        for (name, signer) in [("unsigned", None), ("other", Some(&other))] {
+            let error = fetch_signed(&case("required", name), "required", signer, &trusted)
+                .await
+                .unwrap_err();
+            assert_eq!(categorize(&error), Some(Failure::Signature));
+        }
+
+        for policy in ["optional", "never"] {
+            for (name, signer) in [
+                ("unsigned", None),
+                ("other", Some(&other)),
+                ("trusted", Some(&trusted)),
+            ] {
+                fetch_signed(&case(policy, name), policy, signer, &trusted)
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_local_archive() {
+        let dir = env::temp_dir().join(format!("blossom-test-local-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let archive = dir.join("foo-1.0.peach");
+        fs::write(&archive, "foo").unwrap();
+
+        let (trusted, other) = (
+            SecretKey::generate().unwrap(),
+            SecretKey::generate().unwrap(),
+        );
+        let mut config = Config::default();
+        config.repositories.push(RepositoryConfig {
+            name: "core".to_string(),
+            url: "https://example.com".to_string(),
+            mirrors: Vec::new(),
+            priority: 0,
+            keys: vec![trusted.public()],
+            publish: None,
+        });
+
+        let unsigned = verify_local_archive(&archive, &config);
+        other.sign(b"foo").write(signature_path(&archive)).unwrap();
+        let untrusted = verify_local_archive(&archive, &config);
+        trusted
+            .sign(b"foo")
+            .write(signature_path(&archive))
+            .unwrap();
+        let signed = verify_local_archive(&archive, &config);
+        fs::remove_dir_all(&dir).unwrap();
+
+        for result in [unsigned, untrusted] {
+            assert_eq!(categorize(&result.unwrap_err()), Some(Failure::Signature));
+        }
+        signed.unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+use std::{fs, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    config::{CONFIG_FILE, Config},
+    keyring::{Keyring, KeyringEntry, secret_key_path, write_secret_key},
+    signing::{PublicKey, SecretKey},
+};
+
+/// Generates a signing key into the keyring, printing its public key.
+pub fn key_generate<R: AsRef<Path>>(root: R, name: &str) -> Result<()> {
+    let root = root.as_ref();
+    let mut keyring = Keyring::load(root)?;
+
+    if keyring.keys.contains_key(name) || secret_key_path(root, name).exists() {
+        bail!("A key named {name} already exists");
+    }
+
+    let key = SecretKey::generate()?;
+    let path = write_secret_key(root, name, &key)?;
+
+    keyring.keys.insert(
+        name.to_string(),
+        KeyringEntry {
+            key: key.public(),
+            trusted: Vec::new(),
+            revoked: false,
+        },
+    );
+    keyring.save(root)?;
+
+    info!(
+        "Generated key {name} ({}), its secret half is in {}",
+        key.public().id(),
+        path.display()
+    );
+    println!("{}", key.public());
+
+    Ok(())
+}
+
+/// Imports a public key, given as `ed25519:<hex>` or as a file containing
+/// one, or a secret key file to sign with. Imported keys aren't trusted for
+/// any repository until told so.
+pub fn key_import<R: AsRef<Path>>(root: R, name: &str, key: &str) -> Result<()> {
+    let root = root.as_ref();
+    let mut keyring = Keyring::load(root)?;
+
+    if keyring.keys.contains_key(name) {
+        bail!("A key named {name} already exists");
+    }
+
+    let path = Path::new(key);
+    let secret = path.exists().then(|| SecretKey::read(path).ok()).flatten();
+
+    let key: PublicKey = match &secret {
+        Some(secret) => secret.public(),
+        None if path.exists() => fs::read_to_string(path)?.parse()?,
+        None => key.parse()?,
+    };
+
+    if let Some((existing, _)) = keyring.keys.iter().find(|(_, entry)| entry.key == key) {
+        bail!("Key {} is already in the keyring as {existing}", key.id());
+    }
+
+    if let Some(secret) = &secret {
+        let path = write_secret_key(root, name, secret)?;
+        info!(
+            "Imported secret key {name} ({}) to {}",
+            key.id(),
+            path.display()
+        );
+    } else {
+        info!("Imported key {name} ({})", key.id());
+    }
+
+    keyring.keys.insert(
+        name.to_string(),
+        KeyringEntry {
+            key,
+            trusted: Vec::new(),
+            revoked: false,
+        },
+    );
+
+    keyring.save(root)?;
+
+    Ok(())
+}
+
+/// Prints the public key of a keyring key, in the format `key import` and the
+/// repository `keys` take.
+pub fn key_export<R: AsRef<Path>>(root: R, name: &str) -> Result<()> {
+    println!("{}", Keyring::load(root)?.get(name)?.key);
+
+    Ok(())
+}
+
+pub fn key_list<R: AsRef<Path>>(root: R, json: bool) -> Result<()> {
+    let root = root.as_ref();
+    let keyring = Keyring::load(root)?;
+
+    if json {
+        let keys: Vec<_> = keyring
+            .keys
+            .iter()
+            .map(|(name, entry)| {
+                json!({
+                    "name": name,
+                    "id": entry.key.id(),
+                    "key": entry.key.to_string(),
+                    "secret": secret_key_path(root, name).exists(),
+                    "revoked": entry.revoked,
+                    "trusted": entry.trusted,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&keys)?);
+        return Ok(());
+    }
+
+    if keyring.keys.is_empty() {
+        info!("The keyring is empty");
+        return Ok(());
+    }
+
+    for (name, entry) in &keyring.keys {
+        let mut flags = Vec::new();
+
+        if secret_key_path(root, name).exists() {
+            flags.push("secret".to_string());
+        }
+        if entry.revoked {
+            flags.push("revoked".to_string());
+        }
+        if !entry.trusted.is_empty() {
+            flags.push(format!("trusted by {}", entry.trusted.join(", ")));
+        }
+
+        if flags.is_empty() {
+            println!("{name} {}", entry.key.id());
+        } else {
+            println!("{name} {} [{}]", entry.key.id(), flags.join("] ["));
+        }
+    }
+
+    Ok(())
+}
+
+/// Revokes a key, which stops it from being trusted by any repository, even
+/// those listing it in their configured `keys`, and from signing.
+pub fn key_revoke<R: AsRef<Path>>(root: R, name: &str) -> Result<()> {
+    let root = root.as_ref();
+    let mut keyring = Keyring::load(root)?;
+    let entry = keyring.get_mut(name)?;
+
+    if entry.revoked {
+        bail!("Key {name} is already revoked");
+    }
+
+    entry.revoked = true;
+    entry.trusted.clear();
+
+    info!("Revoked key {name} ({})", entry.key.id());
+
+    keyring.save(root)?;
+
+    Ok(())
+}
+
+/// Trusts a key to sign the index of a repository, or withdraws that trust.
+pub fn key_trust<R: AsRef<Path>>(
+    root: R,
+    name: &str,
+    repository: &str,
+    remove: bool,
+) -> Result<()> {
+    let root = root.as_ref();
+    let mut keyring = Keyring::load(root)?;
+    let entry = keyring.get_mut(name)?;
+
+    if remove {
+        if !entry.trusted.iter().any(|trusted| trusted == repository) {
+            bail!("Key {name} isn't trusted by {repository}");
+        }
+
+        entry.trusted.retain(|trusted| trusted != repository);
+        info!("{repository} no longer trusts key {name}");
+
+        keyring.save(root)?;
+
+        return Ok(());
+    }
+
+    if entry.revoked {
+        bail!("Key {name} is revoked");
+    }
+
+    Config::load(root)?.repository(repository).ok_or(anyhow!(
+        "Repository {repository} is not configured in {CONFIG_FILE}"
+    ))?;
+
+    if entry.trusted.iter().any(|trusted| trusted == repository) {
+        bail!("Key {name} is already trusted by {repository}");
+    }
+
+    entry.trusted.push(repository.to_string());
+    entry.trusted.sort();
+    info!("{repository} now trusts key {name}");
+
+    keyring.save(root)?;
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use tracing::info;
+
+use crate::lint::{self, Severity};
+
+/// Lints the recipe at `path`, or the `package.toml` in it, printing the
+/// findings, as JSON with `json`. Fails when any of them is an error.
+pub fn lint<P: AsRef<Path>>(path: P, json: bool) -> Result<()> {
+    let path = match path.as_ref().is_dir() {
+        true => path.as_ref().join("package.toml"),
+        false => path.as_ref().to_path_buf(),
+    };
+    let recipe =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let findings = lint::lint(&recipe, dir)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+    } else {
+        for finding in &findings {
+            println!("{}: {finding}", path.display());
+        }
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        bail!("{} has {errors} errors", path.display());
+    }
+
+    info!(
+        "{} has no errors, {} warnings",
+        path.display(),
+        findings.len()
+    );
+    Ok(())
+}
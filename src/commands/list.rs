@@ -0,0 +1,165 @@
+use std::{cmp::Reverse, collections::HashSet, path::Path};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    commands::{info::format_size, rdepends},
+    database::{Database, InstallReason},
+    glob, repository,
+};
+
+#[derive(Debug, Default)]
+pub struct ListFilter {
+    pub pattern: Option<String>,
+    pub explicit: bool,
+    pub orphans: bool,
+    pub upgradable: bool,
+    pub size: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListEntry {
+    pub name: String,
+    pub version: String,
+    pub reason: InstallReason,
+    pub description: String,
+    pub installed_size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<String>,
+}
+
+pub fn list<R: AsRef<Path>>(root: R, filter: &ListFilter, json: bool) -> Result<()> {
+    let entries = list_packages(&root, filter)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    let name_width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    let version_width = entries.iter().map(|e| e.version.len()).max().unwrap_or(0);
+
+    for entry in &entries {
+        let reason = match entry.reason {
+            InstallReason::Explicit => "explicit",
+            InstallReason::Dependency => "dependency",
+        };
+
+        match &entry.available {
+            _ if filter.size => println!(
+                "{:name_width$}  {:version_width$}  {:>10}",
+                entry.name,
+                entry.version,
+                format_size(entry.installed_size)
+            ),
+            Some(available) => println!(
+                "{:name_width$}  {:version_width$} -> {available}",
+                entry.name, entry.version
+            ),
+            None => println!(
+                "{:name_width$}  {:version_width$}  {reason}",
+                entry.name, entry.version
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn list_packages<R: AsRef<Path>>(root: R, filter: &ListFilter) -> Result<Vec<ListEntry>> {
+    let database = Database::open(&root)?;
+    let packages = database.list()?;
+
+    let pattern = filter.pattern.as_deref().map(glob::to_regex).transpose()?;
+
+    let providers = rdepends::providers(&packages);
+    let required: HashSet<String> = packages
+        .iter()
+        .filter_map(|package| package.dependencies.as_ref())
+        .flat_map(|dependencies| dependencies.required_names())
+        .flat_map(|name| rdepends::resolve(&providers, name))
+        .collect();
+
+    let repositories = if filter.upgradable {
+        repository::load_synced(&root)?
+    } else {
+        Vec::new()
+    };
+
+    let mut entries = Vec::new();
+
+    for package in &packages {
+        if pattern
+            .as_ref()
+            .is_some_and(|pattern| !pattern.is_match(&package.info.name))
+        {
+            continue;
+        }
+
+        if filter.explicit && package.reason != InstallReason::Explicit {
+            continue;
+        }
+
+        if filter.orphans
+            && (package.reason != InstallReason::Dependency
+                || required.contains(&package.info.name))
+        {
+            continue;
+        }
+
+        let available =
+            repository::find_upgrade(&repositories, &package.info.name, &package.info.version)
+                .map(|(_, entry)| entry.info.version.clone());
+
+        if filter.upgradable && available.is_none() {
+            continue;
+        }
+
+        entries.push(ListEntry {
+            name: package.info.name.clone(),
+            version: package.info.version.clone(),
+            reason: package.reason,
+            description: package.info.description.localized().to_string(),
+            installed_size: package.installed_size,
+            available,
+        });
+    }
+
+    if filter.size {
+        entries.sort_by_key(|entry| Reverse(entry.installed_size));
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::test_util::install_test_package;
+
+    #[test]
+    fn test_orphans() {
+        let root = env::temp_dir().join(format!("blossom-test-orphans-{}", process::id()));
+        let explicit = InstallReason::Explicit;
+        let dependency = InstallReason::Dependency;
+        install_test_package(&root, "app", explicit, &["java-runtime"], &[]);
+        install_test_package(&root, "jre", dependency, &[], &["java-runtime=17"]);
+        install_test_package(&root, "libunused", dependency, &[], &[]);
+
+        let filter = ListFilter {
+            orphans: true,
+            ..Default::default()
+        };
+        let orphans = list_packages(&root, &filter).unwrap();
+
+        assert_eq!(
+            orphans.iter().map(|entry| &entry.name).collect::<Vec<_>>(),
+            ["libunused"]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,50 @@
+use std::{env::current_dir, fs, path::Path};
+
+use anyhow::{Result, bail};
+use tracing::info;
+
+use crate::{
+    build::{BuildContext, fetch_sources},
+    config::Config,
+    lockfile::{self, LOCK_FILE, PackageLock},
+    package::Package,
+    paths::Paths,
+};
+
+/// Fetches the sources of the package in the current directory and records
+/// exactly what was fetched, along with the installed toolchain, so that
+/// `build --locked` can reproduce the build inputs later.
+pub async fn lock<R: AsRef<Path>>(root: R) -> Result<()> {
+    let config = Config::load(&root)?;
+    let dir = current_dir()?;
+    let package_path = dir.join("package.toml");
+
+    if !package_path.exists() {
+        bail!("package.toml not found in the specified path.");
+    }
+
+    let package = Package::from_path(package_path)?;
+    let build_dir = Paths::new(root).build(&package.info)?;
+    fs::create_dir_all(&build_dir)?;
+
+    let lock = PackageLock {
+        sources: fetch_sources(
+            &package,
+            None,
+            &BuildContext::new(&dir, &build_dir),
+            &config,
+        )
+        .await?,
+        toolchain: lockfile::toolchain(),
+    };
+
+    lock.write(dir.join(LOCK_FILE))?;
+
+    info!(
+        "Locked {} sources and {} toolchain programs in {LOCK_FILE}",
+        lock.sources.len(),
+        lock.toolchain.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use reqwest::Client;
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    commands::info::format_size,
+    config::{CONFIG_FILE, Config},
+    mirrors::{self, MirrorStats, Ranking},
+};
+
+const RANK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Measures the mirrors of one or every configured repository and persists
+/// the ranking used by sync and package downloads.
+pub async fn mirrors_rank<R: AsRef<Path>>(root: R, repository: Option<&str>) -> Result<()> {
+    let root = root.as_ref();
+    let config = Config::load(root)?;
+    config.require_online("rank mirrors")?;
+
+    let repositories = match repository {
+        Some(name) => vec![config.repository(name).ok_or(anyhow!(
+            "Repository {name} is not configured in {CONFIG_FILE}"
+        ))?],
+        None => config.repositories.iter().collect(),
+    };
+
+    let client = Client::builder().timeout(RANK_TIMEOUT).build()?;
+    let mut ranking = Ranking::load(root)?;
+
+    for repository in repositories {
+        info!("Ranking the mirrors of {}", repository.name);
+
+        let stats = mirrors::rank(&client, repository).await;
+        print_ranking(&repository.name, &stats);
+        ranking.repositories.insert(repository.name.clone(), stats);
+    }
+
+    ranking.ranked = Utc::now();
+    ranking.save(root)?;
+
+    Ok(())
+}
+
+/// Prints the URLs of every repository in the order they are tried in.
+pub fn mirrors_list<R: AsRef<Path>>(root: R, json: bool) -> Result<()> {
+    let config = Config::load(&root)?;
+    let ranking = Ranking::load(&root)?;
+
+    if json {
+        let mut repositories = serde_json::Map::new();
+
+        for repository in &config.repositories {
+            let stats = ranking.repositories.get(&repository.name);
+            let urls: Vec<_> = ranking
+                .urls(repository)
+                .into_iter()
+                .map(|url| {
+                    let stats = stats.and_then(|stats| stats.iter().find(|stats| stats.url == url));
+                    json!({ "url": url, "ranking": stats })
+                })
+                .collect();
+
+            repositories.insert(repository.name.clone(), urls.into());
+        }
+
+        println!("{}", serde_json::to_string_pretty(&repositories)?);
+        return Ok(());
+    }
+
+    for repository in &config.repositories {
+        println!("{}:", repository.name);
+
+        for url in ranking.urls(repository) {
+            let stats = ranking
+                .repositories
+                .get(&repository.name)
+                .and_then(|stats| stats.iter().find(|stats| stats.url == url));
+
+            match stats {
+                Some(stats) => println!("  {url}  {}", describe(stats)),
+                None => println!("  {url}  (unranked)"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_ranking(repository: &str, ranking: &[MirrorStats]) {
+    println!("{repository}:");
+
+    for (position, stats) in ranking.iter().enumerate() {
+        println!("  {}. {}  {}", position + 1, stats.url, describe(stats));
+    }
+}
+
+fn describe(stats: &MirrorStats) -> String {
+    match &stats.error {
+        Some(error) => format!("failed: {error}"),
+        None => format!(
+            "{} ms, {}/s",
+            stats.latency_ms,
+            format_size(stats.throughput)
+        ),
+    }
+}
@@ -1,9 +1,112 @@
+mod analyze;
+mod audit;
+mod bootstrap;
 mod build;
+mod build_order;
+mod bump;
+mod changelog;
+mod completions;
+mod config;
+mod config_diff;
+mod daemon;
+mod db;
+mod export;
+mod files;
+mod fmt;
+mod history;
+mod import;
 mod info;
 mod install;
+mod key;
+mod lint;
+mod list;
+mod lock;
+mod mirrors;
+mod new;
+mod outdated;
+mod publish;
+mod rdepends;
+mod repair;
+mod repo;
+mod rollback;
+mod sbom;
+mod schema;
+mod search;
+mod serve;
+mod summary;
+mod sync;
+mod test;
+mod tree;
 mod uninstall;
+mod upgrade;
+mod verify;
+mod why;
 
+pub use analyze::analyze;
+pub use audit::audit;
+pub use bootstrap::bootstrap;
 pub use build::build;
+pub use build_order::build_order;
+pub use bump::bump;
+pub use changelog::changelog;
+pub use completions::{
+    completions, installed_candidates, package_candidates, repository_candidates,
+};
+pub use config::{config_env, config_get, config_paths, config_set};
+pub use config_diff::config_diff;
+pub use daemon::{Peer, Policy, daemon};
+pub use db::{Snapshot, SnapshotEntry, db_export, db_import};
+pub use export::{export_deb, export_oci, export_pacman, export_rpm, export_sysext};
+pub use files::files;
+pub use fmt::fmt;
+pub use history::{history, undo};
+pub use import::{import_apkbuild, import_formula, import_pkgbuild, import_spec};
 pub use info::info;
-pub use install::install;
+pub use install::{InstallOptions, install, install_packages};
+pub use key::{key_export, key_generate, key_import, key_list, key_revoke, key_trust};
+pub use lint::lint;
+pub use list::{ListEntry, ListFilter, list, list_packages};
+pub use lock::lock;
+pub use mirrors::{mirrors_list, mirrors_rank};
+pub use new::{new, new_from_crate, new_from_github, new_from_pypi};
+pub use outdated::outdated;
+pub use publish::publish;
+pub use rdepends::rdepends;
+pub use repair::repair;
+pub use repo::{repo_add, repo_check, repo_keygen, repo_refresh};
+pub use rollback::rollback;
+pub use sbom::sbom;
+pub use schema::schema;
+pub use search::{SearchResult, search, search_packages};
+pub use serve::serve;
+pub use sync::sync;
+pub use test::test;
+pub use tree::tree;
 pub use uninstall::uninstall;
+pub use upgrade::upgrade;
+pub use verify::verify;
+pub use why::why;
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::resolver::Solver;
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    /// Applications embedding blossom spawn these on multi-threaded runtimes.
+    #[test]
+    fn test_futures_are_send() {
+        let options = InstallOptions::default();
+
+        assert_send(&build("/", false));
+        assert_send(&install_packages("/", &[], &options, false));
+        assert_send(&lock("/"));
+        assert_send(&mirrors_rank("/", None));
+        assert_send(&publish("/", Path::new("package.peach"), "core", None));
+        assert_send(&sync("/", false));
+        assert_send(&upgrade("/", Solver::default(), false, false));
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+use reqwest::Client;
+
+use super::import::print_import;
+use crate::generate::{self, BuildSystem};
+
+/// Prints a recipe for a new package `name` to fill in, with the steps of
+/// `build_system` if any.
+pub fn new(name: &str, build_system: Option<BuildSystem>) -> Result<()> {
+    print!("{}", generate::template(name, build_system)?);
+    Ok(())
+}
+
+/// Prints a recipe building the crate `name` with cargo, from crates.io.
+/// What couldn't be filled in is marked `TODO` at the top of it.
+pub async fn new_from_crate(name: &str) -> Result<()> {
+    print_import(generate::from_crate(&Client::new(), name).await?)
+}
+
+/// Prints a recipe for the latest release of the GitHub repository
+/// `owner/repo`, like [`new_from_crate`].
+pub async fn new_from_github(repository: &str) -> Result<()> {
+    print_import(generate::from_github(&Client::new(), repository).await?)
+}
+
+/// Prints a recipe building the Python project `name` from its sdist on
+/// PyPI, like [`new_from_crate`].
+pub async fn new_from_pypi(name: &str) -> Result<()> {
+    print_import(generate::from_pypi(&Client::new(), name).await?)
+}
@@ -0,0 +1,75 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+use tracing::info;
+
+use crate::{database::Database, repository};
+
+#[derive(Debug, Serialize)]
+struct Outdated {
+    name: String,
+    installed: String,
+    available: String,
+    repository: String,
+}
+
+pub fn outdated<R: AsRef<Path>>(root: R, json: bool) -> Result<()> {
+    let repositories = repository::load_synced(&root)?;
+
+    if repositories.is_empty() && !json {
+        info!("No synced repositories, nothing to compare against");
+        return Ok(());
+    }
+
+    let mut outdated = Vec::new();
+
+    for package in Database::open(&root)?.list()? {
+        if let Some((repository, entry)) =
+            repository::find_upgrade(&repositories, &package.info.name, &package.info.version)
+        {
+            outdated.push(Outdated {
+                name: package.info.name,
+                installed: package.info.version,
+                available: entry.info.version.clone(),
+                repository: repository.name.clone(),
+            });
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&outdated)?);
+        return Ok(());
+    }
+
+    if outdated.is_empty() {
+        info!("All packages are up to date");
+        return Ok(());
+    }
+
+    let name_width = outdated.iter().map(|o| o.name.len()).max().unwrap_or(0);
+    let old_width = outdated
+        .iter()
+        .map(|o| o.installed.len())
+        .max()
+        .unwrap_or(0);
+    let new_width = outdated
+        .iter()
+        .map(|o| o.available.len())
+        .max()
+        .unwrap_or(0);
+
+    for Outdated {
+        name,
+        installed,
+        available,
+        repository,
+    } in &outdated
+    {
+        println!(
+            "{name:name_width$}  {installed:old_width$} -> {available:new_width$}  [{repository}]"
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::{env, fs, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use nix::unistd::mkdtemp;
+use reqwest::{Client, StatusCode};
+use tracing::info;
+
+use crate::{
+    commands::{
+        repo::{index_entry, write_index},
+        sync::verify,
+    },
+    config::{CONFIG_FILE, Config, RepositoryConfig},
+    download::{download, join_url, local_path},
+    error::Error,
+    keyring::{Keyring, read_secret_key},
+    metadata::{Role, metadata_file},
+    publish::{PublishConfig, upload},
+    repository::{INDEX_EXTENSION, RepoIndex},
+    signing::{SecretKey, signature_path},
+};
+
+/// Uploads a package to a configured repository along with its signature,
+/// then regenerates the repository index from the currently published one and
+/// uploads it with its delta. Old deltas are never removed remotely.
+/// Publishing isn't atomic, two maintainers publishing to the same repository
+/// at once can drop each other's package from the index.
+pub async fn publish<R: AsRef<Path>>(
+    root: R,
+    package: &Path,
+    repository: &str,
+    sign_key: Option<&Path>,
+) -> Result<()> {
+    let root = root.as_ref();
+    let mut config = Config::load(root)?;
+    config.require_online("publish")?;
+    Keyring::load(root)?.apply(&mut config);
+
+    let repository = config.repository(repository).ok_or(anyhow!(
+        "Repository {repository} is not configured in {CONFIG_FILE}"
+    ))?;
+    let backend = repository.publish.as_ref().ok_or(anyhow!(
+        "Repository {} has no publish backend configured",
+        repository.name
+    ))?;
+
+    let sign_key = sign_key.map(|key| read_secret_key(root, key)).transpose()?;
+
+    if !repository.keys.is_empty() {
+        let Some(key) = &sign_key else {
+            bail!(
+                "The index of {} must be signed by one of its keys, pass --sign",
+                repository.name
+            );
+        };
+
+        if !repository.keys.contains(&key.public()) {
+            bail!(
+                "Key {} is not trusted by {}, clients would reject the index",
+                key.public().id(),
+                repository.name
+            );
+        }
+    }
+
+    // Named at random and only readable by us, as it holds what gets signed.
+    let staging = mkdtemp(&env::temp_dir().join("blossom-publish-XXXXXX"))?;
+
+    let result = publish_staged(repository, backend, package, sign_key.as_ref(), &staging).await;
+
+    fs::remove_dir_all(&staging)?;
+
+    result
+}
+
+async fn publish_staged(
+    repository: &RepositoryConfig,
+    backend: &PublishConfig,
+    package: &Path,
+    sign_key: Option<&SecretKey>,
+    staging: &Path,
+) -> Result<()> {
+    let client = Client::new();
+    let entry = index_entry(package)?;
+
+    let previous = fetch_index(&client, repository, staging).await?;
+    let mut index = previous.clone();
+
+    let mut files = vec![package.to_path_buf()];
+
+    if let Some(key) = sign_key {
+        let signature = staging.join(format!("{}.sig", entry.filename));
+        key.sign(&fs::read(package)?).write(&signature)?;
+        files.push(signature);
+    }
+
+    info!(
+        "Publishing {} {} to {}",
+        entry.info.name, entry.info.version, repository.name
+    );
+
+    index.add(entry);
+
+    // The root metadata carries over, the other roles are signed anew. Clients
+    // reject a root recreated because the download failed as a rollback.
+    if sign_key.is_some() {
+        let root_file = metadata_file(&repository.name, Role::Root);
+        let url = join_url(&repository.url, &root_file);
+
+        if download(&client, &url, &staging.join(&root_file))
+            .await
+            .is_err()
+        {
+            info!("{} has no metadata yet, creating its root", repository.name);
+        }
+    }
+
+    let index_path = staging.join(format!("{}.{INDEX_EXTENSION}", repository.name));
+    let index_files = write_index(&index_path, &previous, &mut index, sign_key)?;
+
+    // Packages go first so the new index never lists missing archives.
+    upload(&client, backend, &repository.url, &files).await?;
+    upload(&client, backend, &repository.url, &index_files).await?;
+
+    info!("Published to {}", repository.name);
+
+    Ok(())
+}
+
+/// Downloads the published index, verified like on sync. A repository without
+/// an index yet starts with an empty one.
+async fn fetch_index(
+    client: &Client,
+    repository: &RepositoryConfig,
+    staging: &Path,
+) -> Result<RepoIndex> {
+    let file = format!("{}.{INDEX_EXTENSION}", repository.name);
+    let path = staging.join(format!("{file}.published"));
+    let url = join_url(&repository.url, &file);
+
+    if let Err(e) = download(client, &url, &path).await {
+        let not_found = match local_path(&url)? {
+            Some(local) => !local.exists(),
+            None => {
+                matches!(&e, Error::Http(e) if e.status() == Some(StatusCode::NOT_FOUND))
+            }
+        };
+
+        if not_found {
+            info!("{} has no index yet, starting a new one", repository.name);
+            return Ok(RepoIndex::default());
+        }
+
+        return Err(e.into());
+    }
+
+    if !repository.keys.is_empty() {
+        let signature = signature_path(&path);
+        download(
+            client,
+            &join_url(&repository.url, &format!("{file}.sig")),
+            &signature,
+        )
+        .await?;
+        verify(&path, &signature, repository)?;
+    }
+
+    Ok(RepoIndex::read(&path)?)
+}
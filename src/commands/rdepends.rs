@@ -0,0 +1,170 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    iter,
+    path::Path,
+};
+
+use anyhow::Result;
+use serde_json::json;
+
+use crate::{
+    database::{Database, InstalledPackage},
+    package::{Dependencies, dependency_name},
+    repository,
+};
+
+pub fn rdepends<R: AsRef<Path>>(root: R, name: &str, repo: bool, json: bool) -> Result<()> {
+    let packages = Database::open(&root)?.list()?;
+    let providers = providers(&packages);
+    let mut graph = graph(&packages, &providers);
+
+    if repo {
+        for repository in repository::load_synced(&root)? {
+            for entry in repository.index.packages {
+                graph
+                    .entry(entry.info.name)
+                    .or_insert_with(|| required(entry.dependencies.as_ref(), &providers));
+            }
+        }
+    }
+
+    let (direct, transitive) = reverse_dependencies(&graph, name);
+
+    if json {
+        let dependents = json!({ "direct": direct, "transitive": transitive });
+        println!("{}", serde_json::to_string_pretty(&dependents)?);
+        return Ok(());
+    }
+
+    let format = |names: &BTreeSet<String>| {
+        if names.is_empty() {
+            "None".to_string()
+        } else {
+            names.iter().cloned().collect::<Vec<_>>().join(" ")
+        }
+    };
+
+    println!("Required directly by     : {}", format(&direct));
+    println!("Required transitively by : {}", format(&transitive));
+
+    Ok(())
+}
+
+/// Maps every installed package to the installed packages it requires, by
+/// name or through what they provide.
+pub(crate) fn installed_graph(database: &Database) -> Result<BTreeMap<String, Vec<String>>> {
+    let packages = database.list()?;
+
+    Ok(graph(&packages, &providers(&packages)))
+}
+
+fn graph(
+    packages: &[InstalledPackage],
+    providers: &BTreeMap<String, Vec<String>>,
+) -> BTreeMap<String, Vec<String>> {
+    packages
+        .iter()
+        .map(|package| {
+            let required = required(package.dependencies.as_ref(), providers);
+            (package.info.name.clone(), required)
+        })
+        .collect()
+}
+
+/// Maps the names `packages` go by, their own and those they provide, to
+/// the packages going by them.
+pub(crate) fn providers(packages: &[InstalledPackage]) -> BTreeMap<String, Vec<String>> {
+    let mut providers = BTreeMap::<String, Vec<String>>::new();
+
+    for package in packages {
+        let provided = package
+            .info
+            .provides
+            .iter()
+            .map(|provided| dependency_name(provided));
+
+        for name in iter::once(package.info.name.as_str()).chain(provided) {
+            providers
+                .entry(name.to_string())
+                .or_default()
+                .push(package.info.name.clone());
+        }
+    }
+
+    providers
+}
+
+/// The installed packages that are or provide `name`, or `name` itself when
+/// none do.
+pub(crate) fn resolve(providers: &BTreeMap<String, Vec<String>>, name: &str) -> Vec<String> {
+    providers
+        .get(name)
+        .cloned()
+        .unwrap_or_else(|| vec![name.to_string()])
+}
+
+fn required(
+    dependencies: Option<&Dependencies>,
+    providers: &BTreeMap<String, Vec<String>>,
+) -> Vec<String> {
+    dependencies
+        .map(|dependencies| {
+            dependencies
+                .required_names()
+                .flat_map(|name| resolve(providers, name))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Walks `graph` (package name to required dependency names) backwards from
+/// `name`, returning its direct dependents and the ones only reachable
+/// through them.
+pub(crate) fn reverse_dependencies(
+    graph: &BTreeMap<String, Vec<String>>,
+    name: &str,
+) -> (BTreeSet<String>, BTreeSet<String>) {
+    let dependents_of = |target: &str| -> Vec<String> {
+        graph
+            .iter()
+            .filter(|(_, dependencies)| dependencies.iter().any(|d| d == target))
+            .map(|(name, _)| name.clone())
+            .collect()
+    };
+
+    let direct: BTreeSet<String> = dependents_of(name).into_iter().collect();
+
+    let mut seen = direct.clone();
+    let mut queue: VecDeque<String> = direct.iter().cloned().collect();
+    let mut transitive = BTreeSet::new();
+
+    while let Some(current) = queue.pop_front() {
+        for dependent in dependents_of(&current) {
+            if dependent != name && seen.insert(dependent.clone()) {
+                transitive.insert(dependent.clone());
+                queue.push_back(dependent);
+            }
+        }
+    }
+
+    (direct, transitive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reverse_dependencies() {
+        let mut graph = BTreeMap::new();
+        graph.insert("app".to_string(), vec!["libfoo".to_string()]);
+        graph.insert("libfoo".to_string(), vec!["libc".to_string()]);
+        graph.insert("tool".to_string(), vec!["libc".to_string()]);
+        graph.insert("libc".to_string(), vec!["app".to_string()]);
+
+        let (direct, transitive) = reverse_dependencies(&graph, "libc");
+
+        assert_eq!(direct, BTreeSet::from(["libfoo".into(), "tool".into()]));
+        assert_eq!(transitive, BTreeSet::from(["app".into()]));
+    }
+}
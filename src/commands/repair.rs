@@ -0,0 +1,122 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use tracing::{info, warn};
+
+use crate::{
+    archive,
+    commands::{
+        install::{remove_existing, special_permission},
+        verify::{ProblemKind, verify_package},
+    },
+    database::{Database, hash_file},
+    lock::Lock,
+    permissions,
+    store::Store,
+};
+
+pub fn repair<R: AsRef<Path>>(
+    root: R,
+    name: &str,
+    package: Option<&Path>,
+    wait: bool,
+) -> Result<()> {
+    let root = root.as_ref();
+    let _lock = Lock::acquire(root, wait)?;
+    let database = Database::open(root)?;
+
+    let installed = database
+        .get(name)?
+        .ok_or(anyhow!("Package \"{name}\" is not installed"))?;
+
+    let mut broken = HashSet::new();
+
+    for problem in verify_package(root, &installed)? {
+        if installed.is_backup(&problem.path) && matches!(problem.kind, ProblemKind::Modified) {
+            warn!("{} was modified locally, leaving it alone", problem.path);
+            continue;
+        }
+
+        broken.insert(problem.path);
+    }
+
+    if broken.is_empty() {
+        info!("Package \"{name}\" has no files to repair");
+        return Ok(());
+    }
+
+    let tarball_path = match package {
+        Some(path) => path.to_path_buf(),
+        None => database.cache_path(&installed.info.name, &installed.info.version),
+    };
+
+    if !tarball_path.exists() {
+        bail!(
+            "No cached archive for \"{name}\" at {}, pass one with --package",
+            tarball_path.display()
+        );
+    }
+
+    let peach_info = archive::read_info(&tarball_path)?;
+
+    if peach_info.info.name != installed.info.name
+        || peach_info.info.version != installed.info.version
+    {
+        bail!(
+            "{} contains {} {}, but {} {} is installed",
+            tarball_path.display(),
+            peach_info.info.name,
+            peach_info.info.version,
+            installed.info.name,
+            installed.info.version
+        );
+    }
+
+    let mut archive = archive::open(&tarball_path)?;
+    let store = Store::open(root);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = archive::entry_path(&entry.path()?)?;
+
+        if !broken.remove(&path) {
+            continue;
+        }
+
+        let dest = root.join(&path);
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mode = entry.header().mode()? & 0o7777;
+        let is_file = entry.header().entry_type().is_file();
+
+        remove_existing(&dest)?;
+        entry.unpack(&dest)?;
+
+        // Stored like install does, replacing the object when it's what got
+        // modified.
+        match special_permission(&peach_info, &path) {
+            Some(permission) => permissions::apply(&dest, permission, mode)?,
+            None if is_file && !installed.is_backup(&path) => {
+                store.add(&dest, &hash_file(&dest)?, mode)?
+            }
+            None => {}
+        }
+
+        info!("Restored {path}");
+    }
+
+    if !broken.is_empty() {
+        bail!(
+            "{} files could not be found in {}",
+            broken.len(),
+            tarball_path.display()
+        );
+    }
+
+    info!("Package \"{name}\" repaired successfully");
+
+    Ok(())
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow, bail};
+use tar::EntryType;
+use tracing::{info, warn};
+
+use crate::{
+    archive::{self, ARCHIVE_EXTENSION},
+    database::hash_file,
+    keyring::read_secret_key,
+    metadata,
+    package::PeachInfo,
+    repository::{
+        INDEX_EXTENSION, IndexEntry, IndexHead, KEPT_DELTAS, RepoIndex, delta_file, head_file,
+    },
+    sbom::Sbom,
+    signing::{SecretKey, signature_path},
+};
+
+/// Adds packages to the repository in `dir`, copying the archives next to the
+/// index and replacing index entries for the same name and version. The
+/// archives and index are signed with `sign_key` if given, a secret key file
+/// or the name of a keyring key.
+pub fn repo_add<R: AsRef<Path>, P: AsRef<Path>>(
+    root: R,
+    dir: P,
+    packages: &[PathBuf],
+    sign_key: Option<&Path>,
+) -> Result<()> {
+    let dir = dir.as_ref();
+    // Fail before touching the repository if the key is unusable.
+    let sign_key = sign_key
+        .map(|key| read_secret_key(&root, key))
+        .transpose()?;
+
+    fs::create_dir_all(dir)?;
+
+    add_packages(dir, &index_path(dir)?, packages, sign_key.as_ref())
+}
+
+fn add_packages(
+    dir: &Path,
+    index_path: &Path,
+    packages: &[PathBuf],
+    sign_key: Option<&SecretKey>,
+) -> Result<()> {
+    let previous = if index_path.exists() {
+        RepoIndex::read(index_path)?
+    } else {
+        RepoIndex::default()
+    };
+    let mut index = previous.clone();
+
+    for package in packages {
+        let entry = index_entry(package)?;
+
+        let target = dir.join(&entry.filename);
+        let replaced = !target.exists() || fs::canonicalize(&target)? != fs::canonicalize(package)?;
+
+        if replaced {
+            fs::copy(package, &target)?;
+        }
+
+        // The signature of an archive left in place is still valid.
+        if replaced || sign_key.is_some() {
+            sign_file(&target, sign_key)?;
+        }
+
+        info!(
+            "Adding {} {} to {}",
+            entry.info.name,
+            entry.info.version,
+            index_path.display()
+        );
+
+        index.add(entry);
+    }
+
+    write_index(index_path, &previous, &mut index, sign_key)?;
+
+    // Clients further behind than the kept deltas download the full index.
+    if let Some(pruned) = index.generation.checked_sub(KEPT_DELTAS) {
+        let delta = index_path.with_file_name(delta_file(&file_name(index_path)?, pruned));
+
+        for path in [signature_path(&delta), delta] {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the next generation of an index, along with the delta from the
+/// previous one and the head pointing at it, signing all of them and the
+/// repository metadata with `key`. Returns the written files in the order to
+/// publish them in, the head and metadata last.
+pub(crate) fn write_index(
+    index_path: &Path,
+    previous: &RepoIndex,
+    index: &mut RepoIndex,
+    key: Option<&SecretKey>,
+) -> Result<Vec<PathBuf>> {
+    let file = file_name(index_path)?;
+    index.generation = previous.generation + 1;
+
+    let delta_path = index_path.with_file_name(delta_file(&file, index.generation));
+    let head_path = index_path.with_file_name(head_file(&file));
+
+    index.write(index_path)?;
+    previous.delta(index).write(&delta_path)?;
+    IndexHead {
+        generation: index.generation,
+        oldest_delta: (index.generation + 1).saturating_sub(KEPT_DELTAS).max(1),
+    }
+    .write(&head_path)?;
+
+    let mut written = Vec::new();
+
+    for path in [index_path.to_path_buf(), delta_path, head_path] {
+        sign_file(&path, key)?;
+
+        written.push(path.clone());
+        if key.is_some() {
+            written.push(signature_path(&path));
+        }
+    }
+
+    if let Some(key) = key {
+        written.extend(write_metadata(index_path, index, key)?);
+    }
+
+    Ok(written)
+}
+
+fn write_metadata(index_path: &Path, index: &RepoIndex, key: &SecretKey) -> Result<Vec<PathBuf>> {
+    let file = file_name(index_path)?;
+    let name = file
+        .strip_suffix(&format!(".{INDEX_EXTENSION}"))
+        .unwrap_or(&file);
+    let dir = index_path.parent().unwrap_or(Path::new("."));
+
+    Ok(metadata::write(dir, name, index, key)?)
+}
+
+fn file_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from)
+        .ok_or(anyhow!("Invalid file name: {}", path.display()))
+}
+
+/// Adds the archives of the local repository `name` in `dir` that its index
+/// doesn't list yet. Signed indexes are left alone, they need the key.
+pub(crate) fn index_new_packages(dir: &Path, name: &str) -> Result<()> {
+    let index_path = dir.join(format!("{name}.{INDEX_EXTENSION}"));
+    let index = if index_path.exists() {
+        RepoIndex::read(&index_path)?
+    } else {
+        RepoIndex::default()
+    };
+
+    let mut new = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let indexed = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| index.packages.iter().any(|entry| entry.filename == name));
+
+        if path.extension().is_some_and(|ext| ext == ARCHIVE_EXTENSION) && !indexed {
+            new.push(path);
+        }
+    }
+
+    if new.is_empty() {
+        return Ok(());
+    }
+
+    if signature_path(&index_path).exists() {
+        warn!(
+            "{} archives in {} are missing from its signed index, add them with `blossom repo add --sign`",
+            new.len(),
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    new.sort();
+    info!("Indexing {} new archives in {}", new.len(), dir.display());
+
+    add_packages(dir, &index_path, &new, None)
+}
+
+/// Writes the signature of a file, or removes the now stale one if there's no
+/// key to sign with.
+fn sign_file(path: &Path, key: Option<&SecretKey>) -> Result<()> {
+    let signature_path = signature_path(path);
+
+    match key {
+        Some(key) => {
+            key.sign(&fs::read(path)?).write(&signature_path)?;
+            info!("Signed {} with key {}", path.display(), key.public().id());
+        }
+        None if signature_path.exists() => {
+            warn!(
+                "Removing the signature of {}, it no longer matches the unsigned file",
+                path.display()
+            );
+            fs::remove_file(signature_path)?;
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Re-signs the metadata of the repository in `dir` before it expires. The
+/// timestamp only stays valid for a day, so this is meant to run daily.
+pub fn repo_refresh<R: AsRef<Path>, P: AsRef<Path>>(
+    root: R,
+    dir: P,
+    sign_key: &Path,
+) -> Result<()> {
+    let dir = dir.as_ref();
+    let key = read_secret_key(root, sign_key)?;
+    let index_path = index_path(dir)?;
+    let index = RepoIndex::read(&index_path)?;
+
+    for path in write_metadata(&index_path, &index, &key)? {
+        info!("Signed {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// How many of the colliding files of a pair of packages are listed.
+const LISTED_COLLISIONS: usize = 5;
+
+/// Checks that no two packages of the repository in `dir` install the same
+/// files, unless one conflicts with or replaces the other. Prints the
+/// collisions and fails if there are any.
+pub fn repo_check<P: AsRef<Path>>(dir: P) -> Result<()> {
+    let index_path = index_path(dir.as_ref())?;
+    let index = RepoIndex::read(&index_path)?;
+    let collisions = index.collisions();
+
+    for collision in &collisions {
+        let (a, b) = &collision.packages;
+        let mut files: Vec<String> = collision
+            .files
+            .iter()
+            .take(LISTED_COLLISIONS)
+            .map(|file| format!("/{file}"))
+            .collect();
+        if collision.files.len() > LISTED_COLLISIONS {
+            files.push(format!(
+                "and {} more",
+                collision.files.len() - LISTED_COLLISIONS
+            ));
+        }
+
+        println!("{a} and {b} both install {}", files.join(", "));
+    }
+
+    if !collisions.is_empty() {
+        bail!(
+            "{} pairs of packages of {} collide, make one conflict with or replace the other",
+            collisions.len(),
+            index_path.display()
+        );
+    }
+
+    info!(
+        "The {} packages of {} don't collide",
+        index.packages.len(),
+        index_path.display()
+    );
+    Ok(())
+}
+
+/// Generates a signing key, printing the public key to add to the `keys` of
+/// repositories it signs.
+pub fn repo_keygen<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+
+    let key = SecretKey::generate()?;
+    key.write(path)?;
+
+    info!(
+        "Wrote secret key {} to {}",
+        key.public().id(),
+        path.display()
+    );
+    println!("{}", key.public());
+
+    Ok(())
+}
+
+/// The index of a repository directory is named after the directory.
+pub(crate) fn index_path(dir: &Path) -> Result<PathBuf> {
+    let name = fs::canonicalize(dir)?
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from)
+        .ok_or(anyhow!("Can't name a repository after {}", dir.display()))?;
+
+    Ok(dir.join(format!("{name}.{INDEX_EXTENSION}")))
+}
+
+pub(crate) fn index_entry(package: &Path) -> Result<IndexEntry> {
+    let peach_info = archive::read_info(package)?;
+    let mut files = Vec::new();
+
+    for entry in archive::open(package)?.entries()? {
+        let entry = entry?;
+        let path = archive::entry_path(&entry.path()?)?;
+
+        if path.as_str().is_empty()
+            || path == PeachInfo::FILE_NAME
+            || path == Sbom::FILE_NAME
+            || entry.header().entry_type() == EntryType::Directory
+        {
+            continue;
+        }
+
+        files.push(path);
+    }
+
+    let filename = package
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(anyhow!("Invalid package file name: {}", package.display()))?
+        .to_string();
+
+    Ok(IndexEntry {
+        info: peach_info.info,
+        dependencies: peach_info.dependencies,
+        filename,
+        checksum: hash_file(package)?,
+        size: fs::metadata(package)?.len(),
+        installed_size: peach_info.installed_size,
+        files,
+        special_permissions: peach_info.special_permissions,
+        changelog: peach_info.changelog,
+    })
+}
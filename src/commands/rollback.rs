@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use tracing::info;
+
+use crate::{database::Database, failure::Failure, lock::Lock, prompt, snapshot};
+
+pub fn rollback<R: AsRef<Path>>(root: R, id: Option<u64>, list: bool, wait: bool) -> Result<()> {
+    let snapshots = snapshot::list(&root)?;
+
+    if list {
+        for snapshot in &snapshots {
+            println!(
+                "#{} {} {:?} {}",
+                snapshot.id,
+                snapshot.created.format("%Y-%m-%d %H:%M:%S"),
+                snapshot.backend,
+                snapshot.packages.join(" ")
+            );
+        }
+
+        return Ok(());
+    }
+
+    let snapshot = match id {
+        Some(id) => snapshots.iter().find(|snapshot| snapshot.id == id),
+        None => snapshots.last(),
+    }
+    .ok_or(anyhow!("No matching snapshot found"))?;
+
+    if !prompt::confirm(&format!("Restore snapshot #{}?", snapshot.id), false)? {
+        return Err(Failure::Aborted.error("Rollback cancelled").into());
+    }
+
+    let _lock = Lock::acquire(&root, wait)?;
+    let database = Database::open(&root)?;
+
+    info!("Restoring snapshot #{}", snapshot.id);
+
+    snapshot::restore(&database, snapshot)?;
+
+    info!("Snapshot #{} restored successfully", snapshot.id);
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::{archive, database::Database, sbom::SbomFormat};
+
+/// Prints the bill of materials of a package archive, or of an installed
+/// package from its cached archive.
+pub fn sbom<R: AsRef<Path>>(root: R, package: &str, format: SbomFormat) -> Result<()> {
+    let path = Path::new(package);
+
+    let tarball_path = if path.is_file() {
+        path.to_path_buf()
+    } else {
+        let database = Database::open(&root)?;
+        let installed = database
+            .get(package)?
+            .ok_or(anyhow!("Package \"{package}\" is not installed"))?;
+        let tarball_path = database.cache_path(&installed.info.name, &installed.info.version);
+
+        if !tarball_path.exists() {
+            bail!(
+                "No cached archive for \"{package}\" at {}",
+                tarball_path.display()
+            );
+        }
+
+        tarball_path
+    };
+
+    let sbom = archive::read_sbom(&tarball_path)?.ok_or(anyhow!(
+        "{} has no bill of materials, it was built before they were recorded",
+        tarball_path.display()
+    ))?;
+
+    println!("{}", serde_json::to_string_pretty(&sbom.render(format))?);
+
+    Ok(())
+}
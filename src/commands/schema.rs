@@ -0,0 +1,12 @@
+use anyhow::Result;
+use schemars::schema_for;
+
+use crate::package::Package;
+
+/// Prints the JSON Schema of recipes, for editors and CI to validate
+/// `package.toml` files with.
+pub fn schema() -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&schema_for!(Package))?);
+
+    Ok(())
+}
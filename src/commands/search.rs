@@ -0,0 +1,289 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+use crate::{
+    database::Database,
+    package::{Info, dependency_name, user_locale},
+    repository, version,
+};
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub repository: String,
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub provides: Vec<String>,
+    /// The installed version, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed: Option<String>,
+    #[serde(skip)]
+    score: u32,
+}
+
+pub fn search<R: AsRef<Path>>(
+    root: R,
+    terms: &[String],
+    tags: &[String],
+    json: bool,
+) -> Result<()> {
+    let results = search_packages(root, terms, tags)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    for result in &results {
+        let installed = match &result.installed {
+            Some(version) if *version == result.version => " [installed]".to_string(),
+            Some(version) => format!(" [installed: {version}]"),
+            None => String::new(),
+        };
+
+        println!(
+            "{}/{} {}{installed}",
+            result.repository, result.name, result.version
+        );
+        println!("    {}", result.description);
+    }
+
+    Ok(())
+}
+
+/// Finds the packages of synced repositories matching every term in their
+/// name, description, provides or keywords, case insensitively, and having
+/// every keyword of `tags`. The best matches come first: exact names, then
+/// name prefixes, names containing the term, keywords, provides and finally
+/// descriptions, in any language. Descriptions are shown in the locale of
+/// the user.
+pub fn search_packages<R: AsRef<Path>>(
+    root: R,
+    terms: &[String],
+    tags: &[String],
+) -> Result<Vec<SearchResult>> {
+    if terms.is_empty() && tags.is_empty() {
+        bail!("No search terms or tags given");
+    }
+
+    let terms: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+
+    let installed: HashMap<String, String> = Database::open(&root)?
+        .list()?
+        .into_iter()
+        .map(|package| (package.info.name, package.info.version))
+        .collect();
+
+    let repositories = repository::load_synced_infos(&root)?;
+
+    Ok(find(
+        &repositories,
+        &terms,
+        tags,
+        &installed,
+        &user_locale(),
+    ))
+}
+
+/// The packages of `repositories` matching lowercase `terms` and having
+/// `tags`, best matches first, see [`search_packages`].
+fn find(
+    repositories: &[(String, Vec<Info>)],
+    terms: &[String],
+    tags: &[String],
+    installed: &HashMap<String, String>,
+    locale: &str,
+) -> Vec<SearchResult> {
+    let mut results = Vec::new();
+
+    for (repository, infos) in repositories {
+        // Only the newest version of each package is matched.
+        let mut newest: BTreeMap<&str, &Info> = BTreeMap::new();
+        for info in infos {
+            match newest.get(info.name.as_str()) {
+                Some(other) if version::compare(&other.version, &info.version).is_ge() => {}
+                _ => {
+                    newest.insert(&info.name, info);
+                }
+            }
+        }
+
+        for (name, info) in newest {
+            if !has_tags(info, tags) {
+                continue;
+            }
+            let Some(score) = score(info, terms) else {
+                continue;
+            };
+
+            results.push(SearchResult {
+                repository: repository.clone(),
+                name: info.name.clone(),
+                version: info.version.clone(),
+                description: info.description.get(locale).to_string(),
+                provides: info.provides.clone(),
+                installed: installed.get(name).cloned(),
+                score,
+            });
+        }
+    }
+
+    // Repositories are ordered by preference, the stable sort keeps it among
+    // equal scores.
+    results.sort_by_key(|result| Reverse(result.score));
+
+    results
+}
+
+/// Sums how well each term matches, or `None` if any term doesn't match.
+fn score(info: &Info, terms: &[String]) -> Option<u32> {
+    let name = info.name.to_lowercase();
+    let descriptions: Vec<String> = info
+        .description
+        .translations()
+        .map(|(_, description)| description.to_lowercase())
+        .collect();
+
+    terms
+        .iter()
+        .map(|term| {
+            if name == *term {
+                Some(100)
+            } else if name.starts_with(term.as_str()) {
+                Some(50)
+            } else if name.contains(term.as_str()) {
+                Some(30)
+            } else if info
+                .keywords
+                .iter()
+                .any(|keyword| keyword.to_lowercase() == *term)
+            {
+                Some(25)
+            } else if info.provides.iter().any(|provided| {
+                dependency_name(provided)
+                    .to_lowercase()
+                    .contains(term.as_str())
+            }) {
+                Some(20)
+            } else if descriptions
+                .iter()
+                .any(|description| description.contains(term.as_str()))
+            {
+                Some(10)
+            } else {
+                None
+            }
+        })
+        .sum()
+}
+
+/// Whether the package has every keyword of `tags`, case insensitively.
+fn has_tags(info: &Info, tags: &[String]) -> bool {
+    tags.iter().all(|tag| {
+        info.keywords
+            .iter()
+            .any(|keyword| keyword.eq_ignore_ascii_case(tag))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        let mut info: Info = toml_edit::de::from_str(
+            r#"
+            name = "openjdk17"
+            version = "17.0.2"
+            description = "Open source Java runtime"
+            license = "GPL-2.0-only"
+            "#,
+        )
+        .unwrap();
+        info.provides = vec!["java-runtime=17".to_string()];
+        info.keywords = vec!["Java".to_string(), "jvm".to_string()];
+
+        let terms =
+            |terms: &[&str]| -> Vec<String> { terms.iter().map(|term| term.to_string()).collect() };
+
+        assert_eq!(score(&info, &terms(&["openjdk17"])), Some(100));
+        assert_eq!(score(&info, &terms(&["openjdk"])), Some(50));
+        assert_eq!(score(&info, &terms(&["jdk"])), Some(30));
+        assert_eq!(score(&info, &terms(&["java-runtime"])), Some(20));
+        assert_eq!(score(&info, &terms(&["jvm"])), Some(25));
+        assert_eq!(score(&info, &terms(&["jdk", "source"])), Some(40));
+        assert_eq!(score(&info, &terms(&["jdk", "python"])), None);
+        assert_eq!(score(&info, &terms(&[])), Some(0));
+
+        assert!(has_tags(&info, &terms(&["java"])));
+        assert!(has_tags(&info, &terms(&["jvm", "JAVA"])));
+        assert!(!has_tags(&info, &terms(&["java", "editor"])));
+
+        info.description = toml_edit::de::from_str::<Info>(
+            r#"
+            name = "openjdk17"
+            version = "17.0.2"
+            description.en = "Open source Java runtime"
+            description.de = "Quelloffene Java-Laufzeitumgebung"
+            license = "GPL-2.0-only"
+            "#,
+        )
+        .unwrap()
+        .description;
+
+        assert_eq!(score(&info, &terms(&["source"])), Some(10));
+        assert_eq!(score(&info, &terms(&["quelloffene"])), Some(10));
+    }
+
+    #[test]
+    fn test_find_tags() {
+        let info = |name: &str, description: &str, keywords: &[&str]| {
+            let mut info: Info = toml_edit::de::from_str(&format!(
+                r#"
+                name = "{name}"
+                version = "1.0"
+                description = "{description}"
+                license = "MIT"
+                "#
+            ))
+            .unwrap();
+            info.keywords = keywords.iter().map(|keyword| keyword.to_string()).collect();
+            info
+        };
+        let repositories = vec![(
+            "core".to_string(),
+            vec![
+                info(
+                    "neovim",
+                    "Vim-fork focused on extensibility",
+                    &["Editor", "vim"],
+                ),
+                info("vimpager", "Use vim as a pager", &["pager"]),
+                info("ed", "The standard text editor", &[]),
+            ],
+        )];
+        let names = |terms: &[&str], tags: &[&str]| -> Vec<String> {
+            let terms: Vec<String> = terms.iter().map(|term| term.to_string()).collect();
+            let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+
+            find(&repositories, &terms, &tags, &HashMap::new(), "en")
+                .into_iter()
+                .map(|result| result.name)
+                .collect()
+        };
+
+        // Tags only match keywords, not the text describing the package.
+        assert_eq!(names(&[], &["editor"]), ["neovim"]);
+        assert_eq!(names(&["vim"], &[]), ["vimpager", "neovim"]);
+        assert_eq!(names(&["vim"], &["editor"]), ["neovim"]);
+        assert!(names(&["pager"], &["editor"]).is_empty());
+    }
+}
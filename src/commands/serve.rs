@@ -0,0 +1,327 @@
+use std::{
+    io::{self, SeekFrom},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use http_body_util::{BodyExt, Empty, StreamBody, combinators::BoxBody};
+use hyper::{
+    Method, Request, Response, StatusCode,
+    body::{Frame, Incoming},
+    header::{self, HeaderMap, HeaderValue},
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+    net::TcpListener,
+};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+
+type Body = BoxBody<Bytes, io::Error>;
+
+/// Serves the files of a repository directory over HTTP, with conditional
+/// and range requests, until interrupted. Only files directly inside `dir`
+/// are served, there are no directory listings.
+pub async fn serve<P: AsRef<Path>>(dir: P, listen: SocketAddr) -> Result<()> {
+    let dir = Arc::new(dir.as_ref().canonicalize()?);
+    let listener = TcpListener::bind(listen).await?;
+
+    info!("Serving {} on http://{listen}", dir.display());
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let dir = Arc::clone(&dir);
+
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let dir = Arc::clone(&dir);
+                async move { Ok::<_, io::Error>(respond(&dir, peer, request).await) }
+            });
+
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                warn!("Connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+async fn respond(dir: &Path, peer: SocketAddr, request: Request<Incoming>) -> Response<Body> {
+    let response = match handle(dir, &request).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Failed to serve {}: {e}", request.uri().path());
+            status(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    };
+
+    info!(
+        "{peer} {} {} {}",
+        request.method(),
+        request.uri().path(),
+        response.status().as_u16()
+    );
+
+    response
+}
+
+async fn handle(dir: &Path, request: &Request<Incoming>) -> Result<Response<Body>> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        let mut response = status(StatusCode::METHOD_NOT_ALLOWED);
+        response
+            .headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+        return Ok(response);
+    }
+
+    let Some(path) = resolve(dir, request.uri().path()) else {
+        return Ok(status(StatusCode::NOT_FOUND));
+    };
+
+    let Ok(mut file) = File::open(&path).await else {
+        return Ok(status(StatusCode::NOT_FOUND));
+    };
+
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Ok(status(StatusCode::NOT_FOUND));
+    }
+
+    let size = metadata.len();
+    let modified = metadata.modified()?;
+    let etag = etag(size, modified);
+
+    let mut response = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &etag)
+        .header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+
+    if not_modified(request.headers(), &etag, modified) {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).body(empty())?);
+    }
+
+    let (start, length) = match range(request.headers(), size) {
+        Range::Full => (0, size),
+        Range::Partial { start, end } => {
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}"));
+            (start, end - start + 1)
+        }
+        Range::Unsatisfiable => {
+            return Ok(response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+                .body(empty())?);
+        }
+    };
+
+    let response = response
+        .header(header::CONTENT_LENGTH, length)
+        .header(header::CONTENT_TYPE, "application/octet-stream");
+
+    if request.method() == Method::HEAD {
+        return Ok(response.body(empty())?);
+    }
+
+    file.seek(SeekFrom::Start(start)).await?;
+    let stream = ReaderStream::new(file.take(length)).map_ok(Frame::data);
+
+    Ok(response.body(BodyExt::boxed(StreamBody::new(stream)))?)
+}
+
+/// Maps a request path to a file directly inside `dir`, rejecting anything
+/// that could escape it.
+fn resolve(dir: &Path, path: &str) -> Option<PathBuf> {
+    let name = percent_decode(path.strip_prefix('/')?)?;
+
+    if name.is_empty() || name.contains(['/', '\\', '\0']) || name.starts_with('.') {
+        return None;
+    }
+
+    Some(dir.join(name))
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            decoded.push(u8::from_str_radix(value.get(i + 1..i + 3)?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+fn etag(size: u64, modified: SystemTime) -> String {
+    let modified = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    format!("\"{size:x}-{:x}\"", modified.as_nanos())
+}
+
+/// Whether the client's copy is current. `If-None-Match` takes precedence
+/// over `If-Modified-Since`.
+fn not_modified(headers: &HeaderMap, etag: &str, modified: SystemTime) -> bool {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if let Some(tags) = header(header::IF_NONE_MATCH) {
+        return tags.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+
+    header(header::IF_MODIFIED_SINCE)
+        .and_then(|since| httpdate::parse_http_date(since).ok())
+        .is_some_and(|since| {
+            // HTTP dates have a resolution of a second.
+            modified
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .ok()
+                .zip(since.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .is_some_and(|(modified, since)| modified.as_secs() <= since.as_secs())
+        })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Range {
+    Full,
+    /// An inclusive byte range.
+    Partial {
+        start: u64,
+        end: u64,
+    },
+    Unsatisfiable,
+}
+
+/// Parses a single `Range: bytes=...` header. Multiple ranges aren't
+/// supported and get the full file, which the specification allows.
+fn range(headers: &HeaderMap, size: u64) -> Range {
+    let Some(ranges) = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().strip_prefix("bytes="))
+    else {
+        return Range::Full;
+    };
+
+    if ranges.contains(',') {
+        return Range::Full;
+    }
+
+    let Some((start, end)) = ranges.split_once('-') else {
+        return Range::Full;
+    };
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return Range::Full,
+        // The last `suffix` bytes.
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return Range::Unsatisfiable,
+            Ok(suffix) => (size.saturating_sub(suffix), size.saturating_sub(1)),
+            Err(_) => return Range::Full,
+        },
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return Range::Full;
+            };
+            let end = match end {
+                "" => size.saturating_sub(1),
+                end => match end.parse::<u64>() {
+                    Ok(end) => end.min(size.saturating_sub(1)),
+                    Err(_) => return Range::Full,
+                },
+            };
+            (start, end)
+        }
+    };
+
+    if size == 0 || start >= size || start > end {
+        return Range::Unsatisfiable;
+    }
+
+    Range::Partial { start, end }
+}
+
+fn empty() -> Body {
+    Empty::new().map_err(|never| match never {}).boxed()
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(empty());
+    *response.status_mut() = status;
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range_of(value: &str, size: u64) -> Range {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_str(value).unwrap());
+
+        range(&headers, size)
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(range(&HeaderMap::new(), 100), Range::Full);
+        assert_eq!(
+            range_of("bytes=0-9", 100),
+            Range::Partial { start: 0, end: 9 }
+        );
+        assert_eq!(
+            range_of("bytes=90-", 100),
+            Range::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            range_of("bytes=-10", 100),
+            Range::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(
+            range_of("bytes=50-500", 100),
+            Range::Partial { start: 50, end: 99 }
+        );
+        assert_eq!(range_of("bytes=100-", 100), Range::Unsatisfiable);
+        assert_eq!(range_of("bytes=0-1,5-6", 100), Range::Full);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = Path::new("/srv/core");
+
+        assert_eq!(
+            resolve(dir, "/core.index"),
+            Some(PathBuf::from("/srv/core/core.index"))
+        );
+        assert_eq!(
+            resolve(dir, "/libfoo%2B-1.0.peach"),
+            Some(PathBuf::from("/srv/core/libfoo+-1.0.peach"))
+        );
+        assert_eq!(resolve(dir, "/../etc/passwd"), None);
+        assert_eq!(resolve(dir, "/%2e%2e%2fetc%2fpasswd"), None);
+        assert_eq!(resolve(dir, "/"), None);
+    }
+}
@@ -0,0 +1,302 @@
+use std::{cmp::Ordering, fmt::Display};
+
+use anyhow::Result;
+
+use crate::{
+    commands::info::{format_size, format_size_delta},
+    config::Config,
+    database::Database,
+    resolver::Plan,
+    version,
+};
+
+const BOLD: &str = "1";
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+
+/// What a transaction does to a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Install,
+    Upgrade,
+    Downgrade,
+    Reinstall,
+    Remove,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Install => write!(f, "install"),
+            Change::Upgrade => write!(f, "upgrade"),
+            Change::Downgrade => write!(f, "downgrade"),
+            Change::Reinstall => write!(f, "reinstall"),
+            Change::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+impl Change {
+    fn color(&self) -> Option<&'static str> {
+        match self {
+            Change::Install => Some(GREEN),
+            Change::Upgrade | Change::Downgrade => Some(YELLOW),
+            Change::Reinstall => None,
+            Change::Remove => Some(RED),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Row {
+    change: Change,
+    name: String,
+    old: Option<String>,
+    new: Option<String>,
+    old_size: u64,
+    new_size: u64,
+    /// The archive size, unless it is cached already.
+    download: Option<u64>,
+}
+
+/// A cell of the table: its text, and the same text with colors.
+struct Cell {
+    text: String,
+    styled: String,
+}
+
+impl Cell {
+    fn new(text: String, color: Option<&str>, colors: bool) -> Self {
+        Self {
+            styled: paint(&text, color, colors),
+            text,
+        }
+    }
+}
+
+fn paint(text: &str, color: Option<&str>, colors: bool) -> String {
+    match color {
+        Some(color) if colors && !text.is_empty() => format!("\x1b[{color}m{text}\x1b[0m"),
+        _ => text.to_string(),
+    }
+}
+
+/// Prints what a transaction changes, package by package, along with how
+/// much it downloads and how much disk space it takes or frees.
+pub(crate) fn print_summary(database: &Database, plan: &Plan) -> Result<()> {
+    let colors = Config::load(database.root())?.colors.enabled();
+    let width = terminal_size::terminal_size().map(|(width, _)| width.0 as usize);
+
+    for line in render(&rows(database, plan)?, colors, width) {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+fn rows(database: &Database, plan: &Plan) -> Result<Vec<Row>> {
+    let mut rows = Vec::new();
+
+    for removal in &plan.remove {
+        let installed = database.get(&removal.name)?;
+
+        rows.push(Row {
+            change: Change::Remove,
+            name: removal.name.clone(),
+            old: Some(removal.version.clone()),
+            new: None,
+            old_size: installed.map_or(0, |installed| installed.installed_size),
+            new_size: 0,
+            download: None,
+        });
+    }
+
+    for planned in &plan.install {
+        let info = &planned.entry.info;
+        let installed = database.get(&info.name)?;
+
+        let change = match &installed {
+            None => Change::Install,
+            Some(installed) => match version::compare(&info.version, &installed.info.version) {
+                Ordering::Greater => Change::Upgrade,
+                Ordering::Less => Change::Downgrade,
+                Ordering::Equal => Change::Reinstall,
+            },
+        };
+
+        rows.push(Row {
+            change,
+            name: info.name.clone(),
+            old: installed
+                .as_ref()
+                .map(|installed| installed.info.version.clone()),
+            new: Some(info.version.clone()),
+            old_size: installed.map_or(0, |installed| installed.installed_size),
+            new_size: planned.entry.installed_size,
+            download: (!database.cache_path(&info.name, &info.version).exists())
+                .then_some(planned.entry.size),
+        });
+    }
+
+    Ok(rows)
+}
+
+/// Lays the rows out as a table, followed by the totals. Columns that don't
+/// fit in `width` are left out, the totals still account for them.
+fn render(rows: &[Row], colors: bool, width: Option<usize>) -> Vec<String> {
+    let header = ["Action", "Package", "Version", "Size", "Download"];
+
+    let table: Vec<Vec<Cell>> = rows
+        .iter()
+        .map(|row| {
+            let version = match (&row.old, &row.new) {
+                (Some(old), Some(new)) if old != new => Cell {
+                    text: format!("{old} → {new}"),
+                    styled: format!(
+                        "{} → {}",
+                        paint(old, Some(RED), colors),
+                        paint(new, Some(GREEN), colors)
+                    ),
+                },
+                (_, Some(version)) | (Some(version), None) => {
+                    Cell::new(version.clone(), None, colors)
+                }
+                (None, None) => Cell::new(String::new(), None, colors),
+            };
+
+            vec![
+                Cell::new(row.change.to_string(), row.change.color(), colors),
+                Cell::new(row.name.clone(), None, colors),
+                version,
+                Cell::new(format_size_delta(row.old_size, row.new_size), None, colors),
+                Cell::new(
+                    row.download.map(format_size).unwrap_or_default(),
+                    None,
+                    colors,
+                ),
+            ]
+        })
+        .collect();
+
+    let mut widths: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .map(|(i, title)| {
+            table
+                .iter()
+                .map(|cells| cells[i].text.chars().count())
+                .chain([title.len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    // Rows are indented by two spaces and columns separated by two more.
+    let fits = |widths: &[usize]| {
+        width.is_none_or(|width| widths.iter().sum::<usize>() + 2 * widths.len() <= width)
+    };
+
+    // The download and size columns go first, the totals cover them.
+    while widths.len() > 3 && !fits(&widths) {
+        widths.pop();
+    }
+
+    let line = |cells: Vec<(String, usize)>| {
+        let mut line = String::from(" ");
+
+        for (styled, padding) in cells {
+            line.push_str(&format!(" {styled}{:padding$} ", ""));
+        }
+
+        line.trim_end().to_string()
+    };
+
+    let mut lines = vec![line(
+        header
+            .iter()
+            .zip(&widths)
+            .map(|(title, width)| (paint(title, Some(BOLD), colors), width - title.len()))
+            .collect(),
+    )];
+
+    for cells in table {
+        lines.push(line(
+            cells
+                .into_iter()
+                .zip(&widths)
+                .map(|(cell, width)| (cell.styled, width - cell.text.chars().count()))
+                .collect(),
+        ));
+    }
+
+    let download: u64 = rows.iter().filter_map(|row| row.download).sum();
+    let old: u64 = rows.iter().map(|row| row.old_size).sum();
+    let new: u64 = rows.iter().map(|row| row.new_size).sum();
+
+    lines.push(String::new());
+    lines.push(format!("Download size:    {}", format_size(download)));
+    lines.push(format!("Net size change:  {}", format_size_delta(old, new)));
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<Row> {
+        vec![
+            Row {
+                change: Change::Remove,
+                name: "old".to_string(),
+                old: Some("2.0".to_string()),
+                new: None,
+                old_size: 2048,
+                new_size: 0,
+                download: None,
+            },
+            Row {
+                change: Change::Upgrade,
+                name: "libc".to_string(),
+                old: Some("1.0".to_string()),
+                new: Some("1.1".to_string()),
+                old_size: 1024,
+                new_size: 4096,
+                download: Some(1024),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(&rows(), false, None),
+            [
+                "  Action   Package  Version    Size       Download",
+                "  remove   old      2.0        -2.00 KiB",
+                "  upgrade  libc     1.0 → 1.1  +3.00 KiB  1.00 KiB",
+                "",
+                "Download size:    1.00 KiB",
+                "Net size change:  +1.00 KiB",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_narrow() {
+        let lines = render(&rows(), false, Some(40));
+
+        assert_eq!(lines[0], "  Action   Package  Version    Size");
+        assert_eq!(lines[2], "  upgrade  libc     1.0 → 1.1  +3.00 KiB");
+        assert_eq!(lines[4], "Download size:    1.00 KiB");
+    }
+
+    #[test]
+    fn test_render_colors() {
+        let lines = render(&rows(), true, None);
+
+        assert!(lines[2].contains("\x1b[33mupgrade\x1b[0m"));
+        assert!(lines[2].contains("\x1b[31m1.0\x1b[0m → \x1b[32m1.1\x1b[0m"));
+    }
+}
@@ -0,0 +1,484 @@
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow, bail};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    commands::repo::index_new_packages,
+    config::{CONFIG_FILE, Config, RepositoryConfig, SignaturePolicy},
+    download::{self, Validators, download, download_if_modified, join_url, local_path},
+    failure::Failure,
+    keyring::Keyring,
+    lock::Lock,
+    lookup::Lookup,
+    metadata::{self, Verified},
+    mirrors::Ranking,
+    repository::{self, INDEX_EXTENSION, IndexDelta, IndexHead, RepoIndex, delta_file, head_file},
+    signing::{PublicKey, Signature, signature_path},
+};
+
+const VALIDATORS_FILE: &str = "validators.toml";
+
+/// Cache validators of the files last synced for a repository, only valid for
+/// requests to the URL they were synced from with the same trusted keys.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Synced {
+    url: String,
+    /// Identifiers of the keys the files were verified against.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    index: Option<Validators>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    head: Option<Validators>,
+}
+
+/// What [`fetch_verified`] downloaded, if the file changed at all.
+struct Fetched {
+    signature: Option<PathBuf>,
+    validators: Validators,
+}
+
+/// Downloads the index of every configured repository, verifying its
+/// signature against the repository's keys and those the keyring trusts for
+/// it. A repository failing to
+/// sync keeps its previous index and doesn't prevent the others from syncing.
+pub async fn sync<R: AsRef<Path>>(root: R, wait: bool) -> Result<()> {
+    let root = root.as_ref();
+    let _lock = Lock::acquire_async(root, wait).await?;
+    let mut config = Config::load(root)?;
+    Keyring::load(root)?.apply(&mut config);
+
+    if config.signatures == SignaturePolicy::Never {
+        for repository in &mut config.repositories {
+            repository.keys.clear();
+        }
+    }
+
+    if config.repositories.is_empty() {
+        info!("No repositories configured in {CONFIG_FILE}");
+        return Ok(());
+    }
+
+    let dir = repository::sync_dir(root);
+    fs::create_dir_all(&dir)?;
+
+    let client = Client::new();
+    let ranking = Ranking::load(root)?;
+    let validators_path = dir.join(VALIDATORS_FILE);
+    let mut synced: BTreeMap<String, Synced> = if validators_path.exists() {
+        toml_edit::de::from_str(&fs::read_to_string(&validators_path)?)?
+    } else {
+        BTreeMap::new()
+    };
+    let mut failed = Vec::new();
+
+    for repository in &config.repositories {
+        let urls: Vec<String> = ranking
+            .urls(repository)
+            .into_iter()
+            .filter(|url| !config.offline || !download::is_remote(url))
+            .collect();
+
+        if urls.is_empty() {
+            warn!("Not syncing {} while offline", repository.name);
+            continue;
+        }
+
+        let result = sync_repository(
+            &client,
+            repository,
+            &urls,
+            &dir,
+            config.signatures,
+            &mut synced,
+        )
+        .await;
+
+        if let Err(e) = result {
+            warn!("Failed to sync {}: {e:?}", repository.name);
+            failed.push(repository.name.as_str());
+        }
+    }
+
+    fs::write(validators_path, toml_edit::ser::to_string(&synced)?)?;
+
+    if !failed.is_empty() {
+        bail!(
+            "Failed to sync {} of {} repositories: {}",
+            failed.len(),
+            config.repositories.len(),
+            failed.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Syncs from the first of `urls` that works.
+async fn sync_repository(
+    client: &Client,
+    repository: &RepositoryConfig,
+    urls: &[String],
+    dir: &Path,
+    policy: SignaturePolicy,
+    synced: &mut BTreeMap<String, Synced>,
+) -> Result<()> {
+    if policy == SignaturePolicy::Required && repository.keys.is_empty() {
+        bail!(
+            "{} has no trusted keys, but signatures are required by {CONFIG_FILE}. \
+             Import the key its index is signed with using `blossom key import` and trust it with \
+             `blossom key trust <name> {}`",
+            repository.name,
+            repository.name
+        );
+    }
+
+    let mut errors = Vec::new();
+
+    // A failed sync may leave files the validators don't describe anymore.
+    let previous = synced.remove(&repository.name);
+    // An unchanged index still has to be verified against newly trusted keys.
+    let keys: Vec<String> = repository.keys.iter().map(PublicKey::id).collect();
+
+    for url in urls {
+        let previous = previous
+            .as_ref()
+            .filter(|previous| previous.url == *url && previous.keys == keys);
+
+        match sync_from(client, repository, url, dir, policy, previous).await {
+            Ok(validators) => {
+                synced.insert(
+                    repository.name.clone(),
+                    Synced {
+                        keys: keys.clone(),
+                        ..validators
+                    },
+                );
+                return Ok(());
+            }
+            Err(e) if urls.len() > 1 => {
+                warn!("Failed to sync {} from {url}: {e}", repository.name);
+                errors.push(format!("{url}: {e}"));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    bail!("Every mirror failed:\n  {}", errors.join("\n  "))
+}
+
+async fn sync_from(
+    client: &Client,
+    repository: &RepositoryConfig,
+    url: &str,
+    dir: &Path,
+    policy: SignaturePolicy,
+    previous: Option<&Synced>,
+) -> Result<Synced> {
+    if url.starts_with("http://") {
+        warn!(
+            "Repository {} is synced over plain HTTP from {url}, prefer HTTPS",
+            repository.name
+        );
+    }
+
+    // Local repositories are reindexed as archives get dropped into them.
+    let local = local_path(url)?;
+    if let Some(path) = &local {
+        index_new_packages(path, &repository.name)?;
+    }
+
+    // Nobody sits between a local repository and its users.
+    if repository.keys.is_empty() && local.is_none() && policy == SignaturePolicy::Optional {
+        warn!(
+            "No keys configured for {}, its index can't be verified",
+            repository.name
+        );
+    }
+
+    let file = format!("{}.{INDEX_EXTENSION}", repository.name);
+
+    // Without keys there's nothing to verify the metadata against.
+    let metadata = if repository.keys.is_empty() {
+        None
+    } else {
+        metadata::fetch(client, repository, url, dir).await?
+    };
+    let metadata = metadata.as_ref();
+
+    let head = previous.and_then(|previous| previous.head.as_ref());
+
+    match sync_incrementally(client, repository, url, dir, &file, head, metadata).await {
+        Ok(Some(head)) => {
+            if let Some(metadata) = metadata {
+                metadata.save(dir, &repository.name)?;
+            }
+
+            return Ok(Synced {
+                url: url.to_string(),
+                keys: Vec::new(),
+                index: None,
+                head: Some(head),
+            });
+        }
+        Ok(None) => {}
+        Err(e) => warn!(
+            "Incremental sync of {} failed, downloading the full index: {e}",
+            repository.name
+        ),
+    }
+
+    let target = dir.join(&file);
+    let tmp = dir.join(format!("{file}.new"));
+
+    let validators = previous
+        .and_then(|previous| previous.index.as_ref())
+        .filter(|_| target.exists());
+
+    let Some(fetched) = fetch_verified(client, repository, url, &file, &tmp, validators).await?
+    else {
+        if let Some(metadata) = metadata {
+            metadata.check(&RepoIndex::read(&target)?)?;
+            metadata.save(dir, &repository.name)?;
+        }
+
+        info!("{} is unchanged", repository.name);
+
+        return Ok(Synced {
+            url: url.to_string(),
+            keys: Vec::new(),
+            index: validators.cloned(),
+            head: None,
+        });
+    };
+
+    // Only replace the previous index once the new one is known to be valid.
+    let index = match RepoIndex::read(&tmp) {
+        Ok(index) => index,
+        Err(e) => {
+            remove_with_signature(&tmp)?;
+            bail!("Invalid index: {e}");
+        }
+    };
+
+    if let Some(Err(e)) = metadata.map(|metadata| metadata.check(&index)) {
+        remove_with_signature(&tmp)?;
+        return Err(e.into());
+    }
+
+    fs::rename(&tmp, &target)?;
+    Lookup::write(&index, &target)?;
+
+    if let Some(metadata) = metadata {
+        metadata.save(dir, &repository.name)?;
+    }
+
+    // Keep the signature next to the index it belongs to, or drop a stale one.
+    match fetched.signature {
+        Some(signature) => fs::rename(signature, signature_path(&target))?,
+        None if signature_path(&target).exists() => fs::remove_file(signature_path(&target))?,
+        None => {}
+    }
+
+    info!(
+        "Synced {}, {} packages available",
+        repository.name,
+        index.packages.len()
+    );
+
+    Ok(Synced {
+        url: url.to_string(),
+        keys: Vec::new(),
+        index: Some(fetched.validators),
+        head: None,
+    })
+}
+
+/// Brings the synced index up to date by applying the deltas published since
+/// its generation, returning the validators of the head. Returns `None` when
+/// the full index has to be downloaded: nothing synced yet, no deltas
+/// published, or the needed ones pruned.
+async fn sync_incrementally(
+    client: &Client,
+    repository: &RepositoryConfig,
+    url: &str,
+    dir: &Path,
+    file: &str,
+    validators: Option<&Validators>,
+    metadata: Option<&Verified>,
+) -> Result<Option<Validators>> {
+    let target = dir.join(file);
+
+    let Ok(mut index) = RepoIndex::read(&target) else {
+        return Ok(None);
+    };
+
+    if index.generation == 0 {
+        return Ok(None);
+    }
+
+    let head_path = dir.join(format!("{}.new", head_file(file)));
+
+    // Servers without deltas only publish the full index.
+    let Ok(fetched) = fetch_verified(
+        client,
+        repository,
+        url,
+        &head_file(file),
+        &head_path,
+        validators,
+    )
+    .await
+    else {
+        return Ok(None);
+    };
+
+    // An unchanged head means nothing was published since the last sync.
+    let Some(fetched) = fetched else {
+        if let Some(metadata) = metadata {
+            metadata.check(&index)?;
+        }
+
+        info!(
+            "{} is unchanged at generation {}",
+            repository.name, index.generation
+        );
+        return Ok(validators.cloned());
+    };
+
+    let head = IndexHead::read(&head_path);
+    remove_with_signature(&head_path)?;
+    let head = head?;
+
+    match head.generation.cmp(&index.generation) {
+        Ordering::Equal => {
+            if let Some(metadata) = metadata {
+                metadata.check(&index)?;
+            }
+
+            info!(
+                "{} is up to date at generation {}",
+                repository.name, index.generation
+            );
+            return Ok(Some(fetched.validators));
+        }
+        Ordering::Less => {
+            warn!(
+                "{url} serves generation {} of {}, older than the synced {}",
+                head.generation, repository.name, index.generation
+            );
+            return Ok(None);
+        }
+        Ordering::Greater if head.oldest_delta > index.generation + 1 => return Ok(None),
+        Ordering::Greater => {}
+    }
+
+    for generation in index.generation + 1..=head.generation {
+        let delta_file = delta_file(file, generation);
+        let path = dir.join(&delta_file);
+
+        fetch_verified(client, repository, url, &delta_file, &path, None).await?;
+
+        let delta = IndexDelta::read(&path);
+        remove_with_signature(&path)?;
+        index.apply(delta?)?;
+    }
+
+    if let Some(metadata) = metadata {
+        metadata.check(&index)?;
+    }
+
+    index.write(&target)?;
+    Lookup::write(&index, &target)?;
+
+    // The signature covered the full index this one was derived from.
+    if signature_path(&target).exists() {
+        fs::remove_file(signature_path(&target))?;
+    }
+
+    info!(
+        "Synced {} incrementally to generation {}, {} packages available",
+        repository.name,
+        index.generation,
+        index.packages.len()
+    );
+
+    Ok(Some(fetched.validators))
+}
+
+/// Downloads `file` to `target` along with its signature if the repository
+/// has keys, and verifies it. Returns `None` if `validators` show the file is
+/// unchanged, nothing is left behind if verification fails.
+async fn fetch_verified(
+    client: &Client,
+    repository: &RepositoryConfig,
+    url: &str,
+    file: &str,
+    target: &Path,
+    validators: Option<&Validators>,
+) -> Result<Option<Fetched>> {
+    let Some(validators) =
+        download_if_modified(client, &join_url(url, file), target, validators).await?
+    else {
+        return Ok(None);
+    };
+
+    if repository.keys.is_empty() {
+        return Ok(Some(Fetched {
+            signature: None,
+            validators,
+        }));
+    }
+
+    let signature = signature_path(target);
+    let signature_file = format!("{file}.sig");
+
+    let result = match download(client, &join_url(url, &signature_file), &signature).await {
+        Ok(()) => verify(target, &signature, repository),
+        Err(e) => Err(anyhow!("Failed to download {signature_file}: {e}")),
+    };
+
+    if let Err(e) = result {
+        remove_with_signature(target)?;
+        return Err(e);
+    }
+
+    Ok(Some(Fetched {
+        signature: Some(signature),
+        validators,
+    }))
+}
+
+fn remove_with_signature(path: &Path) -> Result<()> {
+    for path in [signature_path(path), path.to_path_buf()] {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn verify(index: &Path, signature: &Path, repository: &RepositoryConfig) -> Result<()> {
+    let key = Signature::read(signature)
+        .map_err(|e| Failure::Signature.error(format!("Invalid index signature: {e}")))?
+        .verify(&fs::read(index)?, &repository.keys)
+        .map_err(|e| {
+            Failure::Signature.error(format!(
+                "Index of {} failed verification: {e}",
+                repository.name
+            ))
+        })?;
+
+    info!("Index of {} is signed by key {}", repository.name, key.id());
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::{
+    fs,
+    path::{self, Path},
+};
+
+use anyhow::{Result, bail};
+use tracing::info;
+
+use crate::{
+    build::{BuildContext, check_package},
+    config::Config,
+    database::Database,
+    package::{Package, dependency_name},
+    paths::Paths,
+};
+
+/// Runs the `check` step of the recipe at `path`, or the `package.toml` in
+/// it, reusing its build directory when it was built before. Fails if the
+/// check dependencies of the recipe aren't installed in `root`.
+pub async fn test<R: AsRef<Path>, P: AsRef<Path>>(root: R, path: P) -> Result<()> {
+    let root = root.as_ref();
+    let config = Config::load(root)?;
+    let path = match path.as_ref().is_dir() {
+        true => path.as_ref().join("package.toml"),
+        false => path.as_ref().to_path_buf(),
+    };
+    let package_path = path::absolute(&path)?;
+    let Some(dir) = package_path.parent() else {
+        bail!("{} isn't a recipe", path.display());
+    };
+
+    if !package_path.exists() {
+        bail!("{} not found", path.display());
+    }
+
+    let build_dir = Paths::new(root).build(&Package::from_path(&package_path)?.info)?;
+    fs::create_dir_all(&build_dir)?;
+
+    let mut context = BuildContext::new(dir, &build_dir);
+    context.jobs = config.parallelism.jobs();
+    let package = Package::from_path_in(&package_path, &context)?;
+
+    let missing = missing_dependencies(&Database::open(root)?, &package)?;
+    if !missing.is_empty() {
+        bail!(
+            "The check dependencies {} aren't installed, install them with `blossom install --asdeps`",
+            missing.join(", ")
+        );
+    }
+
+    info!(
+        "Testing package \"{}\" version {}",
+        package.info.name, package.info.version
+    );
+
+    check_package(&config, &package, &context).await?;
+
+    info!("Package '{}' passed its checks", package.info.name);
+    Ok(())
+}
+
+/// The check dependencies of `package` that no installed package is or
+/// provides.
+fn missing_dependencies<'a>(database: &Database, package: &'a Package) -> Result<Vec<&'a str>> {
+    let installed = database.list()?;
+    let check = package
+        .dependencies
+        .iter()
+        .flat_map(|dependencies| &dependencies.check)
+        .map(|dependency| dependency_name(dependency));
+
+    Ok(check
+        .filter(|name| {
+            !installed.iter().any(|package| {
+                package.info.name == *name
+                    || package
+                        .info
+                        .provides
+                        .iter()
+                        .any(|provided| dependency_name(provided) == *name)
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+
+use crate::{
+    database::Database,
+    package::{Dependencies, dependency_name},
+    repository,
+};
+
+struct Node {
+    version: String,
+    dependencies: Vec<String>,
+}
+
+pub fn tree<R: AsRef<Path>>(
+    root: R,
+    name: &str,
+    depth: Option<usize>,
+    dot: bool,
+    json: bool,
+) -> Result<()> {
+    let mut nodes = BTreeMap::new();
+
+    for package in Database::open(&root)?.list()? {
+        nodes.insert(
+            package.info.name,
+            Node {
+                version: package.info.version,
+                dependencies: required(package.dependencies.as_ref()),
+            },
+        );
+    }
+
+    for repository in repository::load_synced(&root)? {
+        for entry in repository.index.packages {
+            nodes.entry(entry.info.name).or_insert_with(|| Node {
+                version: entry.info.version,
+                dependencies: required(entry.dependencies.as_ref()),
+            });
+        }
+    }
+
+    let Some(node) = nodes.get(name) else {
+        bail!("Package \"{name}\" is neither installed nor available in any repository");
+    };
+
+    if dot {
+        print_dot(&nodes, name, depth);
+        return Ok(());
+    }
+
+    let mut path = vec![name.to_string()];
+
+    if json {
+        let tree = json!({
+            "name": name,
+            "version": node.version,
+            "dependencies": json_children(&nodes, node, &mut path, depth),
+        });
+        println!("{}", serde_json::to_string_pretty(&tree)?);
+        return Ok(());
+    }
+
+    println!("{name} {}", node.version);
+    print_children(&nodes, node, "", &mut path, depth);
+
+    Ok(())
+}
+
+fn required(dependencies: Option<&Dependencies>) -> Vec<String> {
+    dependencies
+        .map(|dependencies| dependencies.required.clone())
+        .unwrap_or_default()
+}
+
+fn print_children(
+    nodes: &BTreeMap<String, Node>,
+    node: &Node,
+    prefix: &str,
+    path: &mut Vec<String>,
+    depth: Option<usize>,
+) {
+    if depth.is_some_and(|depth| path.len() > depth) {
+        return;
+    }
+
+    for (i, dependency) in node.dependencies.iter().enumerate() {
+        let last = i == node.dependencies.len() - 1;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        let name = dependency_name(dependency);
+
+        let Some(child) = nodes.get(name) else {
+            println!("{prefix}{branch}{dependency} (not found)");
+            continue;
+        };
+
+        if path.iter().any(|visited| visited == name) {
+            println!("{prefix}{branch}{dependency} (cycle)");
+            continue;
+        }
+
+        println!("{prefix}{branch}{dependency} {}", child.version);
+
+        path.push(name.to_string());
+        print_children(nodes, child, &format!("{prefix}{indent}"), path, depth);
+        path.pop();
+    }
+}
+
+/// The dependencies of `node` as JSON, the way [`print_children`] prints
+/// them.
+fn json_children(
+    nodes: &BTreeMap<String, Node>,
+    node: &Node,
+    path: &mut Vec<String>,
+    depth: Option<usize>,
+) -> Vec<Value> {
+    if depth.is_some_and(|depth| path.len() > depth) {
+        return Vec::new();
+    }
+
+    node.dependencies
+        .iter()
+        .map(|dependency| {
+            let name = dependency_name(dependency);
+
+            let Some(child) = nodes.get(name) else {
+                return json!({ "requirement": dependency, "name": name, "missing": true });
+            };
+
+            if path.iter().any(|visited| visited == name) {
+                return json!({
+                    "requirement": dependency,
+                    "name": name,
+                    "version": child.version,
+                    "cycle": true,
+                });
+            }
+
+            path.push(name.to_string());
+            let dependencies = json_children(nodes, child, path, depth);
+            path.pop();
+
+            json!({
+                "requirement": dependency,
+                "name": name,
+                "version": child.version,
+                "dependencies": dependencies,
+            })
+        })
+        .collect()
+}
+
+fn print_dot(nodes: &BTreeMap<String, Node>, name: &str, depth: Option<usize>) {
+    let mut edges = BTreeSet::new();
+    let mut seen = BTreeSet::from([name.to_string()]);
+    let mut queue = VecDeque::from([(name.to_string(), 0)]);
+
+    while let Some((current, level)) = queue.pop_front() {
+        if depth.is_some_and(|depth| level >= depth) {
+            continue;
+        }
+
+        let Some(node) = nodes.get(&current) else {
+            continue;
+        };
+
+        for dependency in &node.dependencies {
+            let dependency = dependency_name(dependency).to_string();
+
+            edges.insert((current.clone(), dependency.clone()));
+
+            if seen.insert(dependency.clone()) {
+                queue.push_back((dependency, level + 1));
+            }
+        }
+    }
+
+    println!("digraph \"{name}\" {{");
+
+    for (from, to) in &edges {
+        println!("    \"{from}\" -> \"{to}\";");
+    }
+
+    println!("}}");
+}
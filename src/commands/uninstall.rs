@@ -1,7 +1,233 @@
-use anyhow::Result;
-use tracing::info;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    commands::{
+        install::{is_modified, remove_existing},
+        rdepends::{installed_graph, reverse_dependencies},
+    },
+    database::{Database, FileKind},
+    events::{self, Action, Event, Phase, Transaction},
+    failure::Failure,
+    history::{Change, History},
+    hooks::Triggers,
+    lock::Lock,
+    prompt, snapshot,
+    store::Store,
+};
+
+pub fn uninstall<R: AsRef<Path>>(
+    root: R,
+    name: &str,
+    cascade: bool,
+    force: bool,
+    wait: bool,
+    json: bool,
+) -> Result<()> {
+    let _lock = Lock::acquire(&root, wait)?;
+    let database = Database::open(&root)?;
+
+    if database.get(name)?.is_none() {
+        bail!("Package \"{name}\" is not installed");
+    }
+
+    let graph = installed_graph(&database)?;
+    let (direct, transitive) = reverse_dependencies(&graph, name);
+
+    let mut targets = BTreeSet::from([name.to_string()]);
+
+    if !direct.is_empty() {
+        let dependents = direct.iter().cloned().collect::<Vec<_>>().join(", ");
+
+        if cascade {
+            targets.extend(direct);
+            targets.extend(transitive);
+
+            let cascaded = targets.iter().filter(|t| *t != name).cloned();
+            info!(
+                "Also removing packages depending on \"{name}\": {}",
+                cascaded.collect::<Vec<_>>().join(", ")
+            );
+        } else if force {
+            warn!("Removing \"{name}\" even though it is required by {dependents}");
+        } else {
+            bail!(
+                "Package \"{name}\" is required by {dependents}, pass --cascade to remove them too or --force to remove it anyway"
+            );
+        }
+    }
+
+    let order = removal_order(&graph, targets);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({ "remove": order }))?
+        );
+    }
+
+    if !prompt::confirm(&format!("Remove {}?", order.join(", ")), false)? {
+        return Err(Failure::Aborted.error("Uninstall cancelled").into());
+    }
+
+    snapshot::before_transaction(&database, &order)?;
+
+    let mut changes = Vec::new();
+    let mut triggers = Triggers::default();
+
+    events::emit(Event::Phase {
+        phase: Phase::Remove,
+    });
+
+    let mut transaction = Transaction::start(order.len());
+
+    for target in order {
+        if let Some(package) = database.get(&target)? {
+            transaction.package(Action::Remove, &target, &package.info.version);
+        }
+        changes.push(remove_package(&database, &target, &mut triggers)?);
+        transaction.done();
+    }
+    transaction.finish();
+
+    History::open(&root)?.record(changes)?;
+
+    events::emit(Event::Phase {
+        phase: Phase::Hooks,
+    });
+    triggers.run(&root)?;
+    events::emit(Event::Phase { phase: Phase::Done });
 
-pub fn uninstall(name: &str) -> Result<()> {
-    info!("Removing package: {}", name);
     Ok(())
 }
+
+/// Orders `targets` so that every package is removed before the packages it
+/// depends on. Cycles are broken arbitrarily.
+fn removal_order(
+    graph: &BTreeMap<String, Vec<String>>,
+    mut targets: BTreeSet<String>,
+) -> Vec<String> {
+    let mut order = Vec::new();
+
+    while !targets.is_empty() {
+        let is_required = |name: &String| {
+            targets
+                .iter()
+                .any(|other| other != name && graph[other].contains(name))
+        };
+
+        let next = targets
+            .iter()
+            .find(|name| !is_required(name))
+            .or(targets.first())
+            .cloned()
+            .expect("targets is not empty");
+
+        targets.remove(&next);
+        order.push(next);
+    }
+
+    order
+}
+
+/// Removes an installed package from an already locked database. Backup files
+/// carrying local modifications are kept around as `.peachsave`.
+pub(crate) fn remove_package(
+    database: &Database,
+    name: &str,
+    triggers: &mut Triggers,
+) -> Result<Change> {
+    let root = database.root();
+
+    let installed = database
+        .get(name)?
+        .ok_or(anyhow!("Package \"{name}\" is not installed"))?;
+
+    info!(
+        "Removing package \"{}\" version {}",
+        installed.info.name, installed.info.version
+    );
+
+    let mut directories = Vec::new();
+
+    for entry in &installed.files {
+        let dest = root.join(&entry.path);
+
+        match entry.kind {
+            FileKind::Directory => directories.push(dest),
+            _ if installed.is_backup(&entry.path) && is_modified(&dest, entry)? => {
+                let mut save = dest.clone().into_os_string();
+                save.push(".peachsave");
+
+                fs::rename(&dest, &save)?;
+                warn!(
+                    "{} was modified locally, saved as {}.peachsave",
+                    entry.path, entry.path
+                );
+            }
+            _ => remove_existing(&dest)?,
+        }
+    }
+
+    directories.sort();
+
+    for dir in directories.iter().rev() {
+        // Directories shared with other packages or holding user files stay.
+        let _ = fs::remove_dir(dir);
+    }
+
+    Store::open(root).release(&installed.files)?;
+    database.remove(name)?;
+
+    info!("Package \"{name}\" removed successfully");
+
+    let change = Change::Remove {
+        name: installed.info.name,
+        version: installed.info.version,
+    };
+
+    triggers.record(&change, installed.files.iter().map(|entry| &entry.path));
+
+    Ok(change)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::{database::InstallReason, test_util::install_test_package};
+
+    #[test]
+    fn test_removal_order() {
+        let mut graph = BTreeMap::new();
+        graph.insert("app".to_string(), vec!["libfoo".to_string()]);
+        graph.insert("libfoo".to_string(), vec!["libc".to_string()]);
+        graph.insert("libc".to_string(), vec![]);
+
+        let targets = BTreeSet::from(["libc".into(), "app".into(), "libfoo".into()]);
+
+        assert_eq!(removal_order(&graph, targets), ["app", "libfoo", "libc"]);
+    }
+
+    #[test]
+    fn test_provided_dependency() {
+        let root = env::temp_dir().join(format!("blossom-test-uninstall-{}", process::id()));
+        let reason = InstallReason::Explicit;
+        install_test_package(&root, "app", reason, &["java-runtime"], &[]);
+        install_test_package(&root, "jre", reason, &[], &["java-runtime=17"]);
+
+        let error = uninstall(&root, "jre", false, false, false, false).unwrap_err();
+        assert!(error.to_string().contains("required by app"), "{error}");
+        assert!(Database::open(&root).unwrap().get("jre").unwrap().is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::{
+    commands::install::{confirm_plan, execute_plan, fetch_archives, plan_json, prompt_provider},
+    database::Database,
+    events::{self, Event, Phase},
+    lock::Lock,
+    repository,
+    resolver::{self, Resolver, Solver},
+};
+
+/// Upgrades every installed package a synced repository has a newer version
+/// of.
+pub async fn upgrade<R: AsRef<Path>>(
+    root: R,
+    solver: Solver,
+    wait: bool,
+    json: bool,
+) -> Result<()> {
+    let root = root.as_ref();
+    let _lock = Lock::acquire_async(root, wait).await?;
+    let database = Database::open(root)?;
+
+    let repositories = repository::load_synced(root)?;
+
+    if repositories.is_empty() {
+        info!("No synced repositories, nothing to upgrade from");
+        return Ok(());
+    }
+
+    let installed = database.list()?;
+    let resolver = Resolver::new(&repositories, &installed)
+        .preferences(resolver::load_preferences(root)?)
+        .solver(solver)
+        .upgrade(true)
+        .prompt(&prompt_provider);
+
+    events::emit(Event::Phase {
+        phase: Phase::Resolve,
+    });
+    let plan = match solver {
+        Solver::Greedy => {
+            let outdated: Vec<String> = installed
+                .iter()
+                .filter(|package| {
+                    repository::find_upgrade(
+                        &repositories,
+                        &package.info.name,
+                        &package.info.version,
+                    )
+                    .is_some()
+                })
+                .map(|package| package.info.name.clone())
+                .collect();
+
+            resolver.resolve(&outdated)?
+        }
+        // The solver considers every installed package on its own.
+        Solver::Sat => resolver.resolve(&[])?,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&plan_json(&plan))?);
+    }
+
+    if plan.install.is_empty() && plan.remove.is_empty() {
+        info!("All packages are up to date");
+        return Ok(());
+    }
+
+    confirm_plan(&database, &plan, json)?;
+    let archives = fetch_archives(&database, &plan).await?;
+
+    execute_plan(&database, &plan, &archives, false)
+}
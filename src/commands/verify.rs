@@ -0,0 +1,138 @@
+use std::{fmt::Display, fs, io::ErrorKind, os::unix::fs::PermissionsExt, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use camino::Utf8PathBuf;
+use serde_json::json;
+use tracing::info;
+
+use crate::database::{Database, FileKind, InstalledPackage, hash_file};
+
+#[derive(Debug)]
+pub struct Problem {
+    pub path: Utf8PathBuf,
+    pub kind: ProblemKind,
+}
+
+#[derive(Debug)]
+pub enum ProblemKind {
+    Missing,
+    Modified,
+    PermissionsChanged { expected: u32, actual: u32 },
+}
+
+impl Display for ProblemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "missing"),
+            Self::Modified => write!(f, "modified"),
+            Self::PermissionsChanged { expected, actual } => {
+                write!(f, "permissions changed ({expected:o} -> {actual:o})")
+            }
+        }
+    }
+}
+
+pub fn verify<R: AsRef<Path>>(root: R, name: Option<&str>, json: bool) -> Result<()> {
+    let database = Database::open(&root)?;
+
+    let packages = match name {
+        Some(name) => vec![
+            database
+                .get(name)?
+                .ok_or(anyhow!("Package \"{name}\" is not installed"))?,
+        ],
+        None => database.list()?,
+    };
+
+    let mut failed = 0;
+    let mut report = Vec::new();
+
+    for package in &packages {
+        let problems = verify_package(root.as_ref(), package)?;
+
+        for problem in &problems {
+            let backup = package.is_backup(&problem.path);
+
+            if json {
+                report.push(json!({
+                    "package": package.info.name,
+                    "path": problem.path,
+                    "problem": problem.kind.to_string(),
+                    "backup": backup,
+                }));
+                continue;
+            }
+
+            let note = if backup { " (backup)" } else { "" };
+
+            println!(
+                "{}: {} {}{note}",
+                package.info.name, problem.kind, problem.path
+            );
+        }
+
+        failed += problems.len();
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+
+    if failed > 0 {
+        bail!("{failed} files failed verification");
+    }
+
+    info!("Verified {} packages, no problems found", packages.len());
+
+    Ok(())
+}
+
+pub(crate) fn verify_package(root: &Path, package: &InstalledPackage) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+
+    for entry in &package.files {
+        let path = root.join(&entry.path);
+
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                problems.push(Problem {
+                    path: entry.path.clone(),
+                    kind: ProblemKind::Missing,
+                });
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let modified = match entry.kind {
+            FileKind::Directory => !metadata.is_dir(),
+            FileKind::Symlink => !metadata.is_symlink(),
+            FileKind::File => {
+                !metadata.is_file() || entry.hash.as_deref() != Some(hash_file(&path)?.as_str())
+            }
+        };
+
+        if modified {
+            problems.push(Problem {
+                path: entry.path.clone(),
+                kind: ProblemKind::Modified,
+            });
+            continue;
+        }
+
+        let actual = metadata.permissions().mode() & 0o7777;
+
+        if entry.kind != FileKind::Symlink && actual != entry.mode {
+            problems.push(Problem {
+                path: entry.path.clone(),
+                kind: ProblemKind::PermissionsChanged {
+                    expected: entry.mode,
+                    actual,
+                },
+            });
+        }
+    }
+
+    Ok(problems)
+}
@@ -0,0 +1,111 @@
+use std::{collections::VecDeque, path::Path};
+
+use anyhow::{Result, bail};
+use serde_json::json;
+
+use crate::{
+    database::{Database, InstallReason, InstalledPackage},
+    package::dependency_name,
+};
+
+/// Explains why an installed package is there, showing the chains of
+/// dependencies leading to it from explicitly installed packages.
+pub fn why<R: AsRef<Path>>(root: R, name: &str, json: bool) -> Result<()> {
+    let installed = Database::open(&root)?.list()?;
+
+    let Some(target) = installed
+        .iter()
+        .position(|package| package.info.name == name)
+    else {
+        bail!("Package \"{name}\" is not installed");
+    };
+
+    let explicit = installed[target].reason == InstallReason::Explicit;
+    let chains = chains(&installed, target)?;
+
+    if json {
+        let why = json!({ "name": name, "explicit": explicit, "chains": chains });
+        println!("{}", serde_json::to_string_pretty(&why)?);
+        return Ok(());
+    }
+
+    if explicit {
+        println!("{name} is explicitly installed");
+    }
+
+    if chains.is_empty() {
+        if !explicit {
+            println!("{name} was installed as a dependency but nothing requires it anymore");
+        }
+
+        return Ok(());
+    }
+
+    println!("{name} is required through:");
+
+    for chain in chains {
+        println!("  {}", chain.join(" → "));
+    }
+
+    Ok(())
+}
+
+/// Finds the shortest chain from every explicitly installed package that
+/// requires `target`, directly or not.
+fn chains(installed: &[InstalledPackage], target: usize) -> Result<Vec<Vec<String>>> {
+    // Walking backwards, `next[i]` is the package `i` requires on its way to
+    // the target, along with the requirement it goes through.
+    let mut next: Vec<Option<(usize, String)>> = vec![None; installed.len()];
+    let mut seen = vec![false; installed.len()];
+    let mut queue = VecDeque::from([target]);
+    let mut roots = Vec::new();
+    seen[target] = true;
+
+    while let Some(current) = queue.pop_front() {
+        for (index, package) in installed.iter().enumerate() {
+            if seen[index] {
+                continue;
+            }
+
+            let Some(dependencies) = &package.dependencies else {
+                continue;
+            };
+
+            for dependency in &dependencies.required {
+                if installed[current].info.matches(dependency)? {
+                    seen[index] = true;
+                    next[index] = Some((current, dependency_name(dependency).to_string()));
+                    queue.push_back(index);
+
+                    if package.reason == InstallReason::Explicit {
+                        roots.push(index);
+                    }
+
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut chains = Vec::new();
+
+    for root in roots {
+        let mut chain = vec![format!("{} (explicit)", installed[root].info.name)];
+        let mut current = root;
+
+        while let Some((dependency, requirement)) = &next[current] {
+            let name = &installed[*dependency].info.name;
+
+            chain.push(if requirement == name {
+                name.clone()
+            } else {
+                format!("{name} (as {requirement})")
+            });
+            current = *dependency;
+        }
+
+        chains.push(chain);
+    }
+
+    Ok(chains)
+}
@@ -0,0 +1,592 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    fmt::Display,
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{LazyLock, Mutex},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serde_with::{DisplayFromStr, serde_as};
+
+use crate::{
+    error::{Result, bail, format_err},
+    failure::Failure,
+    glob,
+    paths::Paths,
+    publish::PublishConfig,
+    signing::PublicKey,
+};
+
+pub const CONFIG_FILE: &str = "etc/blossom/config.toml";
+/// The per-user configuration, relative to `$XDG_CONFIG_HOME` or
+/// `~/.config`, layered over the system one.
+pub const USER_CONFIG_FILE: &str = "blossom/config.toml";
+
+/// Environment variables overriding settings are named after the setting,
+/// upper cased, with `__` between nested keys, e.g. `BLOSSOM_COMPRESSION` or
+/// `BLOSSOM_PARALLELISM__JOBS`.
+pub const ENV_PREFIX: &str = "BLOSSOM_";
+
+/// The environment variables blossom reads, for `blossom config --env`. Any
+/// other setting can be overridden the same way.
+pub const ENVIRONMENT: [(&str, &str); 13] = [
+    ("BLOSSOM_ROOT", "The root to operate on, like --root"),
+    ("BLOSSOM_CACHE_DIR", "Where downloaded packages are kept"),
+    ("BLOSSOM_COLORS", "auto, always or never"),
+    ("BLOSSOM_OFFLINE", "true to never reach the network"),
+    (
+        "BLOSSOM_OUTPUT",
+        "text, or json for machine-readable output",
+    ),
+    (
+        "BLOSSOM_EVENTS",
+        "A file descriptor or unix:PATH to send progress events to",
+    ),
+    ("BLOSSOM_SIGNATURES", "required, optional or never"),
+    ("BLOSSOM_COMPRESSION", "The zstd level of built packages"),
+    (
+        "BLOSSOM_PARALLELISM__DOWNLOADS",
+        "How many packages are downloaded at once",
+    ),
+    (
+        "BLOSSOM_PARALLELISM__JOBS",
+        "How many jobs build steps run, 0 for one per CPU",
+    ),
+    (
+        "BLOSSOM_PARALLELISM__COMPRESSION",
+        "How many threads compress built packages, 0 for one per CPU",
+    ),
+    (
+        "BLOSSOM_PARALLELISM__EXTRACTION",
+        "How many threads write the files of installed packages, 0 for one per CPU",
+    ),
+    (
+        "BLOSSOM_DOWNLOAD__SEGMENTS",
+        "How many ranges of a large file are downloaded at once",
+    ),
+];
+
+/// Configurations already loaded, by root.
+static LOADED: LazyLock<Mutex<HashMap<PathBuf, Config>>> = LazyLock::new(Default::default);
+/// Settings given on the command line, layered over everything else.
+static OVERRIDES: LazyLock<Mutex<Value>> =
+    LazyLock::new(|| Mutex::new(Value::Object(Default::default())));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(default)]
+    pub repositories: Vec<RepositoryConfig>,
+    /// Packages that may only come from one repository, e.g.
+    /// `kernel = "stable"`. Keys are package names or globs, an exact name
+    /// takes precedence over globs.
+    #[serde(default)]
+    pub pins: BTreeMap<String, String>,
+    #[serde(default)]
+    pub signatures: SignaturePolicy,
+    /// Where downloaded package archives are kept, relative to the root.
+    /// Defaults to the package cache of [`Paths`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
+    /// The zstd level built packages are compressed with.
+    pub compression: i32,
+    pub parallelism: Parallelism,
+    pub download: DownloadConfig,
+    pub colors: Colors,
+    /// Never reach the network: only cached packages and sources and local
+    /// repositories are used, commands needing more fail.
+    pub offline: bool,
+    pub output: Output,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            repositories: Vec::new(),
+            pins: BTreeMap::new(),
+            signatures: SignaturePolicy::default(),
+            cache_dir: None,
+            compression: 22,
+            parallelism: Parallelism::default(),
+            download: DownloadConfig::default(),
+            colors: Colors::default(),
+            offline: false,
+            output: Output::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Parallelism {
+    /// How many package archives are downloaded at once.
+    pub downloads: usize,
+    /// How many jobs build steps run, through `MAKEFLAGS` and
+    /// `BLOSSOM_JOBS`. `0` means one per CPU.
+    pub jobs: usize,
+    /// How many threads compress built packages. `0` means one per CPU.
+    pub compression: usize,
+    /// How many threads write the files of installed packages. `0` means one
+    /// per CPU.
+    pub extraction: usize,
+}
+
+impl Default for Parallelism {
+    fn default() -> Self {
+        Self {
+            downloads: 4,
+            jobs: 0,
+            compression: 0,
+            extraction: 0,
+        }
+    }
+}
+
+impl Parallelism {
+    pub fn jobs(&self) -> usize {
+        per_cpu(self.jobs)
+    }
+
+    pub fn compression(&self) -> usize {
+        per_cpu(self.compression)
+    }
+
+    pub fn extraction(&self) -> usize {
+        per_cpu(self.extraction)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// How many ranges of a large source or package archive are downloaded
+    /// at once, from servers accepting ranges. `1` downloads them whole.
+    pub segments: usize,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self { segments: 4 }
+    }
+}
+
+/// `count`, or the number of CPUs if it's `0`.
+fn per_cpu(count: usize) -> usize {
+    match count {
+        0 => std::thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        count => count,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Colors {
+    /// Colored when writing to a terminal, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Display for Colors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Colors::Auto => write!(f, "auto"),
+            Colors::Always => write!(f, "always"),
+            Colors::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for Colors {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Colors::Auto),
+            "always" => Ok(Colors::Always),
+            "never" => Ok(Colors::Never),
+            _ => Err(format!(
+                "Unknown color mode \"{s}\", expected auto, always or never"
+            )),
+        }
+    }
+}
+
+impl Colors {
+    pub fn enabled(&self) -> bool {
+        match self {
+            Self::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// What commands print on stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    #[default]
+    Text,
+    /// A single JSON document, with logs going to stderr and no prompts.
+    Json,
+}
+
+/// How strictly signatures are checked on sync and install.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignaturePolicy {
+    /// Every repository needs trusted keys, its index and every package
+    /// installed from it must be signed by one of them.
+    Required,
+    /// Indexes of repositories with keys are verified, packages are covered by
+    /// the checksums of the verified index.
+    #[default]
+    Optional,
+    /// Nothing is verified, even for repositories with keys.
+    Never,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositoryConfig {
+    pub name: String,
+    /// Base URL serving `<name>.index` and the package archives it lists.
+    pub url: String,
+    /// Alternative base URLs serving the same files, tried when `url` fails.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// Repositories with a higher priority are preferred.
+    #[serde(default)]
+    pub priority: i32,
+    /// Keys trusted to sign the index. When set, an index without a valid
+    /// signature from one of them is rejected on sync.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<PublicKey>,
+    /// How `blossom publish` uploads to this repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish: Option<PublishConfig>,
+}
+
+impl RepositoryConfig {
+    /// The main URL followed by the mirrors.
+    pub fn urls(&self) -> Vec<String> {
+        [&self.url]
+            .into_iter()
+            .chain(&self.mirrors)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Config {
+    /// Overrides settings for the rest of the run, from `key=value`
+    /// assignments where the key is a dotted path such as `parallelism.jobs`
+    /// and the value is TOML, or else taken as a string.
+    pub fn set_overrides<S: AsRef<str>>(overrides: &[S]) -> Result<()> {
+        let mut layer = Value::Object(Default::default());
+
+        for assignment in overrides {
+            let assignment = assignment.as_ref();
+            let (key, value) = assignment.split_once('=').ok_or(format_err!(
+                "Invalid override {assignment}, expected key=value"
+            ))?;
+
+            merge(
+                &mut layer,
+                setting(&key.split('.').collect::<Vec<_>>(), value)?,
+            );
+        }
+
+        *OVERRIDES
+            .lock()
+            .map_err(|_| format_err!("Configuration lock poisoned"))? = layer;
+        LOADED
+            .lock()
+            .map_err(|_| format_err!("Configuration lock poisoned"))?
+            .clear();
+
+        Ok(())
+    }
+
+    /// The configuration of `root`: the system configuration, then the user
+    /// one, the environment and the command line, each taking precedence
+    /// over the previous. It is read once, later calls share the same
+    /// settings.
+    pub fn load<R: AsRef<Path>>(root: R) -> Result<Self> {
+        let root = root.as_ref();
+        let mut loaded = LOADED
+            .lock()
+            .map_err(|_| format_err!("Configuration lock poisoned"))?;
+
+        if let Some(config) = loaded.get(root) {
+            return Ok(config.clone());
+        }
+
+        let layers = [Some(root.join(CONFIG_FILE)), user_config_path()];
+        let mut merged = Value::Object(Default::default());
+
+        for path in layers.into_iter().flatten().filter(|path| path.exists()) {
+            let layer: Value = toml_edit::de::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| format_err!("Invalid configuration {}: {e}", path.display()))?;
+
+            merge(&mut merged, layer);
+        }
+
+        merge(&mut merged, environment_layer(env::vars())?);
+        merge(
+            &mut merged,
+            OVERRIDES
+                .lock()
+                .map_err(|_| format_err!("Configuration lock poisoned"))?
+                .clone(),
+        );
+
+        let config: Self = serde_json::from_value(merged)
+            .map_err(|e| format_err!("Invalid configuration: {e}"))?;
+        loaded.insert(root.to_path_buf(), config.clone());
+
+        Ok(config)
+    }
+
+    /// Fails with what couldn't be done when offline.
+    pub fn require_online(&self, action: &str) -> Result<()> {
+        if self.offline {
+            return Err(Failure::Network.error(format!("Can't {action} while offline")));
+        }
+
+        Ok(())
+    }
+
+    /// The package cache directory of `root`.
+    pub fn cache_dir<R: AsRef<Path>>(&self, root: R) -> PathBuf {
+        match &self.cache_dir {
+            Some(dir) => root.as_ref().join(dir.strip_prefix("/").unwrap_or(dir)),
+            None => Paths::new(root).packages(),
+        }
+    }
+
+    pub fn repository(&self, name: &str) -> Option<&RepositoryConfig> {
+        self.repositories
+            .iter()
+            .find(|repository| repository.name == name)
+    }
+
+    /// Compiles the pinning rules.
+    pub fn pins(&self) -> Result<Pins> {
+        let mut pins = Pins::default();
+
+        for (pattern, repository) in &self.pins {
+            if pattern.contains(['*', '?']) {
+                pins.globs
+                    .push((glob::to_regex(pattern)?, repository.clone()));
+            } else {
+                pins.exact.insert(pattern.clone(), repository.clone());
+            }
+        }
+
+        Ok(pins)
+    }
+}
+
+pub(crate) fn user_config_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    Some(dir.join(USER_CONFIG_FILE))
+}
+
+/// The settings overridden by `BLOSSOM_` environment variables. Variables that
+/// aren't settings, like those blossom sets for build steps and hooks, are left
+/// out.
+fn environment_layer(vars: impl Iterator<Item = (String, String)>) -> Result<Value> {
+    let mut layer = Value::Object(Default::default());
+
+    for (name, value) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        if matches!(key, "ROOT" | "JOBS" | "EVENTS") {
+            continue;
+        }
+
+        let key = key.to_lowercase();
+        merge(
+            &mut layer,
+            setting(&key.split("__").collect::<Vec<_>>(), &value)
+                .map_err(|e| format_err!("Invalid {name}: {e}"))?,
+        );
+    }
+
+    Ok(layer)
+}
+
+/// A layer setting the value at `key`, nested tables first.
+fn setting(key: &[&str], value: &str) -> Result<Value> {
+    if key.iter().any(|part| part.is_empty()) {
+        bail!("Invalid setting name {}", key.join("."));
+    }
+
+    // Values that aren't valid TOML, like most paths, are plain strings.
+    let mut value = toml_edit::de::from_str::<Value>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.get_mut("value").map(Value::take))
+        .unwrap_or(Value::String(value.to_string()));
+
+    for part in key.iter().rev() {
+        value = Value::Object([(part.to_string(), value)].into_iter().collect());
+    }
+
+    Ok(value)
+}
+
+/// Layers `layer` over `base`: tables are merged key by key, repositories by
+/// name, and anything else is replaced.
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(Value::Array(repositories)) if key == "repositories" => {
+                        merge_repositories(repositories, value)
+                    }
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+fn merge_repositories(repositories: &mut Vec<Value>, layer: Value) {
+    let Value::Array(layer) = layer else {
+        return;
+    };
+
+    for repository in layer {
+        match repositories.iter_mut().find(|existing| {
+            existing.get("name").is_some() && existing.get("name") == repository.get("name")
+        }) {
+            Some(existing) => *existing = repository,
+            None => repositories.push(repository),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Pins {
+    exact: BTreeMap<String, String>,
+    globs: Vec<(Regex, String)>,
+}
+
+impl Pins {
+    /// The repository `package` is pinned to, if any.
+    pub fn get(&self, package: &str) -> Option<&str> {
+        self.exact.get(package).map(String::as_str).or_else(|| {
+            self.globs
+                .iter()
+                .find(|(pattern, _)| pattern.is_match(package))
+                .map(|(_, repository)| repository.as_str())
+        })
+    }
+
+    pub fn repositories(&self) -> impl Iterator<Item = &str> {
+        self.exact
+            .values()
+            .chain(self.globs.iter().map(|(_, repository)| repository))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge() {
+        let mut base = json!({
+            "signatures": "required",
+            "pins": { "kernel": "stable" },
+            "repositories": [
+                { "name": "core", "url": "https://example.org/core" },
+                { "name": "extra", "url": "https://example.org/extra" },
+            ],
+        });
+
+        merge(
+            &mut base,
+            json!({
+                "pins": { "firefox": "testing" },
+                "parallelism": { "downloads": 8 },
+                "repositories": [
+                    { "name": "extra", "url": "https://mirror.example.org/extra" },
+                    { "name": "local", "url": "file:///srv/local" },
+                ],
+            }),
+        );
+
+        let config: Config = serde_json::from_value(base).unwrap();
+
+        assert_eq!(config.signatures, SignaturePolicy::Required);
+        assert_eq!(config.pins.len(), 2);
+        assert_eq!(config.parallelism.downloads, 8);
+        assert_eq!(config.compression, 22);
+        assert_eq!(
+            config
+                .repositories
+                .iter()
+                .map(|repository| repository.url.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "https://example.org/core",
+                "https://mirror.example.org/extra",
+                "file:///srv/local"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut config = json!({ "compression": 19, "parallelism": { "downloads": 2 } });
+
+        merge(
+            &mut config,
+            environment_layer(
+                [
+                    ("BLOSSOM_PARALLELISM__JOBS", "3"),
+                    ("BLOSSOM_COMPRESSION", "10"),
+                    ("BLOSSOM_JOBS", "8"),
+                    ("PATH", "/usr/bin"),
+                ]
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+            )
+            .unwrap(),
+        );
+        merge(&mut config, setting(&["compression"], "3").unwrap());
+        merge(&mut config, setting(&["cache_dir"], "/tmp/cache").unwrap());
+
+        let config: Config = serde_json::from_value(config).unwrap();
+
+        assert_eq!(config.compression, 3);
+        assert_eq!(config.parallelism.downloads, 2);
+        assert_eq!(config.parallelism.jobs, 3);
+        assert_eq!(config.cache_dir, Some(PathBuf::from("/tmp/cache")));
+        assert!(setting(&["parallelism", ""], "1").is_err());
+    }
+}
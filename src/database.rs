@@ -0,0 +1,171 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    error::Result,
+    package::{ChangelogEntry, Dependencies, Info, PeachInfo},
+    permissions::SpecialPermission,
+};
+
+pub const DATABASE_DIR: &str = "var/lib/blossom";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledPackage {
+    #[serde(default)]
+    pub reason: InstallReason,
+    pub info: Info,
+    pub dependencies: Option<Dependencies>,
+    #[serde(default)]
+    pub backup: Vec<Utf8PathBuf>,
+    pub build_date: Option<DateTime<Utc>>,
+    pub install_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub installed_size: u64,
+    #[serde(default)]
+    pub files: Vec<FileEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub special_permissions: Vec<SpecialPermission>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changelog: Vec<ChangelogEntry>,
+}
+
+impl InstalledPackage {
+    pub fn new(peach_info: PeachInfo, reason: InstallReason, files: Vec<FileEntry>) -> Self {
+        Self {
+            reason,
+            info: peach_info.info,
+            dependencies: peach_info.dependencies,
+            backup: peach_info.backup,
+            build_date: peach_info.build_date,
+            install_date: Some(Utc::now()),
+            installed_size: peach_info.installed_size,
+            files,
+            special_permissions: peach_info.special_permissions,
+            changelog: peach_info.changelog,
+        }
+    }
+
+    pub fn file(&self, path: &Utf8Path) -> Option<&FileEntry> {
+        self.files.iter().find(|entry| entry.path == path)
+    }
+
+    pub fn is_backup(&self, path: &Utf8Path) -> bool {
+        self.backup.iter().any(|backup| backup == path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallReason {
+    #[default]
+    Explicit,
+    Dependency,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: Utf8PathBuf,
+    pub kind: FileKind,
+    pub mode: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+pub struct Database {
+    root: PathBuf,
+    path: PathBuf,
+    cache: PathBuf,
+}
+
+impl Database {
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        let path = root.join(DATABASE_DIR).join("local");
+
+        fs::create_dir_all(&path)?;
+
+        let cache = Config::load(&root)?.cache_dir(&root);
+
+        Ok(Self { root, path, cache })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn cache_path(&self, name: &str, version: &str) -> PathBuf {
+        self.cache.join(format!("{name}-{version}.peach"))
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<InstalledPackage>> {
+        let path = self.entry_path(name);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(toml_edit::de::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    pub fn put(&self, package: &InstalledPackage) -> Result<()> {
+        let path = self.entry_path(&package.info.name);
+        let tmp_path = path.with_extension("toml.tmp");
+
+        fs::write(&tmp_path, toml_edit::ser::to_string_pretty(package)?)?;
+        fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        fs::remove_file(self.entry_path(name))?;
+
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<InstalledPackage>> {
+        let mut packages = Vec::new();
+
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                packages.push(toml_edit::de::from_str(&fs::read_to_string(path)?)?);
+            }
+        }
+
+        packages.sort_by(|a: &InstalledPackage, b| a.info.name.cmp(&b.info.name));
+
+        Ok(packages)
+    }
+
+    fn entry_path(&self, name: &str) -> PathBuf {
+        self.path.join(format!("{name}.toml"))
+    }
+}
+
+pub fn hash_bytes(contents: &[u8]) -> String {
+    format!("blake3:{}", blake3::hash(contents).to_hex())
+}
+
+pub fn hash_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_mmap_rayon(path)?;
+
+    Ok(format!("blake3:{}", hasher.finalize().to_hex()))
+}
@@ -0,0 +1,366 @@
+use std::{
+    io::{self, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use reqwest::{
+    Client, Response, StatusCode,
+    header::{
+        ACCEPT_ENCODING, ACCEPT_RANGES, ETAG, HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::mpsc::{self, UnboundedSender},
+    task::JoinSet,
+};
+use url::Url;
+
+use crate::{
+    error::{Result, bail, format_err},
+    events::Download,
+    log::{info, warn},
+};
+
+/// Files are only split into ranges of at least this size.
+const MIN_SEGMENT: u64 = 8 << 20;
+
+/// Downloads `url` to `target`, going through a temporary file so that an
+/// interrupted download never leaves a truncated file behind. `file://` URLs
+/// are hard linked, or copied when the link fails. Files are written without
+/// blocking the async runtime.
+pub async fn download(client: &Client, url: &str, target: &Path) -> Result<()> {
+    download_if_modified(client, url, target, None).await?;
+
+    Ok(())
+}
+
+/// HTTP cache validators of a downloaded file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+}
+
+/// Like [`download`], but sends the validators of a previous download as a
+/// conditional request. Returns `None` without touching `target` if the file
+/// didn't change, otherwise the validators of the new download.
+pub async fn download_if_modified(
+    client: &Client,
+    url: &str,
+    target: &Path,
+    validators: Option<&Validators>,
+) -> Result<Option<Validators>> {
+    Ok(fetch(client, url, target, validators, 1)
+        .await?
+        .map(|(validators, _)| validators))
+}
+
+/// Like [`download`], for large files: they're downloaded in up to
+/// `segments` ranges at once from servers accepting ranges. Returns where the
+/// file was served from after redirects.
+pub async fn download_segmented(
+    client: &Client,
+    url: &str,
+    target: &Path,
+    segments: usize,
+) -> Result<String> {
+    let (_, served) = fetch(client, url, target, None, segments)
+        .await?
+        .ok_or_else(|| format_err!("\"{url}\" wasn't downloaded"))?;
+
+    Ok(served)
+}
+
+async fn fetch(
+    client: &Client,
+    url: &str,
+    target: &Path,
+    validators: Option<&Validators>,
+    segments: usize,
+) -> Result<Option<(Validators, String)>> {
+    let tmp = target.with_extension("part");
+
+    if let Some(source) = local_path(url)? {
+        info!("Copying \"{}\"", source.display());
+        let download = Download::start(url, None);
+
+        if fs::try_exists(&tmp).await? {
+            fs::remove_file(&tmp).await?;
+        }
+
+        if fs::hard_link(&source, &tmp).await.is_err() {
+            fs::copy(&source, &tmp)
+                .await
+                .map_err(|e| format_err!("{}: {e}", source.display()))?;
+        }
+
+        fs::rename(tmp, target).await?;
+        download.finish();
+
+        return Ok(Some((Validators::default(), url.to_string())));
+    }
+
+    let mut request = client.get(url);
+
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = request.send().await?.error_for_status()?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        info!("\"{url}\" is unchanged");
+        return Ok(None);
+    }
+
+    info!("Downloading \"{url}\"");
+
+    let validators = Validators::from_headers(response.headers());
+    let served = response.url().to_string();
+
+    receive(client, url, response, &tmp, segments).await?;
+    fs::rename(tmp, target).await?;
+
+    Ok(Some((validators, served)))
+}
+
+/// Writes the body of `response`, the file at `url`, to `path`. When it's
+/// large and the server accepts ranges, the rest of the file is requested in
+/// ranges alongside it, starting over with a single request if that fails.
+async fn receive(
+    client: &Client,
+    url: &str,
+    mut response: Response,
+    path: &Path,
+    segments: usize,
+) -> Result<()> {
+    let mut download = Download::start(url, response.content_length());
+
+    let ranges = response
+        .content_length()
+        .filter(|_| accepts_ranges(response.headers()))
+        .map(|total| ranges(total, segments))
+        .unwrap_or_default();
+
+    if !ranges.is_empty() {
+        match receive_ranges(client, response, path, &ranges, &mut download).await {
+            Ok(()) => {
+                download.finish();
+                return Ok(());
+            }
+            Err(e) => {
+                warn!("Downloading \"{url}\" in segments failed, downloading it whole: {e}");
+                download.finish();
+
+                response = client.get(url).send().await?.error_for_status()?;
+                download = Download::start(url, response.content_length());
+            }
+        }
+    }
+
+    let mut file = File::create(path).await?;
+
+    while let Some(chunk) = response.chunk().await? {
+        download.inc(chunk.len() as u64);
+        file.write_all(&chunk).await?;
+    }
+
+    file.flush().await?;
+    download.finish();
+
+    Ok(())
+}
+
+/// Downloads the `ranges` of a file into the file at `path`, the first one
+/// from the body of `response` and the others with a request each, at once.
+async fn receive_ranges(
+    client: &Client,
+    response: Response,
+    path: &Path,
+    ranges: &[Range<u64>],
+    download: &mut Download<'_>,
+) -> Result<()> {
+    let total = ranges.last().map_or(0, |range| range.end);
+    File::create(path).await?.set_len(total).await?;
+
+    let served = response.url().clone();
+    // Ranges only come from the same version of the file as the response.
+    let version = [ETAG, LAST_MODIFIED]
+        .iter()
+        .find_map(|name| response.headers().get(name).cloned());
+
+    let (progress, mut written) = mpsc::unbounded_channel();
+    let mut segments = JoinSet::new();
+
+    segments.spawn(report(
+        write_range(
+            response,
+            path.to_path_buf(),
+            ranges[0].clone(),
+            progress.clone(),
+        ),
+        progress.clone(),
+    ));
+
+    for range in &ranges[1..] {
+        let mut request = client
+            .get(served.clone())
+            .header(RANGE, format!("bytes={}-{}", range.start, range.end - 1))
+            .header(ACCEPT_ENCODING, "identity");
+        if let Some(version) = &version {
+            request = request.header(IF_RANGE, version.clone());
+        }
+
+        let (path, range, sender) = (path.to_path_buf(), range.clone(), progress.clone());
+        let segment = async move {
+            let response = request.send().await?.error_for_status()?;
+            if response.status() != StatusCode::PARTIAL_CONTENT {
+                bail!("the server sent the whole file for a range");
+            }
+
+            write_range(response, path, range, sender).await
+        };
+        segments.spawn(report(segment, progress.clone()));
+    }
+    drop(progress);
+
+    // The first failure ends the download, the other segments being aborted
+    // as they're dropped.
+    while let Some(written) = written.recv().await {
+        download.inc(written?);
+    }
+
+    while let Some(segment) = segments.join_next().await {
+        segment.map_err(io::Error::from)?;
+    }
+
+    Ok(())
+}
+
+/// Runs `segment`, sending its error to `progress` if it fails.
+async fn report(segment: impl Future<Output = Result<()>>, progress: UnboundedSender<Result<u64>>) {
+    if let Err(e) = segment.await {
+        let _ = progress.send(Err(e));
+    }
+}
+
+/// Writes the body of `response` to `range` of the file at `path`, sending
+/// how many bytes were written to `progress` as they are. Whatever comes
+/// after the range is left unread.
+async fn write_range(
+    mut response: Response,
+    path: PathBuf,
+    range: Range<u64>,
+    progress: UnboundedSender<Result<u64>>,
+) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).open(&path).await?;
+    file.seek(SeekFrom::Start(range.start)).await?;
+
+    let mut remaining = range.end - range.start;
+
+    while remaining > 0 {
+        let Some(chunk) = response.chunk().await? else {
+            bail!("the download ended {remaining} bytes early");
+        };
+        let len = remaining.min(chunk.len() as u64);
+
+        file.write_all(&chunk[..len as usize]).await?;
+        remaining -= len;
+        let _ = progress.send(Ok(len));
+    }
+
+    file.flush().await?;
+
+    Ok(())
+}
+
+fn accepts_ranges(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT_RANGES)
+        .is_some_and(|value| value.as_bytes() == b"bytes")
+}
+
+/// Splits a file of `total` bytes into up to `segments` ranges of at least
+/// [`MIN_SEGMENT`] bytes. Files too small for two are left whole, without
+/// any range.
+fn ranges(total: u64, segments: usize) -> Vec<Range<u64>> {
+    let count = (segments as u64).min(total / MIN_SEGMENT);
+    if count < 2 {
+        return Vec::new();
+    }
+
+    let size = total.div_ceil(count);
+
+    (0..count)
+        .map(|i| i * size..((i + 1) * size).min(total))
+        .collect()
+}
+
+/// Joins a repository base URL and a file name.
+pub fn join_url(base: &str, file: &str) -> String {
+    format!("{}/{file}", base.trim_end_matches('/'))
+}
+
+/// Whether fetching `url` needs the network.
+pub fn is_remote(url: &str) -> bool {
+    !(url.starts_with("file://") || url.starts_with('/'))
+}
+
+/// The local directory or file a `file://` URL points to.
+pub fn local_path(url: &str) -> Result<Option<PathBuf>> {
+    if !url.starts_with("file://") {
+        return Ok(None);
+    }
+
+    Url::parse(url)?
+        .to_file_path()
+        .map(Some)
+        .map_err(|()| format_err!("{url} is not a local path"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranges() {
+        assert!(ranges(MIN_SEGMENT, 4).is_empty());
+        assert!(ranges(100 * MIN_SEGMENT, 1).is_empty());
+        assert_eq!(
+            ranges(3 * MIN_SEGMENT + 2, 2),
+            [
+                0..3 * MIN_SEGMENT / 2 + 1,
+                3 * MIN_SEGMENT / 2 + 1..3 * MIN_SEGMENT + 2
+            ]
+        );
+        assert_eq!(ranges(3 * MIN_SEGMENT, 8).len(), 3);
+    }
+}
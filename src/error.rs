@@ -0,0 +1,77 @@
+use std::{io, path::PathBuf, process::ExitStatus};
+
+use thiserror::Error;
+
+use crate::failure::Failure;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything the library can fail with. Failures worth telling apart get
+/// their own variant, the rest carry a message.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{} doesn't match its checksum, expected {expected} but got {actual}", path.display())]
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    #[error("Invalid checksum {0}, expected blake3:<hex> or sha256:<hex>")]
+    InvalidChecksum(String),
+    #[error("Failed to parse {what}: {message}")]
+    Parse { what: String, message: String },
+    #[error("Invalid package: {0}")]
+    InvalidPackage(String),
+    #[error("Unknown variable %{{{0}}}")]
+    UnknownVariable(String),
+    #[error("Step '{step}' failed ({status})")]
+    StepFailed { step: String, status: ExitStatus },
+    /// A failure with its own exit code.
+    #[error("{message}")]
+    Failed { failure: Failure, message: String },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    TomlDe(#[from] toml_edit::de::Error),
+    #[error(transparent)]
+    TomlSer(#[from] toml_edit::ser::Error),
+    #[error(transparent)]
+    Toml(#[from] toml_edit::TomlError),
+    #[error("Invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("Invalid key: {0}")]
+    Key(#[from] ed25519_dalek::SignatureError),
+    #[error("{0}")]
+    Message(String),
+}
+
+impl Error {
+    /// The failure category of this error, if it has one.
+    pub fn failure(&self) -> Option<Failure> {
+        match self {
+            Self::Failed { failure, .. } => Some(*failure),
+            Self::Http(_) => Some(Failure::Network),
+            _ => None,
+        }
+    }
+}
+
+/// Returns early with an error message, like `anyhow::bail!`.
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::error::Error::Message(format!($($arg)*)))
+    };
+}
+
+/// An error message, like `anyhow::anyhow!`.
+macro_rules! format_err {
+    ($($arg:tt)*) => {
+        $crate::error::Error::Message(format!($($arg)*))
+    };
+}
+
+pub(crate) use {bail, format_err};
@@ -0,0 +1,612 @@
+use std::{
+    fmt::Debug,
+    fs::File,
+    future::Future,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::{
+        fd::{BorrowedFd, FromRawFd},
+        unix::net::UnixStream,
+    },
+    process::{Command, ExitStatus, Stdio},
+    sync::{Arc, LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "progress")]
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use nix::fcntl::{FcntlArg, fcntl};
+use serde::Serialize;
+use tokio::task::{self, JoinHandle};
+#[cfg(feature = "tracing")]
+use tracing::{Level, Subscriber, field::Field};
+#[cfg(feature = "tracing")]
+use tracing_subscriber::{Layer, layer::Context};
+
+#[cfg(not(feature = "progress"))]
+use self::quiet::{MultiProgress, ProgressBar, ProgressStyle};
+use crate::{
+    error::{Result, bail, format_err},
+    log::warn,
+};
+
+/// How often download and extraction progress is reported at most.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Every progress bar, so that the transaction bar stays below the download
+/// or extraction currently going on.
+static BARS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+/// Where events go, if a frontend asked for them.
+static SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Receives what blossom is doing, for applications embedding it to render
+/// their own interface. Pass one to [`observe`] along with any entry point,
+/// e.g. `observe(observer, commands::build(root, false)).await`.
+pub trait BlossomObserver: Send + Sync {
+    /// Every event, as written to the event stream. The methods below are
+    /// called for the events they cover as well.
+    fn on_event(&self, _event: &Event) {}
+
+    /// A download started, progressed or finished, `total` being unknown when
+    /// the server doesn't tell.
+    fn on_download_progress(&self, _url: &str, _downloaded: u64, _total: Option<u64>) {}
+
+    /// A line printed by a build step, on its stdout or stderr. Step output
+    /// goes here instead of the terminal.
+    fn on_step_output(&self, _package: &str, _step: &str, _line: &str) {}
+
+    /// An install, upgrade or removal moved on to `phase`, `Done` coming
+    /// last whether or not it changed anything.
+    fn on_transaction_phase(&self, _phase: Phase) {}
+
+    /// A warning, provided the `tracing` feature is enabled and
+    /// `ObserverLayer` is part of the tracing subscriber.
+    fn on_warning(&self, _message: &str) {}
+}
+
+tokio::task_local! {
+    static OBSERVER: Arc<dyn BlossomObserver>;
+}
+
+/// Runs `future` with `observer` receiving its events. Operations running
+/// concurrently can each have their own observer.
+///
+/// The observer is task-local: blossom hands it to the threads and blocking
+/// tasks it starts for an operation, but not to the rayon pool hashing
+/// archives, which reports nothing, and tasks the application spawns from
+/// within `future` have to be wrapped in `observe` again to be observed.
+pub async fn observe<F: Future>(observer: Arc<dyn BlossomObserver>, future: F) -> F::Output {
+    OBSERVER.scope(observer, future).await
+}
+
+/// The observer of the current operation, if any.
+pub fn observer() -> Option<Arc<dyn BlossomObserver>> {
+    OBSERVER.try_with(Arc::clone).ok()
+}
+
+/// Runs `f` with `observer` receiving its events, for threads the observer
+/// of an operation doesn't follow to on its own.
+pub fn with_observer<R>(observer: Option<Arc<dyn BlossomObserver>>, f: impl FnOnce() -> R) -> R {
+    match observer {
+        Some(observer) => OBSERVER.sync_scope(observer, f),
+        None => f(),
+    }
+}
+
+/// Like [`task::spawn_blocking`], `f` keeping the observer of the current
+/// operation.
+pub fn spawn_blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> JoinHandle<R> {
+    let observer = observer();
+
+    task::spawn_blocking(move || with_observer(observer, f))
+}
+
+/// A tracing layer passing warnings on to the observer of the operation
+/// logging them.
+#[cfg(feature = "tracing")]
+pub struct ObserverLayer;
+
+#[cfg(feature = "tracing")]
+impl<S: Subscriber> Layer<S> for ObserverLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::WARN {
+            return;
+        }
+
+        let Some(observer) = observer() else {
+            return;
+        };
+
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        observer.on_warning(&message.0);
+    }
+}
+
+#[cfg(feature = "tracing")]
+struct Message(String);
+
+#[cfg(feature = "tracing")]
+impl tracing::field::Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
+        }
+    }
+}
+
+/// Runs the step `command`, sending its output line by line to `observer`.
+pub fn run_observed(
+    command: &mut Command,
+    observer: &dyn BlossomObserver,
+    package: &str,
+    step: &str,
+) -> io::Result<ExitStatus> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    let forward = |output: Box<dyn Read + Send>| {
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            observer.on_step_output(package, step, &line);
+        }
+    };
+
+    thread::scope(|scope| {
+        if let Some(stdout) = stdout {
+            scope.spawn(|| forward(Box::new(stdout)));
+        }
+        if let Some(stderr) = stderr {
+            scope.spawn(|| forward(Box::new(stderr)));
+        }
+
+        child.wait()
+    })
+}
+
+/// A progress event, written as a JSON line for frontends rendering their own
+/// progress.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    DownloadStarted {
+        url: &'a str,
+        total: Option<u64>,
+    },
+    DownloadProgress {
+        url: &'a str,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    DownloadFinished {
+        url: &'a str,
+        downloaded: u64,
+    },
+    ExtractStarted {
+        package: &'a str,
+        total: u64,
+    },
+    ExtractProgress {
+        package: &'a str,
+        files: u64,
+        extracted: u64,
+        total: u64,
+    },
+    ExtractFinished {
+        package: &'a str,
+        files: u64,
+        extracted: u64,
+    },
+    StepStarted {
+        package: &'a str,
+        step: &'a str,
+    },
+    StepFinished {
+        package: &'a str,
+        step: &'a str,
+        success: bool,
+    },
+    Phase {
+        phase: Phase,
+    },
+    /// A package of the transaction is being installed or removed.
+    Package {
+        action: Action,
+        name: &'a str,
+        version: &'a str,
+    },
+    /// `done` out of `total` packages of the transaction are installed or
+    /// removed.
+    TransactionProgress {
+        done: usize,
+        total: usize,
+    },
+}
+
+/// The phases of a transaction, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Resolve,
+    Download,
+    Remove,
+    Install,
+    Hooks,
+    Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Install,
+    Remove,
+}
+
+/// Sends events to `target`: a file descriptor inherited from the frontend,
+/// as `3` or `fd:3`, or a listening unix socket as `unix:/path`.
+pub fn open(target: &str) -> Result<()> {
+    let sink: Box<dyn Write + Send> = match target.strip_prefix("unix:") {
+        Some(path) => Box::new(
+            UnixStream::connect(path)
+                .map_err(|e| format_err!("Failed to connect to {path}: {e}"))?,
+        ),
+        None => {
+            let fd = target
+                .strip_prefix("fd:")
+                .unwrap_or(target)
+                .parse()
+                .map_err(|_| {
+                    format_err!(
+                        "Invalid event target {target}, expected a file descriptor or unix:PATH"
+                    )
+                })?;
+
+            if fd < 0 {
+                bail!("Invalid event target {target}, expected a file descriptor or unix:PATH");
+            }
+
+            // SAFETY: the descriptor is only borrowed to check that it is open.
+            fcntl(unsafe { BorrowedFd::borrow_raw(fd) }, FcntlArg::F_GETFD)
+                .map_err(|e| format_err!("File descriptor {fd} isn't open: {e}"))?;
+
+            // SAFETY: the descriptor is open and only used for events from
+            // here on.
+            Box::new(unsafe { File::from_raw_fd(fd) })
+        }
+    };
+
+    *SINK
+        .lock()
+        .map_err(|_| format_err!("Event stream lock poisoned"))? = Some(sink);
+
+    Ok(())
+}
+
+/// Writes an event to the stream, if there is one, and passes it to the
+/// observer. A stream that can't be written to anymore is dropped, the
+/// frontend went away.
+pub fn emit(event: Event) {
+    if let Some(observer) = observer() {
+        notify(&*observer, &event);
+    }
+
+    let Ok(mut sink) = SINK.lock() else {
+        return;
+    };
+
+    let Some(writer) = sink.as_mut() else {
+        return;
+    };
+
+    let result = serde_json::to_writer(&mut *writer, &event)
+        .map_err(std::io::Error::from)
+        .and_then(|_| writer.write_all(b"\n"))
+        .and_then(|_| writer.flush());
+
+    if let Err(e) = result {
+        warn!("Stopped sending events: {e}");
+        *sink = None;
+    }
+}
+
+fn notify(observer: &dyn BlossomObserver, event: &Event) {
+    observer.on_event(event);
+
+    match *event {
+        Event::DownloadStarted { url, total } => observer.on_download_progress(url, 0, total),
+        Event::DownloadProgress {
+            url,
+            downloaded,
+            total,
+        } => observer.on_download_progress(url, downloaded, total),
+        Event::DownloadFinished { url, downloaded } => {
+            observer.on_download_progress(url, downloaded, Some(downloaded))
+        }
+        Event::Phase { phase } => observer.on_transaction_phase(phase),
+        _ => {}
+    }
+}
+
+/// Tracks a download on the terminal and in the event stream.
+pub struct Download<'a> {
+    url: &'a str,
+    total: Option<u64>,
+    downloaded: u64,
+    reported: Instant,
+    progress_bar: ProgressBar,
+}
+
+impl<'a> Download<'a> {
+    pub fn start(url: &'a str, total: Option<u64>) -> Self {
+        emit(Event::DownloadStarted { url, total });
+
+        Self {
+            url,
+            total,
+            downloaded: 0,
+            reported: Instant::now(),
+            progress_bar: BARS.add(ProgressBar::new(total.unwrap_or(0))),
+        }
+    }
+
+    pub fn inc(&mut self, bytes: u64) {
+        self.downloaded += bytes;
+        self.progress_bar.inc(bytes);
+
+        if self.reported.elapsed() >= PROGRESS_INTERVAL {
+            self.reported = Instant::now();
+            emit(Event::DownloadProgress {
+                url: self.url,
+                downloaded: self.downloaded,
+                total: self.total,
+            });
+        }
+    }
+
+    pub fn finish(self) {
+        self.progress_bar.finish();
+        emit(Event::DownloadFinished {
+            url: self.url,
+            downloaded: self.downloaded,
+        });
+    }
+}
+
+/// Tracks unpacking a package on the terminal and in the event stream, by
+/// files and by bytes out of its installed size.
+pub struct Extraction<'a> {
+    package: &'a str,
+    total: u64,
+    files: u64,
+    extracted: u64,
+    reported: Instant,
+    progress_bar: ProgressBar,
+}
+
+impl<'a> Extraction<'a> {
+    pub fn start(package: &'a str, total: u64) -> Self {
+        emit(Event::ExtractStarted { package, total });
+
+        let progress_bar = BARS.add(ProgressBar::new(total));
+        if let Ok(style) =
+            ProgressStyle::with_template("{msg} {wide_bar} {bytes}/{total_bytes} ({prefix} files)")
+        {
+            progress_bar.set_style(style);
+        }
+        progress_bar.set_message(package.to_string());
+        progress_bar.set_prefix("0");
+
+        Self {
+            package,
+            total,
+            files: 0,
+            extracted: 0,
+            reported: Instant::now(),
+            progress_bar,
+        }
+    }
+
+    /// Records one more file of `bytes` bytes.
+    pub fn file(&mut self, bytes: u64) {
+        self.files += 1;
+        self.extracted += bytes;
+        self.progress_bar.inc(bytes);
+
+        if self.reported.elapsed() >= PROGRESS_INTERVAL {
+            self.reported = Instant::now();
+            self.progress_bar.set_prefix(self.files.to_string());
+            emit(Event::ExtractProgress {
+                package: self.package,
+                files: self.files,
+                extracted: self.extracted,
+                total: self.total,
+            });
+        }
+    }
+
+    pub fn finish(self) {
+        self.progress_bar.finish_and_clear();
+        emit(Event::ExtractFinished {
+            package: self.package,
+            files: self.files,
+            extracted: self.extracted,
+        });
+    }
+}
+
+/// Tracks the packages of a transaction on the terminal and in the event
+/// stream.
+pub struct Transaction {
+    done: usize,
+    total: usize,
+    progress_bar: ProgressBar,
+}
+
+impl Transaction {
+    pub fn start(total: usize) -> Self {
+        let progress_bar = BARS.add(ProgressBar::new(total as u64));
+        if let Ok(style) = ProgressStyle::with_template("[{pos}/{len}] {wide_bar} {msg}") {
+            progress_bar.set_style(style);
+        }
+
+        Self {
+            done: 0,
+            total,
+            progress_bar,
+        }
+    }
+
+    /// Starts installing or removing a package.
+    pub fn package(&self, action: Action, name: &str, version: &str) {
+        self.progress_bar.set_message(format!(
+            "{} {name} {version}",
+            match action {
+                Action::Install => "Installing",
+                Action::Remove => "Removing",
+            }
+        ));
+        emit(Event::Package {
+            action,
+            name,
+            version,
+        });
+    }
+
+    /// Finishes the current package.
+    pub fn done(&mut self) {
+        self.done += 1;
+        self.progress_bar.inc(1);
+        emit(Event::TransactionProgress {
+            done: self.done,
+            total: self.total,
+        });
+    }
+
+    pub fn finish(self) {
+        self.progress_bar.finish_and_clear();
+    }
+}
+
+/// Stand-ins for the progress bars of `indicatif`: without the `progress`
+/// feature, nothing is drawn and progress only goes to events.
+#[cfg(not(feature = "progress"))]
+mod quiet {
+    pub struct MultiProgress;
+
+    impl MultiProgress {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn add(&self, progress_bar: ProgressBar) -> ProgressBar {
+            progress_bar
+        }
+    }
+
+    pub struct ProgressBar;
+
+    impl ProgressBar {
+        pub fn new(_len: u64) -> Self {
+            Self
+        }
+
+        pub fn inc(&self, _delta: u64) {}
+
+        pub fn set_style(&self, _style: ProgressStyle) {}
+
+        pub fn set_message(&self, _message: impl Into<String>) {}
+
+        pub fn set_prefix(&self, _prefix: impl Into<String>) {}
+
+        pub fn finish(&self) {}
+
+        pub fn finish_and_clear(&self) {}
+    }
+
+    pub struct ProgressStyle;
+
+    impl ProgressStyle {
+        pub fn with_template(_template: &str) -> Result<Self, ()> {
+            Ok(Self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl BlossomObserver for Recorder {
+        fn on_download_progress(&self, url: &str, downloaded: u64, total: Option<u64>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{url} {downloaded}/{total:?}"));
+        }
+
+        fn on_step_output(&self, package: &str, step: &str, line: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{package} {step}: {line}"));
+        }
+
+        fn on_transaction_phase(&self, phase: Phase) {
+            self.0.lock().unwrap().push(format!("{phase:?}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observe() {
+        let recorder = Arc::new(Recorder::default());
+
+        observe(recorder.clone(), async {
+            emit(Event::Phase {
+                phase: Phase::Download,
+            });
+            emit(Event::DownloadFinished {
+                url: "foo.peach",
+                downloaded: 10,
+            });
+
+            let mut command = Command::new("/bin/sh");
+            command.args(["-c", "echo built"]);
+            let status = run_observed(&mut command, &*observer().unwrap(), "foo", "build");
+            assert!(status.unwrap().success());
+
+            spawn_blocking(|| {
+                emit(Event::Phase {
+                    phase: Phase::Install,
+                })
+            })
+            .await
+            .unwrap();
+        })
+        .await;
+
+        emit(Event::Phase { phase: Phase::Done });
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "Download",
+                "foo.peach 10/Some(10)",
+                "foo build: built",
+                "Install"
+            ]
+        );
+    }
+}
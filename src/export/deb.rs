@@ -0,0 +1,250 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use flate2::{Compression, write::GzEncoder};
+use tar::{Builder, Header};
+
+use super::{Naming, architecture, copy_payload};
+use crate::{
+    archive,
+    error::Result,
+    package::{PeachInfo, dependency_constraints, dependency_name},
+    version::Operator,
+};
+
+/// Rewraps the package archive at `path` into a Debian binary package in
+/// `out_dir`, for hosts running dpkg. Returns the path of the package.
+pub fn deb(path: &Path, out_dir: &Path) -> Result<PathBuf> {
+    let peach_info = archive::read_info(path)?;
+    let mtime = peach_info
+        .build_date
+        .unwrap_or_else(Utc::now)
+        .timestamp()
+        .max(0) as u64;
+
+    let mut control = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    append_file(
+        &mut control,
+        "./control",
+        &control_file(&peach_info)?,
+        mtime,
+    )?;
+    if !peach_info.backup.is_empty() {
+        let conffiles: String = peach_info
+            .backup
+            .iter()
+            .map(|path| format!("/{path}\n"))
+            .collect();
+        append_file(&mut control, "./conffiles", &conffiles, mtime)?;
+    }
+    let control = control.into_inner()?.finish()?;
+
+    let mut data = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    copy_payload(path, &mut data)?;
+    let data = data.into_inner()?.finish()?;
+
+    let deb_path = out_dir.join(format!(
+        "{}_{}_{}.deb",
+        package_name(&peach_info.info.name),
+        version(&peach_info.info.version),
+        architecture(Naming::Deb)
+    ));
+    let mut deb = File::create(&deb_path)?;
+
+    deb.write_all(b"!<arch>\n")?;
+    append_member(&mut deb, "debian-binary", b"2.0\n", mtime)?;
+    append_member(&mut deb, "control.tar.gz", &control, mtime)?;
+    append_member(&mut deb, "data.tar.gz", &data, mtime)?;
+
+    Ok(deb_path)
+}
+
+/// The `control` file describing the package to dpkg.
+fn control_file(peach_info: &PeachInfo) -> Result<String> {
+    let info = &peach_info.info;
+    let mut control = format!(
+        "Package: {}\nVersion: {}\nArchitecture: {}\nMaintainer: unknown\n\
+         Installed-Size: {}\n",
+        package_name(&info.name),
+        version(&info.version),
+        architecture(Naming::Deb),
+        peach_info.installed_size.div_ceil(1024),
+    );
+
+    let mut relationships = Vec::new();
+    if let Some(dependencies) = &peach_info.dependencies {
+        relationships.push(("Depends", &dependencies.required));
+        relationships.push(("Suggests", &dependencies.optional));
+    }
+    relationships.push(("Provides", &info.provides));
+    relationships.push(("Conflicts", &info.conflicts));
+    relationships.push(("Replaces", &info.replaces));
+
+    for (field, dependencies) in relationships {
+        let relations = dependencies
+            .iter()
+            .map(|dependency| relations(dependency))
+            .collect::<Result<Vec<_>>>()?
+            .concat();
+
+        if !relations.is_empty() {
+            control.push_str(&format!("{field}: {}\n", relations.join(", ")));
+        }
+    }
+
+    // The first line of a description is its synopsis, which can't be empty.
+    let description = match info.description.english().trim() {
+        "" => &info.name,
+        description => description,
+    };
+    control.push_str(&format!(
+        "Description: {}\n",
+        description.replace('\n', " ")
+    ));
+
+    Ok(control)
+}
+
+/// A blossom dependency as Debian relations, one for each of its constraints
+/// as Debian relations take one.
+fn relations(dependency: &str) -> Result<Vec<String>> {
+    // Optional dependencies are followed by why they are needed.
+    let requirement = dependency.split(':').next().unwrap_or(dependency);
+    let name = package_name(dependency_name(requirement));
+    let constraints = dependency_constraints(requirement)?;
+
+    if constraints.is_empty() {
+        return Ok(vec![name]);
+    }
+
+    Ok(constraints
+        .iter()
+        .map(|constraint| {
+            let operator = match constraint.operator {
+                Operator::Less => "<<",
+                Operator::LessEqual => "<=",
+                Operator::Equal => "=",
+                Operator::GreaterEqual => ">=",
+                Operator::Greater => ">>",
+            };
+
+            format!("{name} ({operator} {})", version(&constraint.version))
+        })
+        .collect())
+}
+
+/// `name` as a Debian package name, which only holds lowercase letters,
+/// digits, `+`, `-` and `.`.
+fn package_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '+' | '-' | '.' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect()
+}
+
+/// `version` as a Debian version, in which `-` would start a revision and
+/// which has to start with a digit.
+fn version(version: &str) -> String {
+    let version: String = version
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '~') {
+                true => c,
+                false => '+',
+            },
+        )
+        .collect();
+
+    match version.starts_with(|c: char| c.is_ascii_digit()) {
+        true => version,
+        false => format!("0+{version}"),
+    }
+}
+
+fn append_file<W: Write>(
+    tar: &mut Builder<W>,
+    path: &str,
+    contents: &str,
+    mtime: u64,
+) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_cksum();
+
+    Ok(tar.append_data(&mut header, path, contents.as_bytes())?)
+}
+
+/// Appends a member to an ar archive, the container of Debian packages.
+fn append_member<W: Write>(ar: &mut W, name: &str, data: &[u8], mtime: u64) -> Result<()> {
+    writeln!(
+        ar,
+        "{name:<16}{mtime:<12}{:<6}{:<6}{:<8o}{:<10}`",
+        0,
+        0,
+        0o100644,
+        data.len()
+    )?;
+    ar.write_all(data)?;
+
+    // Members are aligned on two bytes.
+    if data.len() % 2 == 1 {
+        ar.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{Dependencies, Info};
+
+    #[test]
+    fn test_control_file() {
+        let peach_info = PeachInfo {
+            info: Info {
+                name: "Foo_bar".to_string(),
+                version: "1.2-rc1".to_string(),
+                description: "A foo".into(),
+                license: spdx::Expression::parse("MIT").unwrap(),
+                provides: vec!["foo=1.2".to_string()],
+                conflicts: Vec::new(),
+                replaces: vec!["oldfoo".to_string()],
+                keywords: Vec::new(),
+            },
+            dependencies: Some(Dependencies {
+                required: vec!["libc>=2.30,<3".to_string()],
+                optional: vec!["bash: for the completions".to_string()],
+                build: vec!["gcc".to_string()],
+                check: Vec::new(),
+            }),
+            backup: Vec::new(),
+            build_date: None,
+            installed_size: 2049,
+            special_permissions: Vec::new(),
+            changelog: Vec::new(),
+        };
+
+        assert_eq!(
+            control_file(&peach_info).unwrap(),
+            format!(
+                "Package: foo-bar\nVersion: 1.2+rc1\nArchitecture: {}\nMaintainer: unknown\n\
+                 Installed-Size: 3\nDepends: libc (>= 2.30), libc (<< 3)\nSuggests: bash\n\
+                 Provides: foo (= 1.2)\nReplaces: oldfoo\nDescription: A foo\n",
+                architecture(Naming::Deb)
+            )
+        );
+        assert_eq!(version("git"), "0+git");
+    }
+}
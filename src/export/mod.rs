@@ -0,0 +1,129 @@
+mod deb;
+mod oci;
+mod pacman;
+mod rpm;
+mod sysext;
+
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use camino::Utf8PathBuf;
+use tar::{Builder, EntryType, Header};
+
+use crate::{
+    archive::{self, entry_path},
+    error::Result,
+    package::PeachInfo,
+    sbom::Sbom,
+};
+
+pub use deb::deb;
+pub use oci::oci;
+pub use pacman::pacman;
+pub use rpm::rpm;
+pub use sysext::{Extension, sysext};
+
+/// The package formats blossom exports to, which each name architectures
+/// their own way.
+#[derive(Clone, Copy)]
+enum Naming {
+    Deb,
+    Rpm,
+    Pacman,
+    Oci,
+    Systemd,
+}
+
+/// The name `naming` gives to the architecture blossom was built for, which
+/// packages are built for too. Rust calls both byte orders of 64-bit POWER
+/// powerpc64, so the byte order tells them apart.
+fn architecture(naming: Naming) -> &'static str {
+    let little_endian = cfg!(target_endian = "little");
+
+    match (std::env::consts::ARCH, naming) {
+        ("x86_64", Naming::Deb | Naming::Oci) => "amd64",
+        ("x86_64", Naming::Systemd) => "x86-64",
+        ("x86", Naming::Deb) => "i386",
+        ("x86", Naming::Oci) => "386",
+        ("x86", Naming::Rpm | Naming::Pacman) => "i686",
+        ("aarch64", Naming::Deb | Naming::Oci | Naming::Systemd) => "arm64",
+        ("arm", Naming::Deb) => "armhf",
+        ("arm", Naming::Rpm) => "armv7hl",
+        ("arm", Naming::Pacman) => "armv7h",
+        ("powerpc64", Naming::Deb) if little_endian => "ppc64el",
+        ("powerpc64", Naming::Rpm | Naming::Oci) if little_endian => "ppc64le",
+        ("powerpc64", Naming::Pacman) if little_endian => "powerpc64le",
+        ("powerpc64", Naming::Systemd) if little_endian => "ppc64-le",
+        ("powerpc64", Naming::Deb | Naming::Rpm | Naming::Oci | Naming::Systemd) => "ppc64",
+        ("loongarch64", Naming::Deb | Naming::Oci) => "loong64",
+        (arch, _) => arch,
+    }
+}
+
+/// A file of a package archive, owned by root once exported.
+struct PayloadFile {
+    /// The path relative to the install root, empty for the root itself.
+    path: Utf8PathBuf,
+    header: Header,
+    /// What symbolic and hard links point to.
+    link: Option<Utf8PathBuf>,
+    data: Vec<u8>,
+}
+
+/// The files of the package archive at `path`, leaving out the metadata only
+/// blossom reads.
+fn read_payload(path: &Path) -> Result<Vec<PayloadFile>> {
+    let mut archive = archive::open(path)?;
+    let mut files = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry_path(&entry.path()?)?;
+
+        if path == PeachInfo::FILE_NAME || path == Sbom::FILE_NAME {
+            continue;
+        }
+
+        let mut header = entry.header().clone();
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_username("root")?;
+        header.set_groupname("root")?;
+
+        let link = match header.entry_type() {
+            EntryType::Symlink | EntryType::Link => entry
+                .link_name()?
+                .map(|link| Utf8PathBuf::from(link.to_string_lossy().into_owned())),
+            _ => None,
+        };
+
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+
+        files.push(PayloadFile {
+            path,
+            header,
+            link,
+            data,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Copies the files of the package archive at `path` into `builder`, under
+/// `./`.
+fn copy_payload<W: Write>(path: &Path, builder: &mut Builder<W>) -> Result<()> {
+    for mut file in read_payload(path)? {
+        let target = format!("./{}", file.path);
+
+        match &file.link {
+            Some(link) => builder.append_link(&mut file.header, &target, link)?,
+            None => builder.append_data(&mut file.header, &target, file.data.as_slice())?,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use chrono::{SecondsFormat, Utc};
+use flate2::{Compression, write::GzEncoder};
+use nix::unistd::geteuid;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tar::{Builder, EntryType, Header, HeaderMode};
+
+use super::{Naming, architecture};
+use crate::error::{Result, bail};
+
+const MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+/// Packs the installed root at `root` into an OCI image archive in `out_dir`,
+/// as a single layer tagged `reference` (`name:tag`). The archive is an OCI
+/// layout that `docker load` and `podman load` also read. Returns its path.
+pub fn oci(root: &Path, reference: &str, out_dir: &Path) -> Result<PathBuf> {
+    let (name, tag) = split_reference(reference)?;
+
+    let layer = layer(root)?;
+    let diff_id = digest(&layer);
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    gz.write_all(&layer)?;
+    let layer = gz.finish()?;
+
+    let config = serde_json::to_vec(&json!({
+        "created": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        "architecture": architecture(Naming::Oci),
+        "os": "linux",
+        "config": {
+            "Env": ["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"],
+        },
+        "rootfs": {
+            "type": "layers",
+            "diff_ids": [diff_id],
+        },
+        "history": [{ "created_by": "blossom export oci" }],
+    }))?;
+
+    let manifest = serde_json::to_vec(&json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_TYPE,
+        "config": descriptor(CONFIG_TYPE, &config),
+        "layers": [descriptor(LAYER_TYPE, &layer)],
+    }))?;
+
+    let mut manifest_descriptor = descriptor(MANIFEST_TYPE, &manifest);
+    manifest_descriptor["annotations"] = json!({
+        "io.containerd.image.name": format!("{name}:{tag}"),
+        "org.opencontainers.image.ref.name": tag,
+    });
+    let index = serde_json::to_vec(&json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [manifest_descriptor],
+    }))?;
+
+    // What docker load reads instead of the index.
+    let docker_manifest = serde_json::to_vec(&json!([{
+        "Config": blob_path(&config),
+        "RepoTags": [format!("{name}:{tag}")],
+        "Layers": [blob_path(&layer)],
+    }]))?;
+
+    let image_path = out_dir.join(format!("{}-{tag}.tar", name.replace('/', "-")));
+    let mut tar = Builder::new(File::create(&image_path)?);
+
+    append_file(&mut tar, "oci-layout", br#"{"imageLayoutVersion":"1.0.0"}"#)?;
+    append_file(&mut tar, "index.json", &index)?;
+    append_file(&mut tar, "manifest.json", &docker_manifest)?;
+    for blob in [&config, &layer, &manifest] {
+        append_file(&mut tar, &blob_path(blob), blob)?;
+    }
+    tar.into_inner()?.flush()?;
+
+    Ok(image_path)
+}
+
+/// Splits `name:tag`, the tag defaulting to `latest` as with docker. The
+/// colon of a registry port isn't a tag.
+fn split_reference(reference: &str) -> Result<(&str, &str)> {
+    let (name, tag) = match reference.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name, tag),
+        _ => (reference, "latest"),
+    };
+
+    if name.is_empty() || tag.is_empty() {
+        bail!("Invalid image reference {reference}, expected name:tag");
+    }
+
+    Ok((name, tag))
+}
+
+/// The uncompressed tarball of every file under `root`, in a stable order so
+/// that the same root gives the same layer.
+fn layer(root: &Path) -> Result<Vec<u8>> {
+    let mut tar = Builder::new(Vec::new());
+    let mut links = HashMap::new();
+
+    append_tree(&mut tar, root, Path::new(""), &mut links)?;
+
+    Ok(tar.into_inner()?)
+}
+
+fn append_tree(
+    tar: &mut Builder<Vec<u8>>,
+    root: &Path,
+    dir: &Path,
+    links: &mut HashMap<(u64, u64), PathBuf>,
+) -> Result<()> {
+    let mut entries = fs::read_dir(root.join(dir))?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = dir.join(entry.file_name());
+        let metadata = entry.metadata()?;
+
+        let mut header = Header::new_gnu();
+        header.set_metadata_in_mode(&metadata, HeaderMode::Complete);
+        // Files of an unprivileged install belong to whoever ran it, rather
+        // than root as they would on a system.
+        if !geteuid().is_root() {
+            header.set_uid(0);
+            header.set_gid(0);
+        }
+
+        let file_type = metadata.file_type();
+        if file_type.is_dir() {
+            header.set_size(0);
+            header.set_cksum();
+            tar.append_data(&mut header, &path, std::io::empty())?;
+            append_tree(tar, root, &path, links)?;
+        } else if file_type.is_symlink() {
+            header.set_size(0);
+            tar.append_link(&mut header, &path, fs::read_link(entry.path())?)?;
+        } else if file_type.is_file() {
+            if metadata.nlink() > 1 {
+                if let Some(target) = links.get(&(metadata.dev(), metadata.ino())) {
+                    header.set_entry_type(EntryType::Link);
+                    header.set_size(0);
+                    tar.append_link(&mut header, &path, target)?;
+                    continue;
+                }
+                links.insert((metadata.dev(), metadata.ino()), path.clone());
+            }
+
+            header.set_cksum();
+            tar.append_data(&mut header, &path, File::open(entry.path())?)?;
+        } else {
+            bail!(
+                "{} isn't a file, directory or symbolic link",
+                entry.path().display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn descriptor(media_type: &str, blob: &[u8]) -> Value {
+    json!({
+        "mediaType": media_type,
+        "digest": digest(blob),
+        "size": blob.len(),
+    })
+}
+
+fn digest(blob: &[u8]) -> String {
+    format!(
+        "sha256:{}",
+        base16ct::lower::encode_string(&Sha256::digest(blob))
+    )
+}
+
+fn blob_path(blob: &[u8]) -> String {
+    format!("blobs/sha256/{}", &digest(blob)["sha256:".len()..])
+}
+
+fn append_file<W: Write>(tar: &mut Builder<W>, path: &str, contents: &[u8]) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+
+    Ok(tar.append_data(&mut header, path, contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_reference() {
+        assert_eq!(
+            split_reference("myimg:latest").unwrap(),
+            ("myimg", "latest")
+        );
+        assert_eq!(split_reference("myimg").unwrap(), ("myimg", "latest"));
+        assert_eq!(
+            split_reference("localhost:5000/myimg").unwrap(),
+            ("localhost:5000/myimg", "latest")
+        );
+        assert!(split_reference("myimg:").is_err());
+    }
+
+    #[test]
+    fn test_layer() {
+        let root = std::env::temp_dir().join(format!("blossom-test-oci-{}", std::process::id()));
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::write(root.join("usr/bin/foo"), "foo").unwrap();
+        fs::hard_link(root.join("usr/bin/foo"), root.join("usr/bin/bar")).unwrap();
+        std::os::unix::fs::symlink("usr/bin", root.join("bin")).unwrap();
+
+        let layer = layer(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let mut archive = tar::Archive::new(layer.as_slice());
+        let entries: Vec<(String, EntryType)> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (
+                    entry.path().unwrap().to_string_lossy().into_owned(),
+                    entry.header().entry_type(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            entries,
+            [
+                ("bin".to_string(), EntryType::Symlink),
+                ("usr".to_string(), EntryType::Directory),
+                ("usr/bin".to_string(), EntryType::Directory),
+                ("usr/bin/bar".to_string(), EntryType::Regular),
+                ("usr/bin/foo".to_string(), EntryType::Link),
+            ]
+        );
+    }
+}
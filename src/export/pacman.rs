@@ -0,0 +1,259 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use flate2::{Compression, write::GzEncoder};
+use sha2::{Digest, Sha256};
+use tar::{Builder, EntryType, Header};
+
+use super::{Naming, PayloadFile, architecture, read_payload};
+use crate::{
+    archive,
+    error::Result,
+    package::{PeachInfo, dependency_constraints, dependency_name},
+};
+
+/// The release of exported packages, blossom versions don't have one.
+const RELEASE: &str = "1";
+
+/// Rewraps the package archive at `path` into a pacman package in `out_dir`,
+/// for Arch hosts. Returns the path of the package.
+pub fn pacman(path: &Path, out_dir: &Path) -> Result<PathBuf> {
+    let peach_info = archive::read_info(path)?;
+    let mtime = peach_info
+        .build_date
+        .unwrap_or_else(Utc::now)
+        .timestamp()
+        .max(0) as u64;
+
+    // pacman extracts over the root, which packages don't carry.
+    let files: Vec<PayloadFile> = read_payload(path)?
+        .into_iter()
+        .filter(|file| !file.path.as_str().is_empty())
+        .collect();
+
+    let pkginfo = pkginfo(&peach_info)?;
+    let mut mtree = String::from("#mtree\n");
+    mtree.push_str(&mtree_entry(
+        ".PKGINFO",
+        mtime,
+        "file",
+        0o644,
+        &format!(
+            " size={} sha256digest={}",
+            pkginfo.len(),
+            sha256(pkginfo.as_bytes())
+        ),
+    ));
+    for file in &files {
+        mtree.push_str(&mtree_file(file)?);
+    }
+
+    let mut mtree_gz = GzEncoder::new(Vec::new(), Compression::default());
+    mtree_gz.write_all(mtree.as_bytes())?;
+    let mtree = mtree_gz.finish()?;
+
+    let package_path = out_dir.join(format!(
+        "{}-{}-{RELEASE}-{}.pkg.tar.zst",
+        peach_info.info.name,
+        version(&peach_info.info.version),
+        architecture(Naming::Pacman)
+    ));
+    let mut tar = Builder::new(zstd::Encoder::new(File::create(&package_path)?, 19)?);
+
+    append_metadata(&mut tar, ".PKGINFO", pkginfo.as_bytes(), mtime)?;
+    append_metadata(&mut tar, ".MTREE", &mtree, mtime)?;
+
+    for mut file in files {
+        match &file.link {
+            Some(link) => tar.append_link(&mut file.header, &file.path, link)?,
+            None => tar.append_data(&mut file.header, &file.path, file.data.as_slice())?,
+        }
+    }
+    tar.into_inner()?.finish()?;
+
+    Ok(package_path)
+}
+
+/// The `.PKGINFO` file describing the package to pacman.
+fn pkginfo(peach_info: &PeachInfo) -> Result<String> {
+    let info = &peach_info.info;
+    let mut pkginfo = format!(
+        "# Generated by blossom\npkgname = {name}\npkgbase = {name}\npkgver = {}-{RELEASE}\n\
+         pkgdesc = {}\nbuilddate = {}\npackager = Unknown Packager\nsize = {}\narch = {}\n\
+         license = {}\n",
+        version(&info.version),
+        info.description.english().replace('\n', " "),
+        peach_info.build_date.unwrap_or_else(Utc::now).timestamp(),
+        peach_info.installed_size,
+        architecture(Naming::Pacman),
+        info.license,
+        name = info.name,
+    );
+
+    let mut relationships = vec![
+        ("replaces", &info.replaces),
+        ("conflict", &info.conflicts),
+        ("provides", &info.provides),
+    ];
+    if let Some(dependencies) = &peach_info.dependencies {
+        relationships.push(("depend", &dependencies.required));
+        relationships.push(("makedepend", &dependencies.build));
+    }
+
+    for (key, dependencies) in relationships {
+        for dependency in dependencies {
+            for relation in relations(dependency)? {
+                pkginfo.push_str(&format!("{key} = {relation}\n"));
+            }
+        }
+    }
+
+    // Optional dependencies are written the same in both, along with why
+    // they are needed.
+    if let Some(dependencies) = &peach_info.dependencies {
+        for dependency in &dependencies.optional {
+            pkginfo.push_str(&format!("optdepend = {dependency}\n"));
+        }
+    }
+
+    for path in &peach_info.backup {
+        pkginfo.push_str(&format!("backup = {path}\n"));
+    }
+
+    Ok(pkginfo)
+}
+
+/// A blossom dependency as pacman ones, which take a single constraint.
+fn relations(dependency: &str) -> Result<Vec<String>> {
+    let name = dependency_name(dependency);
+    let constraints = dependency_constraints(dependency)?;
+
+    if constraints.is_empty() {
+        return Ok(vec![name.to_string()]);
+    }
+
+    Ok(constraints
+        .iter()
+        .map(|constraint| format!("{name}{constraint}"))
+        .collect())
+}
+
+fn mtree_file(file: &PayloadFile) -> Result<String> {
+    let mode = file.header.mode()? & 0o7777;
+    let mtime = file.header.mtime()?;
+
+    Ok(match (file.header.entry_type(), &file.link) {
+        (EntryType::Directory, _) => mtree_entry(file.path.as_str(), mtime, "dir", mode, ""),
+        (EntryType::Symlink, Some(link)) => mtree_entry(
+            file.path.as_str(),
+            mtime,
+            "link",
+            mode,
+            &format!(" link={}", escape(link.as_str())),
+        ),
+        _ => mtree_entry(
+            file.path.as_str(),
+            mtime,
+            "file",
+            mode,
+            &format!(
+                " size={} sha256digest={}",
+                file.data.len(),
+                sha256(&file.data)
+            ),
+        ),
+    })
+}
+
+fn mtree_entry(path: &str, mtime: u64, kind: &str, mode: u32, rest: &str) -> String {
+    format!(
+        "./{} time={mtime}.0 uid=0 gid=0 mode={mode:o} type={kind}{rest}\n",
+        escape(path)
+    )
+}
+
+/// Escapes the bytes of `s` mtree would read as separators, in octal.
+fn escape(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'!'..=b'~' if byte != b'\\' && byte != b'#' => (byte as char).to_string(),
+            _ => format!("\\{byte:03o}"),
+        })
+        .collect()
+}
+
+fn sha256(data: &[u8]) -> String {
+    base16ct::lower::encode_string(&Sha256::digest(data))
+}
+
+fn append_metadata<W: Write>(
+    tar: &mut Builder<W>,
+    path: &str,
+    contents: &[u8],
+    mtime: u64,
+) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_cksum();
+
+    Ok(tar.append_data(&mut header, path, contents)?)
+}
+
+/// `version` as a pacman version, in which `-` would start a release.
+fn version(version: &str) -> String {
+    version.replace(['-', ':'], "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{Dependencies, Info};
+
+    #[test]
+    fn test_pkginfo() {
+        let peach_info = PeachInfo {
+            info: Info {
+                name: "foo".to_string(),
+                version: "1.2-rc1".to_string(),
+                description: "A foo".into(),
+                license: spdx::Expression::parse("MIT").unwrap(),
+                provides: Vec::new(),
+                conflicts: vec!["bar".to_string()],
+                replaces: Vec::new(),
+                keywords: Vec::new(),
+            },
+            dependencies: Some(Dependencies {
+                required: vec!["libc>=2.30,<3".to_string()],
+                optional: vec!["bash: for the completions".to_string()],
+                build: Vec::new(),
+                check: Vec::new(),
+            }),
+            backup: vec!["etc/foo.conf".into()],
+            build_date: Some(chrono::DateTime::from_timestamp(1700000000, 0).unwrap()),
+            installed_size: 2049,
+            special_permissions: Vec::new(),
+            changelog: Vec::new(),
+        };
+
+        assert_eq!(
+            pkginfo(&peach_info).unwrap(),
+            format!(
+                "# Generated by blossom\npkgname = foo\npkgbase = foo\npkgver = 1.2_rc1-1\n\
+                 pkgdesc = A foo\nbuilddate = 1700000000\npackager = Unknown Packager\n\
+                 size = 2049\narch = {}\nlicense = MIT\nconflict = bar\ndepend = libc>=2.30\n\
+                 depend = libc<3\noptdepend = bash: for the completions\n\
+                 backup = etc/foo.conf\n",
+                architecture(Naming::Pacman)
+            )
+        );
+        assert_eq!(escape("a b#"), "a\\040b\\043");
+    }
+}
@@ -0,0 +1,640 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use flate2::{Compression, write::GzEncoder};
+use sha2::{Digest, Sha256};
+use tar::EntryType;
+
+use super::{Naming, PayloadFile, architecture, read_payload};
+use crate::{
+    archive::{self, entry_path},
+    error::Result,
+    package::{PeachInfo, dependency_constraints, dependency_name},
+    version::Operator,
+};
+
+// The tags of the headers, from rpmtag.h.
+const HEADER_SIGNATURES: u32 = 62;
+const HEADER_IMMUTABLE: u32 = 63;
+const HEADER_I18NTABLE: u32 = 100;
+const SIG_SHA256: u32 = 273;
+const SIG_SIZE: u32 = 1000;
+const SIG_PAYLOADSIZE: u32 = 1007;
+const NAME: u32 = 1000;
+const VERSION: u32 = 1001;
+const RELEASE: u32 = 1002;
+const SUMMARY: u32 = 1004;
+const DESCRIPTION: u32 = 1005;
+const BUILDTIME: u32 = 1006;
+const BUILDHOST: u32 = 1007;
+const SIZE: u32 = 1009;
+const LICENSE: u32 = 1014;
+const GROUP: u32 = 1016;
+const OS: u32 = 1021;
+const ARCH: u32 = 1022;
+const FILESIZES: u32 = 1028;
+const FILEMODES: u32 = 1030;
+const FILERDEVS: u32 = 1033;
+const FILEMTIMES: u32 = 1034;
+const FILEDIGESTS: u32 = 1035;
+const FILELINKTOS: u32 = 1036;
+const FILEFLAGS: u32 = 1037;
+const FILEUSERNAME: u32 = 1039;
+const FILEGROUPNAME: u32 = 1040;
+const SOURCERPM: u32 = 1044;
+const PROVIDENAME: u32 = 1047;
+const REQUIREFLAGS: u32 = 1048;
+const REQUIRENAME: u32 = 1049;
+const REQUIREVERSION: u32 = 1050;
+const CONFLICTFLAGS: u32 = 1053;
+const CONFLICTNAME: u32 = 1054;
+const CONFLICTVERSION: u32 = 1055;
+const OBSOLETENAME: u32 = 1090;
+const FILEDEVICES: u32 = 1095;
+const FILEINODES: u32 = 1096;
+const PROVIDEFLAGS: u32 = 1112;
+const PROVIDEVERSION: u32 = 1113;
+const OBSOLETEFLAGS: u32 = 1114;
+const OBSOLETEVERSION: u32 = 1115;
+const DIRINDEXES: u32 = 1116;
+const BASENAMES: u32 = 1117;
+const DIRNAMES: u32 = 1118;
+const PAYLOADFORMAT: u32 = 1124;
+const PAYLOADCOMPRESSOR: u32 = 1125;
+const PAYLOADFLAGS: u32 = 1126;
+const FILEDIGESTALGO: u32 = 5011;
+const SUGGESTNAME: u32 = 5049;
+const SUGGESTVERSION: u32 = 5050;
+const SUGGESTFLAGS: u32 = 5051;
+const PAYLOADDIGEST: u32 = 5092;
+const PAYLOADDIGESTALGO: u32 = 5093;
+
+/// The hash algorithm number of SHA-256, from OpenPGP.
+const SHA256_ALGO: u32 = 8;
+
+// Flags of dependencies.
+const SENSE_LESS: u32 = 1 << 1;
+const SENSE_GREATER: u32 = 1 << 2;
+const SENSE_EQUAL: u32 = 1 << 3;
+const SENSE_RPMLIB: u32 = 1 << 24;
+
+// Flags of files.
+const FILE_CONFIG: u32 = 1 << 0;
+const FILE_NOREPLACE: u32 = 1 << 4;
+
+/// Features of rpm the package relies on, which rpm checks it has.
+const RPMLIB_FEATURES: [(&str, &str); 3] = [
+    ("rpmlib(CompressedFileNames)", "3.0.4-1"),
+    ("rpmlib(FileDigests)", "4.6.0-1"),
+    ("rpmlib(PayloadFilesHavePrefix)", "4.0-1"),
+];
+
+/// The release of exported packages, blossom versions don't have one.
+const RELEASE_NUMBER: &str = "1";
+
+#[derive(Debug, Clone, Copy)]
+enum Type {
+    Int16 = 3,
+    Int32 = 4,
+    String = 6,
+    Bin = 7,
+    StringArray = 8,
+    I18nString = 9,
+}
+
+/// A header of an rpm package, a table of typed values indexed by tags.
+#[derive(Debug, Default)]
+struct Header {
+    entries: Vec<(u32, Type, u32, Vec<u8>)>,
+}
+
+impl Header {
+    fn string(&mut self, tag: u32, value: &str) {
+        self.entries
+            .push((tag, Type::String, 1, nul_terminated([value])));
+    }
+
+    fn i18n_string(&mut self, tag: u32, value: &str) {
+        self.entries
+            .push((tag, Type::I18nString, 1, nul_terminated([value])));
+    }
+
+    fn strings<S: AsRef<str>>(&mut self, tag: u32, values: &[S]) {
+        if !values.is_empty() {
+            self.entries.push((
+                tag,
+                Type::StringArray,
+                values.len() as u32,
+                nul_terminated(values.iter().map(AsRef::as_ref)),
+            ));
+        }
+    }
+
+    fn int32s(&mut self, tag: u32, values: &[u32]) {
+        if !values.is_empty() {
+            let data = values
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect();
+            self.entries
+                .push((tag, Type::Int32, values.len() as u32, data));
+        }
+    }
+
+    fn int16s(&mut self, tag: u32, values: &[u16]) {
+        if !values.is_empty() {
+            let data = values
+                .iter()
+                .flat_map(|value| value.to_be_bytes())
+                .collect();
+            self.entries
+                .push((tag, Type::Int16, values.len() as u32, data));
+        }
+    }
+
+    /// Serializes the header, its entries sorted by tag after the `region`
+    /// entry marking them all as immutable.
+    fn into_bytes(mut self, region: u32) -> Vec<u8> {
+        self.entries.sort_by_key(|(tag, ..)| *tag);
+
+        let count = self.entries.len() as u32 + 1;
+        let mut index = Vec::new();
+        let mut store = Vec::new();
+
+        for (tag, kind, values, data) in self.entries {
+            let alignment = match kind {
+                Type::Int16 => 2,
+                Type::Int32 => 4,
+                _ => 1,
+            };
+            while store.len() % alignment != 0 {
+                store.push(0);
+            }
+
+            index.extend([tag, kind as u32, store.len() as u32, values].map(u32::to_be_bytes));
+            store.extend(data);
+        }
+
+        // The region entry points to a trailer at the end of the data, which
+        // in turn points back to the start of the index.
+        let trailer = store.len() as u32;
+        store.extend(
+            [region, Type::Bin as u32, (-(count as i32 * 16)) as u32, 16]
+                .map(u32::to_be_bytes)
+                .concat(),
+        );
+
+        let mut header = vec![0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0];
+        header.extend(count.to_be_bytes());
+        header.extend((store.len() as u32).to_be_bytes());
+        header.extend(
+            [region, Type::Bin as u32, trailer, 16]
+                .map(u32::to_be_bytes)
+                .concat(),
+        );
+        header.extend(index.concat());
+        header.extend(store);
+        header
+    }
+}
+
+fn nul_terminated<'a>(values: impl IntoIterator<Item = &'a str>) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for value in values {
+        data.extend(value.as_bytes());
+        data.push(0);
+    }
+
+    data
+}
+
+/// Rewraps the package archive at `path` into an rpm package in `out_dir`,
+/// for hosts running rpm. Returns the path of the package.
+pub fn rpm(path: &Path, out_dir: &Path) -> Result<PathBuf> {
+    let peach_info = archive::read_info(path)?;
+    let info = &peach_info.info;
+    let build_time = peach_info
+        .build_date
+        .unwrap_or_else(Utc::now)
+        .timestamp()
+        .max(0) as u32;
+
+    let name = info.name.as_str();
+    let version = version(&info.version);
+    let files = files(read_payload(path)?)?;
+
+    let (payload, payload_size) = payload(&files)?;
+
+    let mut header = Header::default();
+    header.strings(HEADER_I18NTABLE, &["C"]);
+    header.string(NAME, name);
+    header.string(VERSION, &version);
+    header.string(RELEASE, RELEASE_NUMBER);
+    header.i18n_string(
+        SUMMARY,
+        info.description.english().lines().next().unwrap_or(name),
+    );
+    header.i18n_string(DESCRIPTION, info.description.english());
+    header.int32s(BUILDTIME, &[build_time]);
+    header.string(BUILDHOST, "localhost");
+    header.int32s(SIZE, &[peach_info.installed_size as u32]);
+    header.string(LICENSE, info.license.as_ref());
+    header.i18n_string(GROUP, "Unspecified");
+    header.string(OS, "linux");
+    header.string(ARCH, architecture(Naming::Rpm));
+    // rpm tells binary packages apart from source ones by this.
+    header.string(
+        SOURCERPM,
+        &format!("{name}-{version}-{RELEASE_NUMBER}.src.rpm"),
+    );
+    file_tags(&mut header, &files, &peach_info);
+    dependency_tags(&mut header, &peach_info)?;
+    header.string(PAYLOADFORMAT, "cpio");
+    header.string(PAYLOADCOMPRESSOR, "gzip");
+    header.string(PAYLOADFLAGS, "6");
+    header.strings(
+        PAYLOADDIGEST,
+        &[base16ct::lower::encode_string(&Sha256::digest(&payload))],
+    );
+    header.int32s(PAYLOADDIGESTALGO, &[SHA256_ALGO]);
+    let header = header.into_bytes(HEADER_IMMUTABLE);
+
+    let mut signature = Header::default();
+    signature.string(
+        SIG_SHA256,
+        &base16ct::lower::encode_string(&Sha256::digest(&header)),
+    );
+    signature.int32s(SIG_SIZE, &[(header.len() + payload.len()) as u32]);
+    signature.int32s(SIG_PAYLOADSIZE, &[payload_size as u32]);
+    let mut signature = signature.into_bytes(HEADER_SIGNATURES);
+    signature.resize(signature.len().next_multiple_of(8), 0);
+
+    let rpm_path = out_dir.join(format!(
+        "{name}-{version}-{RELEASE_NUMBER}.{}.rpm",
+        architecture(Naming::Rpm)
+    ));
+    let mut rpm = File::create(&rpm_path)?;
+
+    rpm.write_all(&lead(&format!("{name}-{version}-{RELEASE_NUMBER}")))?;
+    rpm.write_all(&signature)?;
+    rpm.write_all(&header)?;
+    rpm.write_all(&payload)?;
+
+    Ok(rpm_path)
+}
+
+/// The lead, which predates headers and of which rpm only checks the magic
+/// and versions now.
+fn lead(name: &str) -> Vec<u8> {
+    let mut lead = vec![0xed, 0xab, 0xee, 0xdb, 3, 0];
+    // A binary package, for an architecture the header names.
+    lead.extend([0, 0, 0, 0]);
+
+    let mut name = name.as_bytes().to_vec();
+    name.resize(66, 0);
+    name[65] = 0;
+    lead.extend(name);
+
+    // Linux, and signatures in a header.
+    lead.extend([0, 1, 0, 5]);
+    lead.resize(96, 0);
+    lead
+}
+
+/// The files of the payload sorted by path as rpm expects, without the root.
+/// Hard links become copies, as they would need their own inode numbers.
+fn files(payload: Vec<PayloadFile>) -> Result<Vec<PayloadFile>> {
+    let mut files: Vec<PayloadFile> = Vec::new();
+    let mut contents = HashMap::new();
+
+    for mut file in payload {
+        if file.path.as_str().is_empty() {
+            continue;
+        }
+
+        let is_link = file.header.entry_type() == EntryType::Link;
+        if let Some(link) = file.link.take_if(|_| is_link) {
+            let target = entry_path(link.as_std_path())?;
+            if let Some(&i) = contents.get(&target) {
+                let original: &PayloadFile = &files[i];
+                file.data = original.data.clone();
+                file.header.set_entry_type(EntryType::Regular);
+                file.header.set_mode(original.header.mode()?);
+            }
+        }
+
+        contents.insert(file.path.clone(), files.len());
+        files.push(file);
+    }
+
+    files.sort_by(|a, b| a.path.as_str().cmp(b.path.as_str()));
+    Ok(files)
+}
+
+/// The mode of `file` along with the bits of its type, which tar headers
+/// keep apart.
+fn mode(file: &PayloadFile) -> Result<u32> {
+    let kind = match file.header.entry_type() {
+        EntryType::Directory => 0o040000,
+        EntryType::Symlink => 0o120000,
+        _ => 0o100000,
+    };
+
+    Ok(kind | (file.header.mode()? & 0o7777))
+}
+
+/// The contents of `file` in the payload, the target of symbolic links.
+fn contents(file: &PayloadFile) -> &[u8] {
+    match (file.header.entry_type(), &file.link) {
+        (EntryType::Symlink, Some(link)) => link.as_str().as_bytes(),
+        (EntryType::Directory, _) => &[],
+        _ => &file.data,
+    }
+}
+
+/// The payload, a gzipped cpio archive of `files`, and its size once
+/// extracted.
+fn payload(files: &[PayloadFile]) -> Result<(Vec<u8>, usize)> {
+    let mut cpio = Vec::new();
+
+    let mut append = |name: &str, ino: usize, mode: u32, mtime: u64, data: &[u8]| {
+        cpio.extend(
+            format!(
+                "070701{ino:08x}{mode:08x}{:08x}{:08x}{:08x}{mtime:08x}{:08x}{:08x}{:08x}\
+                 {:08x}{:08x}{:08x}{:08x}",
+                0,
+                0,
+                1,
+                data.len(),
+                0,
+                1,
+                0,
+                0,
+                name.len() + 1,
+                0
+            )
+            .as_bytes(),
+        );
+        cpio.extend(name.as_bytes());
+        cpio.push(0);
+        cpio.resize(cpio.len().next_multiple_of(4), 0);
+        cpio.extend(data);
+        cpio.resize(cpio.len().next_multiple_of(4), 0);
+    };
+
+    for (i, file) in files.iter().enumerate() {
+        append(
+            &format!("./{}", file.path),
+            i + 1,
+            mode(file)?,
+            file.header.mtime()?,
+            contents(file),
+        );
+    }
+    append("TRAILER!!!", 0, 0, 0, &[]);
+
+    let size = cpio.len();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(6));
+    encoder.write_all(&cpio)?;
+
+    Ok((encoder.finish()?, size))
+}
+
+fn file_tags(header: &mut Header, files: &[PayloadFile], peach_info: &PeachInfo) {
+    let mut dirnames: Vec<String> = Vec::new();
+    let mut dirindexes = Vec::new();
+    let mut basenames = Vec::new();
+
+    for file in files {
+        let dirname = match file.path.parent().map(|parent| parent.as_str()) {
+            Some("") | None => "/".to_string(),
+            Some(parent) => format!("/{parent}/"),
+        };
+
+        let index = match dirnames.iter().position(|name| *name == dirname) {
+            Some(index) => index,
+            None => {
+                dirnames.push(dirname);
+                dirnames.len() - 1
+            }
+        };
+
+        dirindexes.push(index as u32);
+        basenames.push(file.path.file_name().unwrap_or_default());
+    }
+
+    let digests: Vec<String> = files
+        .iter()
+        .map(|file| match file.header.entry_type() {
+            EntryType::Regular | EntryType::Continuous => {
+                base16ct::lower::encode_string(&Sha256::digest(&file.data))
+            }
+            _ => String::new(),
+        })
+        .collect();
+
+    let flags: Vec<u32> = files
+        .iter()
+        .map(|file| match peach_info.backup.contains(&file.path) {
+            true => FILE_CONFIG | FILE_NOREPLACE,
+            false => 0,
+        })
+        .collect();
+
+    let values = |f: &dyn Fn(&PayloadFile) -> u32| files.iter().map(f).collect::<Vec<_>>();
+
+    header.int32s(FILESIZES, &values(&|file| contents(file).len() as u32));
+    header.int16s(
+        FILEMODES,
+        &files
+            .iter()
+            .map(|file| mode(file).unwrap_or(0) as u16)
+            .collect::<Vec<_>>(),
+    );
+    header.int16s(FILERDEVS, &vec![0; files.len()]);
+    header.int32s(
+        FILEMTIMES,
+        &values(&|file| file.header.mtime().unwrap_or(0) as u32),
+    );
+    header.strings(FILEDIGESTS, &digests);
+    header.strings(
+        FILELINKTOS,
+        &files
+            .iter()
+            .map(|file| match file.header.entry_type() {
+                EntryType::Symlink => file.link.as_ref().map_or("", |link| link.as_str()),
+                _ => "",
+            })
+            .collect::<Vec<_>>(),
+    );
+    header.int32s(FILEFLAGS, &flags);
+    header.strings(FILEUSERNAME, &vec!["root"; files.len()]);
+    header.strings(FILEGROUPNAME, &vec!["root"; files.len()]);
+    header.int32s(FILEDEVICES, &vec![1; files.len()]);
+    header.int32s(FILEINODES, &(1..=files.len() as u32).collect::<Vec<_>>());
+    header.int32s(DIRINDEXES, &dirindexes);
+    header.strings(BASENAMES, &basenames);
+    header.strings(DIRNAMES, &dirnames);
+    if !files.is_empty() {
+        header.int32s(FILEDIGESTALGO, &[SHA256_ALGO]);
+    }
+}
+
+/// The names, flags and versions of a kind of dependencies.
+#[derive(Debug, Default)]
+struct Relations {
+    names: Vec<String>,
+    flags: Vec<u32>,
+    versions: Vec<String>,
+}
+
+impl Relations {
+    fn push(&mut self, name: &str, flags: u32, version: &str) {
+        self.names.push(name.to_string());
+        self.flags.push(flags);
+        self.versions.push(version.to_string());
+    }
+
+    /// Adds a blossom dependency, with one relation for each of its
+    /// constraints as rpm relations take one.
+    fn extend(&mut self, dependencies: &[String]) -> Result<()> {
+        for dependency in dependencies {
+            // Optional dependencies are followed by why they are needed.
+            let requirement = dependency.split(':').next().unwrap_or(dependency);
+            let name = dependency_name(requirement);
+            let constraints = dependency_constraints(requirement)?;
+
+            if constraints.is_empty() {
+                self.push(name, 0, "");
+            }
+
+            for constraint in constraints {
+                let flags = match constraint.operator {
+                    Operator::Less => SENSE_LESS,
+                    Operator::LessEqual => SENSE_LESS | SENSE_EQUAL,
+                    Operator::Equal => SENSE_EQUAL,
+                    Operator::GreaterEqual => SENSE_GREATER | SENSE_EQUAL,
+                    Operator::Greater => SENSE_GREATER,
+                };
+
+                self.push(name, flags, &version(&constraint.version));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, header: &mut Header, tags: [u32; 3]) {
+        header.strings(tags[0], &self.names);
+        header.int32s(tags[1], &self.flags);
+        header.strings(tags[2], &self.versions);
+    }
+}
+
+fn dependency_tags(header: &mut Header, peach_info: &PeachInfo) -> Result<()> {
+    let info = &peach_info.info;
+    let (required, optional) = match &peach_info.dependencies {
+        Some(dependencies) => (
+            dependencies.required.as_slice(),
+            dependencies.optional.as_slice(),
+        ),
+        None => (&[][..], &[][..]),
+    };
+
+    let mut requires = Relations::default();
+    for (feature, version) in RPMLIB_FEATURES {
+        requires.push(feature, SENSE_RPMLIB | SENSE_LESS | SENSE_EQUAL, version);
+    }
+    requires.extend(required)?;
+    requires.write(header, [REQUIRENAME, REQUIREFLAGS, REQUIREVERSION]);
+
+    let mut suggests = Relations::default();
+    suggests.extend(optional)?;
+    suggests.write(header, [SUGGESTNAME, SUGGESTFLAGS, SUGGESTVERSION]);
+
+    // Packages provide themselves, which rpm resolves dependencies against.
+    let mut provides = Relations::default();
+    provides.push(
+        &info.name,
+        SENSE_EQUAL,
+        &format!("{}-{RELEASE_NUMBER}", version(&info.version)),
+    );
+    provides.extend(&info.provides)?;
+    provides.write(header, [PROVIDENAME, PROVIDEFLAGS, PROVIDEVERSION]);
+
+    let mut conflicts = Relations::default();
+    conflicts.extend(&info.conflicts)?;
+    conflicts.write(header, [CONFLICTNAME, CONFLICTFLAGS, CONFLICTVERSION]);
+
+    let mut obsoletes = Relations::default();
+    obsoletes.extend(&info.replaces)?;
+    obsoletes.write(header, [OBSOLETENAME, OBSOLETEFLAGS, OBSOLETEVERSION]);
+
+    Ok(())
+}
+
+/// `version` as an rpm version, in which `-` would start a release.
+fn version(version: &str) -> String {
+    version
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '~' | '^') {
+                true => c,
+                false => '+',
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let mut header = Header::default();
+        header.int32s(SIZE, &[7]);
+        header.string(NAME, "foo");
+        let bytes = header.into_bytes(HEADER_IMMUTABLE);
+
+        let word = |i: usize| u32::from_be_bytes(bytes[i..i + 4].try_into().unwrap());
+        let (count, size) = (word(8), word(12));
+        assert_eq!((count, size), (3, 24));
+
+        // The region entry, then the others sorted by tag.
+        assert_eq!([word(16), word(20), word(24)], [HEADER_IMMUTABLE, 7, 8]);
+        assert_eq!([word(32), word(36), word(40)], [NAME, 6, 0]);
+        assert_eq!([word(48), word(52), word(56)], [SIZE, 4, 4]);
+
+        let store = 16 + 16 * count as usize;
+        assert_eq!(&bytes[store..store + 4], b"foo\0");
+        assert_eq!(word(store + 4), 7);
+        assert_eq!(word(store + 8), HEADER_IMMUTABLE);
+        assert_eq!(word(store + 16) as i32, -48);
+    }
+
+    #[test]
+    fn test_relations() {
+        let mut relations = Relations::default();
+        relations
+            .extend(&[
+                "libc>=2.30,<3".to_string(),
+                "bash: for the completions".to_string(),
+            ])
+            .unwrap();
+
+        assert_eq!(relations.names, ["libc", "libc", "bash"]);
+        assert_eq!(
+            relations.flags,
+            [SENSE_GREATER | SENSE_EQUAL, SENSE_LESS, 0]
+        );
+        assert_eq!(relations.versions, ["2.30", "3", ""]);
+        assert_eq!(version("1.2-rc1"), "1.2+rc1");
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    env, fs,
+    os::unix::fs::{PermissionsExt, symlink},
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use camino::Utf8Path;
+use tar::EntryType;
+
+use super::{Naming, architecture, read_payload};
+use crate::{
+    archive,
+    error::{Result, bail, format_err},
+};
+
+/// The extensions systemd merges, into `/usr` and `/opt` or into `/etc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extension {
+    Sysext,
+    Confext,
+}
+
+impl Extension {
+    /// The top level directories the extension can carry files in.
+    fn dirs(self) -> &'static [&'static str] {
+        match self {
+            Extension::Sysext => &["usr", "opt"],
+            Extension::Confext => &["etc"],
+        }
+    }
+
+    fn release_dir(self) -> &'static str {
+        match self {
+            Extension::Sysext => "usr/lib/extension-release.d",
+            Extension::Confext => "etc/extension-release.d",
+        }
+    }
+}
+
+/// Merges the package archives at `paths` into a systemd extension image
+/// named `name` in `out_dir`, a directory or, if `raw`, a squashfs image
+/// made with mksquashfs. Returns the path of the image.
+pub fn sysext(
+    paths: &[PathBuf],
+    name: &str,
+    extension: Extension,
+    raw: bool,
+    out_dir: &Path,
+) -> Result<PathBuf> {
+    if name.is_empty() || name.contains('/') {
+        bail!("Invalid extension name {name}");
+    }
+
+    let image = match raw {
+        true => out_dir.join(format!("{name}.raw")),
+        false => out_dir.join(name),
+    };
+    if image.exists() {
+        bail!("{} already exists", image.display());
+    }
+
+    let dir = match raw {
+        true => env::temp_dir().join(format!("blossom-sysext-{}/{name}", process::id())),
+        false => image.clone(),
+    };
+
+    let result = populate(paths, name, extension, &dir).and_then(|()| match raw {
+        true => squash(&dir, &image),
+        false => Ok(()),
+    });
+
+    if raw || result.is_err() {
+        let staging = match raw {
+            true => dir.parent().unwrap_or(&dir),
+            false => &dir,
+        };
+        if staging.exists() {
+            fs::remove_dir_all(staging)?;
+        }
+    }
+
+    result.map(|()| image)
+}
+
+/// Extracts the packages into `dir` along with the release file systemd
+/// matches the extension against the host with.
+fn populate(paths: &[PathBuf], name: &str, extension: Extension, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut has_units = false;
+
+    for path in paths {
+        let package = archive::read_info(path)?.info.name;
+
+        for file in read_payload(path)? {
+            if file.path.as_str().is_empty() {
+                continue;
+            }
+            check_path(&package, &file.path, extension)?;
+            has_units |= file.path.starts_with("usr/lib/systemd/system");
+
+            let dest = dir.join(&file.path);
+            let mode = file.header.mode()?;
+
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            match (file.header.entry_type(), &file.link) {
+                (EntryType::Directory, _) => {
+                    fs::create_dir_all(&dest)?;
+                    fs::set_permissions(&dest, fs::Permissions::from_mode(mode))?;
+                    continue;
+                }
+                _ if dest.symlink_metadata().is_ok() => {
+                    bail!("{} of {package} is in another package too", file.path)
+                }
+                (EntryType::Symlink, Some(link)) => symlink(link, &dest)?,
+                (EntryType::Link, Some(link)) => fs::hard_link(dir.join(link), &dest)?,
+                _ => {
+                    fs::write(&dest, &file.data)?;
+                    fs::set_permissions(&dest, fs::Permissions::from_mode(mode))?;
+                }
+            }
+        }
+    }
+
+    let release_dir = dir.join(extension.release_dir());
+    fs::create_dir_all(&release_dir)?;
+    fs::write(
+        release_dir.join(format!("extension-release.{name}")),
+        extension_release(extension, has_units),
+    )?;
+
+    Ok(())
+}
+
+/// Extensions can only carry files in the directories systemd merges.
+fn check_path(package: &str, path: &Utf8Path, extension: Extension) -> Result<()> {
+    let top = path.components().next().map_or("", |top| top.as_str());
+
+    if !extension.dirs().contains(&top) {
+        bail!(
+            "{package} installs {path}, an extension can only carry files in /{}",
+            extension.dirs().join(" and /")
+        );
+    }
+
+    Ok(())
+}
+
+/// The release file of the extension, which applies to any host of the
+/// architecture blossom was built for.
+fn extension_release(extension: Extension, has_units: bool) -> String {
+    let mut release = format!("ID=_any\nARCHITECTURE={}\n", architecture(Naming::Systemd));
+
+    // Makes systemd-sysext reload units once the extension is merged.
+    if extension == Extension::Sysext && has_units {
+        release.push_str("EXTENSION_RELOAD_MANAGER=1\n");
+    }
+
+    release
+}
+
+fn squash(dir: &Path, image: &Path) -> Result<()> {
+    let status = Command::new("mksquashfs")
+        .arg(dir)
+        .arg(image)
+        .args(["-all-root", "-noappend", "-quiet"])
+        .status()
+        .map_err(|e| format_err!("Failed to run mksquashfs, needed for raw images: {e}"))?;
+
+    if !status.success() {
+        bail!("mksquashfs failed with {status}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension() {
+        assert!(check_path("foo", Utf8Path::new("usr/bin/foo"), Extension::Sysext).is_ok());
+        assert!(check_path("foo", Utf8Path::new("etc/foo.conf"), Extension::Sysext).is_err());
+        assert!(check_path("foo", Utf8Path::new("etc/foo.conf"), Extension::Confext).is_ok());
+
+        assert_eq!(
+            extension_release(Extension::Sysext, true),
+            format!(
+                "ID=_any\nARCHITECTURE={}\nEXTENSION_RELOAD_MANAGER=1\n",
+                architecture(Naming::Systemd)
+            )
+        );
+        assert_eq!(
+            extension_release(Extension::Confext, true),
+            format!("ID=_any\nARCHITECTURE={}\n", architecture(Naming::Systemd))
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt::Display;
+
+use crate::error::Error;
+
+/// Failures that scripts may want to handle differently, each with its own
+/// exit code. Anything else exits with 1, and invalid arguments with 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Requested packages or their dependencies can't be resolved.
+    Dependency,
+    /// A signature is missing, invalid or made by an untrusted key.
+    Signature,
+    /// A package would overwrite files of another package, or conflicts with
+    /// one.
+    Conflict,
+    /// Something couldn't be downloaded.
+    Network,
+    /// The user declined to go on, or couldn't be asked.
+    Aborted,
+    /// Another instance of blossom holds the lock.
+    Locked,
+}
+
+/// Every exit code, as documented in `blossom --help`.
+pub const EXIT_CODES: [(u8, &str); 9] = [
+    (0, "Success"),
+    (1, "Any other failure"),
+    (2, "Invalid arguments"),
+    (Failure::Dependency.code(), "Dependency resolution failed"),
+    (Failure::Signature.code(), "Signature verification failed"),
+    (Failure::Conflict.code(), "Conflicting packages or files"),
+    (Failure::Network.code(), "Network error"),
+    (Failure::Aborted.code(), "Aborted by the user"),
+    (
+        Failure::Locked.code(),
+        "Another instance of blossom is running",
+    ),
+];
+
+impl Failure {
+    pub const fn code(self) -> u8 {
+        match self {
+            Failure::Dependency => 3,
+            Failure::Signature => 4,
+            Failure::Conflict => 5,
+            Failure::Network => 6,
+            Failure::Aborted => 7,
+            Failure::Locked => 8,
+        }
+    }
+
+    /// An error of this kind, displayed as `message`.
+    pub fn error<M: Display>(self, message: M) -> Error {
+        Error::Failed {
+            failure: self,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+/// The kind of failure behind `error`, looking through its whole chain.
+/// Failed requests count as network errors wherever they happened.
+pub fn categorize(error: &anyhow::Error) -> Option<Failure> {
+    error.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<Error>() {
+            error.failure()
+        } else if cause.is::<reqwest::Error>() {
+            Some(Failure::Network)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(feature = "cli")]
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    categorize(error).map_or(1, Failure::code)
+}
+
+#[cfg(feature = "cli")]
+/// Exits with the code matching `error`, once it has been reported.
+pub fn exit(error: &anyhow::Error) -> ! {
+    std::process::exit(exit_code(error).into())
+}
+
+/// The exit codes, as listed at the end of `blossom --help`.
+pub fn help() -> String {
+    let mut help = String::from("Exit codes:\n");
+
+    for (code, description) in EXIT_CODES {
+        help.push_str(&format!("  {code}  {description}\n"));
+    }
+
+    help
+}
+
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let error =
+            anyhow::Error::from(Failure::Locked.error("Another instance of blossom is running"));
+        assert_eq!(error.to_string(), "Another instance of blossom is running");
+        assert_eq!(exit_code(&error), 8);
+
+        let error = Err::<(), _>(Failure::Dependency.error("Unsatisfiable requirement"))
+            .context("Failed to resolve")
+            .unwrap_err();
+        assert_eq!(exit_code(&error), 3);
+
+        assert_eq!(exit_code(&anyhow::anyhow!("Something else")), 1);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    io,
+    path::Path,
+    pin::Pin,
+    process::Command,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use reqwest::Client;
+
+use crate::{
+    download,
+    error::{Error, Result, bail, format_err},
+    events,
+    log::info,
+};
+
+pub type FetchFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// What fetching a source produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// An archive, verified against the checksum of the source and extracted.
+    Archive,
+    /// A checkout of a repository, pinned to a commit.
+    Checkout,
+}
+
+/// A source to fetch.
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    /// The URL of the source, or where it was served from when it was locked.
+    pub url: &'a str,
+    /// The revision to check out, `HEAD` if there is none.
+    pub rev: Option<&'a str>,
+    /// The file to download an archive to, or the directory to check out
+    /// into.
+    pub target: &'a Path,
+    /// How many ranges of a large archive are downloaded at once, from
+    /// servers accepting them.
+    pub segments: usize,
+}
+
+/// Fetches sources whose URLs have one of its schemes.
+pub trait Fetcher: Send + Sync {
+    /// The URL schemes handled. Only the part before a `+` counts, so `git`
+    /// covers `git+https://` and `git+file://` alike.
+    fn schemes(&self) -> &[&str];
+
+    fn kind(&self) -> Kind {
+        Kind::Archive
+    }
+
+    /// Whether fetching `url` needs the network, it isn't fetched while
+    /// offline then.
+    fn is_remote(&self, _url: &str) -> bool {
+        true
+    }
+
+    /// Fetches a source to `request.target`. Returns where an archive was
+    /// served from after redirects, or the commit checked out.
+    fn fetch<'a>(&'a self, request: Request<'a>) -> FetchFuture<'a, String>;
+
+    /// Where `url` is served from, for archives that are already downloaded.
+    fn resolve<'a>(&'a self, url: &'a str) -> FetchFuture<'a, String> {
+        Box::pin(async move { Ok(url.to_string()) })
+    }
+}
+
+/// Downloads archives over HTTP.
+#[derive(Debug, Clone, Default)]
+pub struct Http {
+    client: Client,
+}
+
+impl Fetcher for Http {
+    fn schemes(&self) -> &[&str] {
+        &["http", "https"]
+    }
+
+    fn fetch<'a>(&'a self, request: Request<'a>) -> FetchFuture<'a, String> {
+        Box::pin(download::download_segmented(
+            &self.client,
+            request.url,
+            request.target,
+            request.segments,
+        ))
+    }
+
+    fn resolve<'a>(&'a self, url: &'a str) -> FetchFuture<'a, String> {
+        Box::pin(async move {
+            Ok(match self.client.head(url).send().await {
+                Ok(res) => res.url().to_string(),
+                Err(_) => url.to_string(),
+            })
+        })
+    }
+}
+
+/// Copies archives from the local filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct File;
+
+impl Fetcher for File {
+    fn schemes(&self) -> &[&str] {
+        &["file"]
+    }
+
+    fn is_remote(&self, _url: &str) -> bool {
+        false
+    }
+
+    fn fetch<'a>(&'a self, request: Request<'a>) -> FetchFuture<'a, String> {
+        Box::pin(async move {
+            let source = download::local_path(request.url)?
+                .ok_or_else(|| format_err!("{} is not a local path", request.url))?;
+
+            info!("Copying \"{}\"", source.display());
+
+            tokio::fs::copy(&source, request.target)
+                .await
+                .map_err(|e| format_err!("{}: {e}", source.display()))?;
+
+            Ok(request.url.to_string())
+        })
+    }
+}
+
+/// Clones git repositories, from `git+` URLs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Git;
+
+impl Fetcher for Git {
+    fn schemes(&self) -> &[&str] {
+        &["git"]
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Checkout
+    }
+
+    fn is_remote(&self, url: &str) -> bool {
+        download::is_remote(url.strip_prefix("git+").unwrap_or(url))
+    }
+
+    fn fetch<'a>(&'a self, request: Request<'a>) -> FetchFuture<'a, String> {
+        let url = request.url.strip_prefix("git+").unwrap_or(request.url);
+        let (url, dir) = (url.to_string(), request.target.to_path_buf());
+        let revision = request.rev.unwrap_or("HEAD").to_string();
+
+        Box::pin(async move {
+            events::spawn_blocking(move || clone(&url, &dir, &revision))
+                .await
+                .map_err(io::Error::from)?
+        })
+    }
+}
+
+/// Clones `url` into `dir` and checks out `revision`, returning the commit.
+fn clone(url: &str, dir: &Path, revision: &str) -> Result<String> {
+    let dir = dir
+        .to_str()
+        .ok_or(format_err!("Non UTF-8 path: {}", dir.display()))?;
+
+    info!("Cloning \"{url}\" at {revision}");
+
+    git(&["clone", "--quiet", url, dir])?;
+    git(&["-C", dir, "checkout", "--quiet", "--detach", revision])?;
+
+    git(&["-C", dir, "rev-parse", "HEAD"])
+}
+
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).output()?;
+
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The fetchers sources can be fetched with, by scheme.
+static FETCHERS: LazyLock<RwLock<BTreeMap<String, Arc<dyn Fetcher>>>> = LazyLock::new(|| {
+    let mut fetchers = BTreeMap::new();
+
+    for fetcher in [
+        Arc::new(Http::default()) as Arc<dyn Fetcher>,
+        Arc::new(File),
+        Arc::new(Git),
+    ] {
+        for scheme in fetcher.schemes() {
+            fetchers.insert(scheme.to_string(), fetcher.clone());
+        }
+    }
+
+    RwLock::new(fetchers)
+});
+
+/// Makes `fetcher` fetch the sources with its schemes, replacing the fetchers
+/// registered for them, built-in ones included.
+pub fn register(fetcher: Arc<dyn Fetcher>) {
+    if let Ok(mut fetchers) = FETCHERS.write() {
+        for scheme in fetcher.schemes() {
+            fetchers.insert(scheme.to_string(), fetcher.clone());
+        }
+    }
+}
+
+/// The fetcher for the scheme of `url`.
+pub fn get(url: &str) -> Result<Arc<dyn Fetcher>> {
+    let scheme = url
+        .split_once("://")
+        .map(|(scheme, _)| scheme.split('+').next().unwrap_or(scheme))
+        .ok_or_else(|| Error::Parse {
+            what: "source URL".to_string(),
+            message: format!("{url} has no scheme"),
+        })?;
+
+    let fetchers = FETCHERS
+        .read()
+        .map_err(|_| format_err!("Fetcher registry lock poisoned"))?;
+
+    fetchers.get(scheme).cloned().ok_or_else(|| {
+        format_err!(
+            "Can't fetch {url}, expected one of the schemes {}",
+            fetchers.keys().cloned().collect::<Vec<_>>().join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ipfs;
+
+    impl Fetcher for Ipfs {
+        fn schemes(&self) -> &[&str] {
+            &["ipfs"]
+        }
+
+        fn fetch<'a>(&'a self, request: Request<'a>) -> FetchFuture<'a, String> {
+            Box::pin(async move { Ok(request.url.to_string()) })
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(
+            get("https://example.com/a.tar.gz").unwrap().kind(),
+            Kind::Archive
+        );
+        assert_eq!(
+            get("git+https://example.com/a.git").unwrap().kind(),
+            Kind::Checkout
+        );
+        assert!(
+            !get("file:///srv/a.tar.gz")
+                .unwrap()
+                .is_remote("file:///srv/a.tar.gz")
+        );
+        assert!(get("ipfs://bafy/a.tar.gz").is_err());
+        assert!(get("a.tar.gz").is_err());
+
+        register(Arc::new(Ipfs));
+
+        assert_eq!(get("ipfs://bafy/a.tar.gz").unwrap().schemes(), ["ipfs"]);
+    }
+}
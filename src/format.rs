@@ -0,0 +1,213 @@
+//! Formats recipes the way blossom writes them, keeping their comments.
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::error::{Error, Result};
+
+/// The fields of recipes in the order [`Package::to_recipe`] writes them.
+/// Fields that aren't listed keep their order after those.
+///
+/// [`Package::to_recipe`]: crate::package::Package::to_recipe
+const ROOT: [&str; 11] = [
+    "backup",
+    "special_permissions",
+    "network",
+    "pkgver",
+    "upstream_keys",
+    "info",
+    "dependencies",
+    "sources",
+    "steps",
+    "directories",
+    "changelog",
+];
+const INFO: [&str; 8] = [
+    "name",
+    "version",
+    "description",
+    "license",
+    "provides",
+    "conflicts",
+    "replaces",
+    "keywords",
+];
+const DEPENDENCIES: [&str; 4] = ["required", "optional", "build", "check"];
+const SOURCE: [&str; 5] = ["url", "checksum", "rev", "filename", "signature"];
+const STEP: [&str; 4] = ["name", "runner", "command", "path"];
+const CHANGELOG: [&str; 3] = ["version", "date", "changes"];
+
+/// Formats the recipe `s`: its fields in canonical order, its tables and
+/// arrays of tables written out rather than inline, and a single trailing
+/// newline.
+pub fn format(s: &str) -> Result<String> {
+    let mut document: DocumentMut = s.parse().map_err(|e| Error::Parse {
+        what: "the recipe".to_string(),
+        message: format!("{e}"),
+    })?;
+    let root = document.as_table_mut();
+
+    for key in [
+        "info",
+        "dependencies",
+        "directories",
+        "sources",
+        "steps",
+        "changelog",
+    ] {
+        let Some(item) = root.get_mut(key) else {
+            continue;
+        };
+        if !item.is_value() {
+            continue;
+        }
+
+        let mut converted = match key {
+            "sources" | "steps" | "changelog" => std::mem::take(item)
+                .into_array_of_tables()
+                .map(Item::ArrayOfTables),
+            _ => std::mem::take(item).into_table().map(Item::Table),
+        }
+        .unwrap_or_else(|item| item);
+
+        // The comments above the field go above the header replacing it.
+        if let Some(mut key) = root.key_mut(key) {
+            let comments = key
+                .leaf_decor()
+                .prefix()
+                .and_then(|prefix| prefix.as_str())
+                .filter(|prefix| !prefix.trim().is_empty())
+                .map(str::to_string);
+
+            let first = match &mut converted {
+                Item::Table(table) => Some(table),
+                Item::ArrayOfTables(tables) => tables.iter_mut().next(),
+                _ => None,
+            };
+            if let Some(table) = first {
+                key.leaf_decor_mut().clear();
+                if let Some(comments) = comments {
+                    table.decor_mut().set_prefix(comments);
+                }
+            }
+        }
+
+        root[key] = converted;
+    }
+
+    sort(root, &ROOT);
+
+    // Tables are written in the order of their positions, not of the keys.
+    let mut position = 0;
+    let mut place = |table: &mut Table, order: &[&str]| {
+        position += 1;
+        table.set_position(position);
+        sort(table, order);
+
+        // A single blank line before each header, and its comments.
+        let comments = table
+            .decor()
+            .prefix()
+            .and_then(|prefix| prefix.as_str())
+            .map_or("", |prefix| prefix.trim_start())
+            .to_string();
+        table.decor_mut().set_prefix(format!("\n{comments}"));
+    };
+
+    for key in ROOT {
+        match root.get_mut(key) {
+            Some(Item::Table(table)) => place(
+                table,
+                match key {
+                    "info" => &INFO,
+                    "dependencies" => &DEPENDENCIES,
+                    _ => &[],
+                },
+            ),
+            Some(Item::ArrayOfTables(tables)) => {
+                for table in tables.iter_mut() {
+                    place(
+                        table,
+                        match key {
+                            "sources" => &SOURCE,
+                            "steps" => &STEP,
+                            "changelog" => &CHANGELOG,
+                            _ => &[],
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let formatted = document.to_string();
+    Ok(format!("{}\n", formatted.trim()))
+}
+
+/// Sorts the fields of `table` in `order`, keeping the order of the others.
+fn sort(table: &mut Table, order: &[&str]) {
+    let rank = |key: &str| {
+        order
+            .iter()
+            .position(|field| *field == key)
+            .unwrap_or(order.len())
+    };
+
+    table.sort_values_by(|a, _, b, _| rank(a.get()).cmp(&rank(b.get())));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let recipe = r#"
+# Upstream only ships a Makefile.
+steps = [{ command = "make", name = "build", runner = "shell" }]
+
+[dependencies]
+build = ["gcc"]
+required = ["libc"]
+
+[info]
+version = "1.0"
+name = "foo" # Named after the upstream project.
+license = "MIT"
+description = "A foo"
+
+[[sources]]
+checksum = "blake3:ab12"
+url = "https://example.com/foo-%{version}.tar.gz"
+
+
+"#;
+
+        assert_eq!(
+            format(recipe).unwrap(),
+            r#"[info]
+name = "foo" # Named after the upstream project.
+version = "1.0"
+description = "A foo"
+license = "MIT"
+
+[dependencies]
+required = ["libc"]
+build = ["gcc"]
+
+[[sources]]
+url = "https://example.com/foo-%{version}.tar.gz"
+checksum = "blake3:ab12"
+
+# Upstream only ships a Makefile.
+[[steps]]
+name = "build"
+runner = "shell"
+command = "make"
+"#
+        );
+
+        let formatted = format(recipe).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+}
@@ -0,0 +1,266 @@
+use std::{collections::HashMap, path::Path, process::Command};
+
+use reqwest::{Client, header::USER_AGENT};
+use serde::Deserialize;
+
+use super::AGENT;
+use crate::{
+    error::{Result, bail, format_err},
+    import::{Import, license, step},
+    package::{Changelog, Dependencies, Info, Package, Source},
+};
+
+pub const CRATES_API: &str = "https://crates.io/api/v1";
+const CRATES_DOWNLOAD: &str = "https://static.crates.io/crates";
+
+#[derive(Debug, Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: Crate,
+    versions: Vec<CrateVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Crate {
+    name: String,
+    description: Option<String>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    max_stable_version: Option<String>,
+    max_version: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateVersion {
+    num: String,
+    /// SHA-256 of the `.crate` archive.
+    checksum: String,
+    license: Option<String>,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(default)]
+    bin_names: Vec<String>,
+}
+
+/// What `cargo metadata` says about a local crate.
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataPackage {
+    name: String,
+    version: String,
+}
+
+/// Generates a recipe building the crate `name` with cargo, from what
+/// crates.io knows about its latest stable version. `name` can also be the
+/// path of a local crate, whose version `cargo metadata` tells.
+pub async fn from_crate(client: &Client, name: &str) -> Result<Import> {
+    let (name, version) = match Path::new(name).exists() {
+        true => {
+            let (name, version) = local_crate(Path::new(name))?;
+            (name, Some(version))
+        }
+        false => (name.to_string(), None),
+    };
+
+    let response = client
+        .get(format!("{CRATES_API}/crates/{name}"))
+        .header(USER_AGENT, AGENT)
+        .send()
+        .await?
+        .error_for_status()?;
+    let response: CrateResponse = serde_json::from_slice(&response.bytes().await?)?;
+
+    let version = version.unwrap_or_else(|| {
+        response
+            .krate
+            .max_stable_version
+            .clone()
+            .unwrap_or_else(|| response.krate.max_version.clone())
+    });
+
+    let crate_version = response
+        .versions
+        .iter()
+        .find(|crate_version| crate_version.num == version)
+        .ok_or(format_err!("{name} {version} isn't published on crates.io"))?;
+
+    Ok(recipe(&response.krate, crate_version))
+}
+
+/// The name and version of the crate at `path`, a directory or its manifest.
+fn local_crate(path: &Path) -> Result<(String, String)> {
+    let manifest = match path.is_dir() {
+        true => path.join("Cargo.toml"),
+        false => path.to_path_buf(),
+    };
+
+    let output = Command::new("cargo")
+        .args([
+            "metadata",
+            "--no-deps",
+            "--format-version",
+            "1",
+            "--manifest-path",
+        ])
+        .arg(&manifest)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "cargo metadata failed for {}: {}",
+            manifest.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let metadata: Metadata = serde_json::from_slice(&output.stdout)?;
+    let package = metadata
+        .packages
+        .into_iter()
+        .next()
+        .ok_or(format_err!("{} has no package", manifest.display()))?;
+
+    Ok((package.name, package.version))
+}
+
+fn recipe(krate: &Crate, version: &CrateVersion) -> Import {
+    let name = &krate.name;
+    let mut todos = Vec::new();
+
+    if version.yanked {
+        todos.push(format!("{name} {} was yanked", version.num));
+    }
+
+    let description = krate.description.clone().unwrap_or_default();
+    if description.is_empty() {
+        todos.push("the crate has no description, describe the package".to_string());
+    }
+
+    // Old crates separate alternatives with slashes.
+    let license = license(
+        &version
+            .license
+            .iter()
+            .map(|license| license.replace('/', " OR "))
+            .collect::<Vec<_>>(),
+        &mut todos,
+    );
+
+    // .crate archives extract to a directory named after them.
+    let dir = format!("%{{srcdir}}/{name}-%{{version}}");
+    let mut steps = vec![
+        step("build", &dir, "cargo build --release --locked -j %{jobs}"),
+        step("check", &dir, "cargo test --release --locked -j %{jobs}"),
+    ];
+
+    if version.bin_names.is_empty() {
+        todos.push(format!(
+            "{name} has no binaries, install what the package should ship"
+        ));
+    } else {
+        let install = version
+            .bin_names
+            .iter()
+            .map(|bin| {
+                format!("install -Dm755 \"target/release/{bin}\" \"%{{pkgdir}}/usr/bin/{bin}\"")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        steps.push(step("package", &dir, &install));
+    }
+
+    Import {
+        package: Package {
+            info: Info {
+                name: name.clone(),
+                version: version.num.clone(),
+                description: description.into(),
+                license,
+                provides: Vec::new(),
+                conflicts: Vec::new(),
+                replaces: Vec::new(),
+                keywords: krate.keywords.clone(),
+            },
+            dependencies: Some(Dependencies {
+                required: Vec::new(),
+                optional: Vec::new(),
+                build: vec!["cargo".to_string()],
+                check: Vec::new(),
+            }),
+            sources: vec![Source {
+                url: format!("{CRATES_DOWNLOAD}/{name}/{name}-%{{version}}.crate"),
+                checksum: format!("sha256:{}", version.checksum),
+                rev: None,
+                filename: None,
+                signature: None,
+            }],
+            steps,
+            directories: HashMap::new(),
+            backup: Vec::new(),
+            special_permissions: Vec::new(),
+            // cargo downloads the dependencies of the crate.
+            network: true,
+            pkgver: None,
+            upstream_keys: Vec::new(),
+            changelog: Changelog::default(),
+            extends: None,
+            path: None,
+        },
+        todos,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::StepVariant;
+
+    #[test]
+    fn test_recipe() {
+        let response: CrateResponse = serde_json::from_str(
+            r#"{
+                "crate": {
+                    "name": "ripgrep",
+                    "description": "ripgrep is a line-oriented search tool",
+                    "keywords": ["regex", "grep"],
+                    "max_stable_version": "14.1.1",
+                    "max_version": "14.1.1"
+                },
+                "versions": [
+                    {
+                        "num": "14.1.1",
+                        "checksum": "ab12",
+                        "license": "Unlicense/MIT",
+                        "yanked": false,
+                        "bin_names": ["rg"]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let import = recipe(&response.krate, &response.versions[0]);
+        let package = &import.package;
+
+        assert!(import.todos.is_empty());
+        assert_eq!(package.info.license.to_string(), "Unlicense OR MIT");
+        assert_eq!(package.info.keywords, ["regex", "grep"]);
+        assert_eq!(
+            package.sources[0].url,
+            "https://static.crates.io/crates/ripgrep/ripgrep-%{version}.crate"
+        );
+        assert_eq!(package.sources[0].checksum, "sha256:ab12");
+
+        match &package.steps[2].variant {
+            StepVariant::Command { command, .. } => assert_eq!(
+                command,
+                "cd \"%{srcdir}/ripgrep-%{version}\"\n\
+                 install -Dm755 \"target/release/rg\" \"%{pkgdir}/usr/bin/rg\""
+            ),
+            StepVariant::Move { .. } => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, path::Component};
+
+use flate2::read::GzDecoder;
+use reqwest::{Client, StatusCode, header::USER_AGENT};
+use serde::{Deserialize, de::DeserializeOwned};
+use tar::Archive;
+
+use super::{AGENT, BuildSystem};
+use crate::{
+    error::{Result, bail, format_err},
+    import::{Import, license},
+    package::{Changelog, Dependencies, Info, Package, Source},
+};
+
+pub const GITHUB_API: &str = "https://api.github.com";
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    name: String,
+    description: Option<String>,
+    license: Option<RepositoryLicense>,
+    #[serde(default)]
+    topics: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepositoryLicense {
+    /// `NOASSERTION` when GitHub couldn't tell the license.
+    spdx_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+/// The tarball of a release, as far as recipes need it.
+#[derive(Debug)]
+struct Tarball {
+    checksum: String,
+    /// The directory the tarball extracts to.
+    dir: String,
+    /// The files at the root of that directory.
+    files: Vec<String>,
+}
+
+/// Generates a recipe for the latest release of the GitHub repository
+/// `owner/repo`, downloading its tarball to checksum it and to tell its build
+/// system.
+pub async fn from_github(client: &Client, repository: &str) -> Result<Import> {
+    let Some((owner, repo)) = repository
+        .split_once('/')
+        .filter(|(owner, repo)| !owner.is_empty() && !repo.is_empty() && !repo.contains('/'))
+    else {
+        bail!("Expected a repository as owner/repo, got {repository}");
+    };
+
+    let info: Repository = get(client, &format!("{GITHUB_API}/repos/{owner}/{repo}"))
+        .await?
+        .ok_or(format_err!("{owner}/{repo} doesn't exist"))?;
+    let release: Release = get(
+        client,
+        &format!("{GITHUB_API}/repos/{owner}/{repo}/releases/latest"),
+    )
+    .await?
+    .ok_or(format_err!("{owner}/{repo} has no releases"))?;
+
+    let url = tarball_url(owner, repo, &release.tag_name);
+    let response = client
+        .get(&url)
+        .header(USER_AGENT, AGENT)
+        .send()
+        .await?
+        .error_for_status()?;
+    let tarball = tarball(&response.bytes().await?)?;
+
+    Ok(recipe(owner, &info, &release.tag_name, &tarball))
+}
+
+/// The JSON at `url` of the GitHub API, `None` if it doesn't exist.
+async fn get<T: DeserializeOwned>(client: &Client, url: &str) -> Result<Option<T>> {
+    let response = client.get(url).header(USER_AGENT, AGENT).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let response = response.error_for_status()?;
+    Ok(Some(serde_json::from_slice(&response.bytes().await?)?))
+}
+
+fn tarball_url(owner: &str, repo: &str, tag: &str) -> String {
+    format!("https://github.com/{owner}/{repo}/archive/refs/tags/{tag}.tar.gz")
+}
+
+fn tarball(bytes: &[u8]) -> Result<Tarball> {
+    let mut archive = Archive::new(GzDecoder::new(bytes));
+    let mut dir = None;
+    let mut files = Vec::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?;
+        let components: Vec<&str> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => part.to_str(),
+                _ => None,
+            })
+            .collect();
+
+        match components.as_slice() {
+            [root] => dir = Some(root.to_string()),
+            [_, file] => files.push(file.to_string()),
+            _ => {}
+        }
+    }
+
+    Ok(Tarball {
+        checksum: format!("blake3:{}", blake3::hash(bytes).to_hex()),
+        dir: dir.ok_or(format_err!("The release tarball is empty"))?,
+        files,
+    })
+}
+
+fn recipe(owner: &str, info: &Repository, tag: &str, tarball: &Tarball) -> Import {
+    let mut todos = Vec::new();
+
+    // Tags are mostly versions with a prefix, such as v1.2.
+    let version = tag.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let version = match version.is_empty() {
+        true => {
+            todos.push(format!("the tag {tag} isn't a version, set one"));
+            tag
+        }
+        false => version,
+    };
+    let with_version = |s: &str| s.replacen(version, "%{version}", 1);
+
+    let description = info.description.clone().unwrap_or_default();
+    if description.is_empty() {
+        todos.push("the repository has no description, describe the package".to_string());
+    }
+
+    let license = license(
+        &info
+            .license
+            .as_ref()
+            .and_then(|license| license.spdx_id.clone())
+            .filter(|id| id != "NOASSERTION")
+            .into_iter()
+            .collect::<Vec<_>>(),
+        &mut todos,
+    );
+
+    let dir = format!("%{{srcdir}}/{}", with_version(&tarball.dir));
+    let build_system = BuildSystem::detect(tarball.files.iter().map(String::as_str));
+    let (steps, build) = match build_system {
+        Some(build_system) => (build_system.steps(&dir), build_system.build_dependencies()),
+        None => {
+            todos.push("the build system couldn't be told, add steps".to_string());
+            (Vec::new(), Vec::new())
+        }
+    };
+
+    Import {
+        package: Package {
+            info: Info {
+                name: info.name.to_lowercase(),
+                version: version.to_string(),
+                description: description.into(),
+                license,
+                provides: Vec::new(),
+                conflicts: Vec::new(),
+                replaces: Vec::new(),
+                keywords: info.topics.clone(),
+            },
+            dependencies: Some(Dependencies {
+                required: Vec::new(),
+                optional: Vec::new(),
+                build,
+                check: Vec::new(),
+            }),
+            sources: vec![Source {
+                url: with_version(&tarball_url(owner, &info.name, tag)),
+                checksum: tarball.checksum.clone(),
+                rev: None,
+                // Tags are mostly named after versions alone.
+                filename: Some(format!("{}-%{{version}}.tar.gz", info.name)),
+                signature: None,
+            }],
+            steps,
+            directories: HashMap::new(),
+            backup: Vec::new(),
+            special_permissions: Vec::new(),
+            network: build_system.is_some_and(BuildSystem::needs_network),
+            pkgver: None,
+            upstream_keys: Vec::new(),
+            changelog: Changelog::default(),
+            extends: None,
+            path: None,
+        },
+        todos,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recipe() {
+        let info = Repository {
+            name: "Zstd".to_string(),
+            description: Some("Zstandard - Fast real-time compression algorithm".to_string()),
+            license: Some(RepositoryLicense {
+                spdx_id: Some("NOASSERTION".to_string()),
+            }),
+            topics: vec!["compression".to_string()],
+        };
+        let tarball = Tarball {
+            checksum: "blake3:ab12".to_string(),
+            dir: "zstd-1.5.6".to_string(),
+            files: vec!["Makefile".to_string(), "meson.build".to_string()],
+        };
+
+        let import = recipe("facebook", &info, "v1.5.6", &tarball);
+        let package = &import.package;
+
+        assert_eq!(package.info.name, "zstd");
+        assert_eq!(package.info.version, "1.5.6");
+        assert_eq!(
+            package.sources[0].url,
+            "https://github.com/facebook/Zstd/archive/refs/tags/v%{version}.tar.gz"
+        );
+        assert_eq!(
+            package.dependencies.as_ref().unwrap().build,
+            ["meson", "ninja"]
+        );
+        assert_eq!(package.steps.len(), 3);
+        assert_eq!(import.todos, ["license isn't set"]);
+    }
+
+    #[test]
+    fn test_tarball() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for path in [
+            "zstd-1.5.6/",
+            "zstd-1.5.6/meson.build",
+            "zstd-1.5.6/lib/zstd.h",
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_entry_type(match path.ends_with('/') {
+                true => tar::EntryType::Directory,
+                false => tar::EntryType::Regular,
+            });
+            header.set_cksum();
+            builder.append_data(&mut header, path, &[][..]).unwrap();
+        }
+        let bytes = builder.into_inner().unwrap().finish().unwrap();
+
+        let tarball = tarball(&bytes).unwrap();
+        assert_eq!(tarball.dir, "zstd-1.5.6");
+        assert_eq!(tarball.files, ["meson.build"]);
+        assert!(tarball.checksum.starts_with("blake3:"));
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            BuildSystem::detect(["README", "configure", "CMakeLists.txt"]),
+            Some(BuildSystem::Cmake)
+        );
+        assert_eq!(BuildSystem::detect(["README"]), None);
+    }
+}
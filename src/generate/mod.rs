@@ -0,0 +1,117 @@
+//! Recipes generated from what upstream ecosystems publish about a project,
+//! as opposed to [`crate::import`] converting the build scripts of other
+//! distributions.
+
+mod crates;
+mod github;
+mod pypi;
+mod template;
+
+use std::str::FromStr;
+
+use crate::{import::step, package::Step};
+
+pub use crates::{CRATES_API, from_crate};
+pub use github::{GITHUB_API, from_github};
+pub use pypi::{PYPI_API, from_pypi};
+pub use template::template;
+
+/// crates.io and GitHub turn away requests without a user agent.
+const AGENT: &str = concat!("blossom/", env!("CARGO_PKG_VERSION"));
+
+/// Build systems recipes can be generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildSystem {
+    Autotools,
+    Cmake,
+    Meson,
+    Cargo,
+}
+
+impl FromStr for BuildSystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "autotools" => Ok(BuildSystem::Autotools),
+            "cmake" => Ok(BuildSystem::Cmake),
+            "meson" => Ok(BuildSystem::Meson),
+            "cargo" => Ok(BuildSystem::Cargo),
+            _ => Err(format!(
+                "Unknown build system \"{s}\", expected autotools, cmake, meson or cargo"
+            )),
+        }
+    }
+}
+
+impl BuildSystem {
+    /// The files at the root of the sources telling the build systems apart,
+    /// the first found winning as projects sometimes ship several.
+    const FILES: [(&str, BuildSystem); 4] = [
+        ("meson.build", BuildSystem::Meson),
+        ("CMakeLists.txt", BuildSystem::Cmake),
+        ("Cargo.toml", BuildSystem::Cargo),
+        ("configure", BuildSystem::Autotools),
+    ];
+
+    /// The build system of sources with `files` at their root.
+    pub fn detect<'a>(files: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let files: Vec<&str> = files.into_iter().collect();
+
+        Self::FILES
+            .iter()
+            .find(|(file, _)| files.contains(file))
+            .map(|(_, build_system)| *build_system)
+    }
+
+    /// Steps building, testing and installing the sources in `dir`.
+    pub fn steps(self, dir: &str) -> Vec<Step> {
+        let [build, check, package] = match self {
+            BuildSystem::Autotools => [
+                "./configure --prefix=/usr\nmake -j%{jobs}",
+                "make check",
+                "make DESTDIR=\"%{pkgdir}\" install",
+            ],
+            BuildSystem::Cmake => [
+                "cmake -S . -B build -DCMAKE_INSTALL_PREFIX=/usr -DCMAKE_BUILD_TYPE=Release\n\
+                 cmake --build build -j %{jobs}",
+                "ctest --test-dir build",
+                "DESTDIR=\"%{pkgdir}\" cmake --install build",
+            ],
+            BuildSystem::Meson => [
+                "meson setup --prefix=/usr --buildtype=release build\n\
+                 meson compile -C build -j %{jobs}",
+                "meson test -C build",
+                "meson install -C build --destdir \"%{pkgdir}\"",
+            ],
+            BuildSystem::Cargo => [
+                "cargo build --release --locked -j %{jobs}",
+                "cargo test --release --locked -j %{jobs}",
+                "cargo install --path . --root \"%{pkgdir}/usr\" --locked --no-track",
+            ],
+        };
+
+        vec![
+            step("build", dir, build),
+            step("check", dir, check),
+            step("package", dir, package),
+        ]
+    }
+
+    /// The packages the build system needs.
+    pub fn build_dependencies(self) -> Vec<String> {
+        let dependencies: &[&str] = match self {
+            BuildSystem::Autotools => &["make"],
+            BuildSystem::Cmake => &["cmake"],
+            BuildSystem::Meson => &["meson", "ninja"],
+            BuildSystem::Cargo => &["cargo"],
+        };
+
+        dependencies.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Whether builds download dependencies, as cargo does.
+    pub fn needs_network(self) -> bool {
+        self == BuildSystem::Cargo
+    }
+}
@@ -0,0 +1,398 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::{
+    error::{Result, format_err},
+    import::{Import, license, step},
+    package::{Changelog, Dependencies, Info, Package, Source},
+};
+
+pub const PYPI_API: &str = "https://pypi.org/pypi";
+
+/// Build backends, and the packages providing them.
+const BACKENDS: [(&str, &str); 7] = [
+    ("setuptools.build_meta", "python-setuptools"),
+    ("hatchling.build", "python-hatchling"),
+    ("flit_core.buildapi", "python-flit-core"),
+    ("poetry.core.masonry.api", "python-poetry-core"),
+    ("pdm.backend", "python-pdm-backend"),
+    ("mesonpy", "python-meson-python"),
+    ("maturin", "maturin"),
+];
+
+#[derive(Debug, Deserialize)]
+struct ProjectResponse {
+    info: ProjectInfo,
+    urls: Vec<ReleaseFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectInfo {
+    name: String,
+    version: String,
+    summary: Option<String>,
+    license: Option<String>,
+    /// An SPDX expression, which newer metadata has instead of `license`.
+    license_expression: Option<String>,
+    requires_dist: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseFile {
+    filename: String,
+    url: String,
+    packagetype: String,
+    digests: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PyProject {
+    project: Project,
+    #[serde(rename = "build-system")]
+    build_system: Option<BuildSystem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    name: String,
+    version: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildSystem {
+    #[serde(rename = "build-backend")]
+    build_backend: Option<String>,
+}
+
+/// Generates a recipe building the Python project `name` into a wheel and
+/// installing it, from the sdist of its latest version on PyPI. `name` can
+/// also be the path of a local project, whose `pyproject.toml` tells the
+/// version and build backend.
+pub async fn from_pypi(client: &Client, name: &str) -> Result<Import> {
+    let pyproject = match Path::new(name).exists() {
+        true => Some(pyproject(Path::new(name))?),
+        false => None,
+    };
+
+    let url = match &pyproject {
+        Some(PyProject {
+            project:
+                Project {
+                    name,
+                    version: Some(version),
+                    ..
+                },
+            ..
+        }) => format!("{PYPI_API}/{name}/{version}/json"),
+        Some(pyproject) => format!("{PYPI_API}/{}/json", pyproject.project.name),
+        None => format!("{PYPI_API}/{name}/json"),
+    };
+
+    let response = client.get(url).send().await?.error_for_status()?;
+    let response: ProjectResponse = serde_json::from_slice(&response.bytes().await?)?;
+
+    Ok(recipe(&response, pyproject.as_ref()))
+}
+
+/// The `pyproject.toml` at `path`, or in it if it's a directory.
+fn pyproject(path: &Path) -> Result<PyProject> {
+    let path = match path.is_dir() {
+        true => path.join("pyproject.toml"),
+        false => path.to_path_buf(),
+    };
+
+    toml_edit::de::from_str(&fs::read_to_string(&path)?)
+        .map_err(|e| format_err!("Invalid {}: {e}", path.display()))
+}
+
+fn recipe(response: &ProjectResponse, pyproject: Option<&PyProject>) -> Import {
+    let info = &response.info;
+    let name = format!("python-{}", normalize(&info.name));
+    let mut todos = Vec::new();
+
+    let description = info.summary.clone().unwrap_or_default();
+    if description.is_empty() {
+        todos.push("the project has no summary, describe the package".to_string());
+    }
+
+    let license = license(
+        &info
+            .license_expression
+            .iter()
+            .chain(&info.license)
+            .take(1)
+            .cloned()
+            .collect::<Vec<_>>(),
+        &mut todos,
+    );
+
+    let mut build = vec!["python-build".to_string(), "python-installer".to_string()];
+    let backend = pyproject
+        .and_then(|pyproject| pyproject.build_system.as_ref())
+        .and_then(|build_system| build_system.build_backend.as_deref());
+    match backend.and_then(|backend| BACKENDS.iter().find(|(name, _)| *name == backend)) {
+        Some((_, package)) => build.push(package.to_string()),
+        None => todos.push(match backend {
+            Some(backend) => format!("add the package providing the {backend} build backend"),
+            None => "add the package providing the build backend".to_string(),
+        }),
+    }
+
+    // A local pyproject.toml knows the dependencies even when PyPI doesn't.
+    let requirements = match pyproject {
+        Some(pyproject) => pyproject.project.dependencies.clone(),
+        None => info.requires_dist.clone().unwrap_or_default(),
+    };
+    let mut required = vec!["python".to_string()];
+    for requirement in &requirements {
+        if let Some(dependency) = dependency(requirement, &mut todos) {
+            required.push(dependency);
+        }
+    }
+
+    let mut sources = Vec::new();
+    let mut dir = format!("%{{srcdir}}/{}-%{{version}}", info.name);
+
+    match response
+        .urls
+        .iter()
+        .find(|file| file.packagetype == "sdist")
+    {
+        Some(sdist) if sdist.filename.ends_with(".tar.gz") => {
+            // sdists extract to a directory named after them.
+            dir = format!(
+                "%{{srcdir}}/{}",
+                sdist
+                    .filename
+                    .trim_end_matches(".tar.gz")
+                    .replace(&info.version, "%{version}")
+            );
+
+            let checksum = match sdist.digests.get("sha256") {
+                Some(sha256) => format!("sha256:{sha256}"),
+                None => {
+                    todos.push(format!("{} has no sha256 checksum, add one", sdist.url));
+                    String::new()
+                }
+            };
+
+            sources.push(Source {
+                url: sdist.url.clone(),
+                checksum,
+                rev: None,
+                filename: None,
+                signature: None,
+            });
+        }
+        Some(sdist) => todos.push(format!(
+            "{} isn't an archive blossom can extract",
+            sdist.url
+        )),
+        None => todos.push(format!("{} {} has no sdist", info.name, info.version)),
+    }
+
+    let steps = vec![
+        step("build", &dir, "python -m build --wheel --no-isolation"),
+        step(
+            "package",
+            &dir,
+            "python -m installer --destdir=\"%{pkgdir}\" dist/*.whl",
+        ),
+    ];
+
+    Import {
+        package: Package {
+            info: Info {
+                name,
+                version: info.version.clone(),
+                description: description.into(),
+                license,
+                provides: Vec::new(),
+                conflicts: Vec::new(),
+                replaces: Vec::new(),
+                keywords: Vec::new(),
+            },
+            dependencies: Some(Dependencies {
+                required,
+                optional: Vec::new(),
+                build,
+                check: Vec::new(),
+            }),
+            sources,
+            steps,
+            directories: HashMap::new(),
+            backup: Vec::new(),
+            special_permissions: Vec::new(),
+            network: false,
+            pkgver: None,
+            upstream_keys: Vec::new(),
+            changelog: Changelog::default(),
+            extends: None,
+            path: None,
+        },
+        todos,
+    }
+}
+
+/// A project name as distributions name its package, lowercased with runs
+/// of `-`, `_` and `.` replaced by `-` as PEP 503 normalizes them.
+fn normalize(name: &str) -> String {
+    let mut normalized = String::new();
+
+    for c in name.chars() {
+        match c {
+            '-' | '_' | '.' => {
+                if !normalized.ends_with('-') {
+                    normalized.push('-');
+                }
+            }
+            c => normalized.push(c.to_ascii_lowercase()),
+        }
+    }
+
+    normalized
+}
+
+/// A PEP 508 requirement as a blossom dependency. Those only needed by
+/// extras are left out, as they are optional.
+fn dependency(requirement: &str, todos: &mut Vec<String>) -> Option<String> {
+    let (requirement, marker) = match requirement.split_once(';') {
+        Some((requirement, marker)) => (requirement.trim(), Some(marker.trim())),
+        None => (requirement.trim(), None),
+    };
+
+    if let Some(marker) = marker {
+        if marker.contains("extra") {
+            return None;
+        }
+        todos.push(format!(
+            "{requirement} is only needed when {marker}, check it applies"
+        ));
+    }
+
+    let end = requirement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+    let name = format!("python-{}", normalize(&requirement[..end]));
+
+    // Extras of a dependency (`foo[bar]`) pull in more than the package.
+    let mut specifiers = requirement[end..].trim();
+    if let Some(rest) = specifiers.strip_prefix('[') {
+        todos.push(format!("{requirement} needs extras of {name}"));
+        specifiers = rest.split_once(']').map_or("", |(_, rest)| rest).trim();
+    }
+    let specifiers = specifiers.trim_start_matches('(').trim_end_matches(')');
+
+    let mut constraints = Vec::new();
+    for specifier in specifiers.split(',').map(str::trim) {
+        if specifier.is_empty() {
+            continue;
+        }
+
+        let constraint = match specifier {
+            s if s.starts_with("~=") => Some(format!(">={}", s[2..].trim())),
+            s if s.starts_with("===") => Some(format!("={}", s[3..].trim())),
+            s if s.starts_with("==") && !s.ends_with('*') => Some(format!("={}", s[2..].trim())),
+            s if [">=", "<=", ">", "<"].iter().any(|op| s.starts_with(op)) => {
+                Some(s.replace(' ', ""))
+            }
+            _ => None,
+        };
+
+        match constraint {
+            Some(constraint) => constraints.push(constraint),
+            None => todos.push(format!("{name} {specifier} couldn't be converted")),
+        }
+    }
+
+    Some(format!("{name}{}", constraints.join(",")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::StepVariant;
+
+    #[test]
+    fn test_recipe() {
+        let response: ProjectResponse = serde_json::from_str(
+            r#"{
+                "info": {
+                    "name": "Requests",
+                    "version": "2.32.3",
+                    "summary": "Python HTTP for Humans.",
+                    "license": "Apache-2.0",
+                    "license_expression": null,
+                    "requires_dist": [
+                        "charset-normalizer<4,>=2",
+                        "idna (>=2.5)",
+                        "urllib3~=2.0",
+                        "PySocks!=1.5.7,>=1.5.6; extra == \"socks\""
+                    ]
+                },
+                "urls": [
+                    {
+                        "filename": "requests-2.32.3-py3-none-any.whl",
+                        "url": "https://files.pythonhosted.org/r.whl",
+                        "packagetype": "bdist_wheel",
+                        "digests": {"sha256": "cd34"}
+                    },
+                    {
+                        "filename": "requests-2.32.3.tar.gz",
+                        "url": "https://files.pythonhosted.org/requests-2.32.3.tar.gz",
+                        "packagetype": "sdist",
+                        "digests": {"sha256": "ab12"}
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let import = recipe(&response, None);
+        let package = &import.package;
+
+        assert_eq!(package.info.name, "python-requests");
+        assert_eq!(
+            package.dependencies.as_ref().unwrap().required,
+            [
+                "python",
+                "python-charset-normalizer<4,>=2",
+                "python-idna>=2.5",
+                "python-urllib3>=2.0"
+            ]
+        );
+        assert_eq!(package.sources[0].checksum, "sha256:ab12");
+        assert_eq!(
+            import.todos,
+            ["add the package providing the build backend"]
+        );
+
+        match &package.steps[1].variant {
+            StepVariant::Command { command, .. } => assert_eq!(
+                command,
+                "cd \"%{srcdir}/requests-%{version}\"\n\
+                 python -m installer --destdir=\"%{pkgdir}\" dist/*.whl"
+            ),
+            StepVariant::Move { .. } => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_dependency() {
+        let mut todos = Vec::new();
+
+        assert_eq!(
+            dependency("zope.interface>=5; python_version < \"3.12\"", &mut todos),
+            Some("python-zope-interface>=5".to_string())
+        );
+        assert_eq!(
+            dependency("foo==1.*", &mut todos),
+            Some("python-foo".to_string())
+        );
+        assert_eq!(todos.len(), 2);
+    }
+}
@@ -0,0 +1,137 @@
+use toml_edit::DocumentMut;
+
+use super::BuildSystem;
+use crate::{
+    error::{Error, Result},
+    package::Package,
+};
+
+/// A checksum no source has, so that the first build fails telling the
+/// actual one.
+const PLACEHOLDER_CHECKSUM: &str =
+    "blake3:0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The options recipes can set, commented out at the top of new ones.
+const OPTIONS: &str = "
+# Files in the package users edit, kept across upgrades:
+# backup = [\"etc/%{name}.conf\"]
+# Lets the steps download, for build systems fetching dependencies:
+# network = true
+# Versions packages built from a git repository after what's checked out:
+# pkgver = \"git -C %{name} describe --long --tags | sed 's/^v//;s/-/.r/;s/-/./g'\"
+# Keys of upstream the sources' signature = \"<url>.asc\" are checked with:
+# upstream_keys = [\"upstream.asc\"]
+";
+
+/// The variables steps can use, and the directories adding more of them,
+/// commented out at the bottom of new recipes.
+const VARIABLES: &str = "
+# Sources and steps can use the variables %{version}, %{pkgdir} where steps
+# install the package, %{srcdir} where sources are extracted, %{recipedir}
+# the directory of this recipe, %{jobs} and %{target}. Directories add more:
+#
+# [directories]
+# docdir = \"%{pkgdir}/usr/share/doc/%{name}\"
+";
+
+/// A recipe for a new package `name`, to be filled in: placeholders for
+/// what only upstream knows, steps for `build_system` if any, and the
+/// options and variables recipes can use commented out.
+pub fn template(name: &str, build_system: Option<BuildSystem>) -> Result<String> {
+    let mut builder = Package::builder()
+        .name(name)
+        .version("1.0.0")
+        .description(format!("TODO: describe {name}"))
+        .license("LicenseRef-unknown")
+        .source(
+            format!("https://example.com/{name}-%{{version}}.tar.gz"),
+            PLACEHOLDER_CHECKSUM,
+        );
+
+    let dir = format!("%{{srcdir}}/{name}-%{{version}}");
+    match build_system {
+        Some(build_system) => {
+            for dependency in build_system.build_dependencies() {
+                builder = builder.build_dependency(dependency);
+            }
+        }
+        None => {
+            builder = builder.command(
+                "package",
+                format!("cd \"{dir}\"\n# TODO: build and install the package into \"%{{pkgdir}}\""),
+            )
+        }
+    }
+
+    let mut package = builder.build()?;
+    if let Some(build_system) = build_system {
+        package.steps = build_system.steps(&dir);
+        package.network = build_system.needs_network();
+    }
+
+    let mut recipe = String::from(
+        "# TODO: set the version, description, license and source of the package,\n\
+         # the first build tells the checksum of the source.\n",
+    );
+    recipe.push_str(&OPTIONS.replace("%{name}", name));
+    recipe.push('\n');
+    recipe.push_str(without_defaults(&package.to_recipe()?)?.trim_start());
+    recipe.push_str(&VARIABLES.replace("%{name}", name));
+
+    Ok(recipe)
+}
+
+/// `recipe` without the fields left empty, which the comments show instead.
+fn without_defaults(recipe: &str) -> Result<String> {
+    let mut document: DocumentMut = recipe.parse().map_err(|e| Error::Parse {
+        what: "the recipe".to_string(),
+        message: format!("{e}"),
+    })?;
+
+    for key in ["backup", "special_permissions", "directories"] {
+        document.remove(key);
+    }
+    if document.get("network").and_then(|item| item.as_bool()) == Some(false) {
+        document.remove("network");
+    }
+
+    Ok(document.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::StepVariant;
+
+    #[test]
+    fn test_template() {
+        let recipe = template("foo", Some(BuildSystem::Meson)).unwrap();
+        let package = Package::parse(&recipe).unwrap();
+
+        assert_eq!(package.info.name, "foo");
+        assert_eq!(package.sources[0].checksum, PLACEHOLDER_CHECKSUM);
+        assert_eq!(
+            package.dependencies.as_ref().unwrap().build,
+            ["meson", "ninja"]
+        );
+        assert_eq!(
+            package
+                .steps
+                .iter()
+                .map(|step| step.name.as_str())
+                .collect::<Vec<_>>(),
+            ["build", "check", "package"]
+        );
+        assert!(!recipe.contains("\nbackup = "));
+        assert!(recipe.contains("# [directories]\n# docdir = \"%{pkgdir}/usr/share/doc/foo\"\n"));
+
+        let recipe = template("foo", None).unwrap();
+        let package = Package::parse(&recipe).unwrap();
+        assert!(matches!(
+            &package.steps[..],
+            [step] if matches!(&step.variant, StepVariant::Command { command, .. } if command.contains("TODO"))
+        ));
+
+        assert!(template("foo bar", None).is_err());
+    }
+}
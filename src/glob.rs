@@ -0,0 +1,39 @@
+use regex::Regex;
+
+use crate::error::{Error, Result};
+
+/// Translates a shell-style glob (`*`, `?`) into an anchored regex.
+pub fn to_regex(glob: &str) -> Result<Regex> {
+    let mut pattern = String::from("^");
+
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    pattern.push('$');
+
+    Regex::new(&pattern).map_err(|e| Error::Parse {
+        what: format!("pattern {glob}"),
+        message: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_regex() {
+        let regex = to_regex("lib*-dev?").unwrap();
+
+        assert!(regex.is_match("libfoo-dev1"));
+        assert!(!regex.is_match("libfoo-dev"));
+        assert!(!regex.is_match("xlibfoo-dev1"));
+        assert!(to_regex("a.b").unwrap().is_match("a.b"));
+        assert!(!to_regex("a.b").unwrap().is_match("axb"));
+    }
+}
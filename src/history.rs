@@ -0,0 +1,125 @@
+use std::{
+    env,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{database::DATABASE_DIR, error::Result};
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Change {
+    Install {
+        name: String,
+        version: String,
+    },
+    Remove {
+        name: String,
+        version: String,
+    },
+    Upgrade {
+        name: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl Change {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Install { name, .. } | Self::Remove { name, .. } | Self::Upgrade { name, .. } => {
+                name
+            }
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Install { name, version } => write!(f, "install {name} {version}"),
+            Self::Remove { name, version } => write!(f, "remove {name} {version}"),
+            Self::Upgrade { name, from, to } => write!(f, "upgrade {name} {from} -> {to}"),
+        }
+    }
+}
+
+/// Append-only journal of every transaction applied to a root.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn open<R: AsRef<Path>>(root: R) -> Result<Self> {
+        let dir = root.as_ref().join(DATABASE_DIR);
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            path: dir.join(HISTORY_FILE),
+        })
+    }
+
+    pub fn list(&self) -> Result<Vec<Transaction>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Result<Option<Transaction>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|transaction| transaction.id == id))
+    }
+
+    pub fn record(&self, changes: Vec<Change>) -> Result<Option<Transaction>> {
+        if changes.is_empty() {
+            return Ok(None);
+        }
+
+        let id = self.list()?.last().map_or(1, |last| last.id + 1);
+
+        let transaction = Transaction {
+            id,
+            timestamp: Utc::now(),
+            user: current_user(),
+            changes,
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        writeln!(file, "{}", serde_json::to_string(&transaction)?)?;
+
+        Ok(Some(transaction))
+    }
+}
+
+fn current_user() -> String {
+    env::var("SUDO_USER")
+        .or_else(|_| env::var("USER"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, fs, path::Path, process::Command};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    glob,
+    history::Change,
+    log::{info, warn},
+};
+
+/// Directories, relative to the root, hooks are loaded from. Hooks in earlier
+/// directories override hooks with the same file name in later ones.
+pub const HOOK_DIRS: [&str; 2] = ["etc/blossom/hooks", "usr/share/blossom/hooks"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Install,
+    Upgrade,
+    Remove,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hook {
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default = "default_priority")]
+    pub priority: i32,
+    #[serde(default = "all_operations")]
+    pub operations: Vec<Operation>,
+    pub paths: Vec<String>,
+    pub exec: String,
+}
+
+fn default_priority() -> i32 {
+    50
+}
+
+fn all_operations() -> Vec<Operation> {
+    vec![Operation::Install, Operation::Upgrade, Operation::Remove]
+}
+
+impl Hook {
+    fn matches(&self, operation: Operation, path: &Utf8Path) -> Result<bool> {
+        if !self.operations.contains(&operation) {
+            return Ok(false);
+        }
+
+        for pattern in &self.paths {
+            if glob::to_regex(pattern.trim_start_matches('/'))?.is_match(path.as_str()) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Loads every hook available in a root, ordered by priority and name.
+pub fn load<R: AsRef<Path>>(root: R) -> Result<Vec<Hook>> {
+    let mut hooks = BTreeMap::new();
+
+    for dir in HOOK_DIRS.iter().rev() {
+        let dir = root.as_ref().join(dir);
+
+        if !dir.exists() {
+            continue;
+        }
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.extension().is_none_or(|ext| ext != "toml") {
+                continue;
+            }
+
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let mut hook: Hook = toml_edit::de::from_str(&fs::read_to_string(&path)?)?;
+            hook.name = name.to_string();
+
+            hooks.insert(hook.name.clone(), hook);
+        }
+    }
+
+    let mut hooks: Vec<Hook> = hooks.into_values().collect();
+    hooks.sort_by(|a, b| a.priority.cmp(&b.priority).then(a.name.cmp(&b.name)));
+
+    Ok(hooks)
+}
+
+/// Paths touched by a transaction, collected so that hooks are fired once at
+/// the end of it instead of once per package.
+#[derive(Debug, Default)]
+pub struct Triggers {
+    touched: Vec<(Operation, Utf8PathBuf)>,
+}
+
+impl Triggers {
+    pub fn record<'a, I>(&mut self, change: &Change, paths: I)
+    where
+        I: IntoIterator<Item = &'a Utf8PathBuf>,
+    {
+        let operation = match change {
+            Change::Install { .. } => Operation::Install,
+            Change::Upgrade { .. } => Operation::Upgrade,
+            Change::Remove { .. } => Operation::Remove,
+        };
+
+        self.touched
+            .extend(paths.into_iter().map(|path| (operation, path.clone())));
+    }
+
+    /// Runs every hook matching at least one touched path, once each.
+    pub fn run<R: AsRef<Path>>(&self, root: R) -> Result<()> {
+        if self.touched.is_empty() {
+            return Ok(());
+        }
+
+        for hook in load(&root)? {
+            let mut triggered = false;
+
+            for (operation, path) in &self.touched {
+                if hook.matches(*operation, path)? {
+                    triggered = true;
+                    break;
+                }
+            }
+
+            if !triggered {
+                continue;
+            }
+
+            info!("Running hook: {}", hook.name);
+
+            let status = Command::new("/bin/sh")
+                .arg("-c")
+                .arg(&hook.exec)
+                .current_dir(&root)
+                .env("BLOSSOM_ROOT", root.as_ref())
+                .status();
+
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => warn!("Hook {} failed with {status}", hook.name),
+                Err(e) => warn!("Failed to run hook {}: {e}", hook.name),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_matches() {
+        let hook = Hook {
+            name: "ldconfig".to_string(),
+            priority: 50,
+            operations: vec![Operation::Install, Operation::Upgrade],
+            paths: vec!["/usr/lib/*.so*".to_string()],
+            exec: "ldconfig".to_string(),
+        };
+
+        assert!(
+            hook.matches(Operation::Install, Utf8Path::new("usr/lib/libfoo.so.1"))
+                .unwrap()
+        );
+        assert!(
+            !hook
+                .matches(Operation::Remove, Utf8Path::new("usr/lib/libfoo.so.1"))
+                .unwrap()
+        );
+        assert!(
+            !hook
+                .matches(Operation::Install, Utf8Path::new("usr/bin/foo"))
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use super::{
+    Import, STEPS, is_archive, license,
+    shell::{SPECIALS, Script},
+    step,
+};
+use crate::{
+    error::{Error, Result},
+    package::{Changelog, Dependencies, Info, Package, Source},
+};
+
+/// Dependencies on what a package contains rather than on a package, which
+/// only apk resolves.
+const PROVIDED: [&str; 3] = ["so:", "cmd:", "pc:"];
+
+/// Converts an APKBUILD into a recipe, as far as that can be done without
+/// running it. What couldn't be converted is listed in [`Import::todos`].
+pub fn apkbuild(s: &str) -> Result<Import> {
+    let specials = [SPECIALS.as_slice(), &[("JOBS", "%{jobs}")]].concat();
+    let mut apkbuild = Script::parse(s, &specials);
+    let mut todos = Vec::new();
+
+    let name = apkbuild
+        .scalar("pkgname")
+        .ok_or_else(|| Error::Parse {
+            what: "the APKBUILD".to_string(),
+            message: "pkgname isn't set".to_string(),
+        })?
+        .to_string();
+
+    let version = match apkbuild.scalar("pkgver") {
+        Some(version) => version.to_string(),
+        None => {
+            todos.push("pkgver isn't set".to_string());
+            "0".to_string()
+        }
+    };
+
+    let description = apkbuild.scalar("pkgdesc").unwrap_or_default().to_string();
+    if description.is_empty() {
+        todos.push("pkgdesc isn't set, describe the package".to_string());
+    }
+
+    // Alpine licenses are SPDX expressions already, written as one string.
+    let license = license(
+        &apkbuild
+            .scalar("license")
+            .map(str::to_string)
+            .into_iter()
+            .collect::<Vec<_>>(),
+        &mut todos,
+    );
+
+    let mut conflicts = Vec::new();
+    let required = dependencies(&apkbuild.list("depends"), &mut conflicts, &mut todos);
+    let build = dependencies(&apkbuild.list("makedepends"), &mut conflicts, &mut todos);
+    let check = dependencies(&apkbuild.list("checkdepends"), &mut conflicts, &mut todos);
+
+    let subpackages = apkbuild.list("subpackages");
+    if !subpackages.is_empty() {
+        todos.push(format!(
+            "subpackages aren't supported, {} weren't imported",
+            subpackages.join(", ")
+        ));
+    }
+    if let Some(install) = apkbuild.scalar("install") {
+        todos.push(format!("install scripts {install} weren't imported"));
+    }
+
+    let (sources, patches) = sources(&apkbuild, &mut todos);
+
+    let options = apkbuild.list("options");
+
+    // abuild runs every function in $builddir, the directory of the first
+    // archive by default.
+    if apkbuild.scalar("builddir").is_none() {
+        apkbuild.set("builddir", format!("%{{srcdir}}/{name}-%{{version}}"));
+    }
+    let builddir = apkbuild.scalar("builddir").unwrap_or_default().to_string();
+
+    // default_prepare applies the patches among the sources.
+    let apply_patches = patches
+        .iter()
+        .map(|patch| format!("patch -p1 -i \"%{{recipedir}}/{patch}\""))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut steps = Vec::new();
+
+    for name in STEPS {
+        if name == "check" && options.iter().any(|option| option == "!check") {
+            continue;
+        }
+
+        let body = match (name, apkbuild.function(name)) {
+            ("prepare", Some(body)) => apkbuild
+                .replace(body)
+                .replace("default_prepare", &apply_patches),
+            ("prepare", None) if !patches.is_empty() => apply_patches.clone(),
+            (_, Some(body)) => apkbuild.replace(body),
+            (_, None) => continue,
+        };
+
+        steps.push(step(name, &builddir, &body));
+    }
+
+    for function in apkbuild.functions() {
+        if !STEPS.contains(&function) {
+            todos.push(format!("{function}() wasn't imported"));
+        }
+    }
+
+    todos.extend(apkbuild.todos.iter().cloned());
+
+    Ok(Import {
+        package: Package {
+            info: Info {
+                name,
+                version,
+                description: description.into(),
+                license,
+                provides: apkbuild.list("provides"),
+                conflicts,
+                replaces: apkbuild.list("replaces"),
+                keywords: Vec::new(),
+            },
+            dependencies: Some(Dependencies {
+                required,
+                optional: Vec::new(),
+                build,
+                check,
+            }),
+            sources,
+            steps,
+            directories: HashMap::new(),
+            backup: Vec::new(),
+            special_permissions: Vec::new(),
+            // The `net` option lets builds access the network.
+            network: options.iter().any(|option| option == "net"),
+            pkgver: None,
+            upstream_keys: Vec::new(),
+            changelog: Changelog::default(),
+            extends: None,
+            path: None,
+        },
+        todos,
+    })
+}
+
+/// The packages among `dependencies`. Those starting with `!` are conflicts.
+fn dependencies(
+    dependencies: &[String],
+    conflicts: &mut Vec<String>,
+    todos: &mut Vec<String>,
+) -> Vec<String> {
+    let mut packages = Vec::new();
+
+    for dependency in dependencies {
+        if let Some(conflict) = dependency.strip_prefix('!') {
+            conflicts.push(conflict.to_string());
+        } else if PROVIDED.iter().any(|prefix| dependency.starts_with(prefix)) {
+            todos.push(format!("replace {dependency} by the package providing it"));
+        } else {
+            packages.push(dependency.clone());
+        }
+    }
+
+    packages
+}
+
+/// The sources of an APKBUILD, and the names of its patches. Alpine only
+/// checks sources against sha512 checksums, which recipes don't support.
+fn sources(apkbuild: &Script, todos: &mut Vec<String>) -> (Vec<Source>, Vec<String>) {
+    let mut sources = Vec::new();
+    let mut patches = Vec::new();
+
+    for source in apkbuild.list("source") {
+        // `name::url` renames what is downloaded.
+        let (filename, url) = match source.split_once("::") {
+            Some((filename, url)) => (Some(filename.to_string()), url),
+            None => (None, source.as_str()),
+        };
+
+        if !url.contains("://") {
+            todos.push(format!(
+                "copy {url} next to the recipe and refer to it as %{{recipedir}}/{url}"
+            ));
+            if url.ends_with(".patch") {
+                patches.push(url.to_string());
+            }
+            continue;
+        }
+
+        if !is_archive(filename.as_deref().unwrap_or(url)) {
+            todos.push(format!("{url} isn't an archive blossom can extract"));
+            continue;
+        }
+
+        todos.push(format!("{url} has no sha256 checksum, add one"));
+
+        sources.push(Source {
+            url: url.to_string(),
+            checksum: String::new(),
+            rev: None,
+            filename,
+            signature: None,
+        });
+    }
+
+    (sources, patches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::StepVariant;
+
+    const APKBUILD: &str = r#"# Contributor: Someone <someone@example.com>
+# Maintainer: Someone <someone@example.com>
+pkgname=zstd
+pkgver=1.5.6
+pkgrel=2
+pkgdesc="Zstandard - Fast real-time compression algorithm"
+url="https://www.zstd.net/"
+arch="all"
+license="BSD-3-Clause OR GPL-2.0-or-later"
+depends="!zstd-legacy"
+makedepends="grep meson so:libc.musl-x86_64.so.1"
+checkdepends="file"
+subpackages="$pkgname-libs $pkgname-dev $pkgname-doc"
+source="zstd-$pkgver.tar.gz::https://github.com/facebook/zstd/archive/v$pkgver.tar.gz
+	fix-tests.patch
+	"
+options="!check"
+
+build() {
+	abuild-meson build/meson output
+	meson compile -j $JOBS -C output
+}
+
+check() {
+	meson test -C output
+}
+
+package() {
+	DESTDIR="$pkgdir" meson install --no-rebuild -C output
+}
+
+sha512sums="
+ab12  zstd-1.5.6.tar.gz
+cd34  fix-tests.patch
+"
+"#;
+
+    #[test]
+    fn test_apkbuild() {
+        let import = apkbuild(APKBUILD).unwrap();
+        let package = &import.package;
+
+        assert_eq!(package.info.name, "zstd");
+        assert_eq!(package.info.version, "1.5.6");
+        assert_eq!(
+            package.info.license.to_string(),
+            "BSD-3-Clause OR GPL-2.0-or-later"
+        );
+        assert_eq!(package.info.conflicts, ["zstd-legacy"]);
+
+        let dependencies = package.dependencies.as_ref().unwrap();
+        assert!(dependencies.required.is_empty());
+        assert_eq!(dependencies.build, ["grep", "meson"]);
+        assert_eq!(dependencies.check, ["file"]);
+
+        assert_eq!(package.sources.len(), 1);
+        assert_eq!(
+            package.sources[0].url,
+            "https://github.com/facebook/zstd/archive/v%{version}.tar.gz"
+        );
+
+        let commands: Vec<&str> = package
+            .steps
+            .iter()
+            .map(|step| match &step.variant {
+                StepVariant::Command { command, .. } => command.as_str(),
+                StepVariant::Move { .. } => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "cd \"%{srcdir}/zstd-%{version}\"\npatch -p1 -i \"%{recipedir}/fix-tests.patch\"",
+                "cd \"%{srcdir}/zstd-%{version}\"\nabuild-meson build/meson output\n\
+                 meson compile -j %{jobs} -C output",
+                "cd \"%{srcdir}/zstd-%{version}\"\n\
+                 DESTDIR=\"%{pkgdir}\" meson install --no-rebuild -C output",
+            ]
+        );
+
+        assert_eq!(
+            import.todos,
+            [
+                "replace so:libc.musl-x86_64.so.1 by the package providing it",
+                "subpackages aren't supported, zstd-libs, zstd-dev, zstd-doc weren't imported",
+                "https://github.com/facebook/zstd/archive/v%{version}.tar.gz has no sha256 \
+                 checksum, add one",
+                "copy fix-tests.patch next to the recipe and refer to it as \
+                 %{recipedir}/fix-tests.patch",
+            ]
+        );
+    }
+}
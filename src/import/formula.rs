@@ -0,0 +1,679 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::Regex;
+
+use super::{Import, license, step};
+use crate::{
+    error::{Error, Result},
+    package::{Changelog, Dependencies, Info, Package, Source},
+};
+
+/// Directories formulae install into, and where they are on a system.
+/// Formula methods of the same names return them as paths.
+const DIRS: [(&str, &str); 23] = [
+    ("prefix", "/usr"),
+    ("bin", "/usr/bin"),
+    ("sbin", "/usr/sbin"),
+    ("lib", "/usr/lib"),
+    ("libexec", "/usr/libexec"),
+    ("include", "/usr/include"),
+    ("share", "/usr/share"),
+    ("info", "/usr/share/info"),
+    ("man", "/usr/share/man"),
+    ("man1", "/usr/share/man/man1"),
+    ("man2", "/usr/share/man/man2"),
+    ("man3", "/usr/share/man/man3"),
+    ("man4", "/usr/share/man/man4"),
+    ("man5", "/usr/share/man/man5"),
+    ("man6", "/usr/share/man/man6"),
+    ("man7", "/usr/share/man/man7"),
+    ("man8", "/usr/share/man/man8"),
+    ("etc", "/etc"),
+    ("var", "/var"),
+    ("bash_completion", "/usr/share/bash-completion/completions"),
+    ("zsh_completion", "/usr/share/zsh/site-functions"),
+    ("fish_completion", "/usr/share/fish/vendor_completions.d"),
+    ("frameworks", "/usr/lib"),
+];
+
+/// The arguments Homebrew passes to build systems, with a `/usr` prefix.
+const STD_ARGS: [(&str, &str); 4] = [
+    (
+        "std_configure_args",
+        "--prefix=/usr --disable-dependency-tracking",
+    ),
+    (
+        "std_cmake_args",
+        "-DCMAKE_INSTALL_PREFIX=/usr -DCMAKE_BUILD_TYPE=Release",
+    ),
+    ("std_meson_args", "--prefix=/usr --buildtype=release"),
+    (
+        "std_cargo_args",
+        "--locked --root \"%{pkgdir}/usr\" --path .",
+    ),
+];
+
+/// Blocks that only matter to Homebrew.
+const IGNORED: [&str; 4] = ["bottle", "livecheck", "head", "on_macos"];
+
+static CLASS_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^class\s+(\w+)\s*<\s*Formula").expect("invalid regex"));
+static DEPENDENCY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(depends_on|uses_from_macos)\s+"([^"]+)"(?:\s*=>\s*(.+))?"#)
+        .expect("invalid regex")
+});
+static OPTION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(\w+):\s*"([^"]*)""#).expect("invalid regex"));
+static STRING_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""([^"]*)""#).expect("invalid regex"));
+static INTERPOLATION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#\{([^}]*)\}").expect("invalid regex"));
+static VERSION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\d+(?:\.\d+)+").expect("invalid regex"));
+static INSTALL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\w+)\.install\s+(.+)$").expect("invalid regex"));
+static ENV_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^ENV\["(\w+)"\]\s*=\s*"([^"]*)"$"#).expect("invalid regex"));
+
+/// A formula, read line by line as far as its DSL goes.
+#[derive(Debug, Default)]
+struct Formula {
+    name: String,
+    fields: HashMap<String, String>,
+    url_options: HashMap<String, String>,
+    license: Option<String>,
+    required: Vec<String>,
+    optional: Vec<String>,
+    build: Vec<String>,
+    install: Vec<String>,
+    todos: Vec<String>,
+}
+
+impl Formula {
+    fn parse(s: &str) -> Self {
+        let mut formula = Self::default();
+        // The blocks and methods the line is in.
+        let mut blocks: Vec<String> = Vec::new();
+
+        for line in s.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if let Some(caps) = CLASS_REGEX.captures(trimmed) {
+                formula.name = kebab_case(&caps[1]);
+                blocks.push("class".to_string());
+                continue;
+            }
+
+            let in_install = blocks.iter().any(|block| block == "install");
+            let opened = opened_block(trimmed);
+
+            if trimmed == "end" {
+                if blocks.pop().as_deref() != Some("install") && in_install {
+                    formula.install.push(line.to_string());
+                }
+                continue;
+            }
+
+            if in_install {
+                formula.install.push(line.to_string());
+                blocks.extend(opened);
+                continue;
+            }
+
+            if let Some(block) = opened {
+                let ignored = blocks.iter().any(|block| IGNORED.contains(&block.as_str()));
+                if !ignored {
+                    match block.as_str() {
+                        "install" | "stable" | "on_linux" => {}
+                        b if IGNORED.contains(&b) => {}
+                        "test" => formula
+                            .todos
+                            .push("the test block wasn't imported".to_string()),
+                        b => formula
+                            .todos
+                            .push(format!("{b} wasn't imported: `{trimmed}`")),
+                    }
+                }
+                blocks.push(block);
+                continue;
+            }
+
+            // Only the class body and the blocks relevant on Linux describe
+            // the package.
+            if !blocks
+                .iter()
+                .all(|block| matches!(block.as_str(), "class" | "stable" | "on_linux"))
+            {
+                continue;
+            }
+
+            formula.statement(trimmed);
+        }
+
+        formula
+    }
+
+    fn statement(&mut self, statement: &str) {
+        if let Some(caps) = DEPENDENCY_REGEX.captures(statement) {
+            let (name, kind) = (&caps[2], caps.get(3).map_or("", |m| m.as_str()));
+
+            // Versioned formulae (openssl@3) pin a series of the package.
+            let dependency = match name.split_once('@') {
+                Some((base, series)) => {
+                    self.todos.push(format!(
+                        "{name} was imported as {base}, check version {series} fits"
+                    ));
+                    base.to_string()
+                }
+                None => name.to_string(),
+            };
+
+            if kind.contains(":build") || kind.contains(":test") {
+                self.build.push(dependency);
+            } else if kind.contains(":optional") || kind.contains(":recommended") {
+                self.optional.push(dependency);
+            } else {
+                self.required.push(dependency);
+            }
+            return;
+        }
+
+        let (method, arguments) = statement
+            .split_once(char::is_whitespace)
+            .unwrap_or((statement, ""));
+        let arguments = arguments.trim();
+        let string = STRING_REGEX
+            .captures(arguments)
+            .map(|caps| caps[1].to_string());
+
+        match (method, string) {
+            ("license", _) => self.license(arguments),
+            ("url", Some(url)) => {
+                self.fields.insert("url".to_string(), url);
+                for caps in OPTION_REGEX.captures_iter(arguments) {
+                    self.url_options
+                        .insert(caps[1].to_string(), caps[2].to_string());
+                }
+            }
+            ("desc" | "homepage" | "sha256" | "version", Some(value)) => {
+                self.fields.insert(method.to_string(), value);
+            }
+            ("depends_on" | "uses_from_macos" | "revision" | "mirror", _) => {}
+            ("resource" | "patch", _) => {
+                self.todos
+                    .push(format!("{method} wasn't imported: `{statement}`"));
+            }
+            _ => self.todos.push(format!("ignored `{statement}`")),
+        }
+    }
+
+    /// Reads `license "MIT"`, or the `any_of` and `all_of` lists of licenses.
+    fn license(&mut self, arguments: &str) {
+        let licenses: Vec<String> = STRING_REGEX
+            .captures_iter(arguments)
+            .map(|caps| caps[1].to_string())
+            .collect();
+
+        let operator = match arguments {
+            a if a.starts_with('"') && licenses.len() == 1 => " AND ",
+            a if a.starts_with("any_of:") && !a.contains("with:") => " OR ",
+            a if a.starts_with("all_of:") && !a.contains("with:") => " AND ",
+            _ => {
+                self.todos
+                    .push(format!("license {arguments} wasn't imported"));
+                return;
+            }
+        };
+
+        self.license = Some(licenses.join(operator));
+    }
+}
+
+/// The block or method definition `line` opens, if any.
+fn opened_block(line: &str) -> Option<String> {
+    let first = line.split_whitespace().next().unwrap_or_default();
+
+    if first == "def" {
+        return line
+            .split_whitespace()
+            .nth(1)
+            .map(|name| name.trim_end_matches("()").to_string());
+    }
+    if matches!(first, "if" | "unless" | "case" | "while" | "begin") {
+        return Some(first.to_string());
+    }
+    if line.ends_with(" do") || (line.contains(" do |") && line.ends_with('|')) {
+        return Some(first.to_string());
+    }
+
+    None
+}
+
+/// `HelloWorld` as `hello-world`, as Homebrew names formulae after classes.
+fn kebab_case(class: &str) -> String {
+    let mut name = String::new();
+
+    for (i, c) in class.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            name.push('-');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+
+    name
+}
+
+/// Converts a Homebrew formula into a recipe, as far as its Ruby can be read
+/// without running it. What couldn't be converted is listed in
+/// [`Import::todos`].
+pub fn formula(s: &str) -> Result<Import> {
+    let mut formula = Formula::parse(s);
+    let mut todos = std::mem::take(&mut formula.todos);
+
+    if formula.name.is_empty() {
+        return Err(Error::Parse {
+            what: "the formula".to_string(),
+            message: "no class inherits from Formula".to_string(),
+        });
+    }
+    let name = formula.name.clone();
+
+    let url = formula.fields.get("url").cloned().unwrap_or_default();
+    let version = formula
+        .fields
+        .get("version")
+        .cloned()
+        .or_else(|| {
+            let file = url.rsplit('/').next().unwrap_or(&url);
+            VERSION_REGEX.find(file).map(|m| m.as_str().to_string())
+        })
+        .or_else(|| {
+            formula
+                .url_options
+                .get("tag")
+                .map(|tag| tag.trim_start_matches('v').to_string())
+        })
+        .unwrap_or_else(|| {
+            todos.push("the version couldn't be told, set it".to_string());
+            "0".to_string()
+        });
+    let with_version = |s: &str| s.replace(&version, "%{version}");
+
+    let description = formula.fields.get("desc").cloned().unwrap_or_default();
+    if description.is_empty() {
+        todos.push("desc isn't set, describe the package".to_string());
+    }
+
+    let license = license(
+        &formula.license.iter().cloned().collect::<Vec<_>>(),
+        &mut todos,
+    );
+
+    let mut sources = Vec::new();
+    let mut dir = format!("%{{srcdir}}/{name}-%{{version}}");
+
+    if url.ends_with(".git") || formula.url_options.contains_key("revision") {
+        let checkout = url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(&url)
+            .trim_end_matches(".git");
+        dir = format!("%{{srcdir}}/{checkout}");
+        sources.push(Source {
+            url: format!("git+{url}"),
+            checksum: String::new(),
+            rev: formula
+                .url_options
+                .get("revision")
+                .or(formula.url_options.get("tag"))
+                .cloned(),
+            filename: None,
+            signature: None,
+        });
+    } else if !url.is_empty() {
+        // Homebrew enters the single directory archives extract to, which
+        // is mostly named after them.
+        let file = url.rsplit('/').next().unwrap_or(&url);
+        let stem = [".tar.gz", ".tar.xz", ".tar.bz2", ".tgz", ".zip"]
+            .iter()
+            .find_map(|extension| file.strip_suffix(extension))
+            .unwrap_or(file);
+        if !stem.starts_with(&name) {
+            todos.push(format!(
+                "check {} is the directory {file} extracts to",
+                with_version(stem)
+            ));
+        }
+        dir = format!("%{{srcdir}}/{}", with_version(stem));
+
+        let checksum = match formula.fields.get("sha256") {
+            Some(sha256) => format!("sha256:{sha256}"),
+            None => {
+                todos.push(format!("{url} has no sha256 checksum, add one"));
+                String::new()
+            }
+        };
+        sources.push(Source {
+            url: with_version(&url),
+            checksum,
+            rev: None,
+            filename: None,
+            signature: None,
+        });
+    } else {
+        todos.push("url isn't set, add a source".to_string());
+    }
+
+    let mut steps = Vec::new();
+    if formula.install.is_empty() {
+        todos.push("the formula has no install method, add steps".to_string());
+    } else {
+        todos.push(
+            "install was converted to run with DESTDIR, check files end up in %{pkgdir}"
+                .to_string(),
+        );
+        let body = install(&formula.install, &name, &version, &dir, &mut todos);
+        steps.push(step("package", &dir, &body));
+    }
+
+    Ok(Import {
+        package: Package {
+            info: Info {
+                name,
+                version,
+                description: description.into(),
+                license,
+                provides: Vec::new(),
+                conflicts: Vec::new(),
+                replaces: Vec::new(),
+                keywords: Vec::new(),
+            },
+            dependencies: Some(Dependencies {
+                required: formula.required,
+                optional: formula.optional,
+                build: formula.build,
+                check: Vec::new(),
+            }),
+            sources,
+            steps,
+            directories: HashMap::new(),
+            backup: Vec::new(),
+            special_permissions: Vec::new(),
+            network: false,
+            pkgver: None,
+            upstream_keys: Vec::new(),
+            changelog: Changelog::default(),
+            extends: None,
+            path: None,
+        },
+        todos,
+    })
+}
+
+/// Converts the body of the install method into shell. Formulae install
+/// straight into their prefix, so the whole step runs with `DESTDIR` set,
+/// which make, cmake and meson install into.
+fn install(
+    lines: &[String],
+    name: &str,
+    version: &str,
+    dir: &str,
+    todos: &mut Vec<String>,
+) -> String {
+    let mut commands = vec!["export DESTDIR=\"%{pkgdir}\"".to_string()];
+    // Whether each open block is a `cd` into a directory.
+    let mut blocks = Vec::new();
+    let interpolate = |s: &str, todos: &mut Vec<String>| interpolate(s, name, version, dir, todos);
+
+    for line in lines {
+        let line = line.trim();
+        let (method, arguments) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(method, arguments)| (method, arguments.trim()));
+
+        if line == "end" {
+            if blocks.pop() == Some(true) {
+                commands.push(")".to_string());
+            }
+            continue;
+        }
+
+        if method == "system" {
+            let words: Vec<String> = split_arguments(arguments)
+                .iter()
+                .flat_map(|argument| shell_words(argument, &interpolate, todos))
+                .collect();
+            commands.push(words.join(" "));
+        } else if method == "cd" && line.ends_with(" do") {
+            let dir = arguments.trim_end_matches(" do").trim();
+            let words = shell_words(dir, &interpolate, todos);
+            commands.push(format!("(\ncd {}", words.join(" ")));
+            blocks.push(true);
+        } else if let Some(caps) = INSTALL_REGEX.captures(line) {
+            let target = match DIRS.iter().find(|(dir, _)| *dir == &caps[1]) {
+                Some((_, path)) => path.to_string(),
+                None if &caps[1] == "pkgshare" => format!("/usr/share/{name}"),
+                None if &caps[1] == "doc" => format!("/usr/share/doc/{name}"),
+                None => {
+                    todos.push(format!("`{line}` installs into an unknown directory"));
+                    commands.push(format!("# TODO: {line}"));
+                    continue;
+                }
+            };
+            let mode = match &caps[1] {
+                "bin" | "sbin" | "libexec" => "755",
+                _ => "644",
+            };
+            let files: Vec<String> = split_arguments(&caps[2])
+                .iter()
+                .flat_map(|argument| shell_words(argument, &interpolate, todos))
+                .collect();
+            commands.push(format!(
+                "install -Dm{mode} -t \"%{{pkgdir}}{target}\" {}",
+                files.join(" ")
+            ));
+        } else if let Some(caps) = ENV_REGEX.captures(line) {
+            commands.push(format!(
+                "export {}={}",
+                &caps[1],
+                quote(&interpolate(&caps[2], todos))
+            ));
+        } else {
+            if opened_block(line).is_some() {
+                blocks.push(false);
+            }
+            todos.push(format!("`{line}` in install wasn't converted"));
+            commands.push(format!("# TODO: {line}"));
+        }
+    }
+
+    commands.join("\n")
+}
+
+/// Splits Ruby arguments on the commas outside of strings and brackets.
+fn split_arguments(s: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut argument = String::new();
+    let (mut quoted, mut depth) = (false, 0i32);
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '[' if !quoted => depth += 1,
+            ')' | ']' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                arguments.push(argument.trim().to_string());
+                argument.clear();
+                continue;
+            }
+            _ => {}
+        }
+        argument.push(c);
+    }
+
+    if !argument.trim().is_empty() {
+        arguments.push(argument.trim().to_string());
+    }
+
+    arguments
+}
+
+/// A Ruby argument as shell words: strings, the standard arguments of build
+/// systems, and the directories of the formula.
+fn shell_words(
+    argument: &str,
+    interpolate: &dyn Fn(&str, &mut Vec<String>) -> String,
+    todos: &mut Vec<String>,
+) -> Vec<String> {
+    if let Some(string) = argument
+        .strip_prefix('"')
+        .and_then(|argument| argument.strip_suffix('"'))
+    {
+        return vec![quote(&interpolate(string, todos))];
+    }
+
+    let splat = argument.trim_start_matches('*');
+    if let Some((_, args)) = STD_ARGS.iter().find(|(name, _)| *name == splat) {
+        return vec![args.to_string()];
+    }
+    if let Some((_, path)) = DIRS.iter().find(|(name, _)| *name == argument) {
+        return vec![path.to_string()];
+    }
+
+    todos.push(format!("`{argument}` in install wasn't converted"));
+    vec![format!("\"TODO: {}\"", argument.replace('"', "'"))]
+}
+
+/// Replaces the `#{}` interpolations of a Ruby string, noting those that
+/// aren't known.
+fn interpolate(s: &str, name: &str, version: &str, dir: &str, todos: &mut Vec<String>) -> String {
+    INTERPOLATION_REGEX
+        .replace_all(s, |caps: &regex::Captures| match &caps[1] {
+            "version" => "%{version}".to_string(),
+            "buildpath" => dir.replace("%{version}", version),
+            "name" => name.to_string(),
+            "pkgshare" => format!("/usr/share/{name}"),
+            "doc" => format!("/usr/share/doc/{name}"),
+            expression => match DIRS.iter().find(|(dir, _)| *dir == expression) {
+                Some((_, path)) => path.to_string(),
+                None => {
+                    todos.push(format!("#{{{expression}}} wasn't converted"));
+                    caps[0].to_string()
+                }
+            },
+        })
+        .into_owned()
+}
+
+/// Quotes `s` for the shell, unless it's a word already.
+fn quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:+,@%{}".contains(c))
+    {
+        return s.to_string();
+    }
+
+    let escaped: String = s
+        .chars()
+        .flat_map(|c| match c {
+            '"' | '\\' | '`' | '$' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect();
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::StepVariant;
+
+    const FORMULA: &str = r##"class Hello < Formula
+  desc "Program providing model for GNU coding standards and practices"
+  homepage "https://www.gnu.org/software/hello/"
+  url "https://ftp.gnu.org/gnu/hello/hello-2.12.1.tar.gz"
+  mirror "https://ftpmirror.gnu.org/hello/hello-2.12.1.tar.gz"
+  sha256 "8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20"
+  license any_of: ["GPL-3.0-or-later", "MIT"]
+
+  livecheck do
+    url :stable
+  end
+
+  bottle do
+    sha256 arm64_sonoma: "abcd"
+  end
+
+  depends_on "pkgconf" => :build
+  depends_on "openssl@3"
+  uses_from_macos "zlib"
+
+  on_macos do
+    depends_on "gettext"
+  end
+
+  def install
+    ENV["LC_ALL"] = "C"
+    system "./configure", *std_configure_args, "--disable-silent-rules", "--docdir=#{doc}"
+    system "make", "install"
+    cd "contrib" do
+      system "make", "PREFIX=#{prefix}"
+    end
+    man1.install "doc/hello.1"
+  end
+
+  test do
+    system bin/"hello", "--greeting=brew"
+  end
+end
+"##;
+
+    #[test]
+    fn test_formula() {
+        let import = formula(FORMULA).unwrap();
+        let package = &import.package;
+
+        assert_eq!(package.info.name, "hello");
+        assert_eq!(package.info.version, "2.12.1");
+        assert_eq!(package.info.license.to_string(), "GPL-3.0-or-later OR MIT");
+
+        let dependencies = package.dependencies.as_ref().unwrap();
+        assert_eq!(dependencies.required, ["openssl", "zlib"]);
+        assert_eq!(dependencies.build, ["pkgconf"]);
+
+        assert_eq!(
+            package.sources[0].url,
+            "https://ftp.gnu.org/gnu/hello/hello-%{version}.tar.gz"
+        );
+        assert!(package.sources[0].checksum.starts_with("sha256:8d99"));
+
+        match &package.steps[0].variant {
+            StepVariant::Command { command, .. } => assert_eq!(
+                command,
+                "cd \"%{srcdir}/hello-%{version}\"\n\
+                 export DESTDIR=\"%{pkgdir}\"\n\
+                 export LC_ALL=C\n\
+                 ./configure --prefix=/usr --disable-dependency-tracking --disable-silent-rules \
+                 --docdir=/usr/share/doc/hello\n\
+                 make install\n\
+                 (\ncd contrib\nmake PREFIX=/usr\n)\n\
+                 install -Dm644 -t \"%{pkgdir}/usr/share/man/man1\" doc/hello.1"
+            ),
+            StepVariant::Move { .. } => unreachable!(),
+        }
+
+        assert_eq!(
+            import.todos,
+            [
+                "openssl@3 was imported as openssl, check version 3 fits",
+                "the test block wasn't imported",
+                "install was converted to run with DESTDIR, check files end up in %{pkgdir}",
+            ]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+mod apkbuild;
+mod formula;
+mod pkgbuild;
+mod shell;
+mod spec;
+
+use spdx::Expression;
+
+use crate::{
+    error::Result,
+    package::{Package, Step, StepVariant},
+    runner::StepRunner,
+};
+
+pub use apkbuild::apkbuild;
+pub use formula::formula;
+pub use pkgbuild::pkgbuild;
+pub use spec::spec;
+
+/// The functions both makepkg and abuild run, in the order they run them.
+const STEPS: [&str; 4] = ["prepare", "build", "check", "package"];
+
+/// Archive extensions builds can extract.
+const ARCHIVES: [&str; 4] = [".xz", ".gz", ".bz2", ".crate"];
+
+/// A recipe converted from the build script of another distribution, along
+/// with what the conversion couldn't do.
+#[derive(Debug)]
+pub struct Import {
+    pub package: Package,
+    /// What needs manual attention, written as `TODO` comments at the top of
+    /// the recipe.
+    pub todos: Vec<String>,
+}
+
+impl Import {
+    pub fn to_recipe(&self) -> Result<String> {
+        let mut recipe =
+            String::from("# Imported from a build script, check it before building.\n");
+
+        for todo in &self.todos {
+            recipe.push_str(&format!("# TODO: {todo}\n"));
+        }
+
+        recipe.push('\n');
+        recipe.push_str(&self.package.to_recipe()?);
+
+        Ok(recipe)
+    }
+}
+
+/// Whether builds can extract the source at `url`.
+fn is_archive(url: &str) -> bool {
+    ARCHIVES.iter().any(|extension| url.ends_with(extension))
+}
+
+/// A step running `body` in `dir`, as the build scripts run their functions
+/// somewhere else than where steps start.
+pub(crate) fn step(name: &str, dir: &str, body: &str) -> Step {
+    Step {
+        name: name.to_string(),
+        variant: StepVariant::Command {
+            runner: StepRunner::shell(),
+            command: format!("cd \"{dir}\"\n{body}"),
+        },
+    }
+}
+
+/// `licenses` as one SPDX expression, all of them applying. Names that aren't
+/// SPDX identifiers become `LicenseRef-` ones.
+pub(crate) fn license(licenses: &[String], todos: &mut Vec<String>) -> Expression {
+    let expression = match licenses {
+        [] => {
+            todos.push("license isn't set".to_string());
+            "LicenseRef-unknown".to_string()
+        }
+        licenses => licenses
+            .iter()
+            .map(|license| {
+                if Expression::parse(license).is_ok() {
+                    return license.clone();
+                }
+
+                match Expression::canonicalize(license) {
+                    Ok(Some(canonical)) if Expression::parse(&canonical).is_ok() => canonical,
+                    Ok(None) => license.clone(),
+                    _ => {
+                        let id: String = license
+                            .chars()
+                            .map(|c| match c.is_ascii_alphanumeric() || c == '.' {
+                                true => c,
+                                false => '-',
+                            })
+                            .collect();
+                        todos.push(format!(
+                            "license {license} isn't an SPDX expression, replace LicenseRef-{id}"
+                        ));
+                        format!("LicenseRef-{id}")
+                    }
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" AND "),
+    };
+
+    Expression::parse(&expression)
+        .unwrap_or_else(|_| Expression::parse("LicenseRef-unknown").expect("invalid license"))
+}
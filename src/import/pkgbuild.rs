@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use camino::Utf8PathBuf;
+
+use super::{
+    Import, STEPS, is_archive, license,
+    shell::{SPECIALS, Script},
+    step,
+};
+use crate::{
+    error::{Error, Result},
+    package::{Changelog, Dependencies, Info, Package, Source},
+};
+
+/// Converts a PKGBUILD into a recipe, as far as that can be done without
+/// running it. What couldn't be converted is listed in [`Import::todos`].
+pub fn pkgbuild(s: &str) -> Result<Import> {
+    let pkgbuild = Script::parse(s, &SPECIALS);
+    let mut todos = Vec::new();
+
+    let names = pkgbuild.array("pkgname");
+    let name = names.first().cloned().ok_or_else(|| Error::Parse {
+        what: "the PKGBUILD".to_string(),
+        message: "pkgname isn't set".to_string(),
+    })?;
+    if names.len() > 1 {
+        todos.push(format!(
+            "split packages aren't supported, only {name} was imported out of {}",
+            names.join(", ")
+        ));
+    }
+
+    let version = match pkgbuild.scalar("pkgver") {
+        Some(version) => version.to_string(),
+        None => {
+            todos.push("pkgver isn't set".to_string());
+            "0".to_string()
+        }
+    };
+    if let Some(epoch) = pkgbuild.scalar("epoch") {
+        todos.push(format!(
+            "epochs aren't supported, epoch {epoch} was dropped"
+        ));
+    }
+
+    let description = pkgbuild.scalar("pkgdesc").unwrap_or_default().to_string();
+    if description.is_empty() {
+        todos.push("pkgdesc isn't set, describe the package".to_string());
+    }
+
+    let license = license(pkgbuild.array("license"), &mut todos);
+
+    let dependencies = Dependencies {
+        required: pkgbuild.array("depends").to_vec(),
+        optional: pkgbuild.array("optdepends").to_vec(),
+        build: pkgbuild.array("makedepends").to_vec(),
+        check: pkgbuild.array("checkdepends").to_vec(),
+    };
+
+    let sources = sources(&pkgbuild, &mut todos);
+
+    let mut steps = Vec::new();
+
+    for function in pkgbuild.functions() {
+        if !STEPS.contains(&function) && function != "pkgver" {
+            todos.push(format!("{function}() wasn't imported"));
+        }
+        if function == "prepare" {
+            todos.push(
+                "steps run without network access, set network = true if prepare() downloads \
+                 anything"
+                    .to_string(),
+            );
+        }
+    }
+
+    for name in STEPS {
+        if let Some(body) = pkgbuild.function(name) {
+            // makepkg runs every function in $srcdir.
+            steps.push(step(name, "%{srcdir}", &pkgbuild.replace(body)));
+        }
+    }
+
+    // makepkg runs pkgver() in $srcdir too, once sources are fetched.
+    let pkgver = pkgbuild
+        .function("pkgver")
+        .map(|body| pkgbuild.replace(body).trim().to_string());
+
+    for key in pkgbuild.array("validpgpkeys") {
+        todos.push(format!(
+            "export the public key {key} and add it to upstream_keys"
+        ));
+    }
+
+    for variable in pkgbuild.variables() {
+        if variable.starts_with("source_") || variable.starts_with("depends_") {
+            todos.push(format!("architecture specific {variable} wasn't imported"));
+        }
+    }
+
+    todos.extend(pkgbuild.todos.iter().cloned());
+
+    Ok(Import {
+        package: Package {
+            info: Info {
+                name,
+                version,
+                description: description.into(),
+                license,
+                provides: pkgbuild.array("provides").to_vec(),
+                conflicts: pkgbuild.array("conflicts").to_vec(),
+                replaces: pkgbuild.array("replaces").to_vec(),
+                keywords: Vec::new(),
+            },
+            dependencies: Some(dependencies),
+            sources,
+            steps,
+            directories: HashMap::new(),
+            backup: pkgbuild
+                .array("backup")
+                .iter()
+                .map(Utf8PathBuf::from)
+                .collect(),
+            special_permissions: Vec::new(),
+            network: false,
+            pkgver,
+            upstream_keys: Vec::new(),
+            changelog: Changelog::default(),
+            extends: None,
+            path: None,
+        },
+        todos,
+    })
+}
+
+/// The sources of a PKGBUILD along with their sha256 checksums. Local files
+/// aren't sources of recipes, they have to be copied next to it instead.
+fn sources(pkgbuild: &Script, todos: &mut Vec<String>) -> Vec<Source> {
+    let checksums = pkgbuild.array("sha256sums");
+    let mut sources = Vec::new();
+
+    for (i, source) in pkgbuild.array("source").iter().enumerate() {
+        // `name::url` renames what is downloaded.
+        let (filename, url) = match source.split_once("::") {
+            Some((filename, url)) => (Some(filename.to_string()), url),
+            None => (None, source.as_str()),
+        };
+
+        if !url.contains("://") {
+            todos.push(format!(
+                "copy {url} next to the recipe and refer to it as %{{recipedir}}/{url}"
+            ));
+            continue;
+        }
+
+        if url.starts_with("git+") {
+            let (url, fragment) = url.split_once('#').unwrap_or((url, ""));
+            let rev = fragment
+                .split_once('=')
+                .map(|(_, rev)| rev.to_string())
+                .filter(|rev| !rev.is_empty());
+
+            sources.push(Source {
+                url: url.to_string(),
+                checksum: String::new(),
+                rev,
+                filename,
+                signature: None,
+            });
+            continue;
+        }
+
+        // Signatures follow what they sign, as `url.sig` or `url.asc`.
+        if url.ends_with(".sig") || url.ends_with(".asc") {
+            match sources
+                .last_mut()
+                .filter(|source: &&mut Source| url.starts_with(&source.url))
+            {
+                Some(source) => source.signature = Some(url.to_string()),
+                None => todos.push(format!("signature {url} wasn't imported")),
+            }
+            continue;
+        }
+
+        if !is_archive(filename.as_deref().unwrap_or(url)) {
+            todos.push(format!("{url} isn't an archive blossom can extract"));
+            continue;
+        }
+
+        let checksum = match checksums.get(i).map(String::as_str) {
+            Some("SKIP") | None => {
+                todos.push(format!("{url} has no sha256 checksum, add one"));
+                String::new()
+            }
+            Some(checksum) => format!("sha256:{checksum}"),
+        };
+
+        sources.push(Source {
+            url: url.to_string(),
+            checksum,
+            rev: None,
+            filename,
+            signature: None,
+        });
+    }
+
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::StepVariant;
+
+    const PKGBUILD: &str = r#"# Maintainer: Someone <someone@example.com>
+pkgname=hello
+pkgver=2.12.1
+pkgrel=1
+pkgdesc="Prints a friendly greeting"
+arch=('x86_64')
+url="https://www.gnu.org/software/hello/"
+license=('GPL-3.0-or-later')
+depends=('glibc')
+makedepends=(gettext
+             'texinfo')  # for the manual
+checkdepends=('dejagnu')
+optdepends=('bash: for the completions')
+backup=('etc/hello.conf')
+source=("https://ftp.gnu.org/gnu/$pkgname/$pkgname-$pkgver.tar.gz"
+        "git+https://example.com/extras.git#tag=v1"
+        hello.conf)
+sha256sums=('8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20'
+            'SKIP'
+            'SKIP')
+
+build() {
+  cd "$pkgname-$pkgver"
+  ./configure --prefix=/usr
+  make
+}
+
+package() {
+  cd "${pkgname}-${pkgver}"
+  make DESTDIR="$pkgdir" install
+  install -Dm644 "$srcdir/hello.conf" "$pkgdir/etc/hello.conf"
+}
+"#;
+
+    #[test]
+    fn test_pkgbuild() {
+        let import = pkgbuild(PKGBUILD).unwrap();
+        let package = &import.package;
+
+        assert_eq!(package.info.name, "hello");
+        assert_eq!(package.info.version, "2.12.1");
+        assert_eq!(package.info.license.to_string(), "GPL-3.0-or-later");
+
+        let dependencies = package.dependencies.as_ref().unwrap();
+        assert_eq!(dependencies.required, ["glibc"]);
+        assert_eq!(dependencies.build, ["gettext", "texinfo"]);
+        assert_eq!(dependencies.check, ["dejagnu"]);
+        assert_eq!(dependencies.optional, ["bash: for the completions"]);
+
+        assert_eq!(package.sources.len(), 2);
+        assert_eq!(
+            package.sources[0].url,
+            "https://ftp.gnu.org/gnu/hello/hello-%{version}.tar.gz"
+        );
+        assert_eq!(
+            package.sources[0].checksum,
+            "sha256:8d99142afd92576f30b0cd7cb42a8dc6809998bc5d607d88761f512e26c7db20"
+        );
+        assert_eq!(package.sources[1].url, "git+https://example.com/extras.git");
+        assert_eq!(package.sources[1].rev.as_deref(), Some("v1"));
+
+        assert_eq!(package.steps.len(), 2);
+        assert!(matches!(
+            &package.steps[1].variant,
+            StepVariant::Command { command, .. } if command == "cd \"%{srcdir}\"\n\
+                cd \"hello-%{version}\"\n\
+                make DESTDIR=\"%{pkgdir}\" install\n\
+                install -Dm644 \"%{srcdir}/hello.conf\" \"%{pkgdir}/etc/hello.conf\""
+        ));
+
+        assert_eq!(
+            import.todos,
+            ["copy hello.conf next to the recipe and refer to it as %{recipedir}/hello.conf"]
+        );
+
+        let recipe = import.to_recipe().unwrap();
+        assert!(recipe.starts_with("# Imported from a build script"));
+        assert!(Package::parse(&recipe).is_ok());
+    }
+
+    #[test]
+    fn test_pkgbuild_todos() {
+        let import = pkgbuild(
+            "pkgname=(foo foo-docs)\npkgver=1\nlicense=(custom)\n\
+             source=(https://example.com/foo-$_commit.zip)\npkgver() { git describe; }\n",
+        )
+        .unwrap();
+
+        assert_eq!(import.package.info.name, "foo");
+        assert_eq!(import.package.info.license.to_string(), "LicenseRef-custom");
+        assert!(import.package.sources.is_empty());
+        assert_eq!(import.package.pkgver.as_deref(), Some("git describe;"));
+        assert_eq!(
+            import.todos,
+            [
+                "split packages aren't supported, only foo was imported out of foo, foo-docs",
+                "pkgdesc isn't set, describe the package",
+                "license custom isn't an SPDX expression, replace LicenseRef-custom",
+                "https://example.com/foo-$_commit.zip isn't an archive blossom can extract",
+                "`$_commit` isn't known, replace it",
+            ]
+        );
+
+        assert!(pkgbuild("pkgver=1\n").is_err());
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::LazyLock,
+};
+
+use regex::{Captures, Regex};
+
+/// Variables of both makepkg and abuild, and what they stand for in recipes.
+pub const SPECIALS: [(&str, &str); 5] = [
+    ("pkgver", "%{version}"),
+    ("srcdir", "%{srcdir}"),
+    ("pkgdir", "%{pkgdir}"),
+    ("startdir", "%{recipedir}"),
+    ("CARCH", "%{target}"),
+];
+
+static ASSIGNMENT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)^([A-Za-z_][A-Za-z0-9_]*)(\+?)=(.*)$").expect("invalid regex")
+});
+static FUNCTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)^(?:function\s+)?([A-Za-z_][A-Za-z0-9_-]*)\s*(?:\(\s*\))?\s*\{(.*)\}$")
+        .expect("invalid regex")
+});
+static VARIABLE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\$(?:\{([A-Za-z_][A-Za-z0-9_]*)\}|([A-Za-z_][A-Za-z0-9_]*))")
+        .expect("invalid regex")
+});
+
+/// The variables and functions of a build script, as far as they can be read
+/// without running it.
+#[derive(Debug, Default)]
+pub struct Script {
+    variables: HashMap<String, Vec<String>>,
+    functions: Vec<(String, String)>,
+    specials: HashMap<&'static str, &'static str>,
+    /// What couldn't be read.
+    pub todos: BTreeSet<String>,
+}
+
+impl Script {
+    /// Reads `s`, expanding `specials` to the recipe variables they stand
+    /// for.
+    pub fn parse(s: &str, specials: &[(&'static str, &'static str)]) -> Self {
+        let mut script = Self {
+            specials: specials.iter().copied().collect(),
+            ..Default::default()
+        };
+        let mut statement = String::new();
+
+        for line in s.lines() {
+            if statement.is_empty() && line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            statement.push_str(line);
+            statement.push('\n');
+
+            if is_complete(&statement) {
+                script.statement(statement.trim());
+                statement.clear();
+            }
+        }
+
+        if !statement.trim().is_empty() {
+            script.statement(statement.trim());
+        }
+
+        script
+    }
+
+    fn statement(&mut self, statement: &str) {
+        if statement.is_empty() {
+            return;
+        }
+
+        if let Some(caps) = ASSIGNMENT_REGEX.captures(statement) {
+            let value = caps[3].trim();
+            let mut values = match value.strip_prefix('(').zip(array_end(value)) {
+                Some((array, end)) => self.words(&array[..end - 1]),
+                None => self.words(value).into_iter().take(1).collect(),
+            };
+
+            let name = caps[1].to_string();
+            if &caps[2] == "+" {
+                let mut previous = self.variables.remove(&name).unwrap_or_default();
+                previous.append(&mut values);
+                values = previous;
+            }
+
+            self.variables.insert(name, values);
+        } else if let Some(caps) = FUNCTION_REGEX.captures(statement) {
+            self.functions
+                .push((caps[1].to_string(), dedent(caps[2].trim_matches('\n'))));
+        } else {
+            let line = statement.lines().next().unwrap_or(statement);
+            self.todos.insert(format!("ignored `{line}`"));
+        }
+    }
+
+    pub fn scalar(&self, name: &str) -> Option<&str> {
+        self.variables
+            .get(name)
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+
+    pub fn array(&self, name: &str) -> &[String] {
+        self.variables.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// The words of a variable holding a whitespace separated list.
+    pub fn list(&self, name: &str) -> Vec<String> {
+        self.array(name)
+            .iter()
+            .flat_map(|value| value.split_whitespace())
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn set(&mut self, name: &str, value: String) {
+        self.variables.insert(name.to_string(), vec![value]);
+    }
+
+    /// The names of the variables set, sorted.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables: Vec<&str> = self.variables.keys().map(String::as_str).collect();
+        variables.sort();
+        variables
+    }
+
+    /// The names of the functions, in the order they are defined in.
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.functions.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// The body of the function `name`, dedented.
+    pub fn function(&self, name: &str) -> Option<&str> {
+        self.functions
+            .iter()
+            .find(|(function, _)| function == name)
+            .map(|(_, body)| body.as_str())
+    }
+
+    /// What `$name` stands for in a recipe, `None` if it isn't known.
+    fn expand(&self, name: &str) -> Option<String> {
+        match self.specials.get(name) {
+            Some(special) => Some(special.to_string()),
+            None => self.scalar(name).map(str::to_string),
+        }
+    }
+
+    /// Splits `s` into words like the shell, expanding the variables known
+    /// so far.
+    fn words(&mut self, s: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '#' if word.is_none() => {
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                c if c.is_whitespace() => {
+                    if let Some(word) = word.take() {
+                        words.push(word);
+                    }
+                }
+                '\'' => {
+                    let word = word.get_or_insert_default();
+                    for c in chars.by_ref() {
+                        if c == '\'' {
+                            break;
+                        }
+                        word.push(c);
+                    }
+                }
+                '"' => {
+                    let mut quoted = String::new();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '"' => break,
+                            '\\' if matches!(chars.peek(), Some('"' | '\\' | '$' | '`')) => {
+                                quoted.push(chars.next().unwrap_or('\\'));
+                            }
+                            c => quoted.push(c),
+                        }
+                    }
+                    let expanded = self.substitute(&quoted);
+                    word.get_or_insert_default().push_str(&expanded);
+                }
+                '\\' => match chars.next() {
+                    Some('\n') | None => {}
+                    Some(c) => word.get_or_insert_default().push(c),
+                },
+                c => {
+                    let mut unquoted = String::from(c);
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || matches!(c, '\'' | '"' | '\\') {
+                            break;
+                        }
+                        unquoted.push(c);
+                        chars.next();
+                    }
+                    let expanded = self.substitute(&unquoted);
+                    word.get_or_insert_default().push_str(&expanded);
+                }
+            }
+        }
+
+        words.extend(word);
+        words
+    }
+
+    /// Replaces the variables in `s` that stand for something in a recipe.
+    /// Others are left to the shell, functions have variables of their own.
+    pub fn replace(&self, s: &str) -> String {
+        VARIABLE_REGEX
+            .replace_all(s, |caps: &Captures| {
+                let name = caps.get(1).or(caps.get(2)).map_or("", |m| m.as_str());
+
+                self.expand(name).unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+
+    /// Replaces the variables in a value like [`Script::replace`], noting
+    /// those that are left, as nothing expands them in a recipe.
+    fn substitute(&mut self, s: &str) -> String {
+        let substituted = self.replace(s);
+
+        for caps in VARIABLE_REGEX.captures_iter(&substituted) {
+            self.todos
+                .insert(format!("`{}` isn't known, replace it", &caps[0]));
+        }
+        if substituted.contains("${") {
+            self.todos.insert(format!(
+                "`{substituted}` uses a parameter expansion, replace it"
+            ));
+        }
+
+        substituted
+    }
+}
+
+/// Whether `s` holds whole statements, without open quotes, arrays or
+/// function bodies.
+fn is_complete(s: &str) -> bool {
+    let (mut single, mut double, mut escaped, mut comment) = (false, false, false, false);
+    let (mut parens, mut braces) = (0i32, 0i32);
+    let mut previous = '\n';
+
+    for c in s.chars() {
+        if comment {
+            comment = c != '\n';
+        } else if escaped {
+            escaped = false;
+        } else if single {
+            single = c != '\'';
+        } else if double {
+            match c {
+                '\\' => escaped = true,
+                '"' => double = false,
+                _ => {}
+            }
+        } else {
+            match c {
+                '\\' => escaped = true,
+                '\'' => single = true,
+                '"' => double = true,
+                '#' if previous.is_whitespace() => comment = true,
+                '(' => parens += 1,
+                ')' => parens -= 1,
+                '{' => braces += 1,
+                '}' => braces -= 1,
+                _ => {}
+            }
+        }
+
+        previous = c;
+    }
+
+    !single && !double && !escaped && parens <= 0 && braces <= 0
+}
+
+/// The index of the parenthesis closing the array `s` starts with.
+fn array_end(s: &str) -> Option<usize> {
+    let (mut single, mut double, mut escaped) = (false, false, false);
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if single {
+            single = c != '\'';
+        } else {
+            match c {
+                '\\' => escaped = true,
+                '\'' if !double => single = true,
+                '"' => double = !double,
+                ')' if !double => return Some(i),
+                _ => {}
+            }
+        }
+    }
+
+    None
+}
+
+/// Removes the indentation common to the lines of `s`.
+fn dedent(s: &str) -> String {
+    let indent = s
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    s.lines()
+        .map(|line| line.get(indent..).unwrap_or(line.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
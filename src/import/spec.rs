@@ -0,0 +1,664 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::LazyLock,
+};
+
+use regex::{Captures, Regex};
+
+use super::{Import, is_archive, license, step};
+use crate::{
+    error::{Error, Result},
+    package::{Changelog, Dependencies, Info, Package, Source},
+};
+
+/// Recipe variables, which macros of the same name stand for.
+const RECIPE_VARIABLES: [&str; 6] = ["version", "jobs", "pkgdir", "srcdir", "recipedir", "target"];
+
+/// The macros of rpm most specs use, for a system without multilib.
+const BUILTINS: [(&str, &str); 41] = [
+    ("_prefix", "/usr"),
+    ("_exec_prefix", "%{_prefix}"),
+    ("_bindir", "%{_prefix}/bin"),
+    ("_sbindir", "%{_prefix}/sbin"),
+    ("_libdir", "%{_prefix}/lib"),
+    ("_libexecdir", "%{_prefix}/libexec"),
+    ("_datadir", "%{_prefix}/share"),
+    ("_mandir", "%{_datadir}/man"),
+    ("_infodir", "%{_datadir}/info"),
+    ("_docdir", "%{_datadir}/doc"),
+    ("_includedir", "%{_prefix}/include"),
+    ("_sysconfdir", "/etc"),
+    ("_localstatedir", "/var"),
+    ("_sharedstatedir", "/var/lib"),
+    ("_rundir", "/run"),
+    ("_unitdir", "%{_prefix}/lib/systemd/system"),
+    ("buildroot", "%{pkgdir}"),
+    ("_builddir", "%{srcdir}"),
+    ("_sourcedir", "%{recipedir}"),
+    ("_smp_mflags", "-j%{jobs}"),
+    ("_arch", "%{target}"),
+    ("_target_cpu", "%{target}"),
+    ("optflags", "-O2 -g"),
+    ("dist", ""),
+    ("nil", ""),
+    ("_vpath_builddir", "build"),
+    ("__make", "make"),
+    ("__cmake", "cmake"),
+    ("__meson", "meson"),
+    ("__python3", "python3"),
+    ("python3", "python3"),
+    ("__install", "install"),
+    (
+        "configure",
+        "./configure --prefix=%{_prefix} --exec-prefix=%{_exec_prefix} --bindir=%{_bindir} \
+         --sbindir=%{_sbindir} --sysconfdir=%{_sysconfdir} --datadir=%{_datadir} \
+         --includedir=%{_includedir} --libdir=%{_libdir} --libexecdir=%{_libexecdir} \
+         --localstatedir=%{_localstatedir} --mandir=%{_mandir} --infodir=%{_infodir}",
+    ),
+    ("make_build", "%{__make} %{?_smp_mflags}"),
+    ("make_install", "%{__make} install DESTDIR=%{buildroot}"),
+    (
+        "cmake",
+        "%{__cmake} -S . -B %{_vpath_builddir} -DCMAKE_INSTALL_PREFIX:PATH=%{_prefix} \
+         -DCMAKE_BUILD_TYPE=Release",
+    ),
+    (
+        "cmake_build",
+        "%{__cmake} --build %{_vpath_builddir} %{?_smp_mflags}",
+    ),
+    (
+        "cmake_install",
+        "DESTDIR=%{buildroot} %{__cmake} --install %{_vpath_builddir}",
+    ),
+    (
+        "meson",
+        "%{__meson} setup --prefix=%{_prefix} --libdir=%{_libdir} %{_vpath_builddir}",
+    ),
+    (
+        "meson_build",
+        "%{__meson} compile -C %{_vpath_builddir} %{?_smp_mflags}",
+    ),
+    (
+        "meson_install",
+        "DESTDIR=%{buildroot} %{__meson} install -C %{_vpath_builddir} --no-rebuild",
+    ),
+];
+
+/// Sections whose content becomes a step, and the steps they become.
+const STEPS: [(&str, &str); 4] = [
+    ("prep", "prepare"),
+    ("build", "build"),
+    ("check", "check"),
+    ("install", "package"),
+];
+
+/// Sections that don't become steps and don't need to.
+const IGNORED: [&str; 4] = ["description", "files", "changelog", "clean"];
+
+static SECTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^%(description|package|prep|build|install|check|files|changelog|pre|post|preun|postun|pretrans|posttrans|triggerin|triggerun|triggerpostun|clean|generate_buildrequires|conf|verifyscript)\b\s*(.*)$",
+    )
+    .expect("invalid regex")
+});
+static TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^([A-Za-z][A-Za-z0-9]*)(\([^)]*\))?:\s*(.*)$").expect("invalid regex")
+});
+static MACRO_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"%(?:\{(!?\??)([A-Za-z_][A-Za-z0-9_]*)(?::([^{}]*))?\}|(%)|([A-Za-z_][A-Za-z0-9_]*))",
+    )
+    .expect("invalid regex")
+});
+static PATCH_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^%patch\s*(?:-P\s*)?(\d*)(.*)$").expect("invalid regex"));
+
+/// A spec file, read without rpm.
+#[derive(Debug, Default)]
+struct Spec {
+    macros: HashMap<String, String>,
+    /// The tags of the main package, in order, with their qualifiers such as
+    /// `(post)` in `Requires(post)`.
+    tags: Vec<(String, Option<String>, String)>,
+    /// The sections, with their arguments and lines.
+    sections: Vec<(String, String, Vec<String>)>,
+    todos: BTreeSet<String>,
+}
+
+impl Spec {
+    fn parse(s: &str) -> Self {
+        let mut spec = Self::default();
+        // Whether the lines are kept, for each open conditional.
+        let mut conditionals: Vec<bool> = Vec::new();
+        let mut lines = s.lines();
+
+        while let Some(line) = lines.next() {
+            let mut line = line.to_string();
+            while line.ends_with('\\') && line.trim_start().starts_with("%global") {
+                line.pop();
+                line.push_str(lines.next().unwrap_or_default());
+            }
+
+            let trimmed = line.trim();
+            let directive = trimmed.split_whitespace().next().unwrap_or_default();
+
+            match directive {
+                "%if" | "%ifarch" | "%ifnarch" | "%ifos" | "%ifnos" => {
+                    spec.todos.insert(
+                        "conditionals were read as if they held, check the %if blocks".to_string(),
+                    );
+                    conditionals.push(true);
+                    continue;
+                }
+                "%else" => {
+                    if let Some(kept) = conditionals.last_mut() {
+                        *kept = false;
+                    }
+                    continue;
+                }
+                "%endif" => {
+                    conditionals.pop();
+                    continue;
+                }
+                _ if conditionals.contains(&false) => continue,
+                "%global" | "%define" => {
+                    let mut parts = trimmed.splitn(3, char::is_whitespace).skip(1);
+                    if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                        spec.macros
+                            .insert(name.to_string(), value.trim().to_string());
+                    }
+                    continue;
+                }
+                "%undefine" => {
+                    if let Some(name) = trimmed.split_whitespace().nth(1) {
+                        spec.macros.remove(name);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            if let Some(caps) = SECTION_REGEX.captures(trimmed) {
+                spec.sections
+                    .push((caps[1].to_string(), caps[2].to_string(), Vec::new()));
+            } else if let Some((_, _, lines)) = spec.sections.last_mut() {
+                lines.push(line);
+            } else if let Some(caps) = TAG_REGEX.captures(trimmed) {
+                spec.tags.push((
+                    caps[1].to_lowercase(),
+                    caps.get(2).map(|m| m.as_str().to_string()),
+                    caps[3].trim().to_string(),
+                ));
+            } else if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                spec.todos.insert(format!("ignored `{trimmed}`"));
+            }
+        }
+
+        spec
+    }
+
+    /// The value of the first `tag`, with its macros expanded.
+    fn tag(&mut self, tag: &str) -> Option<String> {
+        let value = self
+            .tags
+            .iter()
+            .find(|(name, qualifier, _)| name == tag && qualifier.is_none())
+            .map(|(_, _, value)| value.clone())?;
+
+        Some(self.expand(&value))
+    }
+
+    /// The values of every `tag` such as `Source0` and `Source1`, numbered
+    /// or not, in order.
+    fn numbered(&mut self, tag: &str) -> Vec<(String, String)> {
+        let values: Vec<(String, String)> = self
+            .tags
+            .iter()
+            .filter(|(name, qualifier, _)| {
+                qualifier.is_none()
+                    && name
+                        .strip_prefix(tag)
+                        .is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+            })
+            .map(|(name, _, value)| (name[tag.len()..].to_string(), value.clone()))
+            .collect();
+
+        values
+            .into_iter()
+            .map(|(n, value)| (n, self.expand(&value)))
+            .collect()
+    }
+
+    /// The dependencies of every `tag`, comma or space separated with
+    /// spaced out operators (`foo >= 1.0, bar`), in blossom's syntax.
+    fn dependencies(&mut self, tag: &str) -> Vec<String> {
+        let values: Vec<String> = self
+            .tags
+            .iter()
+            .filter(|(name, qualifier, _)| name == tag && qualifier.is_none())
+            .map(|(_, _, value)| value.clone())
+            .collect();
+
+        let mut dependencies = Vec::new();
+
+        for value in values {
+            let value = self.expand(&value);
+
+            if value.trim_start().starts_with('(') {
+                self.todos
+                    .insert(format!("rich dependency {value} wasn't imported"));
+                continue;
+            }
+
+            let mut tokens = value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|token| !token.is_empty())
+                .peekable();
+
+            while let Some(name) = tokens.next() {
+                let mut dependency = name.to_string();
+
+                if let Some(&operator) = tokens
+                    .peek()
+                    .filter(|operator| matches!(**operator, "<" | "<=" | "=" | "==" | ">=" | ">"))
+                {
+                    tokens.next();
+                    dependency.push_str(operator);
+                    dependency.push_str(tokens.next().unwrap_or_default());
+                }
+
+                if name.contains('(') || name.starts_with('/') {
+                    self.todos
+                        .insert(format!("replace {dependency} by the package providing it"));
+                } else {
+                    dependencies.push(dependency);
+                }
+            }
+        }
+
+        dependencies
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.definition(name).is_some() || RECIPE_VARIABLES.contains(&name)
+    }
+
+    fn definition(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.macros.get(name) {
+            return Some(value.clone());
+        }
+
+        let tag = match name {
+            "name" | "release" | "summary" | "url" | "license" => Some(name),
+            _ => None,
+        };
+        let tagged = tag.and_then(|tag| {
+            self.tags
+                .iter()
+                .find(|(name, qualifier, _)| name == tag && qualifier.is_none())
+        });
+        if let Some((_, _, value)) = tagged {
+            return Some(value.clone());
+        }
+
+        BUILTINS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, value)| value.to_string())
+    }
+
+    /// Expands the macros of `s`, noting those that aren't known. Recipe
+    /// variables are kept as they are.
+    fn expand(&mut self, s: &str) -> String {
+        let mut expanded = s.to_string();
+        let mut unknown = BTreeSet::new();
+
+        for _ in 0..16 {
+            let next = MACRO_REGEX
+                .replace_all(&expanded, |caps: &Captures| {
+                    if caps.get(4).is_some() {
+                        // `%%` is a literal `%`, kept out of further expansion.
+                        return "\u{0}".to_string();
+                    }
+
+                    if let Some(name) = caps.get(5) {
+                        let name = name.as_str();
+                        return match self.definition(name) {
+                            Some(value) => value,
+                            None if RECIPE_VARIABLES.contains(&name) => format!("%{{{name}}}"),
+                            // Likely not a macro, such as a format in printf.
+                            None => caps[0].to_string(),
+                        };
+                    }
+
+                    let name = &caps[2];
+                    let value = caps.get(3).map(|m| m.as_str());
+
+                    match (&caps[1], self.is_defined(name)) {
+                        ("?", true) => value
+                            .map(str::to_string)
+                            .or_else(|| self.definition(name))
+                            .unwrap_or_else(|| caps[0].to_string()),
+                        ("!?", false) => value.unwrap_or_default().to_string(),
+                        ("?" | "!?", _) => String::new(),
+                        (_, true) => self
+                            .definition(name)
+                            .unwrap_or_else(|| format!("%{{{name}}}")),
+                        (_, false) => {
+                            unknown.insert(caps[0].to_string());
+                            caps[0].to_string()
+                        }
+                    }
+                })
+                .into_owned();
+
+            if next == expanded {
+                break;
+            }
+            expanded = next;
+        }
+
+        for name in unknown {
+            self.todos
+                .insert(format!("macro {name} isn't known, replace it"));
+        }
+
+        expanded
+            .replace("\u{0}", "%")
+            .replace("${RPM_BUILD_ROOT}", "%{pkgdir}")
+            .replace("$RPM_BUILD_ROOT", "%{pkgdir}")
+    }
+}
+
+/// Converts an RPM spec into a recipe, expanding the macros most specs use.
+/// What couldn't be converted is listed in [`Import::todos`].
+pub fn spec(s: &str) -> Result<Import> {
+    let mut spec = Spec::parse(s);
+    let mut todos = Vec::new();
+
+    let name = spec.tag("name").ok_or_else(|| Error::Parse {
+        what: "the spec".to_string(),
+        message: "Name isn't set".to_string(),
+    })?;
+
+    let version = match spec.tag("version") {
+        Some(version) => version,
+        None => {
+            todos.push("Version isn't set".to_string());
+            "0".to_string()
+        }
+    };
+    if let Some(epoch) = spec.tag("epoch") {
+        todos.push(format!(
+            "epochs aren't supported, epoch {epoch} was dropped"
+        ));
+    }
+
+    let description = spec.tag("summary").unwrap_or_default();
+    if description.is_empty() {
+        todos.push("Summary isn't set, describe the package".to_string());
+    }
+
+    let license = license(
+        &spec.tag("license").into_iter().collect::<Vec<_>>(),
+        &mut todos,
+    );
+
+    let dependencies = Dependencies {
+        required: spec.dependencies("requires"),
+        optional: [
+            spec.dependencies("recommends"),
+            spec.dependencies("suggests"),
+        ]
+        .concat(),
+        build: spec.dependencies("buildrequires"),
+        check: Vec::new(),
+    };
+
+    let mut sources = Vec::new();
+
+    for (_, url) in spec.numbered("source") {
+        if !url.contains("://") {
+            todos.push(format!(
+                "copy {url} next to the recipe and refer to it as %{{recipedir}}/{url}"
+            ));
+        } else if !is_archive(&url) {
+            todos.push(format!("{url} isn't an archive blossom can extract"));
+        } else {
+            todos.push(format!("{url} has no sha256 checksum, add one"));
+            sources.push(Source {
+                url,
+                checksum: String::new(),
+                rev: None,
+                filename: None,
+                signature: None,
+            });
+        }
+    }
+
+    let patches: HashMap<String, String> = spec
+        .numbered("patch")
+        .into_iter()
+        .map(|(n, patch)| {
+            let file = patch.rsplit('/').next().unwrap_or(&patch).to_string();
+            todos.push(format!(
+                "copy {file} next to the recipe and refer to it as %{{recipedir}}/{file}"
+            ));
+            (n, file)
+        })
+        .collect();
+
+    // rpm runs the sections in the directory %setup extracted, which is
+    // named after the package by default.
+    let mut builddir = format!("%{{srcdir}}/{name}-%{{version}}");
+    let mut bodies = Vec::new();
+    let sections = std::mem::take(&mut spec.sections);
+
+    for (section, arguments, lines) in &sections {
+        if let Some((_, step_name)) = STEPS.iter().find(|(name, _)| name == section) {
+            let mut body = Vec::new();
+
+            for line in lines {
+                let trimmed = line.trim();
+
+                if trimmed.starts_with("%setup") || trimmed.starts_with("%autosetup") {
+                    let words: Vec<&str> = trimmed.split_whitespace().collect();
+
+                    if let Some(i) = words.iter().position(|word| *word == "-n") {
+                        let dir = spec.expand(words.get(i + 1).unwrap_or(&""));
+                        builddir = format!("%{{srcdir}}/{dir}");
+                    }
+
+                    if trimmed.starts_with("%autosetup") {
+                        let strip = words
+                            .iter()
+                            .find_map(|word| word.strip_prefix("-p"))
+                            .unwrap_or("1");
+
+                        let mut numbers: Vec<&String> = patches.keys().collect();
+                        numbers.sort_by_key(|n| n.parse::<u32>().unwrap_or(0));
+
+                        for n in numbers {
+                            body.push(format!(
+                                "patch -p{strip} -i \"%{{recipedir}}/{}\"",
+                                patches[n]
+                            ));
+                        }
+                    }
+                } else if let Some(caps) = PATCH_REGEX.captures(trimmed) {
+                    let n = if caps[1].is_empty() { "0" } else { &caps[1] };
+                    let options = spec.expand(caps[2].trim());
+
+                    match patches.get(n).or_else(|| patches.get("")) {
+                        Some(file) => body.push(
+                            format!("patch {options} -i \"%{{recipedir}}/{file}\"")
+                                .replace("  ", " "),
+                        ),
+                        None => todos.push(format!("{trimmed} refers to no patch")),
+                    }
+                } else {
+                    body.push(spec.expand(line));
+                }
+            }
+
+            let body = body.join("\n").trim_matches('\n').to_string();
+            if !body.trim().is_empty() {
+                bodies.push((*step_name, body));
+            }
+        } else if section == "package" {
+            todos.push(format!(
+                "subpackages aren't supported, {} wasn't imported",
+                spec.expand(arguments.trim_start_matches("-n ").trim())
+            ));
+        } else if !IGNORED.contains(&section.as_str()) {
+            todos.push(format!("%{section} wasn't imported"));
+        }
+    }
+
+    // Sections can come in any order, %check often comes after %install.
+    bodies.sort_by_key(|(name, _)| STEPS.iter().position(|(_, step)| step == name));
+
+    // %setup is only known once %prep is read, but sets the directory of
+    // every section.
+    let steps = bodies
+        .iter()
+        .map(|(name, body)| step(name, &builddir, body))
+        .collect();
+
+    let provides = spec.dependencies("provides");
+    let conflicts = spec.dependencies("conflicts");
+    let replaces = spec.dependencies("obsoletes");
+
+    todos.extend(spec.todos.iter().cloned());
+
+    Ok(Import {
+        package: Package {
+            info: Info {
+                name,
+                version,
+                description: description.into(),
+                license,
+                provides,
+                conflicts,
+                replaces,
+                keywords: Vec::new(),
+            },
+            dependencies: Some(dependencies),
+            sources,
+            steps,
+            directories: HashMap::new(),
+            backup: Vec::new(),
+            special_permissions: Vec::new(),
+            network: false,
+            pkgver: None,
+            upstream_keys: Vec::new(),
+            changelog: Changelog::default(),
+            extends: None,
+            path: None,
+        },
+        todos,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::StepVariant;
+
+    const SPEC: &str = r#"%global forgeurl https://ftp.gnu.org/gnu/hello
+
+Name:           hello
+Version:        2.12.1
+Release:        1%{?dist}
+Summary:        Prints a familiar, friendly greeting
+License:        GPL-3.0-or-later
+URL:            https://www.gnu.org/software/hello/
+Source0:        %{forgeurl}/%{name}-%{version}.tar.gz
+Patch0:         hello-fix.patch
+
+BuildRequires:  gcc, make
+BuildRequires:  pkgconfig(zlib)
+Requires:       glibc >= 2.30
+Requires(post): info
+
+%description
+The GNU Hello program produces a familiar, friendly greeting.
+
+%prep
+%setup -q
+%patch -P 0 -p1
+
+%build
+%configure
+%make_build
+
+%install
+%make_install
+rm -f $RPM_BUILD_ROOT%{_infodir}/dir
+
+%if %{with tests}
+%check
+make check
+%endif
+
+%files
+%{_bindir}/hello
+
+%changelog
+* Mon Jan 01 2024 Someone <someone@example.com> - 2.12.1-1
+- Update
+"#;
+
+    #[test]
+    fn test_spec() {
+        let import = spec(SPEC).unwrap();
+        let package = &import.package;
+
+        assert_eq!(package.info.name, "hello");
+        assert_eq!(package.info.version, "2.12.1");
+        assert_eq!(package.info.license.to_string(), "GPL-3.0-or-later");
+
+        let dependencies = package.dependencies.as_ref().unwrap();
+        assert_eq!(dependencies.required, ["glibc>=2.30"]);
+        assert_eq!(dependencies.build, ["gcc", "make"]);
+
+        assert_eq!(
+            package.sources[0].url,
+            "https://ftp.gnu.org/gnu/hello/hello-%{version}.tar.gz"
+        );
+
+        let commands: Vec<&str> = package
+            .steps
+            .iter()
+            .map(|step| match &step.variant {
+                StepVariant::Command { command, .. } => command.as_str(),
+                StepVariant::Move { .. } => unreachable!(),
+            })
+            .collect();
+        assert_eq!(commands.len(), 4);
+        assert_eq!(
+            commands[0],
+            "cd \"%{srcdir}/hello-%{version}\"\npatch -p1 -i \"%{recipedir}/hello-fix.patch\""
+        );
+        assert_eq!(commands[1].lines().last(), Some("make -j%{jobs}"));
+        assert_eq!(commands[2], "cd \"%{srcdir}/hello-%{version}\"\nmake check");
+        assert_eq!(
+            commands[3],
+            "cd \"%{srcdir}/hello-%{version}\"\nmake install DESTDIR=%{pkgdir}\n\
+             rm -f %{pkgdir}/usr/share/info/dir"
+        );
+
+        assert!(Package::parse(&import.to_recipe().unwrap()).is_ok());
+        assert_eq!(
+            import.todos,
+            [
+                "https://ftp.gnu.org/gnu/hello/hello-%{version}.tar.gz has no sha256 checksum, \
+                 add one",
+                "copy hello-fix.patch next to the recipe and refer to it as \
+                 %{recipedir}/hello-fix.patch",
+                "conditionals were read as if they held, check the %if blocks",
+                "replace pkgconfig(zlib) by the package providing it",
+            ]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, DirBuilder},
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+
+use crate::{
+    config::Config,
+    error::{Result, bail, format_err},
+    signing::{PublicKey, SecretKey},
+};
+
+pub const KEYRING_FILE: &str = "etc/blossom/keyring.toml";
+/// Secret keys generated into the keyring, readable by root only.
+pub const SECRET_KEYS_DIR: &str = "etc/blossom/keys";
+
+/// A named public key and the repositories it is trusted to sign.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyringEntry {
+    #[serde_as(as = "DisplayFromStr")]
+    pub key: PublicKey,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted: Vec<String>,
+    /// Revoked keys are never trusted, even when listed in the `keys` of a
+    /// repository.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub revoked: bool,
+}
+
+/// The local keyring, complementing the keys configured per repository.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Keyring {
+    #[serde(flatten)]
+    pub keys: BTreeMap<String, KeyringEntry>,
+}
+
+impl Keyring {
+    pub fn load<R: AsRef<Path>>(root: R) -> Result<Self> {
+        let path = root.as_ref().join(KEYRING_FILE);
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(toml_edit::de::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save<R: AsRef<Path>>(&self, root: R) -> Result<()> {
+        let path = root.as_ref().join(KEYRING_FILE);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, toml_edit::ser::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&KeyringEntry> {
+        self.keys
+            .get(name)
+            .ok_or(format_err!("No key named {name} in the keyring"))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut KeyringEntry> {
+        self.keys
+            .get_mut(name)
+            .ok_or(format_err!("No key named {name} in the keyring"))
+    }
+
+    pub fn is_revoked(&self, key: &PublicKey) -> bool {
+        self.keys
+            .values()
+            .any(|entry| entry.revoked && entry.key == *key)
+    }
+
+    /// Adds the keys trusted for each repository to its configured keys and
+    /// drops the revoked ones, so that the configuration reflects the trust
+    /// actually placed in each repository.
+    pub fn apply(&self, config: &mut Config) {
+        for repository in &mut config.repositories {
+            for entry in self.keys.values() {
+                if !entry.revoked
+                    && entry.trusted.contains(&repository.name)
+                    && !repository.keys.contains(&entry.key)
+                {
+                    repository.keys.push(entry.key.clone());
+                }
+            }
+
+            repository.keys.retain(|key| !self.is_revoked(key));
+        }
+    }
+}
+
+/// Where the secret half of a generated keyring key is kept.
+pub fn secret_key_path<R: AsRef<Path>>(root: R, name: &str) -> PathBuf {
+    root.as_ref()
+        .join(SECRET_KEYS_DIR)
+        .join(format!("{name}.secret"))
+}
+
+/// Writes a secret key into the keyring's key directory.
+pub fn write_secret_key<R: AsRef<Path>>(root: R, name: &str, key: &SecretKey) -> Result<PathBuf> {
+    let root = root.as_ref();
+
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(root.join(SECRET_KEYS_DIR))?;
+
+    let path = secret_key_path(root, name);
+    key.write(&path)?;
+
+    Ok(path)
+}
+
+/// Reads a signing key given either as the path of a secret key file or as
+/// the name of a key generated into the keyring. Revoked keys are refused.
+pub fn read_secret_key<R: AsRef<Path>>(root: R, key: &Path) -> Result<SecretKey> {
+    let root = root.as_ref();
+
+    let secret = if key.exists() {
+        SecretKey::read(key)?
+    } else {
+        let name = key
+            .to_str()
+            .filter(|name| !name.contains('/'))
+            .ok_or(format_err!("{} doesn't exist", key.display()))?;
+        let path = secret_key_path(root, name);
+
+        if !path.exists() {
+            bail!("{name} is neither a secret key file nor a key generated into the keyring");
+        }
+
+        SecretKey::read(path)?
+    };
+
+    if Keyring::load(root)?.is_revoked(&secret.public()) {
+        bail!("Key {} is revoked", secret.public().id());
+    }
+
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RepositoryConfig;
+
+    fn repository(name: &str, keys: Vec<PublicKey>) -> RepositoryConfig {
+        RepositoryConfig {
+            name: name.to_string(),
+            url: format!("https://example.org/{name}"),
+            mirrors: Vec::new(),
+            priority: 0,
+            keys,
+            publish: None,
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let configured = SecretKey::generate().unwrap().public();
+        let revoked = SecretKey::generate().unwrap().public();
+        let trusted = SecretKey::generate().unwrap().public();
+
+        let mut config = Config {
+            repositories: vec![
+                repository("core", vec![configured.clone(), revoked.clone()]),
+                repository("extra", Vec::new()),
+            ],
+            ..Default::default()
+        };
+
+        let keyring = Keyring {
+            keys: BTreeMap::from([
+                (
+                    "old".to_string(),
+                    KeyringEntry {
+                        key: revoked,
+                        trusted: Vec::new(),
+                        revoked: true,
+                    },
+                ),
+                (
+                    "release".to_string(),
+                    KeyringEntry {
+                        key: trusted.clone(),
+                        trusted: vec!["core".to_string()],
+                        revoked: false,
+                    },
+                ),
+            ]),
+        };
+
+        keyring.apply(&mut config);
+
+        assert_eq!(config.repositories[0].keys, vec![configured, trusted]);
+        assert!(config.repositories[1].keys.is_empty());
+    }
+}
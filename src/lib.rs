@@ -1,2 +1,47 @@
+pub mod analyze;
+pub mod archive;
+pub mod build;
+#[cfg(feature = "cli")]
 pub mod commands;
+pub mod config;
+pub mod database;
+pub mod download;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod failure;
+pub mod fetch;
+pub mod format;
+pub mod generate;
+pub mod glob;
+pub mod history;
+pub mod hooks;
+pub mod import;
+pub mod keyring;
+pub mod lint;
+pub mod lock;
+pub mod lockfile;
+mod log;
+pub mod lookup;
+pub mod metadata;
+pub mod mirrors;
+pub mod osv;
 pub mod package;
+pub mod paths;
+pub mod permissions;
+pub mod prompt;
+pub mod publish;
+pub mod repository;
+pub mod resolver;
+pub mod runner;
+pub mod sbom;
+pub mod signing;
+pub mod snapshot;
+mod solver;
+pub mod store;
+#[cfg(test)]
+mod test_util;
+pub mod upstream;
+pub mod version;
+
+pub use error::{Error, Result};
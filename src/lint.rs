@@ -0,0 +1,549 @@
+//! Checks recipes for mistakes that building them wouldn't catch, or would
+//! only catch halfway through.
+
+use std::{cmp::Reverse, collections::BTreeSet, fmt, path::Path};
+
+use schemars::schema_for;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use spdx::{Expression, ParseMode};
+
+use crate::{
+    error::{Error, Result},
+    package::{self, Package, StepVariant, VARIABLE_REGEX, VARIABLES},
+};
+
+/// Commands installing what was built, which install into the host unless
+/// `DESTDIR` redirects them.
+const INSTALLS: [(&str, &str); 4] = [
+    ("make", "install"),
+    ("ninja", "install"),
+    ("meson", "install"),
+    ("cmake", "--install"),
+];
+
+/// Commands whose last argument, or every argument for those marked, is the
+/// file they write.
+const WRITES: [(&str, bool); 7] = [
+    ("install", false),
+    ("cp", false),
+    ("mv", false),
+    ("ln", false),
+    ("mkdir", true),
+    ("touch", true),
+    ("tee", true),
+];
+
+/// Absolute paths steps can write to without it ending up on the host.
+const SCRATCH: [&str; 2] = ["/dev/", "/tmp/"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Builds would go wrong, or get packaged wrong.
+    Warning,
+    /// The recipe can't be built.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    /// What was checked, such as `missing-checksum`, stable across releases
+    /// for tools to match on.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+#[derive(Default)]
+struct Findings(Vec<Finding>);
+
+impl Findings {
+    fn error(&mut self, code: &'static str, message: String) {
+        self.push(Severity::Error, code, message);
+    }
+
+    fn warning(&mut self, code: &'static str, message: String) {
+        self.push(Severity::Warning, code, message);
+    }
+
+    fn push(&mut self, severity: Severity, code: &'static str, message: String) {
+        self.0.push(Finding {
+            severity,
+            code,
+            message,
+        });
+    }
+}
+
+/// Lints the recipe `s` in `dir`, most severe findings first. Only a recipe
+/// that isn't TOML at all is an error, everything else is a finding.
+pub fn lint(s: &str, dir: &Path) -> Result<Vec<Finding>> {
+    let document: toml_edit::DocumentMut = s.parse().map_err(|e| Error::Parse {
+        what: "the recipe".to_string(),
+        message: format!("{e}"),
+    })?;
+    let mut findings = Findings::default();
+
+    // Only the fields of the recipe itself are checked, along with the
+    // package it makes with the recipes it extends.
+    let mut document = match package::extend(document.clone(), dir, &mut Vec::new()) {
+        Ok(extended) => extended,
+        Err(e) => {
+            findings.error("invalid-extends", e.to_string());
+            document
+        }
+    };
+
+    let fields: Value = toml_edit::de::from_str(s)?;
+    let schema = serde_json::to_value(schema_for!(Package))?;
+    check_fields(&schema, &schema, &fields, "", &mut findings);
+
+    // The rest of the recipe is checked even when its license doesn't parse.
+    if let Some(license) = document
+        .get_mut("info")
+        .and_then(|info| info.get_mut("license"))
+    {
+        let fixed = check_license(license.as_str().unwrap_or_default(), &mut findings);
+        *license = toml_edit::value(fixed);
+    }
+
+    let merged = document.to_string();
+    match toml_edit::de::from_str::<Package>(&merged) {
+        Ok(package) => check_package(&package, &merged, &mut findings),
+        Err(e) => findings.error("invalid-recipe", e.message().trim().to_string()),
+    }
+
+    let mut findings = findings.0;
+    findings.sort_by_key(|finding| Reverse(finding.severity));
+
+    Ok(findings)
+}
+
+/// Flags the fields of `value` that `schema` doesn't have, which serde would
+/// silently ignore, and those it marks deprecated.
+fn check_fields(root: &Value, schema: &Value, value: &Value, path: &str, findings: &mut Findings) {
+    let nodes = resolve(root, schema);
+
+    match value {
+        Value::Object(fields) => {
+            // Maps such as `directories` take any key.
+            if nodes
+                .iter()
+                .any(|node| node.get("additionalProperties").is_some_and(|a| a != false))
+            {
+                return;
+            }
+
+            let mut properties = Map::new();
+            for node in &nodes {
+                if let Some(Value::Object(node_properties)) = node.get("properties") {
+                    properties.extend(node_properties.clone());
+                }
+            }
+
+            for (key, value) in fields {
+                let path = match path {
+                    "" => key.clone(),
+                    path => format!("{path}.{key}"),
+                };
+
+                match properties.get(key) {
+                    Some(property) => {
+                        if property.get("deprecated") == Some(&Value::Bool(true)) {
+                            findings.warning(
+                                "deprecated-field",
+                                format!("{path} is deprecated, {}", description(property)),
+                            );
+                        }
+                        check_fields(root, property, value, &path, findings);
+                    }
+                    None => findings.warning(
+                        "unknown-field",
+                        format!("{path} isn't a field of recipes, it is ignored"),
+                    ),
+                }
+            }
+        }
+        Value::Array(items) => {
+            let Some(item_schema) = nodes.iter().find_map(|node| node.get("items")) else {
+                return;
+            };
+
+            for (i, item) in items.iter().enumerate() {
+                check_fields(root, item_schema, item, &format!("{path}[{i}]"), findings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The schemas `schema` stands for, following references and alternatives.
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> Vec<&'a Value> {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let pointer = reference.trim_start_matches('#');
+        return root
+            .pointer(pointer)
+            .map(|schema| resolve(root, schema))
+            .unwrap_or_default();
+    }
+
+    let mut nodes = vec![schema];
+    for combinator in ["anyOf", "oneOf", "allOf"] {
+        if let Some(Value::Array(alternatives)) = schema.get(combinator) {
+            for alternative in alternatives {
+                nodes.extend(resolve(root, alternative));
+            }
+        }
+    }
+
+    nodes
+}
+
+fn description(schema: &Value) -> String {
+    schema
+        .get("description")
+        .and_then(Value::as_str)
+        .unwrap_or("check the documentation for what replaces it")
+        .replace('\n', " ")
+}
+
+/// Checks that the license is a strict SPDX expression, which is what
+/// builds accept. Returns it, or a license that parses in its place.
+fn check_license(license: &str, findings: &mut Findings) -> String {
+    if Expression::parse(license).is_ok() {
+        return license.to_string();
+    }
+
+    let canonical = Expression::canonicalize(license).ok().flatten();
+    if let Some(canonical) = canonical.filter(|canonical| Expression::parse(canonical).is_ok()) {
+        findings.error(
+            "non-canonical-license",
+            format!("the license {license} isn't a canonical SPDX expression, write {canonical}"),
+        );
+        return canonical;
+    }
+
+    match Expression::parse_mode(license, ParseMode::LAX) {
+        Ok(expression) => {
+            let deprecated: Vec<&str> = expression
+                .requirements()
+                .filter_map(|requirement| requirement.req.license.id())
+                .filter(|id| id.is_deprecated())
+                .map(|id| id.name)
+                .collect();
+
+            findings.error(
+                "non-canonical-license",
+                match deprecated.is_empty() {
+                    true => format!("the license {license} isn't a canonical SPDX expression"),
+                    false => format!(
+                        "the license {license} uses deprecated SPDX identifiers: {}",
+                        deprecated.join(", ")
+                    ),
+                },
+            );
+        }
+        Err(e) => findings.error(
+            "invalid-license",
+            format!("the license {license} isn't an SPDX expression: {e}"),
+        ),
+    }
+
+    "LicenseRef-unknown".to_string()
+}
+
+fn check_package(package: &Package, recipe: &str, findings: &mut Findings) {
+    let mut names = BTreeSet::new();
+
+    for source in &package.sources {
+        check_variables(
+            Some(package),
+            &format!("source {}", source.url),
+            &source.url,
+            findings,
+        );
+
+        // Sources saved under the same name overwrite each other.
+        let name = source.file_name();
+        if source.filename.is_some() && !package::is_valid_filename(name) {
+            findings.error(
+                "invalid-filename",
+                format!(
+                    "source {} is saved as {name}, which isn't a file name",
+                    source.url
+                ),
+            );
+        } else if !names.insert(name) {
+            findings.error(
+                "duplicate-filename",
+                format!(
+                    "source {} is saved as {name} like another source, set its filename",
+                    source.url
+                ),
+            );
+        }
+
+        if let Some(signature) = &source.signature {
+            check_variables(
+                Some(package),
+                &format!("signature {signature}"),
+                signature,
+                findings,
+            );
+
+            if package.upstream_keys.is_empty() {
+                findings.error(
+                    "missing-upstream-keys",
+                    format!(
+                        "source {} has a signature but there are no upstream_keys to check it with",
+                        source.url
+                    ),
+                );
+            }
+        }
+
+        if source.url.starts_with("git+") {
+            continue;
+        }
+
+        match source.checksum.split_once(':') {
+            Some(("blake3" | "sha256", hash)) if !hash.is_empty() => {}
+            _ if source.checksum.is_empty() => findings.error(
+                "missing-checksum",
+                format!("source {} has no checksum", source.url),
+            ),
+            _ => findings.error(
+                "invalid-checksum",
+                format!(
+                    "source {} has the checksum {}, expected blake3:<hex> or sha256:<hex>",
+                    source.url, source.checksum
+                ),
+            ),
+        }
+    }
+
+    if let Some(pkgver) = &package.pkgver {
+        check_variables(Some(package), "pkgver", pkgver, findings);
+    }
+
+    for step in &package.steps {
+        let what = format!("step {}", step.name);
+
+        match &step.variant {
+            StepVariant::Command { command, .. } => {
+                check_variables(Some(package), &what, command, findings);
+                for message in writes_outside(command) {
+                    findings.warning("outside-pkgdir", format!("{what} {message}"));
+                }
+            }
+            StepVariant::Move { path } => {
+                check_variables(Some(package), &what, path.as_str(), findings);
+                if path.is_absolute() && !SCRATCH.iter().any(|dir| path.starts_with(dir)) {
+                    findings.warning(
+                        "outside-pkgdir",
+                        format!("{what} moves to {path}, out of the build directory"),
+                    );
+                }
+            }
+        }
+    }
+
+    let referenced: BTreeSet<&str> = VARIABLE_REGEX
+        .captures_iter(recipe)
+        .filter_map(|caps| caps.get(1))
+        .map(|name| name.as_str())
+        .collect();
+    let mut directories: Vec<&String> = package.directories.keys().collect();
+    directories.sort();
+    for name in directories {
+        check_variables(
+            None,
+            &format!("directory {name}"),
+            &package.directories[name],
+            findings,
+        );
+
+        if !referenced.contains(name.as_str()) {
+            findings.warning(
+                "unused-variable",
+                format!("directory {name} is declared but %{{{name}}} is never used"),
+            );
+        }
+    }
+}
+
+/// Checks that `s` only uses the variables of builds, or the directories of
+/// `package` if given.
+fn check_variables(package: Option<&Package>, what: &str, s: &str, findings: &mut Findings) {
+    for caps in VARIABLE_REGEX.captures_iter(s) {
+        let name = &caps[1];
+
+        if !VARIABLES.contains(&name)
+            && !package.is_some_and(|package| package.directories.contains_key(name))
+        {
+            findings.error(
+                "unknown-variable",
+                format!(
+                    "{what} uses %{{{name}}}, which is neither a directory nor one of %{{{}}}",
+                    VARIABLES.join("}, %{")
+                ),
+            );
+        }
+    }
+}
+
+/// What `command` writes outside of the package directory, as far as simple
+/// shell tells: installs without `DESTDIR`, and absolute paths written to.
+fn writes_outside(command: &str) -> Vec<String> {
+    let mut writes = Vec::new();
+    let has_destdir = command.contains("DESTDIR");
+
+    for line in command.split(['\n', ';', '|', '&']) {
+        let words: Vec<&str> = line
+            .split_whitespace()
+            .map(|word| word.trim_matches(['"', '\'']))
+            .collect();
+        let Some(program) = words
+            .first()
+            .map(|program| program.rsplit('/').next().unwrap_or(program))
+        else {
+            continue;
+        };
+
+        if !has_destdir
+            && INSTALLS
+                .iter()
+                .any(|(install, argument)| program == *install && words.contains(argument))
+        {
+            writes.push(format!(
+                "runs `{}` without DESTDIR, installing into the host",
+                line.trim()
+            ));
+        }
+
+        let mut targets = Vec::new();
+        for (i, word) in words.iter().enumerate() {
+            if let Some(target) = word.strip_prefix(">>").or(word.strip_prefix('>')) {
+                match target {
+                    "" => targets.extend(words.get(i + 1)),
+                    target => targets.push(target),
+                }
+            }
+        }
+
+        if let Some((_, every)) = WRITES.iter().find(|(write, _)| *write == program) {
+            let arguments: Vec<&str> = words[1..]
+                .iter()
+                .copied()
+                .take_while(|word| !word.starts_with('>'))
+                .filter(|word| !word.starts_with('-'))
+                .collect();
+
+            match (every, words.iter().position(|word| *word == "-t")) {
+                (_, Some(i)) => targets.extend(words.get(i + 1)),
+                (true, None) => targets.extend(arguments),
+                (false, None) => targets.extend(arguments.last()),
+            }
+        }
+
+        for target in targets {
+            if target.starts_with('/') && !SCRATCH.iter().any(|dir| target.starts_with(dir)) {
+                writes.push(format!("writes to {target}, outside of %{{pkgdir}}"));
+            }
+        }
+    }
+
+    writes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPE: &str = r#"
+[info]
+name = "foo"
+version = "1.0"
+description = "A foo"
+license = "GPL-2.0+"
+homepage = "https://example.com"
+
+[[sources]]
+url = "https://example.com/foo-%{version}.tar.gz"
+
+[[sources]]
+url = "https://example.com/extra/foo-%{version}.tar.gz"
+checksum = "blake3:ab12"
+signature = "https://example.com/extra/foo-%{version}.tar.gz.asc"
+
+[[steps]]
+name = "build"
+runner = "shell"
+command = "make -j%{jobs} CC=%{cc}"
+
+[[steps]]
+name = "package"
+runner = "shell"
+command = """
+make install
+install -Dm644 foo.conf /etc/foo.conf
+echo ok > %{pkgdir}/ok"""
+
+[directories]
+docs = "usr/share/doc/foo"
+"#;
+
+    #[test]
+    fn test_lint() {
+        let findings: Vec<(Severity, &str)> = lint(RECIPE, Path::new("."))
+            .unwrap()
+            .iter()
+            .map(|finding| (finding.severity, finding.code))
+            .collect();
+
+        assert_eq!(
+            findings,
+            [
+                (Severity::Error, "non-canonical-license"),
+                (Severity::Error, "missing-checksum"),
+                (Severity::Error, "duplicate-filename"),
+                (Severity::Error, "missing-upstream-keys"),
+                (Severity::Error, "unknown-variable"),
+                (Severity::Warning, "unknown-field"),
+                (Severity::Warning, "outside-pkgdir"),
+                (Severity::Warning, "outside-pkgdir"),
+                (Severity::Warning, "unused-variable"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_writes_outside() {
+        assert!(writes_outside("make DESTDIR=\"%{pkgdir}\" install").is_empty());
+        assert!(writes_outside("install -Dm755 foo -t %{pkgdir}/usr/bin").is_empty());
+        assert!(writes_outside("cp /usr/share/foo %{pkgdir}/foo && echo > /dev/null").is_empty());
+        assert_eq!(
+            writes_outside("mkdir -p /opt/foo\ncmake --install build"),
+            [
+                "writes to /opt/foo, outside of %{pkgdir}",
+                "runs `cmake --install build` without DESTDIR, installing into the host",
+            ]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Write as _},
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{Flock, FlockArg},
+};
+
+use crate::{database::DATABASE_DIR, error::Result, events, failure::Failure, log::info};
+
+const LOCK_FILE: &str = "db.lck";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Exclusive lock over the database of a root, released on drop. It's a
+/// flock(2) on a lock file that stays around, so the kernel releases it
+/// when its owner dies and nobody has to tell stale locks apart.
+#[derive(Debug)]
+pub struct Lock {
+    _file: Flock<File>,
+}
+
+impl Lock {
+    /// Like [`Lock::acquire`], waiting on a blocking thread so the async
+    /// runtime isn't held up.
+    pub async fn acquire_async<R: AsRef<Path>>(root: R, wait: bool) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+
+        events::spawn_blocking(move || Self::acquire(root, wait))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Takes the lock of the database of `root`, kept next to it even when
+    /// the state of unprivileged users lives elsewhere.
+    pub fn acquire<R: AsRef<Path>>(root: R, wait: bool) -> Result<Self> {
+        let dir = root.as_ref().join(DATABASE_DIR);
+        fs::create_dir_all(&dir)?;
+
+        Self::acquire_at(dir.join(LOCK_FILE), wait)
+    }
+
+    /// Takes the lock file at `path`, writing the pid of blossom in it for
+    /// those waiting on it.
+    fn acquire_at(path: PathBuf, wait: bool) -> Result<Self> {
+        let mut file = open(&path)?;
+        let mut waiting = false;
+
+        loop {
+            match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+                Ok(mut lock) => {
+                    // Users who may only read the lock file can still lock
+                    // it, they just don't get to say who they are.
+                    let _ = lock
+                        .set_len(0)
+                        .and_then(|()| writeln!(*lock, "{}", process::id()));
+                    return Ok(Self { _file: lock });
+                }
+                Err((unlocked, Errno::EWOULDBLOCK)) => file = unlocked,
+                Err((_, errno)) => return Err(io::Error::from(errno).into()),
+            }
+
+            let owner = read_owner(&path)
+                .map(|pid| format!(" (pid {pid})"))
+                .unwrap_or_default();
+
+            if !wait {
+                return Err(Failure::Locked.error(format!(
+                    "Another instance of blossom is running{owner}, retry later or pass --wait"
+                )));
+            }
+
+            if !waiting {
+                info!("Waiting for another instance of blossom{owner} to finish");
+                waiting = true;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Opens the lock file at `path`, read-only when it belongs to someone else.
+fn open(path: &Path) -> io::Result<File> {
+    match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+    {
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => File::open(path),
+        result => result,
+    }
+}
+
+fn read_owner(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_acquire() {
+        let dir = env::temp_dir().join(format!("blossom-test-lock-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LOCK_FILE);
+
+        let lock = Lock::acquire_at(path.clone(), false).unwrap();
+        let second = Lock::acquire_at(path.clone(), false);
+        assert!(matches!(
+            second.unwrap_err().failure(),
+            Some(Failure::Locked)
+        ));
+        drop(lock);
+
+        // The lock file stays, and whatever pid a dead owner left in it
+        // doesn't matter.
+        assert!(path.exists());
+        fs::write(&path, "999999999\n").unwrap();
+        let lock = Lock::acquire_at(path.clone(), false).unwrap();
+        assert_eq!(read_owner(&path), Some(process::id()));
+        drop(lock);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
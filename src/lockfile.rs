@@ -0,0 +1,110 @@
+use std::{collections::BTreeMap, fs, path::Path, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, bail},
+    package::Source,
+};
+
+pub const LOCK_FILE: &str = "package.lock";
+
+/// Programs whose version is recorded when locking, if installed.
+const TOOLCHAIN: [&str; 12] = [
+    "cc", "c++", "gcc", "clang", "rustc", "cargo", "make", "cmake", "meson", "ninja", "go",
+    "python3",
+];
+
+/// The exact inputs of a package build, recorded by `blossom lock` so that
+/// `blossom build --locked` later builds from byte-identical sources with the
+/// same toolchain.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageLock {
+    #[serde(default)]
+    pub sources: Vec<LockedSource>,
+    /// The first line of `--version` of every toolchain program found.
+    #[serde(default)]
+    pub toolchain: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedSource {
+    /// The URL as written in `package.toml`.
+    pub url: String,
+    /// Where the archive was fetched from, after redirects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+    /// The blake3 checksum of the archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// The commit checked out for git sources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+impl PackageLock {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(toml_edit::de::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, toml_edit::ser::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// The locked entry of every source, in order. Fails if the sources
+    /// changed since locking.
+    pub fn locked_sources(&self, sources: &[Source]) -> Result<Vec<&LockedSource>> {
+        let urls: Vec<&str> = sources.iter().map(|source| source.url.as_str()).collect();
+        let locked: Vec<&str> = self
+            .sources
+            .iter()
+            .map(|source| source.url.as_str())
+            .collect();
+
+        if urls != locked {
+            bail!("The sources changed since {LOCK_FILE} was written, run `blossom lock` again");
+        }
+
+        Ok(self.sources.iter().collect())
+    }
+
+    /// Fails if the installed toolchain differs from the locked one.
+    pub fn check_toolchain(&self) -> Result<()> {
+        let installed = toolchain();
+        let mut differences = Vec::new();
+
+        for (program, locked) in &self.toolchain {
+            match installed.get(program) {
+                Some(version) if version == locked => {}
+                Some(version) => differences.push(format!("{program}: {locked} -> {version}")),
+                None => differences.push(format!("{program}: {locked} -> not installed")),
+            }
+        }
+
+        if !differences.is_empty() {
+            bail!(
+                "The toolchain differs from {LOCK_FILE}:\n  {}",
+                differences.join("\n  ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The version of every installed toolchain program.
+pub fn toolchain() -> BTreeMap<String, String> {
+    TOOLCHAIN
+        .iter()
+        .filter_map(|program| {
+            let output = Command::new(program).arg("--version").output().ok()?;
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let version = stdout.lines().next()?.trim();
+
+            (output.status.success() && !version.is_empty())
+                .then(|| (program.to_string(), version.to_string()))
+        })
+        .collect()
+}
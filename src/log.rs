@@ -0,0 +1,15 @@
+//! Logging macros, going through `tracing` with the `tracing` feature and
+//! discarded otherwise, so the library doesn't have to depend on it.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{info, warn};
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! discard {
+    ($($arg:tt)*) => {{
+        let _ = || format!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use {discard as info, discard as warn};
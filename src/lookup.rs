@@ -0,0 +1,324 @@
+//! Lookup tables of synced indexes, written next to them at sync and memory
+//! mapped, so that commands looking up a few packages or going through their
+//! names and descriptions don't decode every entry of large indexes.
+//!
+//! A table starts with a header, followed by one record per entry sorted by
+//! name, then by the data of the entries. Each record points at the name of
+//! its entry, its [`Info`] and the entry itself, the latter two as JSON:
+//!
+//! ```text
+//! magic version count index-size index-mtime generation
+//! (offset name-len info-len entry-len padding) * count
+//! (name info entry) * count
+//! ```
+//!
+//! Integers are little endian. The size and modification time of the index
+//! tell whether the table is still up to date with it.
+
+use std::{
+    fs::{self, File},
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use memmap2::Mmap;
+
+use crate::{
+    error::{Result, bail, format_err},
+    package::Info,
+    repository::{IndexEntry, RepoIndex},
+};
+
+pub const LOOKUP_EXTENSION: &str = "lookup";
+
+const MAGIC: &[u8; 8] = b"PEACHLKP";
+const FORMAT: u32 = 1;
+const HEADER_SIZE: usize = 40;
+const RECORD_SIZE: usize = 24;
+
+/// The lookup table of a synced index.
+pub struct Lookup {
+    bytes: Bytes,
+    count: usize,
+    generation: u64,
+}
+
+/// A mapped table, or one built in memory when it couldn't be written.
+enum Bytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Mapped(map) => map,
+            Bytes::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// A record of a table: where the data of its entry starts and how long
+/// each part of it is.
+struct Record {
+    offset: usize,
+    name: usize,
+    info: usize,
+    entry: usize,
+}
+
+impl Lookup {
+    /// Opens the lookup table of the index at `index_path`, writing it first
+    /// if it's missing or out of date. When it can't be written, such as in
+    /// the sync directory of another user, it's built in memory instead.
+    pub fn load<P: AsRef<Path>>(index_path: P) -> Result<Self> {
+        let index_path = index_path.as_ref();
+
+        if let Some(lookup) = Self::open(index_path)? {
+            return Ok(lookup);
+        }
+
+        let index = RepoIndex::read(index_path)?;
+        let bytes = build(&index, stamp(index_path)?)?;
+
+        match write(&bytes, index_path) {
+            Ok(()) => Self::open(index_path)?
+                .ok_or_else(|| format_err!("{} changed while reading it", index_path.display())),
+            Err(_) => Self::parse(Bytes::Owned(bytes)),
+        }
+    }
+
+    /// Writes the lookup table of `index`, read from `index_path`, next to
+    /// it.
+    pub fn write<P: AsRef<Path>>(index: &RepoIndex, index_path: P) -> Result<()> {
+        let index_path = index_path.as_ref();
+
+        write(&build(index, stamp(index_path)?)?, index_path)
+    }
+
+    /// Opens the lookup table of the index at `index_path`, if there's a
+    /// valid one up to date with it.
+    fn open(index_path: &Path) -> Result<Option<Self>> {
+        let path = lookup_path(index_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        // SAFETY: tables are replaced by renaming new ones over them, never
+        // written in place, so the mapped file doesn't change.
+        let map = unsafe { Mmap::map(&File::open(&path)?)? };
+        let Ok(lookup) = Self::parse(Bytes::Mapped(map)) else {
+            return Ok(None);
+        };
+
+        if lookup.bytes[16..32] != stamp(index_path)? {
+            return Ok(None);
+        }
+
+        Ok(Some(lookup))
+    }
+
+    fn parse(bytes: Bytes) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[..8] != MAGIC {
+            bail!("Not a lookup table");
+        }
+        if read_u32(&bytes, 8) != FORMAT {
+            bail!("Unsupported lookup table format {}", read_u32(&bytes, 8));
+        }
+
+        let count = read_u32(&bytes, 12) as usize;
+        if bytes.len() < HEADER_SIZE + count * RECORD_SIZE {
+            bail!("Truncated lookup table");
+        }
+
+        Ok(Self {
+            generation: read_u64(&bytes, 32),
+            bytes,
+            count,
+        })
+    }
+
+    /// The generation of the index the table was written from.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Every entry of `name`, decoded from the table alone.
+    pub fn entries(&self, name: &str) -> Result<Vec<IndexEntry>> {
+        // The first record of `name`, records being sorted by name.
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let middle = (low + high) / 2;
+
+            if self.name(&self.record(middle))? < name {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let mut entries = Vec::new();
+        for i in low..self.count {
+            let record = self.record(i);
+            if self.name(&record)? != name {
+                break;
+            }
+
+            let start = record.offset + record.name + record.info;
+            entries.push(serde_json::from_slice(self.slice(start, record.entry)?)?);
+        }
+
+        Ok(entries)
+    }
+
+    /// The information of every entry, in order of name, without decoding
+    /// the rest of them.
+    pub fn infos(&self) -> impl Iterator<Item = Result<Info>> + '_ {
+        (0..self.count).map(|i| {
+            let record = self.record(i);
+
+            Ok(serde_json::from_slice(
+                self.slice(record.offset + record.name, record.info)?,
+            )?)
+        })
+    }
+
+    fn record(&self, i: usize) -> Record {
+        let start = HEADER_SIZE + i * RECORD_SIZE;
+
+        Record {
+            offset: read_u64(&self.bytes, start) as usize,
+            name: read_u32(&self.bytes, start + 8) as usize,
+            info: read_u32(&self.bytes, start + 12) as usize,
+            entry: read_u32(&self.bytes, start + 16) as usize,
+        }
+    }
+
+    fn name(&self, record: &Record) -> Result<&str> {
+        std::str::from_utf8(self.slice(record.offset, record.name)?)
+            .map_err(|_| format_err!("Invalid package name in lookup table"))
+    }
+
+    fn slice(&self, start: usize, len: usize) -> Result<&[u8]> {
+        start
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(start..end))
+            .ok_or_else(|| format_err!("Truncated lookup table"))
+    }
+}
+
+/// The lookup table of the index at `index_path`.
+pub fn lookup_path(index_path: &Path) -> PathBuf {
+    index_path.with_extension(LOOKUP_EXTENSION)
+}
+
+/// The table of `index`, stamped with the [`stamp`] of its file.
+fn build(index: &RepoIndex, stamp: [u8; 16]) -> Result<Vec<u8>> {
+    let mut entries: Vec<&IndexEntry> = index.packages.iter().collect();
+    entries.sort_by(|a, b| a.info.name.cmp(&b.info.name));
+
+    let mut records = Vec::with_capacity(entries.len() * RECORD_SIZE);
+    let mut data = Vec::new();
+    let data_start = HEADER_SIZE + entries.len() * RECORD_SIZE;
+
+    for entry in entries {
+        let info = serde_json::to_vec(&entry.info)?;
+        let json = serde_json::to_vec(entry)?;
+
+        records.extend(((data_start + data.len()) as u64).to_le_bytes());
+        for len in [entry.info.name.len(), info.len(), json.len()] {
+            let len = u32::try_from(len).map_err(|_| format_err!("Index entry too large"))?;
+            records.extend(len.to_le_bytes());
+        }
+        records.extend(0u32.to_le_bytes());
+
+        data.extend(entry.info.name.as_bytes());
+        data.extend(info);
+        data.extend(json);
+    }
+
+    let mut bytes = Vec::with_capacity(data_start + data.len());
+    bytes.extend(MAGIC);
+    bytes.extend(FORMAT.to_le_bytes());
+    bytes.extend((index.packages.len() as u32).to_le_bytes());
+    bytes.extend(stamp);
+    bytes.extend(index.generation.to_le_bytes());
+    bytes.extend(records);
+    bytes.extend(data);
+
+    Ok(bytes)
+}
+
+fn write(bytes: &[u8], index_path: &Path) -> Result<()> {
+    let path = lookup_path(index_path);
+    let tmp = path.with_extension("lookup.tmp");
+
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, path)?;
+
+    Ok(())
+}
+
+/// The size and modification time of the index at `index_path`.
+fn stamp(index_path: &Path) -> Result<[u8; 16]> {
+    let metadata = fs::metadata(index_path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64);
+
+    let mut stamp = [0; 16];
+    stamp[..8].copy_from_slice(&metadata.len().to_le_bytes());
+    stamp[8..].copy_from_slice(&modified.to_le_bytes());
+
+    Ok(stamp)
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_entry;
+
+    #[test]
+    fn test_lookup() {
+        let index = RepoIndex {
+            generation: 3,
+            packages: vec![
+                test_entry("vim", "9.1", &[]),
+                test_entry("bash", "5.2", &[]),
+                test_entry("vim", "9.0", &[]),
+                test_entry("zsh", "5.9", &[]),
+            ],
+        };
+        let lookup = Lookup::parse(Bytes::Owned(build(&index, [0; 16]).unwrap())).unwrap();
+
+        assert_eq!(lookup.generation(), 3);
+
+        let versions = |name| -> Vec<String> {
+            let entries = lookup.entries(name).unwrap();
+            entries
+                .into_iter()
+                .map(|entry| entry.info.version)
+                .collect()
+        };
+        assert_eq!(versions("vim"), ["9.1", "9.0"]);
+        assert_eq!(versions("zsh"), ["5.9"]);
+        assert!(versions("nano").is_empty());
+
+        let names: Vec<String> = lookup.infos().map(|info| info.unwrap().name).collect();
+        assert_eq!(names, ["bash", "vim", "vim", "zsh"]);
+
+        assert!(Lookup::parse(Bytes::Owned(b"PEACHLKP".to_vec())).is_err());
+    }
+}
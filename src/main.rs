@@ -1,58 +1,955 @@
-use std::path::PathBuf;
+use std::{io, net::SocketAddr, path::PathBuf};
 
-use clap::{Parser, Subcommand};
+use blossom::{
+    commands::{installed_candidates, package_candidates, repository_candidates},
+    config::{Colors, Config, Output},
+    failure,
+    generate::BuildSystem,
+    prompt::{self, Confirm},
+    resolver::Solver,
+    sbom::SbomFormat,
+};
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use clap_complete::{ArgValueCandidates, CompleteEnv, Shell};
 use tracing::error;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
 #[derive(Parser)]
 #[command(name = "blossom")]
 #[command(about = "Blossom - A package manager for linux", long_about = None)]
+#[command(after_long_help = failure::help())]
 struct Cli {
+    #[arg(long, global = true, env = "BLOSSOM_ROOT", default_value = "/")]
+    root: PathBuf,
+    #[arg(long, global = true)]
+    wait: bool,
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+    #[arg(long, global = true)]
+    jobs: Option<usize>,
+    #[arg(long, global = true)]
+    color: Option<Colors>,
+    #[arg(long, global = true)]
+    offline: bool,
+    #[arg(long, global = true)]
+    json: bool,
+    #[arg(long, global = true, alias = "noconfirm", conflicts_with = "confirm")]
+    yes: bool,
+    #[arg(long, global = true)]
+    confirm: Option<Confirm>,
+    #[arg(
+        long,
+        global = true,
+        env = "BLOSSOM_EVENTS",
+        value_name = "FD|unix:PATH"
+    )]
+    events: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    Build,
+    Build {
+        #[arg(long)]
+        locked: bool,
+    },
+    Lock,
+    BuildOrder {
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+    },
+    Lint {
+        #[arg(default_value = "package.toml")]
+        recipe: PathBuf,
+    },
+    Fmt {
+        #[arg(default_value = "package.toml")]
+        paths: Vec<PathBuf>,
+        #[arg(long)]
+        check: bool,
+    },
+    Analyze {
+        package: PathBuf,
+    },
+    Test {
+        #[arg(default_value = "package.toml")]
+        recipe: PathBuf,
+    },
+    Bump {
+        recipe: PathBuf,
+        version: String,
+        #[arg(long, value_name = "MESSAGE")]
+        changelog: Option<String>,
+    },
+    Bootstrap {
+        #[arg(long, required = true, value_delimiter = ',', add = ArgValueCandidates::new(repository_candidates))]
+        packages: Vec<String>,
+        #[arg(long, default_value = "/")]
+        from: PathBuf,
+    },
     Install {
+        #[arg(required_unless_present = "package", add = ArgValueCandidates::new(repository_candidates))]
+        names: Vec<String>,
+        #[arg(short, long, conflicts_with = "names")]
+        package: Option<PathBuf>,
+        #[arg(long)]
+        asdeps: bool,
+        #[arg(long)]
+        no_optional: bool,
+        #[arg(long, default_value = "greedy")]
+        solver: Solver,
+        #[arg(long)]
+        explain: bool,
+    },
+    Uninstall {
+        #[arg(short, long, add = ArgValueCandidates::new(installed_candidates))]
+        name: String,
+        #[arg(short, long)]
+        cascade: bool,
+        #[arg(short, long)]
+        force: bool,
+    },
+    Info {
+        #[arg(short, long, add = ArgValueCandidates::new(package_candidates))]
+        name: String,
+    },
+    Changelog {
+        #[arg(add = ArgValueCandidates::new(package_candidates))]
+        name: String,
+    },
+    #[command(arg_required_else_help = true)]
+    Config {
+        #[arg(long)]
+        env: bool,
+        #[command(subcommand)]
+        command: Option<ConfigCommands>,
+    },
+    ConfigDiff {
+        #[arg(short, long)]
+        list: bool,
+    },
+    Verify {
+        #[arg(required_unless_present = "all", add = ArgValueCandidates::new(installed_candidates))]
+        name: Option<String>,
+        #[arg(short, long, conflicts_with = "name")]
+        all: bool,
+    },
+    Repair {
+        #[arg(add = ArgValueCandidates::new(installed_candidates))]
+        name: String,
+        #[arg(short, long)]
+        package: Option<PathBuf>,
+    },
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+    Export {
+        #[command(subcommand)]
+        command: ExportCommands,
+    },
+    Import {
+        #[command(subcommand)]
+        command: ImportCommands,
+    },
+    #[command(group(ArgGroup::new("from").required(true)))]
+    New {
+        #[arg(group = "from")]
+        name: Option<String>,
+        #[arg(long, requires = "name", value_name = "BUILD_SYSTEM")]
+        template: Option<BuildSystem>,
+        #[arg(long, group = "from")]
+        from_crate: Option<String>,
+        #[arg(long, group = "from")]
+        from_pypi: Option<String>,
+        #[arg(long, group = "from", value_name = "OWNER/REPO")]
+        from_github: Option<String>,
+    },
+    Repo {
+        #[command(subcommand)]
+        command: RepoCommands,
+    },
+    Mirrors {
+        #[command(subcommand)]
+        command: MirrorsCommands,
+    },
+    Key {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+    History,
+    Undo {
+        id: u64,
+    },
+    List {
+        pattern: Option<String>,
+        #[arg(short, long)]
+        explicit: bool,
+        #[arg(short, long)]
+        orphans: bool,
+        #[arg(short, long)]
+        upgradable: bool,
+        #[arg(short, long)]
+        size: bool,
+    },
+    Files {
+        #[arg(add = ArgValueCandidates::new(package_candidates))]
+        name: String,
         #[arg(short, long)]
+        remote: bool,
+    },
+    Outdated,
+    Publish {
         package: PathBuf,
+        #[arg(long)]
+        repo: String,
+        #[arg(long)]
+        sign: Option<PathBuf>,
     },
-    Uninstall {
+    Sync,
+    Search {
+        #[arg(required_unless_present = "tags")]
+        terms: Vec<String>,
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    Serve {
+        dir: PathBuf,
+        #[arg(long, default_value = "0.0.0.0:8080")]
+        listen: SocketAddr,
+    },
+    Daemon {
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+    Why {
+        #[arg(add = ArgValueCandidates::new(installed_candidates))]
+        name: String,
+    },
+    Upgrade {
+        #[arg(long, default_value = "greedy")]
+        solver: Solver,
+    },
+    Rdepends {
+        #[arg(add = ArgValueCandidates::new(package_candidates))]
+        name: String,
         #[arg(short, long)]
+        repo: bool,
+    },
+    Tree {
+        #[arg(add = ArgValueCandidates::new(package_candidates))]
         name: String,
+        #[arg(short, long)]
+        depth: Option<usize>,
+        #[arg(long)]
+        dot: bool,
     },
-    Info {
+    Rollback {
+        id: Option<u64>,
         #[arg(short, long)]
+        list: bool,
+    },
+    Audit {
+        #[arg(long)]
+        recipe: Option<PathBuf>,
+        #[arg(long)]
+        database: Option<PathBuf>,
+        #[arg(long)]
+        ecosystem: Option<String>,
+    },
+    Sbom {
+        #[arg(add = ArgValueCandidates::new(installed_candidates))]
+        package: String,
+        #[arg(short, long, default_value_t = SbomFormat::Spdx)]
+        format: SbomFormat,
+    },
+    Completions {
+        shell: Shell,
+    },
+    Schema,
+}
+
+#[derive(Subcommand)]
+enum ExportCommands {
+    Deb {
+        path: PathBuf,
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    Oci {
+        #[arg(long, required = true, value_delimiter = ',', add = ArgValueCandidates::new(repository_candidates))]
+        packages: Vec<String>,
+        #[arg(long, default_value = "blossom:latest")]
+        tag: String,
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    Pacman {
+        path: PathBuf,
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    Rpm {
+        path: PathBuf,
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    Sysext {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        confext: bool,
+        #[arg(long)]
+        raw: bool,
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportCommands {
+    Pkgbuild { path: PathBuf },
+    Apkbuild { path: PathBuf },
+    Spec { path: PathBuf },
+    Formula { path: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum DbCommands {
+    Export,
+    Import { file: Option<PathBuf> },
+}
+
+#[derive(Subcommand)]
+enum RepoCommands {
+    Add {
+        dir: PathBuf,
+        #[arg(required = true)]
+        packages: Vec<PathBuf>,
+        #[arg(long)]
+        sign: Option<PathBuf>,
+    },
+    Keygen {
+        path: PathBuf,
+    },
+    Refresh {
+        dir: PathBuf,
+        #[arg(long)]
+        sign: PathBuf,
+    },
+    Check {
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    Get {
+        key: String,
+    },
+    Set {
+        key: String,
+        value: String,
+        #[arg(long)]
+        user: bool,
+    },
+    Paths,
+}
+
+#[derive(Subcommand)]
+enum MirrorsCommands {
+    Rank { repository: Option<String> },
+    List,
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    Generate {
+        name: String,
+    },
+    Import {
         name: String,
+        key: String,
+    },
+    Export {
+        name: String,
+    },
+    List,
+    Revoke {
+        name: String,
+    },
+    Trust {
+        name: String,
+        repository: String,
+        #[arg(long)]
+        remove: bool,
     },
 }
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
+    CompleteEnv::with_factory(Cli::command).complete();
 
     let cli = Cli::parse();
 
+    let mut overrides = cli.overrides.clone();
+    if let Some(cache_dir) = &cli.cache_dir {
+        overrides.push(format!("cache_dir={}", cache_dir.display()));
+    }
+    if let Some(jobs) = cli.jobs {
+        overrides.push(format!("parallelism.jobs={jobs}"));
+    }
+    if let Some(color) = cli.color {
+        overrides.push(format!("colors={color}"));
+    }
+    if cli.offline {
+        overrides.push("offline=true".to_string());
+    }
+    if cli.json {
+        overrides.push("output=json".to_string());
+    }
+
+    let config = Config::set_overrides(&overrides).and_then(|_| Config::load(&cli.root));
+
+    let json = config
+        .as_ref()
+        .is_ok_and(|config| config.output == Output::Json);
+
+    tracing_subscriber::fmt()
+        .with_ansi(
+            config
+                .as_ref()
+                .map_or(Colors::default(), |config| config.colors)
+                .enabled(),
+        )
+        .with_writer(if json {
+            BoxMakeWriter::new(io::stderr)
+        } else {
+            BoxMakeWriter::new(io::stdout)
+        })
+        .init();
+
+    if let Err(e) = config.map_err(anyhow::Error::from) {
+        error!("Failed to load the configuration: {:?}", e);
+        failure::exit(&e);
+    }
+
+    prompt::configure(prompt::Settings {
+        assume_yes: cli.yes,
+        confirm: cli.confirm.unwrap_or_default(),
+        interactive: !json,
+    });
+
+    if let Some(Err(e)) = cli
+        .events
+        .as_ref()
+        .map(|target| blossom::events::open(target))
+    {
+        let e = anyhow::Error::from(e);
+        error!("Failed to open the event stream: {:?}", e);
+        failure::exit(&e);
+    }
+
     match &cli.command {
-        Commands::Build => {
-            if let Err(e) = blossom::commands::build().await {
+        Commands::Build { locked } => {
+            if let Err(e) = blossom::commands::build(&cli.root, *locked).await {
                 error!("Failed to build package: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Lock => {
+            if let Err(e) = blossom::commands::lock(&cli.root).await {
+                error!("Failed to lock the package sources: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::BuildOrder { dirs } => {
+            if let Err(e) = blossom::commands::build_order(dirs, json) {
+                error!("Failed to compute the build order: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Lint { recipe } => {
+            if let Err(e) = blossom::commands::lint(recipe, json) {
+                error!("Failed to lint the recipe: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Fmt { paths, check } => {
+            if let Err(e) = blossom::commands::fmt(paths, *check) {
+                error!("Failed to format the recipes: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Analyze { package } => {
+            if let Err(e) = blossom::commands::analyze(&cli.root, package, json) {
+                error!("Failed to analyze the package: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Test { recipe } => {
+            if let Err(e) = blossom::commands::test(&cli.root, recipe).await {
+                error!("Failed to test the package: {:?}", e);
+                failure::exit(&e);
             }
         }
-        Commands::Install { package } => {
-            if let Err(e) = blossom::commands::install(package) {
+        Commands::Bump {
+            recipe,
+            version,
+            changelog,
+        } => {
+            if let Err(e) =
+                blossom::commands::bump(&cli.root, recipe, version, changelog.as_deref()).await
+            {
+                error!("Failed to bump the recipe: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Bootstrap { packages, from } => {
+            if let Err(e) = blossom::commands::bootstrap(&cli.root, from, packages, cli.wait).await
+            {
+                error!("Failed to bootstrap the root: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Install {
+            names,
+            package,
+            asdeps,
+            no_optional,
+            solver,
+            explain,
+        } => {
+            let options = blossom::commands::InstallOptions {
+                as_deps: *asdeps,
+                no_optional: *no_optional,
+                solver: *solver,
+                explain: *explain,
+                json,
+            };
+
+            let result = match package {
+                Some(package) => blossom::commands::install(package, &cli.root, cli.wait),
+                None => {
+                    blossom::commands::install_packages(&cli.root, names, &options, cli.wait).await
+                }
+            };
+
+            if let Err(e) = result {
                 error!("Failed to install package: {:?}", e);
+                failure::exit(&e);
             }
         }
-        Commands::Uninstall { name } => {
-            if let Err(e) = blossom::commands::uninstall(name) {
+        Commands::Uninstall {
+            name,
+            cascade,
+            force,
+        } => {
+            if let Err(e) =
+                blossom::commands::uninstall(&cli.root, name, *cascade, *force, cli.wait, json)
+            {
                 error!("Failed to remove package: {:?}", e);
+                failure::exit(&e);
             }
         }
         Commands::Info { name } => {
-            if let Err(e) = blossom::commands::info(name) {
+            if let Err(e) = blossom::commands::info(&cli.root, name, json) {
                 error!("Failed to retrieve package info: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Changelog { name } => {
+            if let Err(e) = blossom::commands::changelog(&cli.root, name, json) {
+                error!("Failed to show the changelog: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Config { env, command } => match command {
+            None if *env => blossom::commands::config_env(),
+            None => {}
+            Some(ConfigCommands::Get { key }) => {
+                if let Err(e) = blossom::commands::config_get(&cli.root, key, json) {
+                    error!("Failed to read the setting: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            Some(ConfigCommands::Set { key, value, user }) => {
+                if let Err(e) = blossom::commands::config_set(&cli.root, key, value, *user) {
+                    error!("Failed to change the setting: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            Some(ConfigCommands::Paths) => {
+                if let Err(e) = blossom::commands::config_paths(&cli.root, json) {
+                    error!("Failed to resolve the paths: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+        },
+        Commands::ConfigDiff { list } => {
+            if let Err(e) = blossom::commands::config_diff(&cli.root, *list) {
+                error!("Failed to merge configuration files: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Verify { name, .. } => {
+            if let Err(e) = blossom::commands::verify(&cli.root, name.as_deref(), json) {
+                error!("Failed to verify packages: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Repair { name, package } => {
+            if let Err(e) = blossom::commands::repair(&cli.root, name, package.as_deref(), cli.wait)
+            {
+                error!("Failed to repair package: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Db { command } => match command {
+            DbCommands::Export => {
+                if let Err(e) = blossom::commands::db_export(&cli.root) {
+                    error!("Failed to export database: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            DbCommands::Import { file } => {
+                if let Err(e) = blossom::commands::db_import(&cli.root, file.as_deref(), cli.wait) {
+                    error!("Failed to import database: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+        },
+        Commands::Export { command } => match command {
+            ExportCommands::Deb { path, output } => {
+                if let Err(e) = blossom::commands::export_deb(path, output) {
+                    error!("Failed to export the Debian package: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            ExportCommands::Oci {
+                packages,
+                tag,
+                output,
+            } => {
+                if let Err(e) =
+                    blossom::commands::export_oci(&cli.root, packages, tag, output, cli.wait).await
+                {
+                    error!("Failed to export the OCI image: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            ExportCommands::Pacman { path, output } => {
+                if let Err(e) = blossom::commands::export_pacman(path, output) {
+                    error!("Failed to export the pacman package: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            ExportCommands::Rpm { path, output } => {
+                if let Err(e) = blossom::commands::export_rpm(path, output) {
+                    error!("Failed to export the rpm package: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            ExportCommands::Sysext {
+                paths,
+                name,
+                confext,
+                raw,
+                output,
+            } => {
+                if let Err(e) =
+                    blossom::commands::export_sysext(paths, name.as_deref(), *confext, *raw, output)
+                {
+                    error!("Failed to export the extension image: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+        },
+        Commands::Import { command } => match command {
+            ImportCommands::Pkgbuild { path } => {
+                if let Err(e) = blossom::commands::import_pkgbuild(path) {
+                    error!("Failed to import the PKGBUILD: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            ImportCommands::Apkbuild { path } => {
+                if let Err(e) = blossom::commands::import_apkbuild(path) {
+                    error!("Failed to import the APKBUILD: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            ImportCommands::Spec { path } => {
+                if let Err(e) = blossom::commands::import_spec(path) {
+                    error!("Failed to import the spec file: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            ImportCommands::Formula { path } => {
+                if let Err(e) = blossom::commands::import_formula(path) {
+                    error!("Failed to import the Homebrew formula: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+        },
+        Commands::New {
+            name,
+            template,
+            from_crate,
+            from_pypi,
+            from_github,
+        } => {
+            let result = match (name, from_crate, from_pypi, from_github) {
+                (Some(name), _, _, _) => blossom::commands::new(name, *template),
+                (_, Some(name), _, _) => blossom::commands::new_from_crate(name).await,
+                (_, _, Some(name), _) => blossom::commands::new_from_pypi(name).await,
+                (_, _, _, Some(repository)) => blossom::commands::new_from_github(repository).await,
+                (None, None, None, None) => unreachable!("clap requires a source"),
+            };
+
+            if let Err(e) = result {
+                error!("Failed to generate the recipe: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Repo { command } => match command {
+            RepoCommands::Add {
+                dir,
+                packages,
+                sign,
+            } => {
+                if let Err(e) =
+                    blossom::commands::repo_add(&cli.root, dir, packages, sign.as_deref())
+                {
+                    error!("Failed to add packages to the repository: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            RepoCommands::Keygen { path } => {
+                if let Err(e) = blossom::commands::repo_keygen(path) {
+                    error!("Failed to generate a signing key: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            RepoCommands::Refresh { dir, sign } => {
+                if let Err(e) = blossom::commands::repo_refresh(&cli.root, dir, sign) {
+                    error!("Failed to refresh the repository metadata: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            RepoCommands::Check { dir } => {
+                if let Err(e) = blossom::commands::repo_check(dir) {
+                    error!("Failed to check the repository: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+        },
+        Commands::Mirrors { command } => match command {
+            MirrorsCommands::Rank { repository } => {
+                if let Err(e) =
+                    blossom::commands::mirrors_rank(&cli.root, repository.as_deref()).await
+                {
+                    error!("Failed to rank mirrors: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            MirrorsCommands::List => {
+                if let Err(e) = blossom::commands::mirrors_list(&cli.root, json) {
+                    error!("Failed to list mirrors: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+        },
+        Commands::Key { command } => match command {
+            KeyCommands::Generate { name } => {
+                if let Err(e) = blossom::commands::key_generate(&cli.root, name) {
+                    error!("Failed to generate a key: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            KeyCommands::Import { name, key } => {
+                if let Err(e) = blossom::commands::key_import(&cli.root, name, key) {
+                    error!("Failed to import the key: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            KeyCommands::Export { name } => {
+                if let Err(e) = blossom::commands::key_export(&cli.root, name) {
+                    error!("Failed to export the key: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            KeyCommands::List => {
+                if let Err(e) = blossom::commands::key_list(&cli.root, json) {
+                    error!("Failed to list keys: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            KeyCommands::Revoke { name } => {
+                if let Err(e) = blossom::commands::key_revoke(&cli.root, name) {
+                    error!("Failed to revoke the key: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+            KeyCommands::Trust {
+                name,
+                repository,
+                remove,
+            } => {
+                if let Err(e) = blossom::commands::key_trust(&cli.root, name, repository, *remove) {
+                    error!("Failed to update the trust of the key: {:?}", e);
+                    failure::exit(&e);
+                }
+            }
+        },
+        Commands::History => {
+            if let Err(e) = blossom::commands::history(&cli.root, json) {
+                error!("Failed to read transaction history: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Undo { id } => {
+            if let Err(e) = blossom::commands::undo(&cli.root, *id, cli.wait) {
+                error!("Failed to undo transaction: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::List {
+            pattern,
+            explicit,
+            orphans,
+            upgradable,
+            size,
+        } => {
+            let filter = blossom::commands::ListFilter {
+                pattern: pattern.clone(),
+                explicit: *explicit,
+                orphans: *orphans,
+                upgradable: *upgradable,
+                size: *size,
+            };
+
+            if let Err(e) = blossom::commands::list(&cli.root, &filter, json) {
+                error!("Failed to list packages: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Files { name, remote } => {
+            if let Err(e) = blossom::commands::files(&cli.root, name, *remote, json) {
+                error!("Failed to list package files: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Outdated => {
+            if let Err(e) = blossom::commands::outdated(&cli.root, json) {
+                error!("Failed to check for outdated packages: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Search { terms, tags } => {
+            if let Err(e) = blossom::commands::search(&cli.root, terms, tags, json) {
+                error!("Failed to search repositories: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Serve { dir, listen } => {
+            if let Err(e) = blossom::commands::serve(dir, *listen).await {
+                error!("Failed to serve the repository: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Daemon { socket } => {
+            if let Err(e) = blossom::commands::daemon(&cli.root, socket.as_deref()).await {
+                error!("Failed to run the daemon: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Why { name } => {
+            if let Err(e) = blossom::commands::why(&cli.root, name, json) {
+                error!("Failed to explain why the package is installed: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Publish {
+            package,
+            repo,
+            sign,
+        } => {
+            if let Err(e) =
+                blossom::commands::publish(&cli.root, package, repo, sign.as_deref()).await
+            {
+                error!("Failed to publish the package: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Sync => {
+            if let Err(e) = blossom::commands::sync(&cli.root, cli.wait).await {
+                error!("Failed to sync repositories: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Upgrade { solver } => {
+            if let Err(e) = blossom::commands::upgrade(&cli.root, *solver, cli.wait, json).await {
+                error!("Failed to upgrade packages: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Rdepends { name, repo } => {
+            if let Err(e) = blossom::commands::rdepends(&cli.root, name, *repo, json) {
+                error!("Failed to query reverse dependencies: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Tree { name, depth, dot } => {
+            if let Err(e) = blossom::commands::tree(&cli.root, name, *depth, *dot, json) {
+                error!("Failed to print dependency tree: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Rollback { id, list } => {
+            if let Err(e) = blossom::commands::rollback(&cli.root, *id, *list, cli.wait) {
+                error!("Failed to roll back: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Audit {
+            recipe,
+            database,
+            ecosystem,
+        } => {
+            if let Err(e) = blossom::commands::audit(
+                &cli.root,
+                recipe.as_deref(),
+                database.as_deref(),
+                ecosystem.as_deref(),
+                json,
+            )
+            .await
+            {
+                error!("Failed to audit packages: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Sbom { package, format } => {
+            if let Err(e) = blossom::commands::sbom(&cli.root, package, *format) {
+                error!("Failed to export the bill of materials: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Completions { shell } => {
+            if let Err(e) = blossom::commands::completions(&Cli::command(), *shell) {
+                error!("Failed to generate completions: {:?}", e);
+                failure::exit(&e);
+            }
+        }
+        Commands::Schema => {
+            if let Err(e) = blossom::commands::schema() {
+                error!("Failed to generate the schema: {:?}", e);
+                failure::exit(&e);
             }
         }
     }
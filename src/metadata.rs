@@ -0,0 +1,603 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_with::{DisplayFromStr, serde_as};
+
+use crate::{
+    config::RepositoryConfig,
+    download::{download, join_url},
+    error::{Result, bail, format_err},
+    repository::RepoIndex,
+    signing::{PublicKey, SecretKey, Signature},
+};
+
+/// Metadata closer than this to expiring is renewed when signing.
+const RENEW_BEFORE: Duration = Duration::days(7);
+
+/// The roles signing repository metadata, after The Update Framework. The
+/// root role names the keys of every role, targets vouches for the index,
+/// snapshot for the targets version and timestamp, re-signed most often, for
+/// the snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Root,
+    Targets,
+    Snapshot,
+    Timestamp,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Root, Role::Targets, Role::Snapshot, Role::Timestamp];
+
+    /// How long metadata of the role stays valid once signed.
+    pub fn lifetime(self) -> Duration {
+        match self {
+            Role::Root => Duration::days(365),
+            Role::Targets => Duration::days(90),
+            Role::Snapshot => Duration::days(30),
+            Role::Timestamp => Duration::days(1),
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Role::Root => "root",
+            Role::Targets => "targets",
+            Role::Snapshot => "snapshot",
+            Role::Timestamp => "timestamp",
+        })
+    }
+}
+
+/// The keys allowed to sign for a role, and how many of them must.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleKeys {
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub keys: Vec<PublicKey>,
+    pub threshold: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Root {
+    pub version: u64,
+    pub expires: DateTime<Utc>,
+    pub roles: BTreeMap<Role, RoleKeys>,
+}
+
+impl Root {
+    pub fn keys(&self, role: Role) -> Result<&RoleKeys> {
+        self.roles.get(&role).ok_or(format_err!(
+            "Root metadata names no keys for the {role} role"
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Targets {
+    pub version: u64,
+    pub expires: DateTime<Utc>,
+    pub index: IndexTarget,
+}
+
+/// The index the targets role vouches for, which in turn vouches for every
+/// archive through its checksums.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexTarget {
+    pub generation: u64,
+    /// See [`RepoIndex::digest`].
+    pub digest: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u64,
+    pub expires: DateTime<Utc>,
+    /// Version of the targets metadata published along with this snapshot.
+    pub targets: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timestamp {
+    pub version: u64,
+    pub expires: DateTime<Utc>,
+    pub snapshot: FileMeta,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMeta {
+    pub version: u64,
+    pub digest: String,
+    pub size: u64,
+}
+
+impl FileMeta {
+    fn of(version: u64, contents: &[u8]) -> Self {
+        Self {
+            version,
+            digest: format!("blake3:{}", blake3::hash(contents).to_hex()),
+            size: contents.len() as u64,
+        }
+    }
+}
+
+pub trait Metadata: Serialize + DeserializeOwned {
+    const ROLE: Role;
+
+    fn version(&self) -> u64;
+    fn expires(&self) -> DateTime<Utc>;
+}
+
+impl Metadata for Root {
+    const ROLE: Role = Role::Root;
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+}
+
+impl Metadata for Targets {
+    const ROLE: Role = Role::Targets;
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+}
+
+impl Metadata for Snapshot {
+    const ROLE: Role = Role::Snapshot;
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+}
+
+impl Metadata for Timestamp {
+    const ROLE: Role = Role::Timestamp;
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn expires(&self) -> DateTime<Utc> {
+        self.expires
+    }
+}
+
+/// Metadata along with signatures over its JSON serialization.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Metadata")]
+pub struct Signed<T> {
+    pub signed: T,
+    pub signatures: Vec<Signature>,
+}
+
+impl<T: Metadata> Signed<T> {
+    pub fn sign(signed: T, key: &SecretKey) -> Result<Self> {
+        let signature = key.sign(&serde_json::to_vec(&signed)?);
+
+        Ok(Self {
+            signed,
+            signatures: vec![signature],
+        })
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    fn parse(contents: &[u8]) -> Result<Self> {
+        serde_json::from_slice(contents)
+            .map_err(|e| format_err!("Invalid {} metadata: {e}", T::ROLE))
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Checks that at least the threshold of the keys signed the metadata.
+    pub fn verify(&self, keys: &RoleKeys) -> Result<()> {
+        let data = serde_json::to_vec(&self.signed)?;
+        let mut valid: Vec<&str> = Vec::new();
+
+        for signature in &self.signatures {
+            if valid.contains(&signature.key.as_str()) {
+                continue;
+            }
+
+            let key = keys.keys.iter().find(|key| key.id() == signature.key);
+            if key.is_some_and(|key| key.verify(&data, signature).is_ok()) {
+                valid.push(&signature.key);
+            }
+        }
+
+        let threshold = keys.threshold.max(1);
+        if valid.len() < threshold {
+            bail!(
+                "The {} metadata has {} of the {threshold} valid signatures it needs",
+                T::ROLE,
+                valid.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Rejects expired metadata, which a mirror may be replaying to freeze
+    /// clients, and metadata older than the trusted copy, which a mirror may be
+    /// serving to roll them back.
+    fn check_fresh(&self, trusted: Option<&Self>, now: DateTime<Utc>) -> Result<()> {
+        if self.signed.expires() < now {
+            bail!(
+                "The {} metadata expired at {}, the repository may be frozen",
+                T::ROLE,
+                self.signed.expires()
+            );
+        }
+
+        let trusted = trusted.map_or(0, |trusted| trusted.signed.version());
+        if self.signed.version() < trusted {
+            bail!(
+                "The {} metadata is at version {}, older than the trusted version {trusted}, the \
+                 repository may be rolled back",
+                T::ROLE,
+                self.signed.version()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// The metadata file of a role, published next to the index of `name`.
+pub fn metadata_file(name: &str, role: Role) -> String {
+    format!("{name}.{role}.json")
+}
+
+/// Signs the metadata of every role for the index of repository `name` in
+/// `dir` with `key`. The root metadata is created on first use, naming `key`
+/// for every role, and renewed when close to expiring. Returns the written
+/// files in the order to publish them in, the timestamp last.
+pub fn write(dir: &Path, name: &str, index: &RepoIndex, key: &SecretKey) -> Result<Vec<PathBuf>> {
+    let now = Utc::now();
+    let path = |role| dir.join(metadata_file(name, role));
+    let mut written = Vec::new();
+
+    let previous = match path(Role::Root) {
+        path if path.exists() => Some(Signed::<Root>::read(path)?),
+        _ => None,
+    };
+
+    let root = match previous {
+        Some(root) if root.signed.expires - now > RENEW_BEFORE => root,
+        previous => {
+            let signed = Root {
+                version: previous.as_ref().map_or(1, |root| root.signed.version + 1),
+                expires: now + Role::Root.lifetime(),
+                roles: match previous {
+                    Some(root) => root.signed.roles,
+                    None => Role::ALL
+                        .into_iter()
+                        .map(|role| {
+                            let keys = RoleKeys {
+                                keys: vec![key.public()],
+                                threshold: 1,
+                            };
+                            (role, keys)
+                        })
+                        .collect(),
+                },
+            };
+
+            authorize(&signed, Role::Root, key)?;
+
+            let root = Signed::sign(signed, key)?;
+            root.write(path(Role::Root))?;
+            written.push(path(Role::Root));
+
+            root
+        }
+    };
+
+    for role in [Role::Targets, Role::Snapshot, Role::Timestamp] {
+        authorize(&root.signed, role, key)?;
+    }
+
+    let targets = Targets {
+        version: index.generation,
+        expires: now + Role::Targets.lifetime(),
+        index: IndexTarget {
+            generation: index.generation,
+            digest: index.digest()?,
+        },
+    };
+    Signed::sign(targets, key)?.write(path(Role::Targets))?;
+
+    let snapshot = Snapshot {
+        version: index.generation,
+        expires: now + Role::Snapshot.lifetime(),
+        targets: index.generation,
+    };
+    Signed::sign(snapshot, key)?.write(path(Role::Snapshot))?;
+
+    let timestamp = Timestamp {
+        // Timestamps are re-signed without the index changing.
+        version: now.timestamp() as u64,
+        expires: now + Role::Timestamp.lifetime(),
+        snapshot: FileMeta::of(index.generation, &fs::read(path(Role::Snapshot))?),
+    };
+    Signed::sign(timestamp, key)?.write(path(Role::Timestamp))?;
+
+    written.extend([Role::Targets, Role::Snapshot, Role::Timestamp].map(path));
+
+    Ok(written)
+}
+
+fn authorize(root: &Root, role: Role, key: &SecretKey) -> Result<()> {
+    let keys = root.keys(role)?;
+
+    if !keys.keys.contains(&key.public()) {
+        bail!(
+            "Key {} isn't allowed to sign for the {role} role by the root metadata",
+            key.public().id()
+        );
+    }
+
+    if keys.threshold > 1 {
+        bail!(
+            "The {role} role needs {} signatures, but metadata is signed with a single key",
+            keys.threshold
+        );
+    }
+
+    Ok(())
+}
+
+/// Metadata of a repository verified against the previously trusted copies,
+/// vouching for a single index.
+pub struct Verified {
+    root: Signed<Root>,
+    targets: Signed<Targets>,
+    snapshot: Signed<Snapshot>,
+    timestamp: Signed<Timestamp>,
+}
+
+impl Verified {
+    /// Checks that `index` is the one the metadata vouches for.
+    pub fn check(&self, index: &RepoIndex) -> Result<()> {
+        let expected = &self.targets.signed.index;
+
+        if index.generation != expected.generation {
+            bail!(
+                "The index is at generation {}, but its metadata vouches for generation {}, a \
+                 mirror may be mixing files of different publications",
+                index.generation,
+                expected.generation
+            );
+        }
+
+        if index.digest()? != expected.digest {
+            bail!("The index doesn't match the digest its metadata vouches for");
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the metadata as the trusted copies to check the next sync against.
+    pub fn save(&self, dir: &Path, name: &str) -> Result<()> {
+        self.root.write(dir.join(metadata_file(name, Role::Root)))?;
+        self.targets
+            .write(dir.join(metadata_file(name, Role::Targets)))?;
+        self.snapshot
+            .write(dir.join(metadata_file(name, Role::Snapshot)))?;
+        self.timestamp
+            .write(dir.join(metadata_file(name, Role::Timestamp)))?;
+
+        Ok(())
+    }
+}
+
+/// Fetches and verifies the metadata of a repository from `url`, against the
+/// trusted copies saved in `dir` by the last sync. The root metadata must be
+/// signed by one of the repository's keys, and by the previous root's keys
+/// once it is trusted. Returns `None` for repositories that never published
+/// metadata.
+pub async fn fetch(
+    client: &Client,
+    repository: &RepositoryConfig,
+    url: &str,
+    dir: &Path,
+) -> Result<Option<Verified>> {
+    let name = &repository.name;
+    let now = Utc::now();
+    let trusted = Trusted::load(dir, name)?;
+
+    let root = match fetch_file(client, url, dir, &metadata_file(name, Role::Root)).await {
+        Ok(contents) => Signed::<Root>::parse(&contents)?,
+        Err(_) if trusted.root.is_none() => return Ok(None),
+        Err(e) => bail!("{name} published metadata before, but it can't be fetched: {e}"),
+    };
+
+    root.verify(&RoleKeys {
+        keys: repository.keys.clone(),
+        threshold: 1,
+    })?;
+    if let Some(trusted) = &trusted.root {
+        root.verify(trusted.signed.keys(Role::Root)?)?;
+    }
+    root.verify(root.signed.keys(Role::Root)?)?;
+    root.check_fresh(trusted.root.as_ref(), now)?;
+
+    let contents = fetch_file(client, url, dir, &metadata_file(name, Role::Timestamp)).await?;
+    let timestamp = Signed::<Timestamp>::parse(&contents)?;
+    timestamp.verify(root.signed.keys(Role::Timestamp)?)?;
+    timestamp.check_fresh(trusted.timestamp.as_ref(), now)?;
+
+    let contents = fetch_file(client, url, dir, &metadata_file(name, Role::Snapshot)).await?;
+    let expected = &timestamp.signed.snapshot;
+    if FileMeta::of(expected.version, &contents) != *expected {
+        bail!("The snapshot metadata doesn't match the one its timestamp vouches for");
+    }
+    let snapshot = Signed::<Snapshot>::parse(&contents)?;
+    snapshot.verify(root.signed.keys(Role::Snapshot)?)?;
+    snapshot.check_fresh(trusted.snapshot.as_ref(), now)?;
+    if snapshot.signed.version != expected.version {
+        bail!(
+            "The snapshot metadata is at version {}, but its timestamp vouches for version {}",
+            snapshot.signed.version,
+            expected.version
+        );
+    }
+
+    let contents = fetch_file(client, url, dir, &metadata_file(name, Role::Targets)).await?;
+    let targets = Signed::<Targets>::parse(&contents)?;
+    targets.verify(root.signed.keys(Role::Targets)?)?;
+    targets.check_fresh(trusted.targets.as_ref(), now)?;
+    if targets.signed.version != snapshot.signed.targets {
+        bail!(
+            "The targets metadata is at version {}, but its snapshot vouches for version {}",
+            targets.signed.version,
+            snapshot.signed.targets
+        );
+    }
+
+    Ok(Some(Verified {
+        root,
+        targets,
+        snapshot,
+        timestamp,
+    }))
+}
+
+struct Trusted {
+    root: Option<Signed<Root>>,
+    targets: Option<Signed<Targets>>,
+    snapshot: Option<Signed<Snapshot>>,
+    timestamp: Option<Signed<Timestamp>>,
+}
+
+impl Trusted {
+    fn load(dir: &Path, name: &str) -> Result<Self> {
+        fn read<T: Metadata>(dir: &Path, name: &str) -> Result<Option<Signed<T>>> {
+            let path = dir.join(metadata_file(name, T::ROLE));
+
+            path.exists().then(|| Signed::read(path)).transpose()
+        }
+
+        Ok(Self {
+            root: read(dir, name)?,
+            targets: read(dir, name)?,
+            snapshot: read(dir, name)?,
+            timestamp: read(dir, name)?,
+        })
+    }
+}
+
+async fn fetch_file(client: &Client, url: &str, dir: &Path, file: &str) -> Result<Vec<u8>> {
+    let path = dir.join(format!("{file}.new"));
+
+    download(client, &join_url(url, file), &path).await?;
+    let contents = fs::read(&path);
+    fs::remove_file(&path)?;
+
+    Ok(contents?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(key: &SecretKey, threshold: usize) -> Root {
+        Root {
+            version: 1,
+            expires: Utc::now() + Role::Root.lifetime(),
+            roles: Role::ALL
+                .into_iter()
+                .map(|role| {
+                    let keys = RoleKeys {
+                        keys: vec![key.public()],
+                        threshold,
+                    };
+                    (role, keys)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_verify_and_check_fresh() {
+        let key = SecretKey::generate().unwrap();
+        let other = SecretKey::generate().unwrap();
+        let root = root(&key, 1);
+
+        let signed = Signed::sign(root.clone(), &key).unwrap();
+        signed.verify(root.keys(Role::Root).unwrap()).unwrap();
+        assert!(
+            Signed::sign(root.clone(), &other)
+                .unwrap()
+                .verify(root.keys(Role::Root).unwrap())
+                .is_err()
+        );
+
+        let mut tampered = signed.clone();
+        tampered.signed.version = 2;
+        assert!(tampered.verify(root.keys(Role::Root).unwrap()).is_err());
+
+        // Repeating a signature doesn't count towards the threshold.
+        let mut repeated = signed.clone();
+        repeated.signatures.push(signed.signatures[0].clone());
+        assert!(
+            repeated
+                .verify(&RoleKeys {
+                    keys: vec![key.public()],
+                    threshold: 2,
+                })
+                .is_err()
+        );
+
+        let newer = Signed::sign(
+            Root {
+                version: 2,
+                ..root.clone()
+            },
+            &key,
+        )
+        .unwrap();
+        newer.check_fresh(Some(&signed), Utc::now()).unwrap();
+        assert!(signed.check_fresh(Some(&newer), Utc::now()).is_err());
+
+        let expired = Signed::sign(
+            Root {
+                expires: Utc::now() - Duration::days(1),
+                ..root
+            },
+            &key,
+        )
+        .unwrap();
+        assert!(expired.check_fresh(None, Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::RepositoryConfig,
+    download::{join_url, local_path},
+    error::Result,
+    paths::Paths,
+    repository::INDEX_EXTENSION,
+};
+
+pub(crate) const RANKING_FILE: &str = "mirrors.toml";
+
+/// How fast a mirror served the repository index when last ranked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorStats {
+    pub url: String,
+    /// Time until the response headers arrived.
+    pub latency_ms: u64,
+    /// Bytes per second over the whole index download.
+    pub throughput: u64,
+    /// Why the mirror couldn't be measured, if it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The persisted mirror ranking of each repository, best mirror first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ranking {
+    #[serde(default)]
+    pub ranked: DateTime<Utc>,
+    #[serde(default)]
+    pub repositories: BTreeMap<String, Vec<MirrorStats>>,
+}
+
+impl Ranking {
+    pub fn load<R: AsRef<Path>>(root: R) -> Result<Self> {
+        let path = Paths::new(root).state.join(RANKING_FILE);
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(toml_edit::de::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save<R: AsRef<Path>>(&self, root: R) -> Result<()> {
+        let dir = Paths::new(root).state;
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(RANKING_FILE), toml_edit::ser::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// Every URL of a repository in the order to try them in: ranked mirrors
+    /// first, then the unranked ones in configuration order. Mirrors removed
+    /// from the configuration since ranking are dropped.
+    pub fn urls(&self, repository: &RepositoryConfig) -> Vec<String> {
+        let configured = repository.urls();
+
+        let mut urls: Vec<String> = self
+            .repositories
+            .get(&repository.name)
+            .into_iter()
+            .flatten()
+            .filter(|stats| configured.contains(&stats.url))
+            .map(|stats| stats.url.clone())
+            .collect();
+
+        for url in configured {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+
+        urls
+    }
+}
+
+/// Measures every URL of a repository by fetching its index, sorted fastest
+/// first with failed mirrors last.
+pub async fn rank(client: &Client, repository: &RepositoryConfig) -> Vec<MirrorStats> {
+    let file = format!("{}.{INDEX_EXTENSION}", repository.name);
+    let mut ranking = Vec::new();
+
+    for url in repository.urls() {
+        let stats = match measure(client, &join_url(&url, &file)).await {
+            Ok((latency, size, total)) => MirrorStats {
+                url,
+                latency_ms: latency.as_millis() as u64,
+                throughput: (size as f64 / total.as_secs_f64().max(0.001)) as u64,
+                error: None,
+            },
+            Err(e) => MirrorStats {
+                url,
+                latency_ms: 0,
+                throughput: 0,
+                error: Some(e.to_string()),
+            },
+        };
+
+        ranking.push(stats);
+    }
+
+    // Rank on the estimated time to fetch a MiB, so that neither a close but
+    // slow mirror nor a fast but distant one wins outright.
+    ranking.sort_by_key(|stats| {
+        (
+            stats.error.is_some(),
+            stats.latency_ms + 1000 * 1024 * 1024 / stats.throughput.max(1),
+        )
+    });
+
+    ranking
+}
+
+/// Returns the latency, size and total duration of fetching `url`.
+async fn measure(client: &Client, url: &str) -> Result<(Duration, usize, Duration)> {
+    let start = Instant::now();
+
+    if let Some(path) = local_path(url)? {
+        let size = fs::read(path)?.len();
+        return Ok((Duration::ZERO, size, start.elapsed()));
+    }
+
+    let response = client.get(url).send().await?.error_for_status()?;
+    let latency = start.elapsed();
+    let size = response.bytes().await?.len();
+
+    Ok((latency, size, start.elapsed()))
+}
@@ -0,0 +1,445 @@
+use std::{cmp::Ordering, fs, path::Path};
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    error::{Result, format_err},
+    version,
+};
+
+pub const OSV_API: &str = "https://api.osv.dev/v1";
+
+/// An advisory in the OSV schema, as served by the OSV API and found in its
+/// database dumps. Only what auditing needs is read.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Vulnerability {
+    pub id: String,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub affected: Vec<Affected>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Affected {
+    pub package: Option<AffectedPackage>,
+    #[serde(default)]
+    pub ranges: Vec<AffectedRange>,
+    /// Individually listed affected versions.
+    #[serde(default)]
+    pub versions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AffectedPackage {
+    pub name: String,
+    #[serde(default)]
+    pub ecosystem: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AffectedRange {
+    #[serde(rename = "type")]
+    pub kind: RangeKind,
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RangeKind {
+    Semver,
+    Ecosystem,
+    Git,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Event {
+    pub introduced: Option<String>,
+    pub fixed: Option<String>,
+    pub last_affected: Option<String>,
+    pub limit: Option<String>,
+}
+
+impl Event {
+    fn version(&self) -> &str {
+        self.introduced
+            .as_deref()
+            .or(self.fixed.as_deref())
+            .or(self.last_affected.as_deref())
+            .or(self.limit.as_deref())
+            .unwrap_or("0")
+    }
+}
+
+/// What to look up advisories for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    Package {
+        name: String,
+        version: String,
+        /// Restricts matches to an OSV ecosystem, e.g. `Debian` or
+        /// `Debian:12`. Advisories of any ecosystem match otherwise.
+        ecosystem: Option<String>,
+    },
+    /// A commit of a git source.
+    Commit(String),
+}
+
+impl Query {
+    fn to_json(&self) -> Value {
+        match self {
+            Self::Package {
+                name,
+                version,
+                ecosystem: Some(ecosystem),
+            } => json!({ "package": { "name": name, "ecosystem": ecosystem }, "version": version }),
+            Self::Package { name, version, .. } => {
+                json!({ "package": { "name": name }, "version": version })
+            }
+            Self::Commit(commit) => json!({ "commit": commit }),
+        }
+    }
+}
+
+/// An advisory found to affect something audited.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub id: String,
+    pub aliases: Vec<String>,
+    pub summary: String,
+    /// The affected version ranges, e.g. `>=1.0, <1.2`.
+    pub affected: Vec<String>,
+    pub fixed: Vec<String>,
+}
+
+impl Vulnerability {
+    /// How this advisory affects a query, evaluating version ranges locally.
+    /// Commits can't be evaluated without the history of the repository, so
+    /// they never match.
+    pub fn affects(&self, query: &Query) -> Option<Finding> {
+        let Query::Package {
+            name,
+            version,
+            ecosystem,
+        } = query
+        else {
+            return None;
+        };
+
+        let entries: Vec<&Affected> = self
+            .entries(name, ecosystem.as_deref())
+            .filter(|affected| affected.affects(version))
+            .collect();
+
+        (!entries.is_empty()).then(|| self.finding(&entries))
+    }
+
+    /// How this advisory affects a query the OSV API already matched it to.
+    pub fn describe(&self, query: &Query) -> Finding {
+        let entries: Vec<&Affected> = match query {
+            Query::Package {
+                name, ecosystem, ..
+            } => self.entries(name, ecosystem.as_deref()).collect(),
+            Query::Commit(_) => self
+                .affected
+                .iter()
+                .filter(|affected| {
+                    affected
+                        .ranges
+                        .iter()
+                        .any(|range| range.kind == RangeKind::Git)
+                })
+                .collect(),
+        };
+
+        self.finding(&entries)
+    }
+
+    fn entries<'a>(
+        &'a self,
+        name: &'a str,
+        ecosystem: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a Affected> {
+        self.affected.iter().filter(move |affected| {
+            affected.package.as_ref().is_some_and(|package| {
+                package.name == name
+                    && ecosystem.is_none_or(|ecosystem| {
+                        package.ecosystem == ecosystem
+                            || package.ecosystem.split(':').next() == Some(ecosystem)
+                            || ecosystem.split(':').next() == Some(package.ecosystem.as_str())
+                    })
+            })
+        })
+    }
+
+    fn finding(&self, entries: &[&Affected]) -> Finding {
+        let mut affected = Vec::new();
+        let mut fixed = Vec::new();
+
+        for entry in entries {
+            for range in &entry.ranges {
+                affected.push(range.describe());
+                fixed.extend(range.events.iter().filter_map(|event| event.fixed.clone()));
+            }
+
+            if entry.ranges.is_empty() && !entry.versions.is_empty() {
+                affected.push(entry.versions.join(", "));
+            }
+        }
+
+        affected.dedup();
+        fixed.sort_by(|a, b| version::compare(a, b));
+        fixed.dedup();
+
+        Finding {
+            id: self.id.clone(),
+            aliases: self.aliases.clone(),
+            summary: self.summary.clone(),
+            affected,
+            fixed,
+        }
+    }
+}
+
+impl Affected {
+    fn affects(&self, version: &str) -> bool {
+        self.versions.iter().any(|affected| affected == version)
+            || self.ranges.iter().any(|range| range.affects(version))
+    }
+}
+
+impl AffectedRange {
+    /// Evaluates the events in version order, as the OSV schema describes.
+    /// Git ranges need the commit graph and never match.
+    fn affects(&self, version: &str) -> bool {
+        if self.kind == RangeKind::Git {
+            return false;
+        }
+
+        let mut events: Vec<&Event> = self.events.iter().collect();
+        events.sort_by(|a, b| compare_events(a.version(), b.version()));
+
+        let mut affected = false;
+
+        for event in events {
+            if let Some(introduced) = &event.introduced {
+                if compare_events(version, introduced) != Ordering::Less {
+                    affected = true;
+                }
+            } else if let Some(fixed) = &event.fixed {
+                if version::compare(version, fixed) != Ordering::Less {
+                    affected = false;
+                }
+            } else if event.last_affected.as_deref().is_some_and(|last_affected| {
+                version::compare(version, last_affected) == Ordering::Greater
+            }) {
+                affected = false;
+            }
+        }
+
+        affected
+    }
+
+    fn describe(&self) -> String {
+        let mut bounds = Vec::new();
+
+        for event in &self.events {
+            match event {
+                Event {
+                    introduced: Some(introduced),
+                    ..
+                } if introduced != "0" => bounds.push(format!(">={introduced}")),
+                Event {
+                    fixed: Some(fixed), ..
+                } => bounds.push(format!("<{fixed}")),
+                Event {
+                    last_affected: Some(last_affected),
+                    ..
+                } => bounds.push(format!("<={last_affected}")),
+                _ => {}
+            }
+        }
+
+        match (self.kind, bounds.is_empty()) {
+            (_, true) => "all versions".to_string(),
+            (RangeKind::Git, false) => format!("commits {}", bounds.join(", ")),
+            (_, false) => bounds.join(", "),
+        }
+    }
+}
+
+/// Compares versions with `0` as the start of all versions, as it is used
+/// by `introduced`.
+fn compare_events(a: &str, b: &str) -> Ordering {
+    match (a, b) {
+        ("0", "0") => Ordering::Equal,
+        ("0", _) => Ordering::Less,
+        (_, "0") => Ordering::Greater,
+        (a, b) => version::compare(a, b),
+    }
+}
+
+/// Reads an offline OSV database: a JSON advisory, or a directory of them
+/// such as an extracted `all.zip` dump.
+pub fn load_database<P: AsRef<Path>>(path: P) -> Result<Vec<Vulnerability>> {
+    let path = path.as_ref();
+    let mut vulnerabilities = Vec::new();
+
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?.path();
+
+            if entry.is_dir()
+                || entry
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+            {
+                vulnerabilities.extend(load_database(&entry)?);
+            }
+        }
+    } else {
+        vulnerabilities.push(
+            serde_json::from_slice(&fs::read(path)?)
+                .map_err(|e| format_err!("Invalid advisory {}: {e}", path.display()))?,
+        );
+    }
+
+    Ok(vulnerabilities)
+}
+
+/// The ids of the advisories matching each query, as matched by the OSV API.
+pub async fn query_batch(client: &Client, queries: &[Query]) -> Result<Vec<Vec<String>>> {
+    #[derive(Deserialize)]
+    struct Response {
+        #[serde(default)]
+        results: Vec<Matches>,
+    }
+
+    #[derive(Deserialize)]
+    struct Matches {
+        #[serde(default)]
+        vulns: Vec<Id>,
+    }
+
+    #[derive(Deserialize)]
+    struct Id {
+        id: String,
+    }
+
+    let mut ids = Vec::new();
+
+    // The API takes at most 1000 queries per batch.
+    for queries in queries.chunks(1000) {
+        let body = json!({
+            "queries": queries.iter().map(Query::to_json).collect::<Vec<_>>(),
+        });
+
+        let response = client
+            .post(format!("{OSV_API}/querybatch"))
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let response: Response = serde_json::from_slice(&response.bytes().await?)?;
+
+        ids.extend(
+            response
+                .results
+                .into_iter()
+                .map(|matches| matches.vulns.into_iter().map(|id| id.id).collect()),
+        );
+    }
+
+    Ok(ids)
+}
+
+pub async fn fetch_vulnerability(client: &Client, id: &str) -> Result<Vulnerability> {
+    let response = client
+        .get(format!("{OSV_API}/vulns/{id}"))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(serde_json::from_slice(&response.bytes().await?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vulnerability() -> Vulnerability {
+        serde_json::from_value(json!({
+            "id": "OSV-2024-1",
+            "summary": "Heap overflow",
+            "aliases": ["CVE-2024-1"],
+            "affected": [
+                {
+                    "package": { "name": "foo", "ecosystem": "Debian:12" },
+                    "ranges": [{
+                        "type": "ECOSYSTEM",
+                        "events": [
+                            { "introduced": "1.0" },
+                            { "fixed": "1.2.1" },
+                            { "introduced": "2.0" },
+                            { "last_affected": "2.3" },
+                        ],
+                    }],
+                },
+                {
+                    "package": { "name": "foo", "ecosystem": "OSS-Fuzz" },
+                    "ranges": [{ "type": "GIT", "events": [{ "introduced": "0" }] }],
+                    "versions": ["0.9"],
+                },
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn query(version: &str, ecosystem: Option<&str>) -> Query {
+        Query::Package {
+            name: "foo".to_string(),
+            version: version.to_string(),
+            ecosystem: ecosystem.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_affects() {
+        let vulnerability = vulnerability();
+        let affects = |version, ecosystem| vulnerability.affects(&query(version, ecosystem));
+
+        assert!(affects("1.0", None).is_some());
+        assert!(affects("1.2", Some("Debian")).is_some());
+        assert!(affects("1.2.1", None).is_none());
+        assert!(affects("2.3", None).is_some());
+        assert!(affects("2.4", None).is_none());
+        assert!(affects("0.9", None).is_some());
+        assert!(affects("0.9", Some("Debian")).is_none());
+        assert!(affects("1.0", Some("Alpine")).is_none());
+
+        let finding = affects("1.1", Some("Debian:12")).unwrap();
+        assert_eq!(finding.affected, vec![">=1.0, <1.2.1, >=2.0, <=2.3"]);
+        assert_eq!(finding.fixed, vec!["1.2.1"]);
+    }
+
+    #[test]
+    fn test_commits_only_match_online() {
+        let vulnerability = vulnerability();
+        let commit = Query::Commit("0123".to_string());
+
+        assert!(vulnerability.affects(&commit).is_none());
+        assert_eq!(
+            vulnerability.describe(&commit).affected,
+            vec!["all versions"]
+        );
+    }
+}
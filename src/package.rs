@@ -1,28 +1,84 @@
 use std::{
-    collections::HashMap, env::current_dir, fmt::Display, process::Command, str::FromStr,
-    sync::LazyLock,
+    collections::{BTreeMap, HashMap},
+    env::{self, current_dir},
+    fmt, fs,
+    path::{self, Path, PathBuf},
+    sync::{Arc, LazyLock},
 };
 
-use anyhow::{Result, anyhow};
 use camino::Utf8PathBuf;
+use chrono::{DateTime, Utc};
 use regex::{Captures, Regex};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::{DisplayFromStr, serde_as};
 use spdx::Expression;
+use toml_edit::{DocumentMut, Item, TableLike};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::{
+    build::BuildContext,
+    error::{Error, Result},
+    permissions::SpecialPermission,
+    runner::{Runner, StepRunner},
+    version::{self, Constraint},
+};
+
+/// A recipe, as written in `package.toml`.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Package {
+    /// A recipe shared by a family of packages, relative to this one, whose
+    /// fields this one starts from: tables are merged, other fields replaced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub info: Info,
     pub dependencies: Option<Dependencies>,
+    /// Fetched into the build directory before the steps run.
     #[serde(default)]
     pub sources: Vec<Source>,
+    /// Run in order. `%{version}` and the variables of [`BuildContext`] are
+    /// replaced in commands and paths.
     #[serde(default)]
     pub steps: Vec<Step>,
+    /// Named paths, replaced like the variables of [`BuildContext`], whose
+    /// paths can use (`docs = "%{pkgdir}/usr/share/doc/foo"`).
     #[serde(default)]
     pub directories: HashMap<String, String>,
+    /// Configuration files kept when they were modified locally.
+    #[serde(default)]
+    #[schemars(with = "Vec<String>")]
+    pub backup: Vec<Utf8PathBuf>,
+    /// Files allowed to be setuid, setgid or to have file capabilities.
+    #[serde(default)]
+    #[schemars(with = "Vec<String>")]
+    pub special_permissions: Vec<Utf8PathBuf>,
+    /// Whether build steps may access the network. They are cut off from it
+    /// otherwise, sources being fetched before they run.
+    #[serde(default)]
+    pub network: bool,
+    /// A command printing the version of a package built from a repository
+    /// rather than a release, such as one based on `git describe`, run in
+    /// `%{srcdir}` once the sources are fetched. What it prints replaces
+    /// `info.version` in the built package, steps still get the version of
+    /// the recipe as `%{version}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pkgver: Option<String>,
+    /// The public keys of upstream that sources with a `signature` must be
+    /// signed by: armored key blocks, or paths of key files relative to the
+    /// recipe.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upstream_keys: Vec<String>,
+    /// What changed in each version: `[[changelog]]` entries, or the path of
+    /// a file of them relative to the recipe, packaged along with it.
+    #[serde(default, skip_serializing_if = "Changelog::is_empty")]
+    pub changelog: Changelog,
+    /// The `package.toml` the package was read from, for packages read with
+    /// [`Package::from_path`].
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub path: Option<PathBuf>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Dependencies {
     #[serde(default)]
     pub required: Vec<String>,
@@ -30,25 +86,315 @@ pub struct Dependencies {
     pub optional: Vec<String>,
     #[serde(default)]
     pub build: Vec<String>,
+    /// Needed only by the `check` step, which `blossom test` runs alone.
+    #[serde(default)]
+    pub check: Vec<String>,
+}
+
+impl Dependencies {
+    pub fn required_names(&self) -> impl Iterator<Item = &str> {
+        self.required
+            .iter()
+            .map(|dependency| dependency_name(dependency))
+    }
+}
+
+/// Strips the version requirement or description from a dependency, leaving
+/// only the package name.
+pub fn dependency_name(dependency: &str) -> &str {
+    dependency
+        .split(['<', '>', '=', ':'])
+        .next()
+        .unwrap_or(dependency)
+        .trim()
+}
+
+/// Parses the version requirement of a dependency, e.g. `>=1.2,<2` in
+/// `bar>=1.2,<2`.
+pub fn dependency_constraints(dependency: &str) -> Result<Vec<Constraint>> {
+    let requirement = dependency.split(':').next().unwrap_or(dependency);
+
+    version::parse_constraints(&requirement.trim()[dependency_name(dependency).len()..])
+}
+
+/// Strips the description of an optional dependency, keeping the name and
+/// version requirement.
+pub fn dependency_requirement(dependency: &str) -> &str {
+    dependency.split(':').next().unwrap_or(dependency).trim()
 }
 
 #[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Info {
     pub name: String,
     pub version: String,
-    pub description: String,
+    pub description: Description,
+    /// An SPDX license expression (`MIT OR Apache-2.0`).
     #[serde_as(as = "DisplayFromStr")]
     pub license: Expression,
+    /// Virtual packages this package stands in for, optionally versioned
+    /// (`java-runtime=17`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provides: Vec<String>,
+    /// Packages that can't be installed alongside this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<String>,
+    /// Packages this one supersedes, removed when it gets installed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaces: Vec<String>,
+    /// What the package is about (`editor`, `terminal`), for searching.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+}
+
+impl Info {
+    /// Whether this package provides `name` in a version satisfying
+    /// `constraints`. Unversioned provides only satisfy unversioned
+    /// requirements.
+    pub fn provides(&self, name: &str, constraints: &[Constraint]) -> bool {
+        self.provides.iter().any(|provided| {
+            if dependency_name(provided) != name {
+                return false;
+            }
+
+            match provided.split_once('=') {
+                Some((_, version)) => version::satisfies(version.trim(), constraints),
+                None => constraints.is_empty(),
+            }
+        })
+    }
+
+    /// Whether this package matches a reference such as `bar<2`, either by
+    /// name or through what it provides.
+    pub fn matches(&self, reference: &str) -> Result<bool> {
+        let name = dependency_name(reference);
+        let constraints = dependency_constraints(reference)?;
+
+        Ok(
+            (self.name == name && version::satisfies(&self.version, &constraints))
+                || self.provides(name, &constraints),
+        )
+    }
+}
+
+/// What a package is, either in English or in several languages keyed by
+/// locale (`description.en`, `description.de`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Description {
+    Plain(String),
+    Localized(BTreeMap<String, String>),
+}
+
+impl Description {
+    /// The description in `locale` (`de_DE`), or in its language (`de`),
+    /// falling back to English and then to any translation.
+    pub fn get(&self, locale: &str) -> &str {
+        let translations = match self {
+            Description::Plain(description) => return description,
+            Description::Localized(translations) => translations,
+        };
+        let language = locale.split(['_', '-']).next().unwrap_or(locale);
+
+        [locale, language, "en"]
+            .iter()
+            .find_map(|locale| translations.get(*locale))
+            .or_else(|| translations.values().next())
+            .map_or("", String::as_str)
+    }
+
+    /// The description in the locale of the user, see [`user_locale`].
+    pub fn localized(&self) -> &str {
+        self.get(&user_locale())
+    }
+
+    pub fn english(&self) -> &str {
+        self.get("en")
+    }
+
+    /// Every translation, the plain description being English.
+    pub fn translations(&self) -> impl Iterator<Item = (&str, &str)> {
+        let (plain, localized) = match self {
+            Description::Plain(description) => (Some(("en", description.as_str())), None),
+            Description::Localized(translations) => (None, Some(translations)),
+        };
+
+        plain.into_iter().chain(
+            localized
+                .into_iter()
+                .flatten()
+                .map(|(locale, description)| (locale.as_str(), description.as_str())),
+        )
+    }
+}
+
+impl Default for Description {
+    fn default() -> Self {
+        Description::Plain(String::new())
+    }
+}
+
+impl From<String> for Description {
+    fn from(description: String) -> Self {
+        Description::Plain(description)
+    }
+}
+
+impl From<&str> for Description {
+    fn from(description: &str) -> Self {
+        Description::Plain(description.to_string())
+    }
+}
+
+impl fmt::Display for Description {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.english())
+    }
+}
+
+/// The locale messages are shown in, from `LC_ALL`, `LC_MESSAGES` or `LANG`
+/// without its encoding (`de_DE` for `de_DE.UTF-8`). The `C` and `POSIX`
+/// locales are English.
+pub fn user_locale() -> String {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|locale| !locale.is_empty())
+        .unwrap_or_default();
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+
+    match locale {
+        "" | "C" | "POSIX" => "en".to_string(),
+        locale => locale.to_string(),
+    }
+}
+
+/// The changelog of a recipe, written in it or in a file of `[[changelog]]`
+/// entries, which is read when parsing the recipe.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Changelog {
+    Entries(Vec<ChangelogEntry>),
+    File(String),
+}
+
+impl Changelog {
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    /// The entries of the changelog, none for a file that wasn't read.
+    pub fn entries(&self) -> &[ChangelogEntry] {
+        match self {
+            Changelog::Entries(entries) => entries,
+            Changelog::File(_) => &[],
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Default for Changelog {
+    fn default() -> Self {
+        Changelog::Entries(Vec::new())
+    }
+}
+
+/// What changed in a version of a package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ChangelogEntry {
+    pub version: String,
+    /// When the version was released (`2025-03-01`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    pub changes: Vec<String>,
+}
+
+/// A changelog file, made of `[[changelog]]` entries only.
+#[derive(Deserialize)]
+struct ChangelogFile {
+    #[serde(default)]
+    changelog: Vec<ChangelogEntry>,
+}
+
+/// Metadata embedded at the root of every built package archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeachInfo {
+    pub info: Info,
+    pub dependencies: Option<Dependencies>,
+    #[serde(default)]
+    pub backup: Vec<Utf8PathBuf>,
+    pub build_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub installed_size: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub special_permissions: Vec<SpecialPermission>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changelog: Vec<ChangelogEntry>,
+}
+
+impl PeachInfo {
+    pub const FILE_NAME: &str = ".PEACHINFO";
+}
+
+impl From<&Package> for PeachInfo {
+    fn from(package: &Package) -> Self {
+        Self {
+            info: package.info.clone(),
+            dependencies: package.dependencies.clone(),
+            backup: package.backup.clone(),
+            build_date: None,
+            installed_size: 0,
+            special_permissions: Vec::new(),
+            changelog: package.changelog.entries().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Source {
+    /// An archive URL, or a git repository URL prefixed with `git+`.
     pub url: String,
+    /// Checksum of the archive, git sources don't have one.
+    #[serde(default)]
     pub checksum: String,
+    /// Branch, tag or commit to check out of a git source, `HEAD` otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// The name an archive is saved as, keeping its extension, or the
+    /// directory a git source is checked out into, rather than the last
+    /// segment of the URL. Tells apart sources with the same name
+    /// (`v1.2.3.tar.gz`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// URL of the detached upstream signature of an archive (`.asc`,
+    /// `.sig`), checked against [`Package::upstream_keys`] after the
+    /// checksum.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl Source {
+    /// The name the source is saved as, as written in the recipe.
+    pub fn file_name(&self) -> &str {
+        if let Some(filename) = &self.filename {
+            return filename;
+        }
+
+        let url = self.url.split(['?', '#']).next().unwrap_or(&self.url);
+        url.trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(url)
+            .trim_end_matches(".git")
+    }
+}
+
+/// Whether `filename` names a file of the directory it's saved in, rather
+/// than a path.
+pub fn is_valid_filename(filename: &str) -> bool {
+    !matches!(filename, "" | "." | "..") && !filename.contains('/')
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Step {
     pub name: String,
     #[serde(flatten)]
@@ -56,82 +402,142 @@ pub struct Step {
 }
 
 #[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum StepVariant {
     Command {
         #[serde_as(as = "DisplayFromStr")]
-        runner: Runner,
+        runner: StepRunner,
         command: String,
     },
     Move {
+        #[schemars(with = "String")]
         path: Utf8PathBuf,
     },
 }
 
-#[derive(Debug)]
-pub enum Runner {
-    Shell,
-}
+/// The variables replaced in the sources and steps of recipes.
+pub const VARIABLES: [&str; 6] = ["version", "pkgdir", "srcdir", "recipedir", "jobs", "target"];
 
-impl Display for Runner {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Shell => write!(f, "shell"),
-        }
+pub(crate) static VARIABLE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"%\{([^}]+)\}").expect("invalid regex"));
+
+impl Package {
+    /// Starts building a package in code rather than parsing a recipe.
+    pub fn builder() -> PackageBuilder {
+        PackageBuilder::default()
     }
-}
 
-impl FromStr for Runner {
-    // FIXME: use an actual error
-    type Err = anyhow::Error;
+    /// Serializes the package as a recipe, as it would be written in
+    /// `package.toml`.
+    pub fn to_recipe(&self) -> Result<String> {
+        Ok(toml_edit::ser::to_string_pretty(self)?)
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "shell" => Ok(Self::Shell),
-            _ => Err(anyhow!("Unknown runner")),
-        }
+    /// Parses a recipe in the current directory, built there.
+    pub fn parse(s: &str) -> Result<Self> {
+        let dir = current_dir()?;
+
+        Self::parse_in(s, &BuildContext::new(&dir, &dir))
     }
-}
 
-impl Runner {
-    pub fn into_command(&self) -> Command {
-        match self {
-            Self::Shell => {
-                let mut command = Command::new("/bin/sh");
+    /// Parses a recipe built in `context`, whose directories and settings
+    /// replace the variables of sources and steps.
+    pub fn parse_in(s: &str, context: &BuildContext) -> Result<Self> {
+        Self::parse_with(s, "the recipe", context)
+    }
 
-                command.arg("-c");
+    /// Reads the recipe at `path`, built in its directory.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path::absolute(path)?;
+        let dir = recipe_dir(&path);
 
-                command
-            }
-        }
+        Self::from_path_in(&path, &BuildContext::new(dir, dir))
     }
-}
 
-static VARIABLE_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"%\{([^}]+)\}").expect("invalid regex"));
+    /// Reads the recipe at `path`, built in `context`, whose `recipe_dir` is
+    /// usually the directory of `path`. Parse errors name the file.
+    pub fn from_path_in<P: AsRef<Path>>(path: P, context: &BuildContext) -> Result<Self> {
+        let path = path::absolute(path)?;
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| Error::Message(format!("{}: {e}", path.display())))?;
 
-impl Package {
-    pub fn parse(s: &str) -> Result<Self> {
-        let mut package: Package = toml_edit::de::from_str(s)?;
+        let mut package = Self::parse_with(&contents, &path.display().to_string(), context)?;
+        package.path = Some(path);
+
+        Ok(package)
+    }
+
+    /// The directory of the recipe, for packages read with
+    /// [`Package::from_path`].
+    pub fn dir(&self) -> Option<&Path> {
+        self.path.as_deref().map(recipe_dir)
+    }
+
+    /// Parses a recipe, `what` naming it in errors.
+    fn parse_with(s: &str, what: &str, context: &BuildContext) -> Result<Self> {
+        let document = parse_document(s, what)?;
+        let document = extend(document, &context.recipe_dir, &mut Vec::new())?;
+
+        let mut package: Package =
+            toml_edit::de::from_document(document).map_err(|e| Error::Parse {
+                what: what.to_string(),
+                message: e.to_string(),
+            })?;
+
+        if let Changelog::File(file) = &package.changelog {
+            package.changelog = Changelog::Entries(read_changelog(&context.recipe_dir.join(file))?);
+        }
 
+        let jobs = context.jobs.to_string();
         let mut variables = HashMap::new();
 
-        let pkgdir = current_dir()?.join("package");
         variables.insert("version", package.info.version.as_str());
-        variables.insert("pkgdir", pkgdir.to_str().unwrap()); // FIXME: horrible
+        variables.insert("pkgdir", utf8_dir("package", &context.pkg_dir)?);
+        variables.insert("srcdir", utf8_dir("source", &context.src_dir)?);
+        variables.insert("recipedir", utf8_dir("recipe", &context.recipe_dir)?);
+        variables.insert("jobs", jobs.as_str());
+        variables.insert("target", context.target.as_str());
+
+        let directories = package
+            .directories
+            .iter()
+            .filter(|(name, _)| !VARIABLES.contains(&name.as_str()))
+            .map(|(name, path)| Ok((name.clone(), replace_vars(path, &variables)?)))
+            .collect::<Result<Vec<_>>>()?;
+        for (name, path) in &directories {
+            variables.insert(name, path);
+        }
 
         for source in package.sources.iter_mut() {
-            source.url = replace_vars(&source.url, &variables)
+            source.url = replace_vars(&source.url, &variables)?;
+
+            if let Some(filename) = &mut source.filename {
+                *filename = replace_vars(filename, &variables)?;
+                if !is_valid_filename(filename) {
+                    return Err(Error::InvalidPackage(format!(
+                        "source {} can't be saved as \"{filename}\"",
+                        source.url
+                    )));
+                }
+            }
+
+            if let Some(signature) = &mut source.signature {
+                *signature = replace_vars(signature, &variables)?;
+            }
+        }
+
+        if let Some(pkgver) = &mut package.pkgver {
+            *pkgver = replace_vars(pkgver, &variables)?;
         }
 
         for step in package.steps.iter_mut() {
             match &mut step.variant {
                 StepVariant::Command { command, .. } => {
-                    *command = replace_vars(command.as_str(), &variables);
+                    *command = replace_vars(command.as_str(), &variables)?;
                 }
                 StepVariant::Move { path } => {
-                    *path = replace_vars(path.as_str(), &variables).into();
+                    *path = replace_vars(path.as_str(), &variables)?.into();
                 }
             }
         }
@@ -140,12 +546,414 @@ impl Package {
     }
 }
 
-fn replace_vars(haystack: &str, variables: &HashMap<&str, &str>) -> String {
-    VARIABLE_REGEX
-        .replace_all(haystack, |caps: &Captures| {
-            variables.get(&caps[1]).expect("Unknown variable") // FIXME: error handling
+fn parse_document(s: &str, what: &str) -> Result<DocumentMut> {
+    s.parse().map_err(|e: toml_edit::TomlError| Error::Parse {
+        what: what.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Merges the recipe `document` extends, relative to `dir`, into it, along
+/// with those that one extends in turn. `chain` holds the recipes extending
+/// it, which it can't extend again.
+pub fn extend(document: DocumentMut, dir: &Path, chain: &mut Vec<PathBuf>) -> Result<DocumentMut> {
+    let Some(extends) = document.get("extends") else {
+        return Ok(document);
+    };
+    let Some(extends) = extends.as_str() else {
+        return Err(Error::Parse {
+            what: "the recipe".to_string(),
+            message: "extends must be the path of a recipe".to_string(),
+        });
+    };
+
+    let path = dir.join(extends);
+    let path =
+        fs::canonicalize(&path).map_err(|e| Error::Message(format!("{}: {e}", path.display())))?;
+    if chain.contains(&path) {
+        return Err(Error::Message(format!("{} extends itself", path.display())));
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    chain.push(path.clone());
+    let mut base = extend(
+        parse_document(&contents, &path.display().to_string())?,
+        recipe_dir(&path),
+        chain,
+    )?;
+    chain.pop();
+
+    merge(base.as_table_mut(), document.as_table());
+    Ok(base)
+}
+
+/// Reads the entries of the changelog file at `path`.
+fn read_changelog(path: &Path) -> Result<Vec<ChangelogEntry>> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| Error::Message(format!("{}: {e}", path.display())))?;
+    let file: ChangelogFile = toml_edit::de::from_str(&contents).map_err(|e| Error::Parse {
+        what: path.display().to_string(),
+        message: e.to_string(),
+    })?;
+
+    Ok(file.changelog)
+}
+
+/// Merges the tables of `over` into those of `base`, replacing any other
+/// field.
+fn merge(base: &mut dyn TableLike, over: &dyn TableLike) {
+    for (key, item) in over.iter() {
+        match (
+            base.get_mut(key).and_then(Item::as_table_like_mut),
+            item.as_table_like(),
+        ) {
+            (Some(base), Some(over)) => merge(base, over),
+            _ => {
+                base.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+/// Builds a [`Package`], checking it is a valid recipe once everything is
+/// set. Variables such as `%{pkgdir}` are kept as they are, they are only
+/// replaced when parsing a recipe.
+#[derive(Debug, Default)]
+pub struct PackageBuilder {
+    name: Option<String>,
+    version: Option<String>,
+    description: Description,
+    license: Option<String>,
+    provides: Vec<String>,
+    conflicts: Vec<String>,
+    replaces: Vec<String>,
+    keywords: Vec<String>,
+    dependencies: Option<Dependencies>,
+    sources: Vec<Source>,
+    steps: Vec<Step>,
+    directories: HashMap<String, String>,
+    backup: Vec<Utf8PathBuf>,
+    special_permissions: Vec<Utf8PathBuf>,
+    network: bool,
+    pkgver: Option<String>,
+    upstream_keys: Vec<String>,
+    changelog: Vec<ChangelogEntry>,
+}
+
+impl PackageBuilder {
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<Description>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// An SPDX license expression, such as `MIT OR Apache-2.0`.
+    pub fn license(mut self, license: impl Into<String>) -> Self {
+        self.license = Some(license.into());
+        self
+    }
+
+    pub fn provides(mut self, provided: impl Into<String>) -> Self {
+        self.provides.push(provided.into());
+        self
+    }
+
+    pub fn conflicts(mut self, conflict: impl Into<String>) -> Self {
+        self.conflicts.push(conflict.into());
+        self
+    }
+
+    pub fn replaces(mut self, replaced: impl Into<String>) -> Self {
+        self.replaces.push(replaced.into());
+        self
+    }
+
+    pub fn keyword(mut self, keyword: impl Into<String>) -> Self {
+        self.keywords.push(keyword.into());
+        self
+    }
+
+    /// A required dependency, optionally versioned (`openssl>=3.0`).
+    pub fn dependency(mut self, dependency: impl Into<String>) -> Self {
+        self.dependencies_mut().required.push(dependency.into());
+        self
+    }
+
+    /// An optional dependency, optionally described (`python: scripting`).
+    pub fn optional_dependency(mut self, dependency: impl Into<String>) -> Self {
+        self.dependencies_mut().optional.push(dependency.into());
+        self
+    }
+
+    pub fn build_dependency(mut self, dependency: impl Into<String>) -> Self {
+        self.dependencies_mut().build.push(dependency.into());
+        self
+    }
+
+    /// A dependency of the `check` step only.
+    pub fn check_dependency(mut self, dependency: impl Into<String>) -> Self {
+        self.dependencies_mut().check.push(dependency.into());
+        self
+    }
+
+    fn dependencies_mut(&mut self) -> &mut Dependencies {
+        self.dependencies.get_or_insert_with(|| Dependencies {
+            required: Vec::new(),
+            optional: Vec::new(),
+            build: Vec::new(),
+            check: Vec::new(),
+        })
+    }
+
+    /// A source archive and its checksum, e.g. `blake3:<hex>`.
+    pub fn source(mut self, url: impl Into<String>, checksum: impl Into<String>) -> Self {
+        self.sources.push(Source {
+            url: url.into(),
+            checksum: checksum.into(),
+            rev: None,
+            filename: None,
+            signature: None,
+        });
+        self
+    }
+
+    /// A git repository to clone, at `rev` or else `HEAD`.
+    pub fn git_source(mut self, url: impl Into<String>, rev: Option<String>) -> Self {
+        let url = url.into();
+
+        self.sources.push(Source {
+            url: match url.starts_with("git+") {
+                true => url,
+                false => format!("git+{url}"),
+            },
+            checksum: String::new(),
+            rev,
+            filename: None,
+            signature: None,
+        });
+        self
+    }
+
+    /// A step running `command` with the shell.
+    pub fn command(mut self, name: impl Into<String>, command: impl Into<String>) -> Self {
+        self.steps.push(Step {
+            name: name.into(),
+            variant: StepVariant::Command {
+                runner: StepRunner::shell(),
+                command: command.into(),
+            },
+        });
+        self
+    }
+
+    /// A step running `command` with a runner other than the shell, see
+    /// [`runner::register`](crate::runner::register).
+    pub fn command_with(
+        mut self,
+        name: impl Into<String>,
+        runner: Arc<dyn Runner>,
+        command: impl Into<String>,
+    ) -> Self {
+        self.steps.push(Step {
+            name: name.into(),
+            variant: StepVariant::Command {
+                runner: StepRunner::new(runner),
+                command: command.into(),
+            },
+        });
+        self
+    }
+
+    /// A step changing the directory the next steps run in.
+    pub fn move_to(mut self, name: impl Into<String>, path: impl Into<Utf8PathBuf>) -> Self {
+        self.steps.push(Step {
+            name: name.into(),
+            variant: StepVariant::Move { path: path.into() },
+        });
+        self
+    }
+
+    pub fn directory(mut self, name: impl Into<String>, path: impl Into<String>) -> Self {
+        self.directories.insert(name.into(), path.into());
+        self
+    }
+
+    /// A configuration file kept when the package is upgraded or removed.
+    pub fn backup(mut self, path: impl Into<Utf8PathBuf>) -> Self {
+        self.backup.push(path.into());
+        self
+    }
+
+    /// A file allowed to be setuid, setgid or to have file capabilities.
+    pub fn special_permission(mut self, path: impl Into<Utf8PathBuf>) -> Self {
+        self.special_permissions.push(path.into());
+        self
+    }
+
+    /// Whether build steps may access the network.
+    pub fn network(mut self, network: bool) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// A command printing the version the package is built as, see
+    /// [`Package::pkgver`].
+    pub fn pkgver(mut self, command: impl Into<String>) -> Self {
+        self.pkgver = Some(command.into());
+        self
+    }
+
+    /// Trusts `key` to sign sources, see [`Package::upstream_keys`].
+    pub fn upstream_key(mut self, key: impl Into<String>) -> Self {
+        self.upstream_keys.push(key.into());
+        self
+    }
+
+    /// Records what changed in `version`, newest versions first.
+    pub fn change(mut self, version: impl Into<String>, changes: Vec<String>) -> Self {
+        self.changelog.push(ChangelogEntry {
+            version: version.into(),
+            date: None,
+            changes,
+        });
+        self
+    }
+
+    /// Checks everything set so far and builds the package. A name, a
+    /// version and a license are required.
+    pub fn build(self) -> Result<Package> {
+        let invalid = |message: String| Err(Error::InvalidPackage(message));
+
+        let Some(name) = self.name else {
+            return invalid("a name is required".to_string());
+        };
+        if !is_valid_name(&name) {
+            return invalid(format!(
+                "invalid name \"{name}\", names can't be empty or contain whitespace, '/' or any of '<>=:'"
+            ));
+        }
+
+        let Some(version) = self.version else {
+            return invalid(format!("{name} has no version"));
+        };
+        if !is_valid_name(&version) {
+            return invalid(format!("invalid version \"{version}\" for {name}"));
+        }
+
+        let Some(license) = self.license else {
+            return invalid(format!("{name} has no license"));
+        };
+        let license = Expression::parse(&license).map_err(|e| Error::Parse {
+            what: format!("the license of {name}"),
+            message: e.to_string(),
+        })?;
+
+        let references = self
+            .provides
+            .iter()
+            .chain(&self.conflicts)
+            .chain(&self.replaces);
+        let dependencies = self.dependencies.iter().flat_map(|dependencies| {
+            dependencies
+                .required
+                .iter()
+                .chain(&dependencies.optional)
+                .chain(&dependencies.build)
+        });
+
+        for reference in references.chain(dependencies) {
+            if !is_valid_name(dependency_name(reference)) {
+                return invalid(format!("invalid package reference \"{reference}\""));
+            }
+
+            dependency_constraints(reference)?;
+        }
+
+        for source in &self.sources {
+            if source.url.starts_with("git+") {
+                continue;
+            }
+
+            match source.checksum.split_once(':') {
+                Some(("blake3" | "sha256", hash)) if !hash.is_empty() => {}
+                _ if source.checksum.is_empty() => {
+                    return invalid(format!("source {} has no checksum", source.url));
+                }
+                _ => return Err(Error::InvalidChecksum(source.checksum.clone())),
+            }
+        }
+
+        Ok(Package {
+            info: Info {
+                name,
+                version,
+                description: self.description,
+                license,
+                provides: self.provides,
+                conflicts: self.conflicts,
+                replaces: self.replaces,
+                keywords: self.keywords,
+            },
+            dependencies: self.dependencies,
+            sources: self.sources,
+            steps: self.steps,
+            directories: self.directories,
+            backup: self.backup,
+            special_permissions: self.special_permissions,
+            network: self.network,
+            pkgver: self.pkgver,
+            upstream_keys: self.upstream_keys,
+            changelog: Changelog::Entries(self.changelog),
+            extends: None,
+            path: None,
         })
-        .into_owned()
+    }
+}
+
+/// The directory of the recipe at `path`, which is absolute.
+fn recipe_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or(path)
+}
+
+/// The directory `path` as recipes refer to it, which variables only can
+/// when it's UTF-8.
+fn utf8_dir<'a>(what: &str, path: &'a Path) -> Result<&'a str> {
+    path.to_str().ok_or_else(|| {
+        Error::InvalidPackage(format!(
+            "the {what} directory {} isn't valid UTF-8",
+            path.display()
+        ))
+    })
+}
+
+/// Names and versions end up in file names and dependency references.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '/' | '<' | '>' | '=' | ':'))
+}
+
+fn replace_vars(haystack: &str, variables: &HashMap<&str, &str>) -> Result<String> {
+    if let Some(caps) = VARIABLE_REGEX
+        .captures_iter(haystack)
+        .find(|caps| !variables.contains_key(&caps[1]))
+    {
+        return Err(Error::UnknownVariable(caps[1].to_string()));
+    }
+
+    Ok(VARIABLE_REGEX
+        .replace_all(haystack, |caps: &Captures| variables[&caps[1]])
+        .into_owned())
 }
 
 #[cfg(test)]
@@ -154,6 +962,13 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_dependency_name() {
+        assert_eq!(dependency_name("zlib"), "zlib");
+        assert_eq!(dependency_name("openssl>=3.0"), "openssl");
+        assert_eq!(dependency_name("python: scripting support"), "python");
+    }
+
     #[test]
     fn test_basic_replacement() {
         let mut variables = HashMap::new();
@@ -161,16 +976,18 @@ mod tests {
         variables.insert("greeting", "Hello");
 
         let haystack = "%{greeting}, %{name}!";
-        assert_eq!(replace_vars(haystack, &variables), "Hello, Mati!");
+        assert_eq!(replace_vars(haystack, &variables).unwrap(), "Hello, Mati!");
     }
 
     #[test]
-    #[should_panic]
     fn test_missing_variable() {
         let variables = HashMap::new();
         let haystack = "Hi %{name}!";
 
-        replace_vars(haystack, &variables);
+        assert!(matches!(
+            replace_vars(haystack, &variables),
+            Err(Error::UnknownVariable(name)) if name == "name"
+        ));
     }
 
     #[test]
@@ -180,17 +997,283 @@ mod tests {
 
         let haystack = "I love %{word}! %{word} is great!";
         assert_eq!(
-            replace_vars(haystack, &variables),
+            replace_vars(haystack, &variables).unwrap(),
             "I love rust! rust is great!"
         );
     }
 
     #[test]
-    #[should_panic]
     fn test_multiple_missing_variables() {
         let variables = HashMap::new();
         let haystack = "Hi %{name}, welcome to %{city}!";
 
-        replace_vars(haystack, &variables);
+        assert!(matches!(
+            replace_vars(haystack, &variables),
+            Err(Error::UnknownVariable(name)) if name == "name"
+        ));
+    }
+
+    #[test]
+    fn test_builder() {
+        let package = Package::builder()
+            .name("foo")
+            .version("1.0")
+            .description("A test package")
+            .license("MIT OR Apache-2.0")
+            .dependency("libc>=2.0")
+            .optional_dependency("python: scripting support")
+            .source("https://example.com/foo-%{version}.tar.gz", "blake3:abcd")
+            .command("build", "make DESTDIR=%{pkgdir} install")
+            .build()
+            .unwrap();
+
+        assert_eq!(package.info.name, "foo");
+        assert_eq!(package.info.license.to_string(), "MIT OR Apache-2.0");
+        assert_eq!(
+            package.dependencies.as_ref().unwrap().required,
+            ["libc>=2.0"]
+        );
+
+        let recipe = package.to_recipe().unwrap();
+        let parsed =
+            Package::parse_in(&recipe, &BuildContext::new("/recipes/foo", "/build")).unwrap();
+
+        assert_eq!(parsed.info.version, "1.0");
+        assert_eq!(parsed.sources[0].url, "https://example.com/foo-1.0.tar.gz");
+        assert!(matches!(
+            &parsed.steps[0].variant,
+            StepVariant::Command { command, .. } if command == "make DESTDIR=/build/package install"
+        ));
+    }
+
+    #[test]
+    fn test_parse_with() {
+        let recipe = Package::builder()
+            .name("foo")
+            .version("1.0")
+            .license("MIT")
+            .command("patch", "patch -d %{srcdir} < %{recipedir}/fix.patch")
+            .command("make", "make -j%{jobs} ARCH=%{target}")
+            .command("docs", "cp README %{docs}")
+            .directory("docs", "%{pkgdir}/usr/share/doc/foo")
+            .pkgver("git -C %{srcdir}/foo describe --tags")
+            .build()
+            .unwrap()
+            .to_recipe()
+            .unwrap();
+
+        let mut context = BuildContext::new("/recipes/foo", "/build");
+        context.jobs = 4;
+        context.target = "aarch64".to_string();
+
+        let package = Package::parse_with(&recipe, "foo/package.toml", &context).unwrap();
+
+        assert!(matches!(
+            &package.steps[0].variant,
+            StepVariant::Command { command, .. } if command == "patch -d /build/sources < /recipes/foo/fix.patch"
+        ));
+        assert!(matches!(
+            &package.steps[1].variant,
+            StepVariant::Command { command, .. } if command == "make -j4 ARCH=aarch64"
+        ));
+        assert!(matches!(
+            &package.steps[2].variant,
+            StepVariant::Command { command, .. } if command == "cp README /build/package/usr/share/doc/foo"
+        ));
+        assert_eq!(
+            package.pkgver.as_deref(),
+            Some("git -C /build/sources/foo describe --tags")
+        );
+        assert!(package.dir().is_none());
+
+        assert!(matches!(
+            Package::parse_with("[info", "foo/package.toml", &context),
+            Err(Error::Parse { what, .. }) if what == "foo/package.toml"
+        ));
+    }
+
+    #[test]
+    fn test_source_filename() {
+        let recipe = |filename: &str| {
+            format!(
+                r#"
+[info]
+name = "foo"
+version = "1.0"
+description = "A foo"
+license = "MIT"
+
+[[sources]]
+url = "https://github.com/foo/foo/archive/refs/tags/v%{{version}}.tar.gz"
+checksum = "blake3:ab12"
+filename = "{filename}"
+"#
+            )
+        };
+        let context = BuildContext::new("/recipes/foo", "/build");
+
+        let package = Package::parse_in(&recipe("foo-%{version}.tar.gz"), &context).unwrap();
+        assert_eq!(package.sources[0].file_name(), "foo-1.0.tar.gz");
+
+        assert!(matches!(
+            Package::parse_in(&recipe("../foo.tar.gz"), &context),
+            Err(Error::InvalidPackage(_))
+        ));
+    }
+
+    #[test]
+    fn test_non_utf8_dirs() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let recipe = r#"
+[info]
+name = "foo"
+version = "1.0"
+description = "A foo"
+license = "MIT"
+"#;
+        let dir = Path::new(OsStr::from_bytes(b"/\xff"));
+
+        for context in [
+            BuildContext::new("/recipes/foo", dir),
+            BuildContext::new(dir, "/build"),
+        ] {
+            assert!(matches!(
+                Package::parse_in(recipe, &context),
+                Err(Error::InvalidPackage(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut base: DocumentMut = r#"
+network = true
+
+[info]
+version = "0"
+license = "OFL-1.1"
+
+[[steps]]
+name = "package"
+runner = "shell"
+command = "install -Dm644 *.otf -t %{pkgdir}/usr/share/fonts/%{name}"
+
+[directories]
+name = "fonts"
+"#
+        .parse()
+        .unwrap();
+        let recipe: DocumentMut = r#"
+extends = "../templates/font.toml"
+directories = { name = "inter" }
+
+[info]
+name = "inter"
+version = "4.0"
+description = "A typeface for user interfaces"
+"#
+        .parse()
+        .unwrap();
+
+        merge(base.as_table_mut(), recipe.as_table());
+        let package: Package = toml_edit::de::from_document(base).unwrap();
+
+        assert_eq!(package.extends.as_deref(), Some("../templates/font.toml"));
+        assert_eq!(package.info.name, "inter");
+        assert_eq!(package.info.version, "4.0");
+        assert_eq!(package.info.license.to_string(), "OFL-1.1");
+        assert_eq!(package.directories["name"], "inter");
+        assert_eq!(package.steps.len(), 1);
+        assert!(package.network);
+    }
+
+    #[test]
+    fn test_localized_description() {
+        let info: Info = toml_edit::de::from_str(
+            r#"
+            name = "inter"
+            version = "4.0"
+            description.en = "A typeface for user interfaces"
+            description.de = "Eine Schriftart für Benutzeroberflächen"
+            license = "OFL-1.1"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            info.description.get("de_AT"),
+            "Eine Schriftart für Benutzeroberflächen"
+        );
+        assert_eq!(
+            info.description.get("fr_FR"),
+            "A typeface for user interfaces"
+        );
+        assert_eq!(info.description.translations().count(), 2);
+
+        let plain = Description::from("A typeface");
+        assert_eq!(plain.get("de"), "A typeface");
+        assert_eq!(
+            plain.translations().collect::<Vec<_>>(),
+            [("en", "A typeface")]
+        );
+
+        let json = serde_json::to_string(&info).unwrap();
+        let parsed: Info = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.description, info.description);
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let builder = || Package::builder().name("foo").version("1.0").license("MIT");
+
+        assert!(builder().build().is_ok());
+        assert!(matches!(
+            Package::builder().version("1.0").license("MIT").build(),
+            Err(Error::InvalidPackage(_))
+        ));
+        assert!(matches!(
+            builder().name("foo bar").build(),
+            Err(Error::InvalidPackage(_))
+        ));
+        assert!(matches!(
+            builder().license("Not a license").build(),
+            Err(Error::Parse { .. })
+        ));
+        assert!(matches!(
+            builder().dependency("libc>=").build(),
+            Err(Error::Message(_))
+        ));
+        assert!(matches!(
+            builder()
+                .source("https://example.com/foo.tar.gz", "md5:abcd")
+                .build(),
+            Err(Error::InvalidChecksum(_))
+        ));
+        assert!(
+            builder()
+                .git_source("https://example.com/foo.git", None)
+                .build()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = serde_json::to_value(schemars::schema_for!(Package)).unwrap();
+
+        assert_eq!(schema["required"], serde_json::json!(["info"]));
+        assert_eq!(
+            schema["$defs"]["Info"]["required"],
+            serde_json::json!(["name", "version", "description", "license"])
+        );
+        assert_eq!(
+            schema["$defs"]["Info"]["properties"]["license"]["type"],
+            "string"
+        );
+        assert_eq!(
+            schema["$defs"]["Step"]["anyOf"].as_array().unwrap().len(),
+            2
+        );
     }
 }
@@ -0,0 +1,91 @@
+use std::{
+    env::{self, current_dir},
+    fs,
+    path::{self, Path, PathBuf},
+};
+
+use nix::unistd::getuid;
+
+use crate::{database::DATABASE_DIR, error::Result, package::Info};
+
+/// Where blossom keeps what isn't part of the root itself: downloaded
+/// archives, build directories, synced indexes and the like. They live in the
+/// root when run as root, and in the XDG directories of the user otherwise,
+/// so that unprivileged users can sync, search and build. The state of each
+/// root gets its own directory there, since it follows the configuration of
+/// that root; only the cache is shared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    /// Safe to delete, everything in it is downloaded or built again.
+    pub cache: PathBuf,
+    /// Kept across runs: synced indexes and the trust placed in them, and
+    /// mirror rankings.
+    pub state: PathBuf,
+    /// Whether builds happen in per-package directories of the cache rather
+    /// than in the recipe directory.
+    pub isolated_builds: bool,
+}
+
+impl Paths {
+    pub fn new<R: AsRef<Path>>(root: R) -> Self {
+        let root = root.as_ref();
+
+        let xdg = |variable: &str, fallback: &str| {
+            env::var_os(variable)
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(fallback)))
+                .map(|dir| dir.join("blossom"))
+        };
+
+        match (
+            getuid().is_root(),
+            xdg("XDG_CACHE_HOME", ".cache"),
+            xdg("XDG_STATE_HOME", ".local/state"),
+        ) {
+            (false, Some(cache), Some(state)) => Self {
+                cache,
+                state: state.join("roots").join(root_key(root)),
+                isolated_builds: true,
+            },
+            _ => Self {
+                cache: root.join("var/cache/blossom"),
+                state: root.join(DATABASE_DIR),
+                isolated_builds: false,
+            },
+        }
+    }
+
+    /// The default package cache, unless `cache_dir` is configured.
+    pub fn packages(&self) -> PathBuf {
+        self.cache.join("packages")
+    }
+
+    pub fn sync(&self) -> PathBuf {
+        self.state.join("sync")
+    }
+
+    /// Where a package gets built: its own directory of the cache, or the
+    /// current directory when run as root.
+    pub fn build(&self, info: &Info) -> Result<PathBuf> {
+        if !self.isolated_builds {
+            return Ok(current_dir()?);
+        }
+
+        Ok(self
+            .cache
+            .join("build")
+            .join(format!("{}-{}", info.name, info.version)))
+    }
+}
+
+/// Names the state directory of `root` after a hash of its absolute path, so
+/// that `/` and `--root /mnt` don't share synced indexes.
+fn root_key(root: &Path) -> String {
+    let root = fs::canonicalize(root)
+        .or_else(|_| path::absolute(root))
+        .unwrap_or_else(|_| root.to_path_buf());
+    let hash = blake3::hash(root.as_os_str().as_encoded_bytes());
+
+    hash.to_hex()[..16].to_string()
+}
@@ -0,0 +1,299 @@
+use std::{
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, bail, format_err};
+
+/// The extended attribute holding file capabilities.
+const CAPABILITY_XATTR: &str = "security.capability";
+
+const SETUID: u32 = 0o4000;
+const SETGID: u32 = 0o2000;
+
+/// Capability names, indexed by capability number.
+const CAPABILITIES: [&str; 41] = [
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// A packaged file that is setuid, setgid or has file capabilities, which
+/// the recipe has to allow explicitly in `special_permissions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecialPermission {
+    pub path: Utf8PathBuf,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub setuid: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub setgid: bool,
+    /// The raw `security.capability` attribute, hex encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<String>,
+}
+
+impl Display for SpecialPermission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut kinds = Vec::new();
+
+        if self.setuid {
+            kinds.push("setuid".to_string());
+        }
+        if self.setgid {
+            kinds.push("setgid".to_string());
+        }
+        if let Some(capabilities) = &self.capabilities {
+            kinds.push(
+                base16ct::lower::decode_vec(capabilities)
+                    .ok()
+                    .and_then(|raw| describe_capabilities(&raw))
+                    .unwrap_or("capabilities".to_string()),
+            );
+        }
+
+        write!(f, "{} ({})", self.path, kinds.join(", "))
+    }
+}
+
+impl SpecialPermission {
+    /// The setuid and setgid bits of this file.
+    pub fn mode_bits(&self) -> u32 {
+        (if self.setuid { SETUID } else { 0 }) | (if self.setgid { SETGID } else { 0 })
+    }
+}
+
+/// Finds every setuid, setgid or capability-carrying file under `dir`.
+pub fn scan<P: AsRef<Path>>(dir: P) -> Result<Vec<SpecialPermission>> {
+    let mut found = Vec::new();
+    scan_dir(dir.as_ref(), Utf8Path::new(""), &mut found)?;
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(found)
+}
+
+fn scan_dir(dir: &Path, relative: &Utf8Path, found: &mut Vec<SpecialPermission>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let path = relative.join(
+            name.to_str()
+                .ok_or(format_err!("Non UTF-8 path: {}", entry.path().display()))?,
+        );
+        let metadata = entry.path().symlink_metadata()?;
+
+        if metadata.is_dir() {
+            scan_dir(&entry.path(), &path, found)?;
+            continue;
+        }
+
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let mode = metadata.mode();
+        let capabilities = match xattr::get(entry.path(), CAPABILITY_XATTR) {
+            Ok(raw) => raw.map(|raw| base16ct::lower::encode_string(&raw)),
+            // Filesystems without extended attributes can't carry capabilities.
+            Err(e) if e.kind() == ErrorKind::Unsupported => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        if mode & (SETUID | SETGID) != 0 || capabilities.is_some() {
+            found.push(SpecialPermission {
+                path,
+                setuid: mode & SETUID != 0,
+                setgid: mode & SETGID != 0,
+                capabilities,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails unless every special permission found is allowed by the recipe.
+pub fn check_allowed(found: &[SpecialPermission], allowed: &[Utf8PathBuf]) -> Result<()> {
+    let denied: Vec<String> = found
+        .iter()
+        .filter(|permission| !allowed.contains(&permission.path))
+        .map(ToString::to_string)
+        .collect();
+
+    if !denied.is_empty() {
+        bail!(
+            "Files with special permissions must be listed in special_permissions:\n  {}",
+            denied.join("\n  ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Restores the setuid and setgid bits and the capabilities of an installed
+/// file, which unpacking leaves out.
+pub fn apply<P: AsRef<Path>>(path: P, permission: &SpecialPermission, mode: u32) -> Result<()> {
+    let path = path.as_ref();
+
+    if let Some(capabilities) = &permission.capabilities {
+        let raw = base16ct::lower::decode_vec(capabilities)
+            .map_err(|_| format_err!("Invalid capabilities for {}", permission.path))?;
+
+        xattr::set(path, CAPABILITY_XATTR, &raw).map_err(|e| {
+            format_err!("Failed to set the capabilities of {}: {e}", permission.path)
+        })?;
+    }
+
+    // Setting capabilities clears the setuid and setgid bits, they go last.
+    if permission.mode_bits() != 0 {
+        fs::set_permissions(
+            path,
+            fs::Permissions::from_mode(mode & 0o777 | permission.mode_bits()),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Renders a `security.capability` attribute the way `getcap` does, e.g.
+/// `cap_net_raw=ep`.
+pub fn describe_capabilities(raw: &[u8]) -> Option<String> {
+    let word = |i: usize| -> Option<u64> {
+        Some(u32::from_le_bytes(raw.get(i * 4..i * 4 + 4)?.try_into().ok()?) as u64)
+    };
+
+    let magic = word(0)?;
+    let effective = magic & 1 != 0;
+
+    let (permitted, inheritable) = match magic & 0xff00_0000 {
+        // Revision 1 only has the lower 32 capabilities.
+        0x0100_0000 => (word(1)?, word(2)?),
+        0x0200_0000 | 0x0300_0000 => (word(1)? | word(3)? << 32, word(2)? | word(4)? << 32),
+        _ => return None,
+    };
+
+    let names = |set: u64| -> String {
+        (0..64)
+            .filter(|bit| set & (1 << bit) != 0)
+            .map(|bit| match CAPABILITIES.get(bit) {
+                Some(name) => format!("cap_{name}"),
+                None => format!("cap_{bit}"),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    let mut parts = Vec::new();
+
+    if permitted == inheritable && permitted != 0 {
+        parts.push(format!(
+            "{}={}ip",
+            names(permitted),
+            if effective { "e" } else { "" }
+        ));
+    } else {
+        if permitted != 0 {
+            parts.push(format!(
+                "{}={}p",
+                names(permitted),
+                if effective { "e" } else { "" }
+            ));
+        }
+        if inheritable != 0 {
+            parts.push(format!("{}=i", names(inheritable)));
+        }
+    }
+
+    Some(parts.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(magic: u32, words: &[u32]) -> Vec<u8> {
+        std::iter::once(magic)
+            .chain(words.iter().copied())
+            .flat_map(u32::to_le_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn test_describe_capabilities() {
+        let net_raw = 1 << 13;
+        let bind = 1 << 10;
+
+        assert_eq!(
+            describe_capabilities(&capabilities(0x0200_0001, &[net_raw, 0, 0, 0])).unwrap(),
+            "cap_net_raw=ep"
+        );
+        assert_eq!(
+            describe_capabilities(&capabilities(0x0200_0000, &[net_raw | bind, bind, 0, 0]))
+                .unwrap(),
+            "cap_net_bind_service,cap_net_raw=p cap_net_bind_service=i"
+        );
+        assert_eq!(
+            describe_capabilities(&capabilities(0x0300_0001, &[0, 0, 1 << 7, 0, 0])).unwrap(),
+            "cap_bpf=ep"
+        );
+        assert!(describe_capabilities(&[1, 2]).is_none());
+    }
+
+    #[test]
+    fn test_check_allowed() {
+        let found = vec![SpecialPermission {
+            path: Utf8PathBuf::from("usr/bin/su"),
+            setuid: true,
+            setgid: false,
+            capabilities: None,
+        }];
+
+        assert!(check_allowed(&found, &[Utf8PathBuf::from("usr/bin/su")]).is_ok());
+
+        let error = check_allowed(&found, &[]).unwrap_err().to_string();
+        assert!(error.contains("usr/bin/su (setuid)"));
+    }
+}
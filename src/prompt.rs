@@ -0,0 +1,194 @@
+use std::{
+    fmt::Display,
+    io::{self, BufRead, IsTerminal, Write},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use crate::{error::Result, failure::Failure};
+
+/// Which prompts are asked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Confirm {
+    /// Only operations that lose something, like removing packages, are
+    /// confirmed.
+    #[default]
+    Normal,
+    /// Every transaction is confirmed.
+    Strict,
+}
+
+impl Display for Confirm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Confirm::Normal => write!(f, "normal"),
+            Confirm::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+impl FromStr for Confirm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Confirm::Normal),
+            "strict" => Ok(Confirm::Strict),
+            _ => Err(format!(
+                "Unknown confirmation mode \"{s}\", expected normal or strict"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    /// Accept every prompt with its default answer, and confirmations.
+    pub assume_yes: bool,
+    pub confirm: Confirm,
+    /// Whether prompts may be asked at all. They never are when stdin isn't
+    /// a terminal.
+    pub interactive: bool,
+}
+
+static SETTINGS: Mutex<Settings> = Mutex::new(Settings {
+    assume_yes: false,
+    confirm: Confirm::Normal,
+    interactive: true,
+});
+
+/// Sets how prompts are answered for the rest of the run.
+pub fn configure(settings: Settings) {
+    if let Ok(mut current) = SETTINGS.lock() {
+        *current = settings;
+    }
+}
+
+fn settings() -> Settings {
+    SETTINGS.lock().map_or(
+        Settings {
+            assume_yes: false,
+            confirm: Confirm::Normal,
+            interactive: false,
+        },
+        |settings| *settings,
+    )
+}
+
+fn can_ask_with(settings: &Settings) -> bool {
+    !settings.assume_yes && settings.interactive && io::stdin().is_terminal()
+}
+
+/// Whether questions are actually asked, rather than answered by default.
+pub fn can_ask() -> bool {
+    can_ask_with(&settings())
+}
+
+/// Asks `question` and reads the answer, or `None` at the end of input.
+fn ask(question: &str) -> Result<Option<String>> {
+    print!("{question}");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(answer.trim().to_string()))
+}
+
+/// Asks for a yes or no answer. `destructive` operations are confirmed unless
+/// `--yes` is given, others only in strict mode. Without a terminal to ask
+/// on, confirmations that are needed fail.
+pub fn confirm(question: &str, destructive: bool) -> Result<bool> {
+    let settings = settings();
+
+    if settings.assume_yes || (!destructive && settings.confirm == Confirm::Normal) {
+        return Ok(true);
+    }
+
+    if !can_ask_with(&settings) {
+        return Err(Failure::Aborted.error(format!(
+            "{question} Can't ask without a terminal, pass --yes to accept"
+        )));
+    }
+
+    Ok(ask(&format!("{question} [y/N] "))?
+        .is_some_and(|answer| matches!(answer.as_str(), "y" | "Y" | "yes")))
+}
+
+/// Asks to pick one of `choices`, the first being the default. `--yes`
+/// accepts the default, without a terminal to ask on this fails.
+pub fn choose(question: &str, choices: &[String]) -> Result<usize> {
+    let settings = settings();
+
+    if settings.assume_yes {
+        return Ok(0);
+    }
+
+    if !can_ask_with(&settings) {
+        return Err(Failure::Aborted.error(format!(
+            "{question} ({}) Can't ask without a terminal, pass --yes to pick the first",
+            choices.join(", ")
+        )));
+    }
+
+    println!("{question}");
+
+    for (i, choice) in choices.iter().enumerate() {
+        println!("  {}) {choice}", i + 1);
+    }
+
+    loop {
+        let Some(answer) = ask("Enter a number (default=1): ")? else {
+            return Ok(0);
+        };
+
+        if answer.is_empty() {
+            return Ok(0);
+        }
+
+        match answer.parse::<usize>() {
+            Ok(choice) if (1..=choices.len()).contains(&choice) => return Ok(choice - 1),
+            _ => println!("Invalid choice: {answer}"),
+        }
+    }
+}
+
+/// Asks to pick any number of `count` numbered items, none by default.
+/// Nothing is picked with `--yes` or without a terminal to ask on.
+pub fn select(question: &str, count: usize) -> Result<Vec<usize>> {
+    let settings = settings();
+
+    if !can_ask_with(&settings) {
+        return Ok(Vec::new());
+    }
+
+    loop {
+        let Some(answer) = ask(question)? else {
+            return Ok(Vec::new());
+        };
+
+        let choices: Result<Vec<usize>, _> = answer.split_whitespace().map(str::parse).collect();
+
+        match choices {
+            Ok(choices) if choices.iter().all(|c| (1..=count).contains(c)) => {
+                return Ok(choices.into_iter().map(|choice| choice - 1).collect());
+            }
+            _ => println!("Invalid selection: {answer}"),
+        }
+    }
+}
+
+/// Asks a free-form question, `None` meaning the default answer: with
+/// `--yes`, without a terminal to ask on or at the end of input.
+pub fn answer(question: &str) -> Result<Option<String>> {
+    let settings = settings();
+
+    if !can_ask_with(&settings) {
+        return Ok(None);
+    }
+
+    ask(question)
+}
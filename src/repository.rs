@@ -0,0 +1,570 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, Pins},
+    error::{Result, bail},
+    lookup::Lookup,
+    package::{ChangelogEntry, Dependencies, Info, dependency_name},
+    paths::Paths,
+    permissions::SpecialPermission,
+    version,
+};
+
+pub const INDEX_EXTENSION: &str = "index";
+
+/// How many generations of deltas local repositories keep around.
+pub const KEPT_DELTAS: u64 = 32;
+
+/// The metadata of every package a repository provides.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepoIndex {
+    /// Incremented every time the index changes, deltas lead from one
+    /// generation to the next. Indexes without deltas stay at 0.
+    #[serde(default)]
+    pub generation: u64,
+    #[serde(default)]
+    pub packages: Vec<IndexEntry>,
+}
+
+/// Files two packages of an index both install although neither conflicts
+/// with or replaces the other, so that installing both fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub packages: (String, String),
+    pub files: Vec<Utf8PathBuf>,
+}
+
+/// The changes from one index generation to the next.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexDelta {
+    pub generation: u64,
+    /// New entries and entries replacing one of the same name and version.
+    #[serde(default)]
+    pub added: Vec<IndexEntry>,
+    /// Names and versions of the removed entries.
+    #[serde(default)]
+    pub removed: Vec<(String, String)>,
+}
+
+/// Published next to the index so clients can tell which deltas to fetch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexHead {
+    pub generation: u64,
+    /// The oldest generation a delta is still published for.
+    pub oldest_delta: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub info: Info,
+    pub dependencies: Option<Dependencies>,
+    pub filename: String,
+    pub checksum: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub installed_size: u64,
+    #[serde(default)]
+    pub files: Vec<Utf8PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub special_permissions: Vec<SpecialPermission>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changelog: Vec<ChangelogEntry>,
+}
+
+impl RepoIndex {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let decoder = zstd::Decoder::new(File::open(path)?)?;
+
+        Ok(serde_json::from_reader(decoder)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("index.tmp");
+
+        let mut encoder = zstd::Encoder::new(File::create(&tmp)?, 19)?;
+        serde_json::to_writer(&mut encoder, self)?;
+        encoder.finish()?;
+
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+
+    /// The changes leading from this index to `new`.
+    pub fn delta(&self, new: &RepoIndex) -> IndexDelta {
+        let same = |a: &IndexEntry, b: &IndexEntry| {
+            a.info.name == b.info.name && a.info.version == b.info.version
+        };
+
+        IndexDelta {
+            generation: new.generation,
+            added: new
+                .packages
+                .iter()
+                .filter(|entry| {
+                    !self
+                        .packages
+                        .iter()
+                        .any(|old| same(old, entry) && old.checksum == entry.checksum)
+                })
+                .cloned()
+                .collect(),
+            removed: self
+                .packages
+                .iter()
+                .filter(|old| !new.packages.iter().any(|entry| same(old, entry)))
+                .map(|old| (old.info.name.clone(), old.info.version.clone()))
+                .collect(),
+        }
+    }
+
+    /// Applies the delta to the previous generation.
+    pub fn apply(&mut self, delta: IndexDelta) -> Result<()> {
+        if delta.generation != self.generation + 1 {
+            bail!(
+                "Delta to generation {} doesn't apply to generation {}",
+                delta.generation,
+                self.generation
+            );
+        }
+
+        self.packages.retain(|entry| {
+            !delta
+                .removed
+                .iter()
+                .any(|(name, version)| entry.info.name == *name && entry.info.version == *version)
+        });
+
+        for entry in delta.added {
+            self.add(entry);
+        }
+
+        self.generation = delta.generation;
+
+        Ok(())
+    }
+
+    /// Adds an entry, replacing the one for the same name and version.
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.packages.retain(|existing| {
+            existing.info.name != entry.info.name || existing.info.version != entry.info.version
+        });
+        self.packages.push(entry);
+        self.packages.sort_by(|a, b| a.info.name.cmp(&b.info.name));
+    }
+
+    /// A hash of the index contents, independent of how it was compressed and
+    /// of the order of its entries, so that an index rebuilt from deltas
+    /// matches the published one.
+    pub fn digest(&self) -> Result<String> {
+        let mut packages: Vec<&IndexEntry> = self.packages.iter().collect();
+        packages
+            .sort_by(|a, b| (&a.info.name, &a.info.version).cmp(&(&b.info.name, &b.info.version)));
+
+        let json = serde_json::to_vec(&(self.generation, packages))?;
+
+        Ok(format!("blake3:{}", blake3::hash(&json).to_hex()))
+    }
+
+    /// The files packages of the index collide on, by pair of packages.
+    /// Versions of the same package don't collide with each other.
+    pub fn collisions(&self) -> Vec<Collision> {
+        let mut owners: BTreeMap<&Utf8Path, Vec<&IndexEntry>> = BTreeMap::new();
+        for entry in &self.packages {
+            for file in &entry.files {
+                owners.entry(file).or_default().push(entry);
+            }
+        }
+
+        let mut collisions: BTreeMap<(&str, &str), BTreeSet<&Utf8Path>> = BTreeMap::new();
+        for (file, entries) in owners {
+            for (i, a) in entries.iter().enumerate() {
+                for b in &entries[i + 1..] {
+                    if a.info.name == b.info.name || a.excludes(b) || b.excludes(a) {
+                        continue;
+                    }
+
+                    let pair = match a.info.name < b.info.name {
+                        true => (a.info.name.as_str(), b.info.name.as_str()),
+                        false => (b.info.name.as_str(), a.info.name.as_str()),
+                    };
+                    collisions.entry(pair).or_default().insert(file);
+                }
+            }
+        }
+
+        collisions
+            .into_iter()
+            .map(|((a, b), files)| Collision {
+                packages: (a.to_string(), b.to_string()),
+                files: files.into_iter().map(Utf8Path::to_path_buf).collect(),
+            })
+            .collect()
+    }
+
+    /// Returns the newest version of `name` in this index.
+    pub fn get(&self, name: &str) -> Option<&IndexEntry> {
+        self.packages
+            .iter()
+            .filter(|entry| entry.info.name == name)
+            .max_by(|a, b| version::compare(&a.info.version, &b.info.version))
+    }
+}
+
+impl IndexEntry {
+    /// Whether the package conflicts with or replaces `other`, by name or by
+    /// something it provides, so that they are never installed together.
+    fn excludes(&self, other: &IndexEntry) -> bool {
+        let names: Vec<&str> = std::iter::once(other.info.name.as_str())
+            .chain(
+                other
+                    .info
+                    .provides
+                    .iter()
+                    .map(|provided| dependency_name(provided)),
+            )
+            .collect();
+
+        self.info
+            .conflicts
+            .iter()
+            .chain(&self.info.replaces)
+            .any(|excluded| names.contains(&dependency_name(excluded)))
+    }
+}
+
+impl IndexDelta {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_reader(zstd::Decoder::new(File::open(
+            path,
+        )?)?)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut encoder = zstd::Encoder::new(File::create(path)?, 19)?;
+        serde_json::to_writer(&mut encoder, self)?;
+        encoder.finish()?;
+
+        Ok(())
+    }
+}
+
+impl IndexHead {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_vec(self)?)?;
+
+        Ok(())
+    }
+}
+
+/// The file name of the head published next to the index `file`.
+pub fn head_file(file: &str) -> String {
+    format!("{file}.head")
+}
+
+/// The file name of the delta leading to `generation` of the index `file`.
+pub fn delta_file(file: &str, generation: u64) -> String {
+    format!("{file}.{generation}.delta")
+}
+
+/// A synced copy of a remote repository index.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    pub name: String,
+    /// Copied from the configuration, unconfigured repositories default to 0.
+    pub priority: i32,
+    pub index: RepoIndex,
+}
+
+pub fn sync_dir<R: AsRef<Path>>(root: R) -> PathBuf {
+    Paths::new(root).sync()
+}
+
+/// Loads every synced repository index of a root, highest priority first and
+/// by name among equal priorities. Packages pinned to a repository are removed
+/// from every other one, so nothing downstream can pick them from elsewhere.
+pub fn load_synced<R: AsRef<Path>>(root: R) -> Result<Vec<Repository>> {
+    load_synced_with(root.as_ref(), |path| RepoIndex::read(path))
+}
+
+/// Like [`load_synced`], but with only the entries of `names`, looked up in
+/// the lookup tables of the indexes rather than decoding them whole.
+pub fn load_synced_named<R: AsRef<Path>>(root: R, names: &[&str]) -> Result<Vec<Repository>> {
+    load_synced_with(root.as_ref(), |path| {
+        let lookup = Lookup::load(path)?;
+        let mut packages = Vec::new();
+
+        for name in names {
+            packages.extend(lookup.entries(name)?);
+        }
+
+        Ok(RepoIndex {
+            generation: lookup.generation(),
+            packages,
+        })
+    })
+}
+
+/// The information of the packages of every synced repository, by name of
+/// repository and in the order of [`load_synced`], read from the lookup
+/// tables of the indexes without decoding the rest of their entries.
+pub fn load_synced_infos<R: AsRef<Path>>(root: R) -> Result<Vec<(String, Vec<Info>)>> {
+    let (indexes, pins) = synced_indexes(root.as_ref())?;
+    let mut repositories = Vec::new();
+
+    for index in indexes {
+        let mut infos = Vec::new();
+
+        for info in Lookup::load(&index.path)?.infos() {
+            let info = info?;
+
+            if pins
+                .get(&info.name)
+                .is_none_or(|pinned| pinned == index.name)
+            {
+                infos.push(info);
+            }
+        }
+
+        repositories.push((index.name, infos));
+    }
+
+    Ok(repositories)
+}
+
+fn load_synced_with(
+    root: &Path,
+    mut read: impl FnMut(&Path) -> Result<RepoIndex>,
+) -> Result<Vec<Repository>> {
+    let (indexes, pins) = synced_indexes(root)?;
+    let mut repositories = Vec::new();
+
+    for synced in indexes {
+        let mut index = read(&synced.path)?;
+
+        index.packages.retain(|entry| {
+            pins.get(&entry.info.name)
+                .is_none_or(|repository| repository == synced.name)
+        });
+
+        repositories.push(Repository {
+            name: synced.name,
+            priority: synced.priority,
+            index,
+        });
+    }
+
+    Ok(repositories)
+}
+
+/// The synced index of a repository, before it's read.
+struct SyncedIndex {
+    name: String,
+    priority: i32,
+    path: PathBuf,
+}
+
+/// The synced indexes of a root, highest priority first and by name among
+/// equal priorities, along with the pins of its configuration.
+fn synced_indexes(root: &Path) -> Result<(Vec<SyncedIndex>, Pins)> {
+    let dir = sync_dir(root);
+
+    if !dir.exists() {
+        return Ok((Vec::new(), Pins::default()));
+    }
+
+    let config = Config::load(root)?;
+    let pins = config.pins()?;
+
+    for repository in pins.repositories() {
+        if config.repository(repository).is_none() {
+            bail!("Packages are pinned to {repository}, which is not a configured repository");
+        }
+    }
+
+    let mut indexes = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.extension().is_some_and(|ext| ext == INDEX_EXTENSION) {
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            indexes.push(SyncedIndex {
+                name: name.to_string(),
+                priority: config.repository(name).map_or(0, |config| config.priority),
+                path: path.clone(),
+            });
+        }
+    }
+
+    indexes.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    Ok((indexes, pins))
+}
+
+/// Finds the preferred version of `name` across repositories, along with the
+/// repository providing it: the newest one of the highest priority repository
+/// carrying it.
+pub fn find_newest<'a>(
+    repositories: &'a [Repository],
+    name: &str,
+) -> Option<(&'a Repository, &'a IndexEntry)> {
+    candidates(repositories, name).into_iter().next()
+}
+
+/// Every available version of `name` across repositories, in order of
+/// preference: higher priority repositories first, newest first within the
+/// same priority. Earlier repositories come first among equal versions.
+pub fn candidates<'a>(
+    repositories: &'a [Repository],
+    name: &str,
+) -> Vec<(&'a Repository, &'a IndexEntry)> {
+    let mut candidates: Vec<_> = repositories
+        .iter()
+        .flat_map(|repository| {
+            repository
+                .index
+                .packages
+                .iter()
+                .filter(move |entry| entry.info.name == name)
+                .map(move |entry| (repository, entry))
+        })
+        .collect();
+
+    candidates.sort_by(|(repository_a, a), (repository_b, b)| {
+        compare_priority(repository_a, repository_b)
+            .then_with(|| version::compare(&b.info.version, &a.info.version))
+    });
+
+    candidates
+}
+
+/// Orders repositories by preference, the higher priority first.
+pub fn compare_priority(a: &Repository, b: &Repository) -> Ordering {
+    b.priority.cmp(&a.priority)
+}
+
+/// Finds the preferred version of `name`, if it's newer than
+/// `installed_version`.
+pub fn find_upgrade<'a>(
+    repositories: &'a [Repository],
+    name: &str,
+    installed_version: &str,
+) -> Option<(&'a Repository, &'a IndexEntry)> {
+    find_newest(repositories, name)
+        .filter(|(_, entry)| version::compare(&entry.info.version, installed_version).is_gt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_entry;
+
+    fn entry(name: &str, version: &str, checksum: &str) -> IndexEntry {
+        IndexEntry {
+            checksum: checksum.to_string(),
+            ..test_entry(name, version, &[])
+        }
+    }
+
+    #[test]
+    fn test_collisions() {
+        let with_files = |mut entry: IndexEntry, files: &[&str]| {
+            entry.files = files.iter().map(Utf8PathBuf::from).collect();
+            entry
+        };
+
+        let vim = |version, checksum| {
+            let mut vim = with_files(
+                entry("vim", version, checksum),
+                &["usr/bin/vim", "usr/bin/xxd"],
+            );
+            vim.info.provides.push("editor".to_string());
+            vim
+        };
+        let mut neovim = with_files(entry("neovim", "0.10", "b"), &["usr/bin/vim"]);
+        neovim.info.conflicts.push("editor".to_string());
+
+        let index = RepoIndex {
+            generation: 1,
+            packages: vec![
+                vim("9.1", "a"),
+                neovim,
+                vim("9.0", "c"),
+                with_files(entry("xxd", "1.0", "d"), &["usr/bin/xxd"]),
+            ],
+        };
+
+        assert_eq!(
+            index.collisions(),
+            [Collision {
+                packages: ("vim".to_string(), "xxd".to_string()),
+                files: vec![Utf8PathBuf::from("usr/bin/xxd")],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let previous = RepoIndex {
+            generation: 3,
+            packages: vec![
+                entry("app", "1.0", "a"),
+                entry("libc", "1.0", "b"),
+                entry("libfoo", "1.0", "c"),
+            ],
+        };
+        let mut new = previous.clone();
+        new.generation = 4;
+        new.packages.retain(|entry| entry.info.name != "libfoo");
+        new.add(entry("app", "1.0", "rebuilt"));
+        new.add(entry("libc", "1.1", "d"));
+
+        let delta = previous.delta(&new);
+        assert_eq!(delta.added.len(), 2);
+        assert_eq!(delta.removed, [("libfoo".to_string(), "1.0".to_string())]);
+
+        let mut applied = previous.clone();
+        applied.apply(delta.clone()).unwrap();
+
+        let summary = |index: &RepoIndex| -> Vec<(String, String, String)> {
+            index
+                .packages
+                .iter()
+                .map(|e| {
+                    (
+                        e.info.name.clone(),
+                        e.info.version.clone(),
+                        e.checksum.clone(),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(applied.generation, 4);
+        assert_eq!(summary(&applied), summary(&new));
+
+        // Deltas only apply to the generation right before them.
+        assert!(applied.apply(delta).is_err());
+    }
+}
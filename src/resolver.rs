@@ -0,0 +1,966 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    database::InstalledPackage,
+    error::{Error, Result},
+    failure::Failure,
+    package::{Info, dependency_constraints, dependency_name, dependency_requirement},
+    repository::{self, IndexEntry, Repository},
+    solver,
+    version::{self, Constraint},
+};
+
+/// Why a package ended up in a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Requested,
+    Dependency {
+        of: String,
+    },
+    /// Picked to stand in for the virtual package `provides`, required by `of`.
+    Provider {
+        of: String,
+        provides: String,
+    },
+    /// An optional dependency of `of` the user chose to install.
+    Optional {
+        of: String,
+    },
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::Requested => write!(f, "requested"),
+            Reason::Dependency { of } => write!(f, "dependency of {of}"),
+            Reason::Provider { of, provides } => {
+                write!(f, "provider chosen for {provides}, needed by {of}")
+            }
+            Reason::Optional { of } => write!(f, "optional dependency of {of}"),
+        }
+    }
+}
+
+/// An optional dependency of a planned package that isn't installed yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionalDependency {
+    pub requirement: String,
+    pub description: Option<String>,
+    pub wanted_by: String,
+    /// Whether any repository can satisfy it.
+    pub available: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedPackage {
+    pub repository: String,
+    pub entry: IndexEntry,
+    pub reason: Reason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemovalReason {
+    Conflict { with: String },
+    Replaced { by: String },
+}
+
+impl Display for RemovalReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemovalReason::Conflict { with } => write!(f, "conflicts with {with}"),
+            RemovalReason::Replaced { by } => write!(f, "replaced by {by}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedRemoval {
+    pub name: String,
+    pub version: String,
+    pub reason: RemovalReason,
+}
+
+/// Installed packages to remove, followed by packages to install ordered so
+/// that dependencies come before the packages requiring them.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub remove: Vec<PlannedRemoval>,
+    pub install: Vec<PlannedPackage>,
+    /// Runtime dependency cycles among planned packages, as paths starting and
+    /// ending with the same package. Their packages are installed in an
+    /// arbitrary order.
+    pub cycles: Vec<Vec<String>>,
+}
+
+impl Plan {
+    /// Records the cycle closed by `name` at the end of a dependency `chain`,
+    /// unless it is already known.
+    pub(crate) fn record_cycle(&mut self, chain: &[String], name: &str) {
+        let Some(start) = chain.iter().position(|package| package == name) else {
+            return;
+        };
+
+        let mut cycle = chain[start..].to_vec();
+
+        let known = self.cycles.iter().any(|known| {
+            known.len() == cycle.len() + 1 && cycle.iter().all(|package| known.contains(package))
+        });
+
+        if !known {
+            cycle.push(name.to_string());
+            self.cycles.push(cycle);
+        }
+    }
+}
+
+/// Formats a dependency cycle as `a → b → c → a`.
+pub fn format_cycle(cycle: &[String]) -> String {
+    cycle.join(" → ")
+}
+
+/// Preferred providers of virtual packages, e.g. `java-runtime = "openjdk17"`.
+pub const PROVIDERS_FILE: &str = "etc/blossom/providers.toml";
+
+/// Asks which of several packages should provide a virtual package.
+pub type ProviderPrompt<'a> = &'a (dyn Fn(&str, &[String]) -> Result<String> + Sync);
+
+pub fn load_preferences<R: AsRef<Path>>(root: R) -> Result<HashMap<String, String>> {
+    let path = root.as_ref().join(PROVIDERS_FILE);
+
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    Ok(toml_edit::de::from_str(&fs::read_to_string(path)?)?)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Solver {
+    /// Picks the newest fitting version of each package as it goes, starting
+    /// over when a later requirement rules a pick out.
+    #[default]
+    Greedy,
+    /// Encodes the whole system as a SAT problem, finding a consistent
+    /// solution whenever one exists.
+    Sat,
+}
+
+impl FromStr for Solver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "greedy" => Ok(Solver::Greedy),
+            "sat" => Ok(Solver::Sat),
+            _ => Err(format!("Unknown solver \"{s}\", expected greedy or sat")),
+        }
+    }
+}
+
+pub struct Resolver<'a> {
+    repositories: &'a [Repository],
+    installed: BTreeMap<&'a str, &'a InstalledPackage>,
+    preferences: HashMap<String, String>,
+    prompt: Option<ProviderPrompt<'a>>,
+    solver: Solver,
+    upgrade: bool,
+}
+
+/// A version requirement on a package, along with the package declaring it
+/// (`None` for requested packages).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Requirement {
+    by: Option<String>,
+    text: String,
+    constraints: Vec<Constraint>,
+}
+
+impl Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.by {
+            Some(by) => write!(f, "{by} needs {}", self.text),
+            None => write!(f, "{} was requested", self.text),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    requirements: HashMap<String, Vec<Requirement>>,
+    planned: HashMap<String, usize>,
+    /// Packages being visited, with the version picked for them.
+    visiting: HashMap<String, String>,
+    plan: Plan,
+}
+
+impl State {
+    fn require(&mut self, name: &str, requirement: Requirement) {
+        let requirements = self.requirements.entry(name.to_string()).or_default();
+
+        if !requirements.contains(&requirement) {
+            requirements.push(requirement);
+        }
+    }
+
+    fn constraints(&self, name: &str) -> Vec<Constraint> {
+        self.requirements
+            .get(name)
+            .into_iter()
+            .flatten()
+            .flat_map(|requirement| requirement.constraints.iter().cloned())
+            .collect()
+    }
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(repositories: &'a [Repository], installed: &'a [InstalledPackage]) -> Self {
+        Self {
+            repositories,
+            installed: installed
+                .iter()
+                .map(|package| (package.info.name.as_str(), package))
+                .collect(),
+            preferences: HashMap::new(),
+            prompt: None,
+            solver: Solver::Greedy,
+            upgrade: false,
+        }
+    }
+
+    pub fn solver(mut self, solver: Solver) -> Self {
+        self.solver = solver;
+        self
+    }
+
+    /// Lets the SAT solver upgrade every installed package to the newest
+    /// consistent version. The greedy solver only touches what it is asked to.
+    pub fn upgrade(mut self, upgrade: bool) -> Self {
+        self.upgrade = upgrade;
+        self
+    }
+
+    pub fn preferences(mut self, preferences: HashMap<String, String>) -> Self {
+        self.preferences = preferences;
+        self
+    }
+
+    /// Sets how to pick between several providers that have no configured
+    /// preference. Without a prompt, such a choice is an error.
+    pub fn prompt(mut self, prompt: ProviderPrompt<'a>) -> Self {
+        self.prompt = Some(prompt);
+        self
+    }
+
+    /// Computes the closure of required dependencies of `requested`, picking
+    /// the newest version satisfying every version requirement. Requested
+    /// packages are always part of the plan, dependencies only when they are
+    /// not installed yet or the installed version doesn't fit.
+    pub fn resolve(&self, requested: &[String]) -> Result<Plan> {
+        self.resolve_with(requested, &[])
+    }
+
+    /// Same as [`Resolver::resolve`], additionally pulling in the given
+    /// optional dependencies.
+    pub fn resolve_with(
+        &self,
+        requested: &[String],
+        optional: &[OptionalDependency],
+    ) -> Result<Plan> {
+        let requests: Vec<(&str, Reason)> = requested
+            .iter()
+            .map(|dependency| (dependency.as_str(), Reason::Requested))
+            .chain(optional.iter().map(|optional| {
+                let of = optional.wanted_by.clone();
+                (optional.requirement.as_str(), Reason::Optional { of })
+            }))
+            .collect();
+
+        if self.solver == Solver::Sat {
+            let installed: Vec<&InstalledPackage> = self.installed.values().copied().collect();
+            let mut plan = solver::solve(self.repositories, &installed, &requests, self.upgrade)?;
+            self.plan_removals(&mut plan)?;
+
+            return Ok(plan);
+        }
+
+        let mut requirements = HashMap::new();
+
+        // Requirements discovered late can invalidate a version picked
+        // earlier, in which case resolution starts over knowing about them.
+        loop {
+            let mut state = State {
+                requirements,
+                ..Default::default()
+            };
+
+            if self.resolve_pass(&requests, &mut state)? {
+                let mut plan = state.plan;
+                self.plan_removals(&mut plan)?;
+
+                return Ok(plan);
+            }
+
+            requirements = state.requirements;
+        }
+    }
+
+    fn resolve_pass(&self, requests: &[(&str, Reason)], state: &mut State) -> Result<bool> {
+        for (dependency, reason) in requests {
+            let name = dependency_name(dependency);
+            let by = match reason {
+                Reason::Optional { of } => Some(of.clone()),
+                _ => None,
+            };
+
+            let mut chain: Vec<String> = by.iter().cloned().collect();
+
+            state.require(
+                name,
+                Requirement {
+                    by,
+                    text: dependency_requirement(dependency).to_string(),
+                    constraints: dependency_constraints(dependency)?,
+                },
+            );
+
+            if !self.visit(name, reason.clone(), &mut chain, state)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Adds `name` and its dependencies to the plan. Returns `false` when a
+    /// version picked earlier no longer satisfies the known requirements.
+    fn visit(
+        &self,
+        name: &str,
+        reason: Reason,
+        chain: &mut Vec<String>,
+        state: &mut State,
+    ) -> Result<bool> {
+        let constraints = state.constraints(name);
+
+        if let Some(&index) = state.planned.get(name) {
+            return Ok(version::satisfies(
+                &state.plan.install[index].entry.info.version,
+                &constraints,
+            ));
+        }
+
+        if let Some(version) = state.visiting.get(name) {
+            state.plan.record_cycle(chain, name);
+            return Ok(version::satisfies(version, &constraints));
+        }
+
+        if reason != Reason::Requested
+            && self
+                .installed
+                .get(name)
+                .is_some_and(|installed| version::satisfies(&installed.info.version, &constraints))
+        {
+            return Ok(true);
+        }
+
+        if self.candidates(name).is_empty() {
+            return self.visit_virtual(name, &constraints, reason, chain, state);
+        }
+
+        let Some((repository, entry)) = self
+            .candidates(name)
+            .into_iter()
+            .find(|(_, entry)| version::satisfies(&entry.info.version, &constraints))
+        else {
+            return Err(self.unsatisfiable(name, chain, state));
+        };
+
+        chain.push(name.to_string());
+        state
+            .visiting
+            .insert(name.to_string(), entry.info.version.clone());
+
+        if let Some(dependencies) = &entry.dependencies {
+            for dependency in &dependencies.required {
+                let dependency_name = dependency_name(dependency);
+
+                state.require(
+                    dependency_name,
+                    Requirement {
+                        by: Some(name.to_string()),
+                        text: dependency_requirement(dependency).to_string(),
+                        constraints: dependency_constraints(dependency)?,
+                    },
+                );
+
+                let of = Reason::Dependency {
+                    of: name.to_string(),
+                };
+
+                if !self.visit(dependency_name, of, chain, state)? {
+                    return Ok(false);
+                }
+            }
+        }
+
+        state.visiting.remove(name);
+        chain.pop();
+
+        state
+            .planned
+            .insert(name.to_string(), state.plan.install.len());
+        state.plan.install.push(PlannedPackage {
+            repository: repository.name.clone(),
+            entry: entry.clone(),
+            reason,
+        });
+
+        Ok(true)
+    }
+
+    /// Lists the optional dependencies of planned packages that neither are
+    /// installed nor part of the plan.
+    pub fn optional_dependencies(&self, plan: &Plan) -> Result<Vec<OptionalDependency>> {
+        let mut optional: Vec<OptionalDependency> = Vec::new();
+
+        for planned in &plan.install {
+            let Some(dependencies) = &planned.entry.dependencies else {
+                continue;
+            };
+
+            for dependency in &dependencies.optional {
+                let requirement = dependency_requirement(dependency);
+
+                let mut fulfilled = optional
+                    .iter()
+                    .any(|optional| optional.requirement == requirement);
+
+                for package in plan.install.iter().map(|planned| &planned.entry.info) {
+                    fulfilled |= package.matches(requirement)?;
+                }
+
+                for package in self.installed.values().map(|installed| &installed.info) {
+                    fulfilled |= package.matches(requirement)?;
+                }
+
+                if fulfilled {
+                    continue;
+                }
+
+                let mut available = false;
+
+                for repository in self.repositories {
+                    for entry in &repository.index.packages {
+                        available |= entry.info.matches(requirement)?;
+                    }
+                }
+
+                optional.push(OptionalDependency {
+                    requirement: requirement.to_string(),
+                    description: dependency
+                        .split_once(':')
+                        .map(|(_, description)| description.trim().to_string()),
+                    wanted_by: planned.entry.info.name.clone(),
+                    available,
+                });
+            }
+        }
+
+        Ok(optional)
+    }
+
+    /// Plans the removal of installed packages that conflict with or are
+    /// replaced by planned ones. Conflicts between planned packages can't be
+    /// solved that way and are reported instead.
+    fn plan_removals(&self, plan: &mut Plan) -> Result<()> {
+        for (i, a) in plan.install.iter().enumerate() {
+            for b in &plan.install[i + 1..] {
+                if conflict(&a.entry.info, &b.entry.info)? {
+                    return Err(Failure::Conflict.error(format!(
+                        "{} and {} are in conflict and can't be installed together",
+                        a.entry.info.name, b.entry.info.name
+                    )));
+                }
+            }
+        }
+
+        for planned in &plan.install {
+            let info = &planned.entry.info;
+
+            for (name, installed) in &self.installed {
+                if plan.install.iter().any(|p| p.entry.info.name == *name)
+                    || plan.remove.iter().any(|removal| removal.name == *name)
+                {
+                    continue;
+                }
+
+                let mut replaced = false;
+
+                for reference in &info.replaces {
+                    replaced |= installed.info.matches(reference)?;
+                }
+
+                let reason = if replaced {
+                    RemovalReason::Replaced {
+                        by: info.name.clone(),
+                    }
+                } else if conflict(info, &installed.info)? {
+                    RemovalReason::Conflict {
+                        with: info.name.clone(),
+                    }
+                } else {
+                    continue;
+                };
+
+                plan.remove.push(PlannedRemoval {
+                    name: name.to_string(),
+                    version: installed.info.version.clone(),
+                    reason,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Satisfies a requirement on a name no package has, through the packages
+    /// providing it. Providers already planned or installed are preferred.
+    fn visit_virtual(
+        &self,
+        name: &str,
+        constraints: &[Constraint],
+        reason: Reason,
+        chain: &mut Vec<String>,
+        state: &mut State,
+    ) -> Result<bool> {
+        if state
+            .plan
+            .install
+            .iter()
+            .any(|planned| planned.entry.info.provides(name, constraints))
+        {
+            return Ok(true);
+        }
+
+        let installed = self
+            .installed
+            .values()
+            .find(|installed| installed.info.provides(name, constraints));
+
+        if reason != Reason::Requested && installed.is_some() {
+            return Ok(true);
+        }
+
+        let mut providers: Vec<String> = Vec::new();
+
+        for repository in self.repositories {
+            for entry in &repository.index.packages {
+                if entry.info.provides(name, constraints) && !providers.contains(&entry.info.name) {
+                    providers.push(entry.info.name.clone());
+                }
+            }
+        }
+
+        if providers.is_empty() {
+            return Err(self.unsatisfiable(name, chain, state));
+        }
+
+        let provider = match installed {
+            Some(installed) if providers.contains(&installed.info.name) => {
+                installed.info.name.clone()
+            }
+            _ => self.choose_provider(name, &providers)?,
+        };
+
+        let reason = match reason {
+            Reason::Requested => Reason::Requested,
+            Reason::Dependency { of } | Reason::Provider { of, .. } | Reason::Optional { of } => {
+                Reason::Provider {
+                    of,
+                    provides: name.to_string(),
+                }
+            }
+        };
+
+        self.visit(&provider, reason, chain, state)
+    }
+
+    fn choose_provider(&self, name: &str, providers: &[String]) -> Result<String> {
+        if let Some(preferred) = self
+            .preferences
+            .get(name)
+            .filter(|preferred| providers.contains(preferred))
+        {
+            return Ok(preferred.clone());
+        }
+
+        if let [provider] = providers {
+            return Ok(provider.clone());
+        }
+
+        match self.prompt {
+            Some(prompt) => prompt(name, providers),
+            None => Err(Failure::Dependency.error(format!(
+                "Multiple packages provide {name} ({}), set the preferred one in {PROVIDERS_FILE}",
+                providers.join(", ")
+            ))),
+        }
+    }
+
+    fn candidates(&self, name: &str) -> Vec<(&'a Repository, &'a IndexEntry)> {
+        repository::candidates(self.repositories, name)
+    }
+
+    fn unsatisfiable(&self, name: &str, chain: &[String], state: &State) -> Error {
+        let requirements = state.requirements.get(name).cloned().unwrap_or_default();
+        let available = self.candidates(name);
+
+        // Every requirement can be met on its own, they just can't all be met
+        // by the same version.
+        let conflicting = requirements.len() > 1
+            && requirements.iter().all(|requirement| {
+                available.iter().any(|(_, entry)| {
+                    version::satisfies(&entry.info.version, &requirement.constraints)
+                })
+            });
+
+        if conflicting {
+            let requirements: Vec<String> = requirements.iter().map(ToString::to_string).collect();
+
+            return Failure::Dependency.error(format!(
+                "Conflicting requirements on {name}: {}",
+                requirements.join(", ")
+            ));
+        }
+
+        let parent = chain.last();
+        let text = requirements
+            .iter()
+            .find(|requirement| requirement.by.as_ref() == parent)
+            .map_or(name, |requirement| requirement.text.as_str());
+
+        let mut message = format!(
+            "Unsatisfiable requirement: {}",
+            chain
+                .iter()
+                .map(String::as_str)
+                .chain([text])
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        );
+
+        if !available.is_empty() {
+            let versions: Vec<&str> = available
+                .iter()
+                .map(|(_, entry)| entry.info.version.as_str())
+                .collect();
+
+            message.push_str(&format!(" (available: {})", versions.join(", ")));
+        }
+
+        Failure::Dependency.error(message)
+    }
+}
+
+/// Whether either package declares a conflict with the other.
+fn conflict(a: &Info, b: &Info) -> Result<bool> {
+    for reference in &a.conflicts {
+        if b.matches(reference)? {
+            return Ok(true);
+        }
+    }
+
+    for reference in &b.conflicts {
+        if a.matches(reference)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{repository::RepoIndex, test_util::test_entry as entry};
+
+    fn repository(packages: Vec<IndexEntry>) -> Vec<Repository> {
+        vec![Repository {
+            name: "core".to_string(),
+            priority: 0,
+            index: RepoIndex {
+                generation: 0,
+                packages,
+            },
+        }]
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let repositories = repository(vec![
+            entry("app", "1.0", &["libfoo", "libc"]),
+            entry("libfoo", "1.0", &["libc"]),
+            entry("libc", "1.0", &[]),
+        ]);
+
+        let plan = Resolver::new(&repositories, &[])
+            .resolve(&["app".to_string()])
+            .unwrap();
+
+        let names: Vec<_> = plan
+            .install
+            .iter()
+            .map(|p| p.entry.info.name.as_str())
+            .collect();
+        assert_eq!(names, ["libc", "libfoo", "app"]);
+        assert_eq!(plan.install[2].reason, Reason::Requested);
+    }
+
+    #[test]
+    fn test_repository_priority() {
+        let repositories = vec![
+            Repository {
+                name: "stable".to_string(),
+                priority: 10,
+                index: RepoIndex {
+                    generation: 0,
+                    packages: vec![entry("kernel", "6.1", &[])],
+                },
+            },
+            Repository {
+                name: "testing".to_string(),
+                priority: 0,
+                index: RepoIndex {
+                    generation: 0,
+                    packages: vec![entry("kernel", "6.5", &[])],
+                },
+            },
+        ];
+
+        for solver in [Solver::Greedy, Solver::Sat] {
+            let plan = Resolver::new(&repositories, &[])
+                .solver(solver)
+                .resolve(&["kernel".to_string()])
+                .unwrap();
+
+            assert_eq!(plan.install[0].repository, "stable");
+            assert_eq!(plan.install[0].entry.info.version, "6.1");
+        }
+    }
+
+    #[test]
+    fn test_unsatisfiable_chain() {
+        let repositories = repository(vec![
+            entry("app", "1.0", &["libfoo"]),
+            entry("libfoo", "1.0", &["missing"]),
+        ]);
+
+        let error = Resolver::new(&repositories, &[])
+            .resolve(&["app".to_string()])
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Unsatisfiable requirement: app -> libfoo -> missing"
+        );
+    }
+
+    #[test]
+    fn test_version_constraints() {
+        let repositories = repository(vec![
+            entry("app", "1.0", &["libfoo", "libc>=1.1,<2"]),
+            entry("libfoo", "1.0", &["libc>=1.0"]),
+            entry("libc", "1.0", &[]),
+            entry("libc", "1.2", &[]),
+            entry("libc", "2.0", &[]),
+        ]);
+
+        let plan = Resolver::new(&repositories, &[])
+            .resolve(&["app".to_string()])
+            .unwrap();
+
+        let libc = plan
+            .install
+            .iter()
+            .find(|p| p.entry.info.name == "libc")
+            .unwrap();
+        assert_eq!(libc.entry.info.version, "1.2");
+    }
+
+    #[test]
+    fn test_conflicting_constraints() {
+        let repositories = repository(vec![
+            entry("foo", "1.0", &["bar>=2"]),
+            entry("baz", "1.0", &["bar<2"]),
+            entry("bar", "1.0", &[]),
+            entry("bar", "2.0", &[]),
+        ]);
+
+        let error = Resolver::new(&repositories, &[])
+            .resolve(&["foo".to_string(), "baz".to_string()])
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Conflicting requirements on bar: foo needs bar>=2, baz needs bar<2"
+        );
+    }
+
+    #[test]
+    fn test_provider_selection() {
+        let mut openjdk = entry("openjdk17", "17.0", &[]);
+        openjdk.info.provides = vec!["java-runtime=17".to_string()];
+        let mut temurin = entry("temurin", "21.0", &[]);
+        temurin.info.provides = vec!["java-runtime=21".to_string()];
+
+        let repositories = repository(vec![
+            entry("app", "1.0", &["java-runtime>=17"]),
+            openjdk,
+            temurin,
+        ]);
+
+        let requested = ["app".to_string()];
+
+        assert!(
+            Resolver::new(&repositories, &[])
+                .resolve(&requested)
+                .is_err()
+        );
+
+        let plan = Resolver::new(&repositories, &[])
+            .preferences(HashMap::from([(
+                "java-runtime".to_string(),
+                "temurin".to_string(),
+            )]))
+            .resolve(&requested)
+            .unwrap();
+
+        assert_eq!(plan.install[0].entry.info.name, "temurin");
+        assert_eq!(
+            plan.install[0].reason,
+            Reason::Provider {
+                of: "app".to_string(),
+                provides: "java-runtime".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_conflicts_and_replaces() {
+        let installed = |entry: IndexEntry| {
+            let peach_info = crate::package::PeachInfo {
+                info: entry.info,
+                dependencies: entry.dependencies,
+                backup: Vec::new(),
+                build_date: None,
+                installed_size: 0,
+                special_permissions: Vec::new(),
+                changelog: Vec::new(),
+            };
+
+            InstalledPackage::new(peach_info, Default::default(), Vec::new())
+        };
+
+        let mut nano = entry("nano", "8.0", &[]);
+        nano.info.conflicts = vec!["neovim".to_string()];
+        let mut neovim = entry("neovim", "0.10", &[]);
+        neovim.info.replaces = vec!["vim<10".to_string()];
+
+        let installed = [
+            installed(nano),
+            installed(entry("vim", "9.0", &[])),
+            installed(entry("emacs", "29", &[])),
+        ];
+        let repositories = repository(vec![neovim]);
+
+        let plan = Resolver::new(&repositories, &installed)
+            .resolve(&["neovim".to_string()])
+            .unwrap();
+
+        let removals: Vec<_> = plan
+            .remove
+            .iter()
+            .map(|removal| (removal.name.as_str(), removal.reason.clone()))
+            .collect();
+
+        assert_eq!(
+            removals,
+            [
+                (
+                    "nano",
+                    RemovalReason::Conflict {
+                        with: "neovim".to_string()
+                    }
+                ),
+                (
+                    "vim",
+                    RemovalReason::Replaced {
+                        by: "neovim".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_optional_dependencies() {
+        let mut app = entry("app", "1.0", &[]);
+        app.dependencies.as_mut().unwrap().optional = vec![
+            "libfoo: for foo support".to_string(),
+            "libbar".to_string(),
+            "libc".to_string(),
+        ];
+
+        let repositories = repository(vec![
+            app,
+            entry("libfoo", "1.0", &[]),
+            entry("libc", "1.0", &[]),
+        ]);
+        let resolver = Resolver::new(&repositories, &[]);
+
+        let mut plan = resolver
+            .resolve(&["app".to_string(), "libc".to_string()])
+            .unwrap();
+        let optional = resolver.optional_dependencies(&plan).unwrap();
+
+        assert_eq!(optional.len(), 2);
+        assert_eq!(optional[0].requirement, "libfoo");
+        assert_eq!(optional[0].description.as_deref(), Some("for foo support"));
+        assert!(optional[0].available);
+        assert!(!optional[1].available);
+
+        plan = resolver
+            .resolve_with(&["app".to_string()], &optional[..1])
+            .unwrap();
+
+        assert_eq!(
+            plan.install[1].reason,
+            Reason::Optional {
+                of: "app".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_runtime_cycle() {
+        let repositories = repository(vec![
+            entry("a", "1.0", &["b"]),
+            entry("b", "1.0", &["c"]),
+            entry("c", "1.0", &["a"]),
+        ]);
+
+        let plan = Resolver::new(&repositories, &[])
+            .resolve(&["a".to_string()])
+            .unwrap();
+
+        assert_eq!(plan.install.len(), 3);
+        assert_eq!(plan.cycles.len(), 1);
+        assert_eq!(format_cycle(&plan.cycles[0]), "a → b → c → a");
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+    process::Command,
+    str::FromStr,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use crate::error::{Error, Result};
+
+/// Runs the commands of build steps, as named by `runner` in recipes.
+pub trait Runner: Send + Sync {
+    /// The name recipes refer to this runner by.
+    fn name(&self) -> &str;
+
+    /// The process running `script`. The working directory, environment and
+    /// network isolation are set up by the build.
+    fn command(&self, script: &str) -> Command;
+}
+
+/// Runs steps with `/bin/sh -c`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Shell;
+
+impl Runner for Shell {
+    fn name(&self) -> &str {
+        "shell"
+    }
+
+    fn command(&self, script: &str) -> Command {
+        let mut command = Command::new("/bin/sh");
+
+        command.arg("-c").arg(script);
+
+        command
+    }
+}
+
+/// The runners recipes can use, by name.
+static RUNNERS: LazyLock<RwLock<BTreeMap<String, Arc<dyn Runner>>>> = LazyLock::new(|| {
+    let shell: Arc<dyn Runner> = Arc::new(Shell);
+
+    RwLock::new(BTreeMap::from([(shell.name().to_string(), shell)]))
+});
+
+/// Makes `runner` available to recipes parsed from now on, replacing any
+/// runner of the same name, built-in ones included.
+pub fn register(runner: Arc<dyn Runner>) {
+    if let Ok(mut runners) = RUNNERS.write() {
+        runners.insert(runner.name().to_string(), runner);
+    }
+}
+
+/// The runner registered as `name`.
+pub fn get(name: &str) -> Result<Arc<dyn Runner>> {
+    let runners = RUNNERS
+        .read()
+        .map_err(|_| Error::Message("Runner registry lock poisoned".to_string()))?;
+
+    runners.get(name).cloned().ok_or_else(|| Error::Parse {
+        what: "runner".to_string(),
+        message: format!(
+            "unknown runner \"{name}\", expected one of {}",
+            runners.keys().cloned().collect::<Vec<_>>().join(", ")
+        ),
+    })
+}
+
+/// A registered runner as referred to by a step, written by name.
+#[derive(Clone)]
+pub struct StepRunner(Arc<dyn Runner>);
+
+impl StepRunner {
+    pub fn new(runner: Arc<dyn Runner>) -> Self {
+        Self(runner)
+    }
+
+    pub fn shell() -> Self {
+        Self(Arc::new(Shell))
+    }
+
+    pub fn command(&self, script: &str) -> Command {
+        self.0.command(script)
+    }
+}
+
+impl Debug for StepRunner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("StepRunner").field(&self.0.name()).finish()
+    }
+}
+
+impl Display for StepRunner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.name())
+    }
+}
+
+impl FromStr for StepRunner {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        get(s).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Nu;
+
+    impl Runner for Nu {
+        fn name(&self) -> &str {
+            "nu"
+        }
+
+        fn command(&self, script: &str) -> Command {
+            let mut command = Command::new("nu");
+            command.arg("-c").arg(script);
+            command
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        assert_eq!("shell".parse::<StepRunner>().unwrap().to_string(), "shell");
+        assert!(matches!(
+            "fish".parse::<StepRunner>(),
+            Err(Error::Parse { .. })
+        ));
+
+        register(Arc::new(Nu));
+
+        let runner: StepRunner = "nu".parse().unwrap();
+        assert_eq!(runner.command("ls").get_program(), "nu");
+    }
+}
@@ -0,0 +1,425 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    error::{Error, Result, bail},
+    lockfile::LockedSource,
+    package::{Package, dependency_name, dependency_requirement},
+};
+
+/// What went into a built package, embedded in its archive and rendered as
+/// SPDX or CycloneDX by `blossom sbom`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sbom {
+    pub name: String,
+    pub version: String,
+    pub license: String,
+    pub build_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub sources: Vec<SbomSource>,
+    /// Runtime dependencies, with their version requirement.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub optional: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SbomSource {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+    /// Checksums as `<algorithm>:<hex>`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checksums: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    Spdx,
+    CycloneDx,
+}
+
+impl FromStr for SbomFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "spdx" => Ok(Self::Spdx),
+            "cyclonedx" => Ok(Self::CycloneDx),
+            _ => bail!("Unknown SBOM format {value}, expected spdx or cyclonedx"),
+        }
+    }
+}
+
+impl Display for SbomFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spdx => write!(f, "spdx"),
+            Self::CycloneDx => write!(f, "cyclonedx"),
+        }
+    }
+}
+
+impl Sbom {
+    pub const FILE_NAME: &str = ".SBOM";
+
+    /// Describes a package built from the given fetched sources.
+    pub fn new(package: &Package, sources: &[LockedSource], build_date: DateTime<Utc>) -> Self {
+        let sources = package
+            .sources
+            .iter()
+            .zip(sources)
+            .map(|(source, fetched)| {
+                let mut checksums = Vec::new();
+
+                if !source.checksum.is_empty() {
+                    checksums.push(source.checksum.clone());
+                }
+                if let Some(checksum) = fetched
+                    .checksum
+                    .as_ref()
+                    .filter(|checksum| !checksums.contains(checksum))
+                {
+                    checksums.push(checksum.clone());
+                }
+
+                SbomSource {
+                    url: source.url.clone(),
+                    resolved: fetched.resolved.clone(),
+                    checksums,
+                    commit: fetched.commit.clone(),
+                }
+            })
+            .collect();
+
+        let dependencies = package.dependencies.as_ref();
+
+        Self {
+            name: package.info.name.clone(),
+            version: package.info.version.clone(),
+            license: package.info.license.to_string(),
+            build_date: Some(build_date),
+            sources,
+            dependencies: dependencies
+                .map(|dependencies| dependencies.required.clone())
+                .unwrap_or_default(),
+            optional: dependencies
+                .map(|dependencies| dependencies.optional.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn render(&self, format: SbomFormat) -> Value {
+        match format {
+            SbomFormat::Spdx => self.to_spdx(),
+            SbomFormat::CycloneDx => self.to_cyclonedx(),
+        }
+    }
+
+    fn created(&self) -> String {
+        self.build_date
+            .unwrap_or_default()
+            .format("%Y-%m-%dT%H:%M:%SZ")
+            .to_string()
+    }
+
+    /// A name for this document that stays the same across exports.
+    fn digest(&self) -> String {
+        blake3::hash(&serde_json::to_vec(self).unwrap_or_default())
+            .to_hex()
+            .to_string()
+    }
+
+    /// An SPDX 2.3 document.
+    pub fn to_spdx(&self) -> Value {
+        let package_id = format!("SPDXRef-Package-{}", spdx_id(&self.name));
+        let mut packages = vec![json!({
+            "name": self.name,
+            "SPDXID": package_id,
+            "versionInfo": self.version,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "licenseConcluded": self.license,
+            "licenseDeclared": self.license,
+            "copyrightText": "NOASSERTION",
+        })];
+        let mut relationships = vec![json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": package_id,
+        })];
+
+        for (i, source) in self.sources.iter().enumerate() {
+            let id = format!("SPDXRef-Source-{i}");
+            let location = match &source.commit {
+                Some(commit) => format!("{}@{commit}", source.url),
+                None => source.resolved.clone().unwrap_or(source.url.clone()),
+            };
+            let checksums: Vec<Value> = source
+                .checksums
+                .iter()
+                .filter_map(|checksum| checksum.split_once(':'))
+                .map(|(algorithm, value)| {
+                    json!({
+                        "algorithm": algorithm.to_ascii_uppercase(),
+                        "checksumValue": value,
+                    })
+                })
+                .collect();
+
+            let mut package = json!({
+                "name": source_name(&source.url),
+                "SPDXID": id,
+                "downloadLocation": location,
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+            });
+            if !checksums.is_empty() {
+                package["checksums"] = Value::Array(checksums);
+            }
+
+            packages.push(package);
+            relationships.push(json!({
+                "spdxElementId": package_id,
+                "relationshipType": "GENERATED_FROM",
+                "relatedSpdxElement": id,
+            }));
+        }
+
+        for (dependency, optional) in self.all_dependencies() {
+            let id = format!(
+                "SPDXRef-Dependency-{}",
+                spdx_id(dependency_name(dependency))
+            );
+
+            packages.push(json!({
+                "name": dependency_name(dependency),
+                "SPDXID": id,
+                "downloadLocation": "NOASSERTION",
+                "filesAnalyzed": false,
+                "comment": format!("Requirement: {}", dependency_requirement(dependency)),
+            }));
+            relationships.push(if optional {
+                json!({
+                    "spdxElementId": id,
+                    "relationshipType": "OPTIONAL_DEPENDENCY_OF",
+                    "relatedSpdxElement": package_id,
+                })
+            } else {
+                json!({
+                    "spdxElementId": package_id,
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": id,
+                })
+            });
+        }
+
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": format!("{}-{}", self.name, self.version),
+            "documentNamespace": format!(
+                "https://spdx.org/spdxdocs/{}-{}-{}",
+                self.name,
+                self.version,
+                self.digest()
+            ),
+            "creationInfo": {
+                "created": self.created(),
+                "creators": [format!("Tool: blossom-{}", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "relationships": relationships,
+        })
+    }
+
+    /// A CycloneDX 1.5 document.
+    pub fn to_cyclonedx(&self) -> Value {
+        let package_ref = format!("pkg:generic/{}@{}", self.name, self.version);
+
+        let references: Vec<Value> = self
+            .sources
+            .iter()
+            .map(|source| {
+                let hashes: Vec<Value> = source
+                    .checksums
+                    .iter()
+                    .filter_map(|checksum| checksum.split_once(':'))
+                    .filter_map(|(algorithm, value)| {
+                        let algorithm = match algorithm {
+                            "sha256" => "SHA-256",
+                            "blake3" => "BLAKE3",
+                            _ => return None,
+                        };
+
+                        Some(json!({ "alg": algorithm, "content": value }))
+                    })
+                    .collect();
+
+                match &source.commit {
+                    Some(commit) => json!({
+                        "type": "vcs",
+                        "url": source.url.trim_start_matches("git+"),
+                        "comment": format!("Commit {commit}"),
+                    }),
+                    None => json!({
+                        "type": "source-distribution",
+                        "url": source.resolved.as_ref().unwrap_or(&source.url),
+                        "hashes": hashes,
+                    }),
+                }
+            })
+            .collect();
+
+        let mut components = Vec::new();
+        let mut depends_on = Vec::new();
+
+        for (dependency, optional) in self.all_dependencies() {
+            let name = dependency_name(dependency);
+            let dependency_ref = format!("pkg:generic/{name}");
+
+            components.push(json!({
+                "type": "library",
+                "bom-ref": dependency_ref,
+                "name": name,
+                "scope": if optional { "optional" } else { "required" },
+                "description": format!("Requirement: {}", dependency_requirement(dependency)),
+            }));
+            depends_on.push(dependency_ref);
+        }
+
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "timestamp": self.created(),
+                "tools": {
+                    "components": [{
+                        "type": "application",
+                        "name": "blossom",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    }],
+                },
+                "component": {
+                    "type": "application",
+                    "bom-ref": package_ref,
+                    "name": self.name,
+                    "version": self.version,
+                    "licenses": [{ "expression": self.license }],
+                    "externalReferences": references,
+                },
+            },
+            "components": components,
+            "dependencies": [{ "ref": package_ref, "dependsOn": depends_on }],
+        })
+    }
+
+    fn all_dependencies(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.dependencies
+            .iter()
+            .map(|dependency| (dependency.as_str(), false))
+            .chain(
+                self.optional
+                    .iter()
+                    .map(|dependency| (dependency.as_str(), true)),
+            )
+    }
+}
+
+/// SPDX identifiers may only contain letters, digits, `.` and `-`.
+fn spdx_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+fn source_name(url: &str) -> &str {
+    url.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(url)
+        .trim_end_matches(".git")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sbom() -> Sbom {
+        Sbom {
+            name: "foo".to_string(),
+            version: "1.0".to_string(),
+            license: "MIT".to_string(),
+            build_date: None,
+            sources: vec![
+                SbomSource {
+                    url: "https://example.org/foo-1.0.tar.gz".to_string(),
+                    resolved: Some("https://mirror.example.org/foo-1.0.tar.gz".to_string()),
+                    checksums: vec!["sha256:abcd".to_string()],
+                    commit: None,
+                },
+                SbomSource {
+                    url: "git+https://example.org/bar.git".to_string(),
+                    resolved: None,
+                    checksums: Vec::new(),
+                    commit: Some("0123".to_string()),
+                },
+            ],
+            dependencies: vec!["lib_bar>=1.2".to_string()],
+            optional: vec!["baz: for extra things".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_spdx() {
+        let spdx = sbom().to_spdx();
+        let packages = spdx["packages"].as_array().unwrap();
+
+        assert_eq!(packages.len(), 5);
+        assert_eq!(
+            packages[1]["downloadLocation"],
+            "https://mirror.example.org/foo-1.0.tar.gz"
+        );
+        assert_eq!(packages[1]["checksums"][0]["algorithm"], "SHA256");
+        assert_eq!(
+            packages[2]["downloadLocation"],
+            "git+https://example.org/bar.git@0123"
+        );
+        assert_eq!(packages[3]["SPDXID"], "SPDXRef-Dependency-lib-bar");
+        assert_eq!(
+            spdx["relationships"][4]["relationshipType"],
+            "OPTIONAL_DEPENDENCY_OF"
+        );
+    }
+
+    #[test]
+    fn test_cyclonedx() {
+        let bom = sbom().to_cyclonedx();
+        let references = &bom["metadata"]["component"]["externalReferences"];
+
+        assert_eq!(references[0]["hashes"][0]["alg"], "SHA-256");
+        assert_eq!(references[1]["type"], "vcs");
+        assert_eq!(bom["components"][1]["name"], "baz");
+        assert_eq!(bom["components"][1]["scope"], "optional");
+        assert_eq!(
+            bom["dependencies"][0]["dependsOn"],
+            json!(["pkg:generic/lib_bar", "pkg:generic/baz"])
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result, bail, format_err},
+    failure::Failure,
+};
+
+const PUBLIC_PREFIX: &str = "ed25519:";
+const SECRET_PREFIX: &str = "ed25519-secret:";
+
+/// An ed25519 public key, written as `ed25519:<hex>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey(VerifyingKey);
+
+impl PublicKey {
+    /// A short identifier, the first 16 hex digits of the key's blake3 hash.
+    pub fn id(&self) -> String {
+        blake3::hash(self.0.as_bytes()).to_hex()[..16].to_string()
+    }
+
+    pub fn verify(&self, data: &[u8], signature: &Signature) -> Result<()> {
+        let bytes: [u8; 64] = decode(&signature.signature)?;
+
+        self.0
+            .verify_strict(data, &ed25519_dalek::Signature::from_bytes(&bytes))
+            .map_err(|_| {
+                Failure::Signature.error(format!("Signature doesn't match key {}", self.id()))
+            })
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s
+            .trim()
+            .strip_prefix(PUBLIC_PREFIX)
+            .ok_or(format_err!("Public keys start with \"{PUBLIC_PREFIX}\""))?;
+
+        Ok(Self(VerifyingKey::from_bytes(&decode(hex)?)?))
+    }
+}
+
+impl Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{PUBLIC_PREFIX}{}",
+            base16ct::lower::encode_string(self.0.as_bytes())
+        )
+    }
+}
+
+/// An ed25519 signing key, stored as `ed25519-secret:<hex>` in a file only
+/// readable by its owner.
+pub struct SecretKey(SigningKey);
+
+impl SecretKey {
+    pub fn generate() -> Result<Self> {
+        let mut seed = [0; 32];
+        getrandom::fill(&mut seed).map_err(|e| format_err!("Failed to generate a key: {e}"))?;
+
+        Ok(Self(SigningKey::from_bytes(&seed)))
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let hex = contents
+            .trim()
+            .strip_prefix(SECRET_PREFIX)
+            .ok_or(format_err!("{} is not a secret key", path.display()))?;
+
+        Ok(Self(SigningKey::from_bytes(&decode(hex)?)))
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+
+        writeln!(
+            file,
+            "{SECRET_PREFIX}{}",
+            base16ct::lower::encode_string(self.0.as_bytes())
+        )?;
+
+        Ok(())
+    }
+
+    pub fn public(&self) -> PublicKey {
+        PublicKey(self.0.verifying_key())
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Signature {
+        use ed25519_dalek::Signer;
+
+        Signature {
+            key: self.public().id(),
+            signature: base16ct::lower::encode_string(&self.0.sign(data).to_bytes()),
+        }
+    }
+}
+
+/// A detached signature, stored next to the signed file with a `.sig` suffix.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Signature {
+    /// Identifier of the key that made the signature.
+    pub key: String,
+    pub signature: String,
+}
+
+impl Signature {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(toml_edit::de::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, toml_edit::ser::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// Checks the signature against the trusted keys, returning the one that
+    /// made it.
+    pub fn verify<'a>(&self, data: &[u8], trusted: &'a [PublicKey]) -> Result<&'a PublicKey> {
+        let Some(key) = trusted.iter().find(|key| key.id() == self.key) else {
+            return Err(Failure::Signature
+                .error(format!("Signed by key {}, which is not trusted", self.key)));
+        };
+
+        key.verify(data, self)?;
+
+        Ok(key)
+    }
+}
+
+/// Where the detached signature of `path` is stored.
+pub fn signature_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut path = path.as_ref().as_os_str().to_owned();
+    path.push(".sig");
+
+    PathBuf::from(path)
+}
+
+fn decode<const N: usize>(hex: &str) -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    let decoded = base16ct::mixed::decode(hex.trim(), &mut bytes)
+        .map_err(|_| format_err!("Invalid hex encoded key material"))?;
+
+    if decoded.len() != N {
+        bail!("Expected {N} bytes of key material, got {}", decoded.len());
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SecretKey::generate().unwrap();
+        let public: PublicKey = key.public().to_string().parse().unwrap();
+        let other = SecretKey::generate().unwrap().public();
+
+        let signature = key.sign(b"index");
+
+        assert_eq!(
+            signature
+                .verify(b"index", &[other.clone(), public.clone()])
+                .unwrap(),
+            &public
+        );
+        assert!(signature.verify(b"tampered", &[public]).is_err());
+        assert_eq!(
+            signature
+                .verify(b"index", &[other])
+                .unwrap_err()
+                .to_string(),
+            format!("Signed by key {}, which is not trusted", key.public().id())
+        );
+    }
+}
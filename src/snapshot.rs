@@ -0,0 +1,304 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{DATABASE_DIR, Database, FileKind},
+    error::{Result, bail, format_err},
+    log::{info, warn},
+};
+
+pub const CONFIG_FILE: &str = "etc/blossom/snapshot.toml";
+const SNAPSHOT_DIR: &str = "snapshots";
+const FILES_ARCHIVE: &str = "files.tar.zst";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Picks btrfs or zfs depending on the filesystem of the root, and falls
+    /// back to a file-level backup otherwise.
+    #[default]
+    Auto,
+    Btrfs,
+    Zfs,
+    Files,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub backend: Backend,
+    /// Minimum number of packages a transaction must touch to be snapshotted.
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+}
+
+fn default_threshold() -> usize {
+    10
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            backend: Backend::Auto,
+            threshold: default_threshold(),
+        }
+    }
+}
+
+impl SnapshotConfig {
+    pub fn load<R: AsRef<Path>>(root: R) -> Result<Self> {
+        let path = root.as_ref().join(CONFIG_FILE);
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(toml_edit::de::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: u64,
+    pub created: DateTime<Utc>,
+    pub backend: Backend,
+    /// Path of the btrfs snapshot or name of the zfs snapshot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    pub packages: Vec<String>,
+}
+
+fn snapshot_dir(root: &Path) -> PathBuf {
+    root.join(DATABASE_DIR).join(SNAPSHOT_DIR)
+}
+
+/// Lists the snapshots of a root, oldest first.
+pub fn list<R: AsRef<Path>>(root: R) -> Result<Vec<Snapshot>> {
+    let dir = snapshot_dir(root.as_ref());
+
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path().join("snapshot.toml");
+
+        if path.exists() {
+            snapshots.push(toml_edit::de::from_str::<Snapshot>(&fs::read_to_string(
+                path,
+            )?)?);
+        }
+    }
+
+    snapshots.sort_by_key(|snapshot| snapshot.id);
+
+    Ok(snapshots)
+}
+
+/// Takes a snapshot before a transaction touching `packages`, if the root is
+/// configured to and the transaction is large enough.
+pub fn before_transaction(database: &Database, packages: &[String]) -> Result<Option<Snapshot>> {
+    let root = database.root();
+    let config = SnapshotConfig::load(root)?;
+
+    if !config.enabled || packages.len() < config.threshold {
+        return Ok(None);
+    }
+
+    let id = list(root)?.last().map_or(1, |last| last.id + 1);
+    let dir = snapshot_dir(root).join(id.to_string());
+    fs::create_dir_all(&dir)?;
+
+    let backend = match config.backend {
+        Backend::Auto => detect_backend(root),
+        backend => backend,
+    };
+
+    info!(
+        "Creating {backend:?} snapshot #{id} before touching {} packages",
+        packages.len()
+    );
+
+    let reference = match backend {
+        Backend::Btrfs => {
+            let target = root.join(".snapshots").join(format!("blossom-{id}"));
+            fs::create_dir_all(root.join(".snapshots"))?;
+
+            run(Command::new("btrfs")
+                .args(["subvolume", "snapshot", "-r"])
+                .arg(root)
+                .arg(&target))?;
+
+            Some(target.display().to_string())
+        }
+        Backend::Zfs => {
+            let name = format!("{}@blossom-{id}", zfs_dataset(root)?);
+            run(Command::new("zfs").arg("snapshot").arg(&name))?;
+
+            Some(name)
+        }
+        Backend::Files | Backend::Auto => {
+            backup_files(database, packages, &dir.join(FILES_ARCHIVE))?;
+            None
+        }
+    };
+
+    let snapshot = Snapshot {
+        id,
+        created: Utc::now(),
+        backend,
+        reference,
+        packages: packages.to_vec(),
+    };
+
+    fs::write(
+        dir.join("snapshot.toml"),
+        toml_edit::ser::to_string_pretty(&snapshot)?,
+    )?;
+
+    Ok(Some(snapshot))
+}
+
+/// Restores the state captured by `snapshot`. Only zfs and file-level
+/// snapshots can be restored on a live system.
+pub fn restore(database: &Database, snapshot: &Snapshot) -> Result<()> {
+    let root = database.root();
+
+    match snapshot.backend {
+        Backend::Zfs => {
+            let name = snapshot
+                .reference
+                .as_deref()
+                .ok_or(format_err!("Snapshot #{} has no zfs name", snapshot.id))?;
+
+            run(Command::new("zfs").args(["rollback", "-r", name]))
+        }
+        Backend::Btrfs => bail!(
+            "btrfs snapshots can't be restored on a live system, boot into {} or make it the default subvolume",
+            snapshot.reference.as_deref().unwrap_or("the snapshot")
+        ),
+        Backend::Files | Backend::Auto => {
+            // Drop whatever the transaction installed before restoring the
+            // files and database entries that were there before it.
+            for name in &snapshot.packages {
+                if let Some(installed) = database.get(name)? {
+                    for entry in installed.files.iter().rev() {
+                        let path = root.join(&entry.path);
+
+                        match entry.kind {
+                            FileKind::Directory => {
+                                let _ = fs::remove_dir(path);
+                            }
+                            _ => {
+                                let _ = fs::remove_file(path);
+                            }
+                        }
+                    }
+
+                    database.remove(name)?;
+                }
+            }
+
+            let archive = snapshot_dir(root)
+                .join(snapshot.id.to_string())
+                .join(FILES_ARCHIVE);
+
+            let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(archive)?)?);
+            archive.set_preserve_permissions(true);
+            archive.unpack(root)?;
+
+            Ok(())
+        }
+    }
+}
+
+fn backup_files(database: &Database, packages: &[String], target: &Path) -> Result<()> {
+    let root = database.root();
+    let encoder = zstd::Encoder::new(File::create(target)?, 3)?;
+    let mut tar = tar::Builder::new(encoder);
+    tar.follow_symlinks(false);
+
+    for name in packages {
+        let Some(installed) = database.get(name)? else {
+            continue;
+        };
+
+        for entry in &installed.files {
+            let path = root.join(&entry.path);
+
+            if fs::symlink_metadata(&path).is_err() {
+                warn!(
+                    "{} is missing, it won't be part of the snapshot",
+                    entry.path
+                );
+                continue;
+            }
+
+            match entry.kind {
+                FileKind::Directory => tar.append_dir(&entry.path, &path)?,
+                _ => tar.append_path_with_name(&path, &entry.path)?,
+            }
+        }
+
+        let entry = Path::new(DATABASE_DIR)
+            .join("local")
+            .join(format!("{name}.toml"));
+        tar.append_path_with_name(root.join(&entry), entry)?;
+    }
+
+    tar.into_inner()?.finish()?;
+
+    Ok(())
+}
+
+fn detect_backend(root: &Path) -> Backend {
+    let output = Command::new("stat")
+        .args(["-f", "-c", "%T"])
+        .arg(root)
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            match String::from_utf8_lossy(&output.stdout).trim() {
+                "btrfs" => Backend::Btrfs,
+                "zfs" => Backend::Zfs,
+                _ => Backend::Files,
+            }
+        }
+        _ => Backend::Files,
+    }
+}
+
+fn zfs_dataset(root: &Path) -> Result<String> {
+    let output = Command::new("findmnt")
+        .args(["-n", "-o", "SOURCE", "--target"])
+        .arg(root)
+        .output()?;
+
+    if !output.status.success() {
+        bail!("Failed to find the zfs dataset of {}", root.display());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let status = command.status()?;
+
+    if !status.success() {
+        bail!("{command:?} failed with {status}");
+    }
+
+    Ok(())
+}
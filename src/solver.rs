@@ -0,0 +1,727 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashSet, VecDeque},
+};
+
+use crate::{
+    database::InstalledPackage,
+    error::Result,
+    failure::Failure,
+    package::{Dependencies, Info, dependency_name},
+    repository::{IndexEntry, Repository},
+    resolver::{Plan, PlannedPackage, Reason},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lit(usize);
+
+impl Lit {
+    fn positive(var: usize) -> Self {
+        Lit(var * 2)
+    }
+
+    fn negative(var: usize) -> Self {
+        Lit(var * 2 + 1)
+    }
+
+    fn var(self) -> usize {
+        self.0 / 2
+    }
+
+    fn is_negative(self) -> bool {
+        self.0 % 2 == 1
+    }
+
+    fn negate(self) -> Self {
+        Lit(self.0 ^ 1)
+    }
+}
+
+/// A small CDCL solver: unit propagation, first-UIP clause learning and
+/// non-chronological backtracking.
+///
+/// Decisions only ever set a variable to true to satisfy a clause whose
+/// negative literals are all false, picking the first unassigned literal.
+/// Clauses list candidates in order of preference, so the solution installs
+/// as little as possible and prefers earlier candidates.
+struct Sat {
+    clauses: Vec<Vec<Lit>>,
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    reasons: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    trail_limits: Vec<usize>,
+}
+
+impl Sat {
+    fn new(variables: usize, clauses: Vec<Vec<Lit>>) -> Self {
+        Self {
+            clauses,
+            values: vec![None; variables],
+            levels: vec![0; variables],
+            reasons: vec![None; variables],
+            trail: Vec::new(),
+            trail_limits: Vec::new(),
+        }
+    }
+
+    fn value(&self, lit: Lit) -> Option<bool> {
+        self.values[lit.var()].map(|value| value != lit.is_negative())
+    }
+
+    fn level(&self) -> usize {
+        self.trail_limits.len()
+    }
+
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        self.values[lit.var()] = Some(!lit.is_negative());
+        self.levels[lit.var()] = self.level();
+        self.reasons[lit.var()] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Assigns every unit literal, returning the index of a falsified clause
+    /// if any.
+    fn propagate(&mut self) -> Option<usize> {
+        loop {
+            let mut changed = false;
+
+            for index in 0..self.clauses.len() {
+                let mut unassigned = Vec::new();
+                let mut satisfied = false;
+
+                for &lit in &self.clauses[index] {
+                    match self.value(lit) {
+                        Some(true) => {
+                            satisfied = true;
+                            break;
+                        }
+                        Some(false) => {}
+                        None => unassigned.push(lit),
+                    }
+                }
+
+                if satisfied {
+                    continue;
+                }
+
+                match unassigned.as_slice() {
+                    [] => return Some(index),
+                    [lit] => {
+                        self.assign(*lit, Some(index));
+                        changed = true;
+                    }
+                    _ => {}
+                }
+            }
+
+            if !changed {
+                return None;
+            }
+        }
+    }
+
+    /// Derives the first-UIP clause from a conflict, along with the level to
+    /// backtrack to.
+    fn analyze(&self, conflict: usize) -> (Vec<Lit>, usize) {
+        let mut seen = vec![false; self.values.len()];
+        let mut learnt = Vec::new();
+        let mut pending = 0;
+        let mut clause = conflict;
+        let mut index = self.trail.len();
+        let mut pivot: Option<Lit> = None;
+
+        loop {
+            for &lit in &self.clauses[clause] {
+                let var = lit.var();
+
+                if pivot.is_some_and(|pivot| pivot.var() == var)
+                    || seen[var]
+                    || self.levels[var] == 0
+                {
+                    continue;
+                }
+
+                seen[var] = true;
+
+                if self.levels[var] == self.level() {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+
+            loop {
+                index -= 1;
+
+                if seen[self.trail[index].var()] {
+                    break;
+                }
+            }
+
+            let lit = self.trail[index];
+            seen[lit.var()] = false;
+            pending -= 1;
+            pivot = Some(lit);
+
+            if pending == 0 {
+                break;
+            }
+
+            clause = self.reasons[lit.var()].expect("propagated literals have a reason");
+        }
+
+        let backjump = learnt
+            .iter()
+            .map(|lit| self.levels[lit.var()])
+            .max()
+            .unwrap_or(0);
+
+        learnt.insert(
+            0,
+            pivot.expect("conflicts involve the current level").negate(),
+        );
+
+        (learnt, backjump)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        while self.level() > level {
+            let limit = self.trail_limits.pop().unwrap_or(0);
+
+            for lit in self.trail.drain(limit..) {
+                self.values[lit.var()] = None;
+                self.reasons[lit.var()] = None;
+            }
+        }
+    }
+
+    fn decide(&self) -> Option<Lit> {
+        self.clauses.iter().find_map(|clause| {
+            let needed = clause.iter().all(|&lit| {
+                if lit.is_negative() {
+                    self.value(lit) == Some(false)
+                } else {
+                    self.value(lit) != Some(true)
+                }
+            });
+
+            clause
+                .iter()
+                .find(|&&lit| !lit.is_negative() && self.value(lit).is_none())
+                .filter(|_| needed)
+                .copied()
+        })
+    }
+
+    /// Returns the value of every variable in a satisfying assignment, if one
+    /// exists. Variables no decision touched are false.
+    fn solve(mut self) -> Option<Vec<bool>> {
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.level() == 0 {
+                    return None;
+                }
+
+                let (learnt, level) = self.analyze(conflict);
+                self.backtrack(level);
+
+                let lit = learnt[0];
+                self.clauses.push(learnt);
+                self.assign(lit, Some(self.clauses.len() - 1));
+
+                continue;
+            }
+
+            match self.decide() {
+                Some(lit) => {
+                    self.trail_limits.push(self.trail.len());
+                    self.assign(lit, None);
+                }
+                None => {
+                    return Some(
+                        self.values
+                            .iter()
+                            .map(|value| *value == Some(true))
+                            .collect(),
+                    );
+                }
+            }
+        }
+    }
+}
+
+enum Source<'a> {
+    Installed,
+    Repository(&'a Repository, &'a IndexEntry),
+}
+
+struct Candidate<'a> {
+    info: &'a Info,
+    dependencies: Option<&'a Dependencies>,
+    source: Source<'a>,
+    /// The priority of the repository carrying this version. Installed
+    /// packages take the one of the repository carrying the same version, or
+    /// the default of 0 if none does.
+    priority: i32,
+}
+
+impl Candidate<'_> {
+    fn is_installed(&self) -> bool {
+        matches!(self.source, Source::Installed)
+    }
+
+    fn required(&self) -> impl Iterator<Item = &String> {
+        self.dependencies
+            .into_iter()
+            .flat_map(|dependencies| &dependencies.required)
+    }
+}
+
+struct Problem<'a> {
+    candidates: Vec<Candidate<'a>>,
+    /// Clauses that always hold, such as a single version per package.
+    hard: Vec<Vec<Lit>>,
+    /// Requirements, described for unsatisfiable cores.
+    requirements: Vec<(String, Vec<Lit>)>,
+}
+
+impl<'a> Problem<'a> {
+    fn new(repositories: &'a [Repository], installed: &[&'a InstalledPackage]) -> Self {
+        let mut candidates: Vec<Candidate> = installed
+            .iter()
+            .map(|installed| Candidate {
+                info: &installed.info,
+                dependencies: installed.dependencies.as_ref(),
+                source: Source::Installed,
+                priority: repositories
+                    .iter()
+                    .find(|repository| {
+                        repository.index.packages.iter().any(|entry| {
+                            entry.info.name == installed.info.name
+                                && entry.info.version == installed.info.version
+                        })
+                    })
+                    .map_or(0, |repository| repository.priority),
+            })
+            .collect();
+
+        for repository in repositories {
+            // The installed copy stands in for the same version in a repository.
+            for entry in repository.index.packages.iter().filter(|entry| {
+                !installed.iter().any(|installed| {
+                    installed.info.name == entry.info.name
+                        && installed.info.version == entry.info.version
+                })
+            }) {
+                candidates.push(Candidate {
+                    info: &entry.info,
+                    dependencies: entry.dependencies.as_ref(),
+                    source: Source::Repository(repository, entry),
+                    priority: repository.priority,
+                });
+            }
+        }
+
+        Self {
+            candidates,
+            hard: Vec::new(),
+            requirements: Vec::new(),
+        }
+    }
+
+    /// Candidates matching `reference`, in order of preference.
+    fn matching(&self, reference: &str, prefer_installed: bool) -> Result<Vec<usize>> {
+        let mut matching = Vec::new();
+
+        for (index, candidate) in self.candidates.iter().enumerate() {
+            if candidate.info.matches(reference)? {
+                matching.push(index);
+            }
+        }
+
+        matching.sort_by(|&a, &b| {
+            let (a, b) = (&self.candidates[a], &self.candidates[b]);
+
+            let installed = if prefer_installed {
+                b.is_installed().cmp(&a.is_installed())
+            } else {
+                Ordering::Equal
+            };
+
+            installed
+                .then_with(|| b.priority.cmp(&a.priority))
+                .then_with(|| crate::version::compare(&b.info.version, &a.info.version))
+                .then_with(|| b.is_installed().cmp(&a.is_installed()))
+        });
+
+        Ok(matching)
+    }
+
+    fn label(&self, index: usize) -> String {
+        let info = self.candidates[index].info;
+        format!("{} {}", info.name, info.version)
+    }
+
+    /// Generates the clauses of every candidate reachable from `roots`.
+    fn encode(&mut self, roots: Vec<usize>, upgrade: bool) -> Result<()> {
+        let mut queue = VecDeque::from(roots);
+        let mut visited = HashSet::new();
+        let mut names = BTreeSet::new();
+
+        while let Some(index) = queue.pop_front() {
+            if !visited.insert(index) {
+                continue;
+            }
+
+            let candidate = &self.candidates[index];
+            names.insert(candidate.info.name.clone());
+
+            let required: Vec<String> = candidate.required().cloned().collect();
+            let conflicts = candidate.info.conflicts.clone();
+            let replaces = candidate.info.replaces.clone();
+
+            for dependency in required {
+                let matching = self.matching(&dependency, !upgrade)?;
+                let mut clause = vec![Lit::negative(index)];
+                clause.extend(matching.iter().map(|&i| Lit::positive(i)));
+
+                self.requirements
+                    .push((format!("{} needs {dependency}", self.label(index)), clause));
+                queue.extend(matching);
+            }
+
+            for (reference, relation) in conflicts
+                .iter()
+                .map(|reference| (reference, "conflicts with"))
+                .chain(replaces.iter().map(|reference| (reference, "replaces")))
+            {
+                for other in self.matching(reference, false)? {
+                    if self.candidates[other].info.name == self.candidates[index].info.name {
+                        continue;
+                    }
+
+                    self.requirements.push((
+                        format!("{} {relation} {}", self.label(index), self.label(other)),
+                        vec![Lit::negative(index), Lit::negative(other)],
+                    ));
+                }
+            }
+        }
+
+        for name in names {
+            let versions: Vec<usize> = (0..self.candidates.len())
+                .filter(|&i| self.candidates[i].info.name == name)
+                .collect();
+
+            for (i, &a) in versions.iter().enumerate() {
+                for &b in &versions[i + 1..] {
+                    self.hard.push(vec![Lit::negative(a), Lit::negative(b)]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn clauses(&self, skip: &HashSet<usize>) -> Vec<Vec<Lit>> {
+        self.hard
+            .iter()
+            .cloned()
+            .chain(
+                self.requirements
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !skip.contains(i))
+                    .map(|(_, (_, clause))| clause.clone()),
+            )
+            .collect()
+    }
+
+    /// Shrinks the requirements to a minimal set that still can't be met,
+    /// dropping each one in turn.
+    fn unsatisfiable_core(&self) -> Vec<&str> {
+        let mut skip = HashSet::new();
+
+        for i in 0..self.requirements.len() {
+            skip.insert(i);
+
+            if Sat::new(self.candidates.len(), self.clauses(&skip))
+                .solve()
+                .is_some()
+            {
+                skip.remove(&i);
+            }
+        }
+
+        self.requirements
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !skip.contains(i))
+            .map(|(_, (label, _))| label.as_str())
+            .collect()
+    }
+}
+
+/// Resolves `requests` along with every installed package as a whole, so
+/// that the result is consistent for the entire system. Installed packages
+/// are kept at their version unless `upgrade` is set, in which case the
+/// newest consistent versions are picked.
+pub(crate) fn solve(
+    repositories: &[Repository],
+    installed: &[&InstalledPackage],
+    requests: &[(&str, Reason)],
+    upgrade: bool,
+) -> Result<Plan> {
+    let mut problem = Problem::new(repositories, installed);
+    let mut roots = Vec::new();
+
+    for (reference, reason) in requests {
+        let matching = problem.matching(reference, false)?;
+
+        let label = match reason {
+            Reason::Optional { of } => format!("{reference} was selected for {of}"),
+            _ => format!("{reference} was requested"),
+        };
+
+        problem
+            .requirements
+            .push((label, matching.iter().map(|&i| Lit::positive(i)).collect()));
+        roots.extend(matching);
+    }
+
+    for (index, package) in installed.iter().enumerate() {
+        let mut matching: Vec<usize> = problem
+            .matching(&package.info.name, !upgrade)?
+            .into_iter()
+            .filter(|&i| problem.candidates[i].info.name == package.info.name)
+            .collect();
+
+        for (i, candidate) in problem.candidates.iter().enumerate() {
+            for reference in &candidate.info.replaces {
+                if package.info.matches(reference)? && !matching.contains(&i) {
+                    matching.push(i);
+                }
+            }
+        }
+
+        problem.requirements.push((
+            format!("{} is installed", problem.label(index)),
+            matching.iter().map(|&i| Lit::positive(i)).collect(),
+        ));
+        roots.extend(matching);
+    }
+
+    problem.encode(roots, upgrade)?;
+
+    let Some(model) = Sat::new(problem.candidates.len(), problem.clauses(&HashSet::new())).solve()
+    else {
+        return Err(Failure::Dependency.error(format!(
+            "No consistent set of packages satisfies all of:\n  {}",
+            problem.unsatisfiable_core().join("\n  ")
+        )));
+    };
+
+    let chosen: Vec<usize> = (0..model.len()).filter(|&i| model[i]).collect();
+
+    let to_install: Vec<usize> = chosen
+        .iter()
+        .copied()
+        .filter(|&i| !problem.candidates[i].is_installed())
+        .collect();
+
+    let mut plan = Plan::default();
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+
+    for &index in &to_install {
+        topological_order(
+            &problem,
+            &to_install,
+            index,
+            &mut visited,
+            &mut Vec::new(),
+            &mut order,
+            &mut plan,
+        )?;
+    }
+
+    for index in order {
+        let candidate = &problem.candidates[index];
+
+        let Source::Repository(repository, entry) = candidate.source else {
+            continue;
+        };
+
+        plan.install.push(PlannedPackage {
+            repository: repository.name.clone(),
+            entry: entry.clone(),
+            reason: reason(&problem, &chosen, requests, index)?,
+        });
+    }
+
+    Ok(plan)
+}
+
+fn topological_order(
+    problem: &Problem,
+    to_install: &[usize],
+    index: usize,
+    visited: &mut HashSet<usize>,
+    chain: &mut Vec<String>,
+    order: &mut Vec<usize>,
+    plan: &mut Plan,
+) -> Result<()> {
+    let name = &problem.candidates[index].info.name;
+
+    if !visited.insert(index) {
+        plan.record_cycle(chain, name);
+        return Ok(());
+    }
+
+    chain.push(name.clone());
+
+    for dependency in problem.candidates[index].required() {
+        for &other in to_install {
+            if problem.candidates[other].info.matches(dependency)? {
+                topological_order(problem, to_install, other, visited, chain, order, plan)?;
+            }
+        }
+    }
+
+    chain.pop();
+    order.push(index);
+
+    Ok(())
+}
+
+fn reason(
+    problem: &Problem,
+    chosen: &[usize],
+    requests: &[(&str, Reason)],
+    index: usize,
+) -> Result<Reason> {
+    let info = problem.candidates[index].info;
+
+    for (reference, reason) in requests {
+        if info.matches(reference)? {
+            return Ok(reason.clone());
+        }
+    }
+
+    for &other in chosen {
+        for dependency in problem.candidates[other].required() {
+            if other == index || !info.matches(dependency)? {
+                continue;
+            }
+
+            let of = problem.candidates[other].info.name.clone();
+            let name = dependency_name(dependency);
+
+            return Ok(if name == info.name {
+                Reason::Dependency { of }
+            } else {
+                Reason::Provider {
+                    of,
+                    provides: name.to_string(),
+                }
+            });
+        }
+    }
+
+    // Upgrades of installed packages, or their replacements.
+    Ok(Reason::Requested)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        package::PeachInfo,
+        repository::RepoIndex,
+        resolver::{Resolver, Solver},
+        test_util::test_entry as entry,
+    };
+
+    fn installed(entry: IndexEntry) -> InstalledPackage {
+        let peach_info = PeachInfo {
+            info: entry.info,
+            dependencies: entry.dependencies,
+            backup: Vec::new(),
+            build_date: None,
+            installed_size: 0,
+            special_permissions: Vec::new(),
+            changelog: Vec::new(),
+        };
+
+        InstalledPackage::new(peach_info, Default::default(), Vec::new())
+    }
+
+    #[test]
+    fn test_upgrade_whole_system() {
+        let installed = [
+            installed(entry("app", "1.0", &["libc<2"])),
+            installed(entry("libc", "1.0", &[])),
+        ];
+        let repositories = vec![Repository {
+            name: "core".to_string(),
+            priority: 0,
+            index: RepoIndex {
+                generation: 0,
+                packages: vec![
+                    entry("app", "1.0", &["libc<2"]),
+                    entry("app", "2.0", &["libc>=2"]),
+                    entry("libc", "1.0", &[]),
+                    entry("libc", "2.0", &[]),
+                ],
+            },
+        }];
+
+        let plan = Resolver::new(&repositories, &installed)
+            .solver(Solver::Sat)
+            .upgrade(true)
+            .resolve(&[])
+            .unwrap();
+
+        let versions: Vec<_> = plan
+            .install
+            .iter()
+            .map(|p| (p.entry.info.name.as_str(), p.entry.info.version.as_str()))
+            .collect();
+        assert_eq!(versions, [("libc", "2.0"), ("app", "2.0")]);
+
+        let error = Resolver::new(&repositories, &installed)
+            .solver(Solver::Sat)
+            .resolve(&["libc>=2".to_string(), "app<2".to_string()])
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "No consistent set of packages satisfies all of:\n  libc>=2 was requested\n  app<2 was requested\n  app 1.0 needs libc<2"
+        );
+    }
+
+    #[test]
+    fn test_sat_backtracks() {
+        // x0 or x1, x0 implies x2, x2 implies not x0
+        let clauses = vec![
+            vec![Lit::positive(0), Lit::positive(1)],
+            vec![Lit::negative(0), Lit::positive(2)],
+            vec![Lit::negative(2), Lit::negative(0)],
+        ];
+
+        let model = Sat::new(3, clauses).solve().unwrap();
+        assert_eq!(model, [false, true, false]);
+    }
+
+    #[test]
+    fn test_sat_unsatisfiable() {
+        let clauses = vec![
+            vec![Lit::positive(0)],
+            vec![Lit::negative(0), Lit::positive(1)],
+            vec![Lit::negative(1)],
+        ];
+
+        assert!(Sat::new(2, clauses).solve().is_none());
+    }
+}
@@ -0,0 +1,152 @@
+//! The content-addressed store installed files are hard linked from, so that
+//! files shared by packages or by versions of a package are kept once and
+//! reinstalling only links them again.
+//!
+//! Objects are named after the hash and the mode of their contents, a file
+//! only being removed from the store once no installed file links to it.
+//! Configuration files and files with special permissions are never stored,
+//! as they're modified in place or differ from their contents. Other files
+//! can still be edited in place, which edits their object too, so objects are
+//! hashed again before being linked and dropped when they no longer match.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    database::{DATABASE_DIR, FileEntry, FileKind, hash_file},
+    error::Result,
+    log::warn,
+};
+
+const STORE_DIR: &str = "store";
+
+#[derive(Debug, Clone)]
+pub struct Store {
+    path: PathBuf,
+}
+
+impl Store {
+    pub fn open<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            path: root.as_ref().join(DATABASE_DIR).join(STORE_DIR),
+        }
+    }
+
+    /// Where contents with `hash` and `mode` are kept.
+    fn object(&self, hash: &str, mode: u32) -> PathBuf {
+        let hex = hash.split_once(':').map_or(hash, |(_, hex)| hex);
+
+        self.path
+            .join(hex.get(..2).unwrap_or(hex))
+            .join(format!("{hex}-{mode:o}"))
+    }
+
+    /// Whether the object at `object` still holds contents with `hash` and
+    /// `mode`, removing it when it was modified.
+    fn is_intact(&self, object: &Path, hash: &str, mode: u32) -> Result<bool> {
+        let metadata = match fs::metadata(object) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+        if metadata.mode() & 0o7777 == mode && hash_file(object)? == hash {
+            return Ok(true);
+        }
+
+        warn!(
+            "{} was modified in place, removing it from the store",
+            object.display()
+        );
+        match fs::remove_file(object) {
+            // Another worker got to it first.
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(false),
+        }
+    }
+
+    /// Links the stored contents with `hash` and `mode` to `dest`. Returns
+    /// whether they were stored, `dest` having to be written otherwise.
+    pub fn link(&self, hash: &str, mode: u32, dest: &Path) -> Result<bool> {
+        let object = self.object(hash, mode);
+
+        if !self.is_intact(&object, hash, mode)? {
+            return Ok(false);
+        }
+
+        match fs::hard_link(&object, dest) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) if e.kind() == ErrorKind::CrossesDevices => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Adds the file at `path`, whose contents hash to `hash`, to the store,
+    /// replacing it with a link to the stored one if they already are. Files
+    /// on another filesystem than the store are left out.
+    pub fn add(&self, path: &Path, hash: &str, mode: u32) -> Result<()> {
+        let object = self.object(hash, mode);
+
+        if let Some(parent) = object.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match fs::hard_link(path, &object) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                if self.is_intact(&object, hash, mode)? {
+                    fs::remove_file(path)?;
+                    fs::hard_link(&object, path)?;
+                    return Ok(());
+                }
+
+                // Stored afresh, unless another worker already did.
+                match fs::hard_link(path, &object) {
+                    Err(e) if e.kind() != ErrorKind::AlreadyExists => Err(e.into()),
+                    _ => Ok(()),
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::CrossesDevices => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Removes the objects of `files` no installed file links to anymore.
+    pub fn release(&self, files: &[FileEntry]) -> Result<()> {
+        for file in files {
+            let (FileKind::File, Some(hash)) = (&file.kind, &file.hash) else {
+                continue;
+            };
+            let object = self.object(hash, file.mode);
+
+            match fs::metadata(&object) {
+                Ok(metadata) if metadata.nlink() == 1 => fs::remove_file(&object)?,
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object() {
+        let store = Store::open("/");
+
+        assert_eq!(
+            store.object("blake3:af1349b9f5f9a1a6", 0o644),
+            Path::new("/var/lib/blossom/store/af/af1349b9f5f9a1a6-644")
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::path::Path;
+
+use crate::{
+    database::{Database, InstallReason, InstalledPackage},
+    package::{Dependencies, Info, Package, PeachInfo},
+    repository::IndexEntry,
+};
+
+/// An entry of `name` at `version` requiring `required`, for tests.
+pub fn test_entry(name: &str, version: &str, required: &[&str]) -> IndexEntry {
+    IndexEntry {
+        info: Info {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: Default::default(),
+            license: spdx::Expression::parse("MIT").unwrap(),
+            provides: Vec::new(),
+            conflicts: Vec::new(),
+            replaces: Vec::new(),
+            keywords: Vec::new(),
+        },
+        dependencies: Some(Dependencies {
+            required: required.iter().map(|d| d.to_string()).collect(),
+            optional: Vec::new(),
+            build: Vec::new(),
+            check: Vec::new(),
+        }),
+        filename: format!("{name}-{version}.peach"),
+        checksum: String::new(),
+        size: 0,
+        installed_size: 0,
+        files: Vec::new(),
+        special_permissions: Vec::new(),
+        changelog: Vec::new(),
+    }
+}
+
+/// Records `name` as installed in `root` for `reason`, requiring `required`
+/// and providing `provides`, for tests.
+pub fn install_test_package(
+    root: &Path,
+    name: &str,
+    reason: InstallReason,
+    required: &[&str],
+    provides: &[&str],
+) {
+    let mut package = Package::builder().name(name).version("1.0").license("MIT");
+    for dependency in required {
+        package = package.dependency(*dependency);
+    }
+    for provided in provides {
+        package = package.provides(*provided);
+    }
+
+    let peach_info = PeachInfo::from(&package.build().unwrap());
+    Database::open(root)
+        .unwrap()
+        .put(&InstalledPackage::new(peach_info, reason, Vec::new()))
+        .unwrap();
+}
@@ -0,0 +1,149 @@
+//! Upstream signatures of source archives, checked with gpg against a
+//! keyring holding only the keys the recipe trusts, so that a tampered
+//! archive is caught even when its checksum was updated along with it.
+
+use std::{
+    fs::{self, DirBuilder},
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    error::{Result, bail, format_err},
+    failure::Failure,
+};
+
+/// How armored keys start, telling them apart from paths to keys.
+const ARMOR: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----";
+
+/// Where the signature at `url` of the archive at `archive` is downloaded
+/// to: next to it, with the extension of the signature.
+pub fn signature_path(archive: &Path, url: &str) -> PathBuf {
+    let extension = match url.rsplit_once('.') {
+        Some((_, "asc")) => "asc",
+        _ => "sig",
+    };
+
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+
+    PathBuf::from(path)
+}
+
+/// Checks that `signature` is a signature of the file at `path` by one of
+/// `keys`, armored keys or files of them relative to `recipe_dir`. The
+/// keyring is made in `home`, replacing whatever was there.
+pub fn verify(
+    path: &Path,
+    signature: &Path,
+    keys: &[String],
+    recipe_dir: &Path,
+    home: &Path,
+) -> Result<()> {
+    if keys.is_empty() {
+        return Err(Failure::Signature.error(format!(
+            "{} is signed but the recipe has no upstream_keys to check it with",
+            path.display()
+        )));
+    }
+
+    if home.exists() {
+        fs::remove_dir_all(home)?;
+    }
+    // gpg refuses homes other users can read.
+    DirBuilder::new().recursive(true).mode(0o700).create(home)?;
+
+    let mut files = Vec::new();
+    for (i, key) in keys.iter().enumerate() {
+        if key.trim_start().starts_with(ARMOR) {
+            let file = home.join(format!("key-{i}.asc"));
+            fs::write(&file, key)?;
+            files.push(file);
+        } else {
+            files.push(recipe_dir.join(key));
+        }
+    }
+
+    let import = gpg(home)
+        .arg("--import")
+        .args(&files)
+        .output()
+        .map_err(|e| format_err!("Failed to run gpg: {e}"))?;
+    if !import.status.success() {
+        bail!(
+            "Failed to import the upstream keys: {}",
+            String::from_utf8_lossy(&import.stderr).trim()
+        );
+    }
+
+    let output = gpg(home)
+        .args(["--status-fd", "1", "--verify"])
+        .arg(signature)
+        .arg(path)
+        .output()
+        .map_err(|e| format_err!("Failed to run gpg: {e}"))?;
+
+    if !output.status.success() || !is_valid(&String::from_utf8_lossy(&output.stdout)) {
+        return Err(Failure::Signature.error(format!(
+            "{} isn't signed by any of the upstream keys of the recipe",
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+fn gpg(home: &Path) -> Command {
+    let mut command = Command::new("gpg");
+    command
+        .arg("--homedir")
+        .arg(home)
+        .args(["--batch", "--no-tty", "--quiet"]);
+
+    command
+}
+
+/// Whether the status lines gpg wrote tell of a valid signature, and of no
+/// bad one.
+fn is_valid(status: &str) -> bool {
+    let lines = || {
+        status
+            .lines()
+            .filter_map(|line| line.strip_prefix("[GNUPG:] "))
+    };
+
+    lines().any(|line| line.starts_with("VALIDSIG "))
+        && !lines().any(|line| line.starts_with("BADSIG ") || line.starts_with("ERRSIG "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_path() {
+        let archive = Path::new("/build/foo-1.0.tar.gz");
+
+        assert_eq!(
+            signature_path(archive, "https://example.com/foo-1.0.tar.gz.asc"),
+            Path::new("/build/foo-1.0.tar.gz.asc")
+        );
+        assert_eq!(
+            signature_path(archive, "https://example.com/foo-1.0.tar.gz.sig"),
+            Path::new("/build/foo-1.0.tar.gz.sig")
+        );
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(is_valid(
+            "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG 1234 Upstream\n[GNUPG:] VALIDSIG ABCD 2025-01-01\n"
+        ));
+        assert!(!is_valid(
+            "[GNUPG:] NEWSIG\n[GNUPG:] BADSIG 1234 Upstream\n"
+        ));
+        assert!(!is_valid("[GNUPG:] ERRSIG 1234 1 8 00 1735689600 9\n"));
+    }
+}
@@ -0,0 +1,214 @@
+use std::{cmp::Ordering, fmt::Display};
+
+use crate::error::{Result, bail, format_err};
+
+/// Compares two version strings the way rpm and pacman do: runs of digits are
+/// compared numerically, runs of letters lexically, and separators only split
+/// segments. A version with additional segments is considered newer, unless
+/// the extra segment is alphabetic (`1.0rc1` < `1.0`).
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let mut a = a;
+    let mut b = b;
+
+    loop {
+        a = a.trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+        b = b.trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => {
+                return if b.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                };
+            }
+            (false, true) => {
+                return if a.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+            }
+            (false, false) => {}
+        }
+
+        let numeric = a.starts_with(|c: char| c.is_ascii_digit());
+
+        let split = |s: &'_ str| -> usize {
+            s.find(|c: char| {
+                if numeric {
+                    !c.is_ascii_digit()
+                } else {
+                    !c.is_ascii_alphabetic()
+                }
+            })
+            .unwrap_or(s.len())
+        };
+
+        let (segment_a, rest_a) = a.split_at(split(a));
+        let (segment_b, rest_b) = b.split_at(split(b));
+
+        // Numeric segments are always newer than alphabetic ones.
+        if segment_b.is_empty() {
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let ordering = if numeric {
+            let segment_a = segment_a.trim_start_matches('0');
+            let segment_b = segment_b.trim_start_matches('0');
+
+            segment_a
+                .len()
+                .cmp(&segment_b.len())
+                .then_with(|| segment_a.cmp(segment_b))
+        } else {
+            segment_a.cmp(segment_b)
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+
+        a = rest_a;
+        b = rest_b;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+}
+
+impl Operator {
+    fn symbol(self) -> &'static str {
+        match self {
+            Operator::Less => "<",
+            Operator::LessEqual => "<=",
+            Operator::Equal => "=",
+            Operator::GreaterEqual => ">=",
+            Operator::Greater => ">",
+        }
+    }
+}
+
+/// A single version requirement, such as `>=1.2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub operator: Operator,
+    pub version: String,
+}
+
+impl Constraint {
+    pub fn matches(&self, version: &str) -> bool {
+        let ordering = compare(version, &self.version);
+
+        match self.operator {
+            Operator::Less => ordering.is_lt(),
+            Operator::LessEqual => ordering.is_le(),
+            Operator::Equal => ordering.is_eq(),
+            Operator::GreaterEqual => ordering.is_ge(),
+            Operator::Greater => ordering.is_gt(),
+        }
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.operator.symbol(), self.version)
+    }
+}
+
+/// Parses a comma separated list of constraints, e.g. `>=1.2,<2`. An empty
+/// string yields no constraints.
+pub fn parse_constraints(constraints: &str) -> Result<Vec<Constraint>> {
+    let mut parsed = Vec::new();
+
+    for constraint in constraints.split(',').map(str::trim) {
+        if constraint.is_empty() {
+            continue;
+        }
+
+        let (operator, version) = [
+            ("<=", Operator::LessEqual),
+            (">=", Operator::GreaterEqual),
+            ("==", Operator::Equal),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+            ("=", Operator::Equal),
+        ]
+        .into_iter()
+        .find_map(|(symbol, operator)| Some((operator, constraint.strip_prefix(symbol)?)))
+        .ok_or(format_err!("Invalid version constraint \"{constraint}\""))?;
+
+        let version = version.trim();
+
+        if version.is_empty() {
+            bail!("Version constraint \"{constraint}\" is missing a version");
+        }
+
+        parsed.push(Constraint {
+            operator,
+            version: version.to_string(),
+        });
+    }
+
+    Ok(parsed)
+}
+
+/// Whether `version` satisfies every constraint.
+pub fn satisfies(version: &str, constraints: &[Constraint]) -> bool {
+    constraints
+        .iter()
+        .all(|constraint| constraint.matches(version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numeric_segments() {
+        assert_eq!(compare("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(compare("1.2", "1.10"), Ordering::Less);
+        assert_eq!(compare("2.0", "1.99"), Ordering::Greater);
+        assert_eq!(compare("1.01", "1.1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_extra_segments() {
+        assert_eq!(compare("1.0.1", "1.0"), Ordering::Greater);
+        assert_eq!(compare("1.0rc1", "1.0"), Ordering::Less);
+        assert_eq!(compare("1.0a", "1.0b"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_numeric_beats_alpha() {
+        assert_eq!(compare("1.1", "1.a"), Ordering::Greater);
+        assert_eq!(compare("1.a", "1.1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_constraints() {
+        let constraints = parse_constraints(">=1.2, <2").unwrap();
+
+        assert_eq!(constraints.len(), 2);
+        assert!(satisfies("1.2", &constraints));
+        assert!(satisfies("1.10", &constraints));
+        assert!(!satisfies("2.0", &constraints));
+        assert!(!satisfies("1.1", &constraints));
+
+        assert!(parse_constraints("").unwrap().is_empty());
+        assert!(parse_constraints("=1.0").unwrap()[0].matches("1.0"));
+        assert!(parse_constraints("~1.0").is_err());
+        assert!(parse_constraints(">=").is_err());
+    }
+}